@@ -8,11 +8,13 @@
 //! EdenFsInstance - manages EdenFS resources besides Thrift connection (managed by
 //! [`EdenFsClient`]).
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use anyhow::{anyhow, Context};
 
+use edenfs_config::checkout::{load_directory_map, save_directory_map, CLIENTS_DIR};
 use edenfs_config::EdenFsConfig;
 use edenfs_error::{EdenFsError, Result, ResultExt};
 use fbthrift_socket::SocketTransport;
@@ -55,6 +57,53 @@ impl EdenFsInstance {
         )
     }
 
+    /// Directory holding the state of every checkout, one sub-directory per checkout.
+    pub fn clients_dir(&self) -> PathBuf {
+        self.config_dir.join(CLIENTS_DIR)
+    }
+
+    /// Directory of the local store, where data fetched from source control is cached.
+    pub fn storage_dir(&self) -> PathBuf {
+        self.config_dir.join("storage")
+    }
+
+    /// Log file of the daemon.
+    pub fn log_path(&self) -> PathBuf {
+        self.config_dir.join("logs").join("edenfs.log")
+    }
+
+    /// Mount points of all the configured checkouts, with the name of their client directory.
+    pub fn get_configured_mounts_map(&self) -> Result<BTreeMap<PathBuf, String>> {
+        Ok(load_directory_map(&self.config_dir)?)
+    }
+
+    /// Return the client directory of the checkout mounted at `mount_point`.
+    pub fn client_dir_for_mount_point(&self, mount_point: &Path) -> Result<PathBuf> {
+        let mounts = self.get_configured_mounts_map()?;
+        let name = mounts
+            .get(mount_point)
+            .ok_or_else(|| anyhow!("could not find mount path {}", mount_point.display()))?;
+        Ok(self.clients_dir().join(name))
+    }
+
+    /// Record that the checkout stored in `clients/<client_name>` is mounted at `mount_point`.
+    pub fn add_configured_mount(&self, mount_point: &Path, client_name: &str) -> Result<()> {
+        let mut mounts = self.get_configured_mounts_map()?;
+        if mounts.contains_key(mount_point) {
+            return Err(anyhow!("mount path {} already exists", mount_point.display()).into());
+        }
+        mounts.insert(mount_point.to_path_buf(), client_name.to_string());
+        Ok(save_directory_map(&self.config_dir, &mounts)?)
+    }
+
+    pub fn remove_configured_mount(&self, mount_point: &Path) -> Result<()> {
+        let mut mounts = self.get_configured_mounts_map()?;
+        if mounts.remove(mount_point).is_some() {
+            save_directory_map(&self.config_dir, &mounts)?;
+        }
+        Ok(())
+    }
+
     async fn _connect(&self, socket_path: &PathBuf) -> Result<EdenFsClient> {
         let stream = UnixStream::connect(&socket_path)
             .await
@@ -13,5 +13,6 @@ pub mod instance;
 mod utils;
 
 pub use instance::{DaemonHealthy, EdenFsInstance};
+pub use utils::{bytes_from_path, path_from_bytes};
 
 pub type EdenFsClient = Arc<dyn EdenService + Sync>;
@@ -5,7 +5,7 @@
  * GNU General Public License version 2.
  */
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use sysinfo::{ProcessExt, SystemExt};
 use tracing::trace;
 
@@ -39,3 +39,29 @@ pub fn get_executable(pid: sysinfo::Pid) -> Option<PathBuf> {
 
     None
 }
+
+/// Convert a thrift `PathString` to a path.
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::OsStr::from_bytes(bytes).into()
+}
+
+/// Convert a thrift `PathString` to a path.
+#[cfg(windows)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+/// Convert a path to a thrift `PathString`.
+#[cfg(unix)]
+pub fn bytes_from_path(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+/// Convert a path to a thrift `PathString`.
+#[cfg(windows)]
+pub fn bytes_from_path(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
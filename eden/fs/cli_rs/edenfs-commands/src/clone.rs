@@ -0,0 +1,615 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl clone
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use structopt::StructOpt;
+use tracing::{event, Level};
+
+use edenfs_client::{bytes_from_path, EdenFsClient, EdenFsInstance};
+use edenfs_config::checkout::{save_snapshot, CheckoutConfig, Repository};
+use edenfs_error::{Result, ResultExt};
+
+use crate::mount::{mount_checkout, normalize_path};
+use crate::unmount::unmount_checkout;
+use crate::ExitCode;
+
+/// Left in the mount point directory, visible only when the checkout isn't mounted.
+pub(crate) const README_FILE: &str = "README_EDEN.txt";
+const README_TEXT: &str = "\
+This directory is the mount point for a virtual checkout managed by EdenFS.
+
+If you are seeing this file that means that your repository checkout is not
+currently mounted.  This could either be because the edenfs daemon is not
+currently running, or it simply does not have this checkout mounted yet.
+
+You can run \"eden doctor\" to check for problems with EdenFS and try to have it
+automatically remount your checkouts.
+";
+
+const DEFAULT_HG_REVISION: &str = "first(present(master) + .)";
+
+/// Left in the client directory once the post-clone setup of the checkout has completed.
+const CLONE_SUCCEEDED: &str = "clone-succeeded";
+
+/// Appended to the hgrc of the backing repository, unless `hg.extra_hgrc` is configured.
+const DEFAULT_EXTRA_HGRC: &str = "\
+[extensions]
+eden =
+share =
+
+[ui]
+portablefilenames = ignore
+";
+
+/// Version of the `.hg/dirstate` format written for new checkouts.
+const DIRSTATE_VERSION: u32 = 1;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Create a clone of a specific repo and check it out")]
+pub struct CloneCmd {
+    /// The path to an existing repo to clone, or an existing EdenFS checkout
+    repo: PathBuf,
+
+    /// The path where the checkout should be mounted
+    path: PathBuf,
+
+    /// The initial revision to check out
+    #[structopt(long, short = "r")]
+    rev: Option<String>,
+
+    /// Mount the checkout with NFS instead of the platform default
+    #[structopt(long)]
+    nfs: bool,
+}
+
+/// Find the repository backing `repo`, which is either a Mercurial repository or an existing
+/// EdenFS checkout, in which case its backing repository is reused.
+fn resolve_repository(repo: &Path) -> Result<Repository> {
+    if let Ok(client_dir) = fs::read_link(repo.join(".eden").join("client")) {
+        return Ok(CheckoutConfig::load(&client_dir)?.repository);
+    }
+
+    let repo = normalize_path(repo)?;
+    if !repo.join(".hg").is_dir() {
+        return Err(anyhow!(
+            "{} does not look like a Mercurial repository or an EdenFS checkout",
+            repo.display()
+        )
+        .into());
+    }
+    Ok(Repository {
+        path: repo,
+        repo_type: "hg".to_string(),
+        guid: None,
+        protocol: None,
+        case_sensitive: None,
+        require_utf8_path: None,
+    })
+}
+
+fn resolve_commit(hg: &OsStr, repository: &Repository, rev: &str) -> Result<String> {
+    let output = Command::new(hg)
+        .args(["log", "-r", rev, "-T", "{node}", "--cwd"])
+        .arg(&repository.path)
+        .output()
+        .context("Unable to run hg")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "unable to resolve {}: {}",
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if commit.is_empty() {
+        return Err(anyhow!("{} did not match any commit", rev).into());
+    }
+    Ok(commit)
+}
+
+/// Create the mount point, which must be either missing or empty.
+fn create_mount_point(mount_point: &Path) -> Result<()> {
+    fs::create_dir_all(mount_point)
+        .with_context(|| format!("Unable to create {}", mount_point.display()))?;
+    if fs::read_dir(mount_point).from_err()?.next().is_some() {
+        return Err(anyhow!("{} is not empty", mount_point.display()).into());
+    }
+    if cfg!(not(windows)) {
+        // On Windows, anything put in this directory would be visible in the checkout.
+        fs::write(mount_point.join(README_FILE), README_TEXT).from_err()?;
+    }
+    Ok(())
+}
+
+/// Create a new directory in `clients_dir`, named after the mount point. A numeric suffix is
+/// added if the name is already used.
+fn create_client_dir(clients_dir: &Path, mount_point: &Path) -> Result<(PathBuf, String)> {
+    let basename = mount_point
+        .file_name()
+        .ok_or_else(|| {
+            anyhow!(
+                "Suspicious attempt to clone into: {}",
+                mount_point.display()
+            )
+        })?
+        .to_string_lossy()
+        .into_owned();
+    fs::create_dir_all(clients_dir).from_err()?;
+
+    for i in 0.. {
+        let name = if i == 0 {
+            basename.clone()
+        } else {
+            format!("{}-{}", basename, i)
+        };
+        let client_dir = clients_dir.join(&name);
+        match fs::create_dir(&client_dir) {
+            Ok(()) => return Ok((client_dir, name)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Unable to create {}", client_dir.display()))
+                    .map_err(Into::into);
+            }
+        }
+    }
+    unreachable!()
+}
+
+/// The Mercurial binary, `EDEN_HG_BINARY` if set.
+fn hg_binary() -> OsString {
+    std::env::var_os("EDEN_HG_BINARY").unwrap_or_else(|| "hg".into())
+}
+
+/// An empty dirstate whose first parent is `commit`. The format is documented in
+/// `eden/fs/py/eden/dirstate.py`.
+fn empty_dirstate(commit: &str) -> Result<Vec<u8>> {
+    let parent = hex::decode(commit).with_context(|| format!("Invalid commit: {}", commit))?;
+    if parent.len() != 20 {
+        return Err(anyhow!("Invalid commit: {}", commit).into());
+    }
+    let mut dirstate = parent;
+    dirstate.extend_from_slice(&[0; 20]);
+    dirstate.extend_from_slice(&DIRSTATE_VERSION.to_be_bytes());
+    dirstate.push(0xFF);
+    let checksum = Sha256::digest(&dirstate);
+    dirstate.extend_from_slice(checksum.as_slice());
+    Ok(dirstate)
+}
+
+/// The `.hg/requires` of the checkout: the requirements of the backing repository, minus the
+/// ones specific to its dirstate, plus `eden`.
+fn requires_data(backing_hg_dir: &Path) -> Result<String> {
+    let requires = match fs::read_to_string(backing_hg_dir.join("requires")) {
+        Ok(requires) => requires,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).from_err(),
+    };
+    let mut requires: Vec<&str> = requires
+        .lines()
+        .filter(|r| !matches!(*r, "sqldirstate" | "treedirstate"))
+        .chain(std::iter::once("eden"))
+        .collect();
+    requires.sort_unstable();
+    requires.dedup();
+    Ok(requires.iter().map(|r| format!("{}\n", r)).collect())
+}
+
+/// Create the `.hg` directory of a new checkout, sharing the store of the backing repository.
+fn setup_hg_dir(
+    instance: &EdenFsInstance,
+    repository: &Repository,
+    mount_point: &Path,
+    commit: &str,
+) -> Result<()> {
+    let backing_hg_dir = repository.path.join(".hg");
+    let mut hgrc = match fs::read_to_string(backing_hg_dir.join("hgrc")) {
+        Ok(hgrc) => hgrc,
+        // Repositories aren't required to have an hgrc, but their .hg directory must exist.
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            if !backing_hg_dir.is_dir() {
+                return Err(anyhow!(
+                    "backing repository does not exist: {}",
+                    backing_hg_dir.display()
+                )
+                .into());
+            }
+            String::new()
+        }
+        Err(e) => return Err(e).from_err(),
+    };
+    let config = instance.get_config()?;
+    let extra_hgrc = config
+        .get_value("hg", "extra_hgrc")
+        .and_then(|value| value.as_str())
+        .unwrap_or(DEFAULT_EXTRA_HGRC);
+    hgrc.push('\n');
+    hgrc.push_str(extra_hgrc);
+    if !hgrc.ends_with('\n') {
+        hgrc.push('\n');
+    }
+    let requires = requires_data(&backing_hg_dir)?;
+    let dirstate = empty_dirstate(commit)?;
+
+    let hg_dir = mount_point.join(".hg");
+    match fs::create_dir(&hg_dir) {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err(anyhow!("{} directory already exists", hg_dir.display()).into());
+        }
+        result => result.with_context(|| format!("Unable to create {}", hg_dir.display()))?,
+    }
+    fs::write(hg_dir.join("hgrc"), hgrc).from_err()?;
+    fs::write(hg_dir.join("requires"), requires).from_err()?;
+    // Tell Mercurial where the rest of its state is, and that bookmarks are shared too. Like
+    // Mercurial, sharedpath has no trailing newline.
+    fs::write(hg_dir.join("sharedpath"), bytes_from_path(&backing_hg_dir)).from_err()?;
+    fs::write(hg_dir.join("shared"), "bookmarks\n").from_err()?;
+    fs::write(hg_dir.join("bookmarks"), "").from_err()?;
+    // Some shell prompts read the branch even though it isn't used.
+    fs::write(hg_dir.join("branch"), "default\n").from_err()?;
+    fs::write(hg_dir.join("dirstate"), dirstate).from_err()?;
+    Ok(())
+}
+
+/// Set up a newly mounted checkout: create its `.hg` directory on the first mount, then run
+/// the Mercurial post-update hook.
+fn post_clone_checkout_setup(
+    instance: &EdenFsInstance,
+    repository: &Repository,
+    client_dir: &Path,
+    mount_point: &Path,
+    commit: &str,
+    hg: &OsStr,
+) -> Result<()> {
+    let clone_succeeded = client_dir.join(CLONE_SUCCEEDED);
+    let is_hg = repository.repo_type == "hg";
+    if is_hg && !clone_succeeded.is_file() {
+        setup_hg_dir(instance, repository, mount_point, commit)?;
+    }
+    fs::write(&clone_succeeded, "").from_err()?;
+
+    if is_hg {
+        let status = Command::new(hg)
+            .arg("debugedenrunpostupdatehook")
+            .arg("-R")
+            .arg(mount_point)
+            // Set by the par machinery, they interfere with Mercurial's own dynamic library
+            // loading.
+            .env_remove("DYLD_INSERT_LIBRARIES")
+            .env_remove("DYLD_LIBRARY_PATH")
+            .status()
+            .with_context(|| format!("Unable to run {:?}", hg))?;
+        if !status.success() {
+            return Err(anyhow!("hg debugedenrunpostupdatehook failed: {}", status).into());
+        }
+    }
+    Ok(())
+}
+
+/// What a clone has set up so far, beyond the mount point.
+#[derive(Default)]
+struct CloneProgress {
+    client_dir: Option<PathBuf>,
+    mounted: bool,
+}
+
+impl CloneProgress {
+    /// Undo a failed clone, so that it can be retried with the same mount point.
+    async fn remove(self, client: &EdenFsClient, mount_point: &Path) {
+        if self.mounted {
+            if let Err(e) = unmount_checkout(client, mount_point).await {
+                event!(Level::DEBUG, ?e, ?mount_point, "Unable to unmount");
+            }
+        }
+        if let Some(client_dir) = self.client_dir {
+            if let Err(e) = fs::remove_dir_all(&client_dir) {
+                event!(Level::DEBUG, ?e, ?client_dir, "Unable to delete");
+            }
+        }
+        for result in [
+            fs::remove_file(mount_point.join(README_FILE)),
+            fs::remove_dir(mount_point),
+        ] {
+            if let Err(e) = result {
+                event!(
+                    Level::DEBUG,
+                    ?e,
+                    ?mount_point,
+                    "Unable to clean up mount point"
+                );
+            }
+        }
+    }
+}
+
+async fn create_checkout(
+    instance: &EdenFsInstance,
+    client: &EdenFsClient,
+    repository: Repository,
+    mount_point: &Path,
+    commit: &str,
+    hg: &OsStr,
+    progress: &mut CloneProgress,
+) -> Result<PathBuf> {
+    let (client_dir, name) = create_client_dir(&instance.clients_dir(), mount_point)?;
+    progress.client_dir = Some(client_dir.clone());
+    save_snapshot(&client_dir, commit)?;
+    CheckoutConfig::new(repository.clone()).save(&client_dir)?;
+
+    mount_checkout(client, mount_point, &client_dir, false).await?;
+    progress.mounted = true;
+    post_clone_checkout_setup(instance, &repository, &client_dir, mount_point, commit, hg)?;
+    instance.add_configured_mount(mount_point, &name)?;
+    Ok(client_dir)
+}
+
+/// Write the state of a new checkout of `commit`, ask the daemon to mount it, set it up with
+/// `hg` and register it in `config.json`. Everything is removed again if any step fails.
+pub(crate) async fn clone_checkout(
+    instance: &EdenFsInstance,
+    client: &EdenFsClient,
+    repository: Repository,
+    mount_point: &Path,
+    commit: &str,
+    hg: &OsStr,
+) -> Result<PathBuf> {
+    if instance
+        .get_configured_mounts_map()?
+        .contains_key(mount_point)
+    {
+        return Err(anyhow!(
+            "mount path {} is already configured (see `edenfsctl list`). \
+             Do you want to run `edenfsctl mount {}` instead?",
+            mount_point.display(),
+            mount_point.display()
+        )
+        .into());
+    }
+
+    create_mount_point(mount_point)?;
+    let mut progress = CloneProgress::default();
+    let result = create_checkout(
+        instance,
+        client,
+        repository,
+        mount_point,
+        commit,
+        hg,
+        &mut progress,
+    )
+    .await;
+    if result.is_err() {
+        progress.remove(client, mount_point).await;
+    }
+    result
+}
+
+impl CloneCmd {
+    fn get_repository(&self) -> Result<Repository> {
+        let mut repository = resolve_repository(&self.repo)?;
+        if self.nfs {
+            repository.protocol = Some("nfs".to_string());
+        } else if repository.protocol.is_none() {
+            let protocol = if cfg!(windows) { "prjfs" } else { "fuse" };
+            repository.protocol = Some(protocol.to_string());
+        }
+        if repository.case_sensitive.is_none() {
+            repository.case_sensitive = Some(cfg!(target_os = "linux"));
+        }
+        if repository.require_utf8_path.is_none() {
+            repository.require_utf8_path = Some(true);
+        }
+        Ok(repository)
+    }
+}
+
+#[async_trait]
+impl crate::Subcommand for CloneCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let repository = self.get_repository()?;
+        let rev = self.rev.as_deref().unwrap_or(DEFAULT_HG_REVISION);
+        let hg = hg_binary();
+        let commit = resolve_commit(&hg, &repository, rev)?;
+        let mount_point = normalize_path(&self.path)?;
+
+        let client = instance
+            .connect(None)
+            .await
+            .context("EdenFS is not running, start it with `edenfsctl start`")?;
+
+        println!(
+            "Cloning new repository at {} into {}",
+            repository.path.display(),
+            mount_point.display()
+        );
+        clone_checkout(&instance, &client, repository, &mount_point, &commit, &hg).await?;
+        println!("Success. Checked out commit {:.8}", commit);
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{instance, repository, Calls, MockClient};
+
+    const COMMIT: &str = "0101010101010101010101010101010101010101";
+
+    /// A backing repository in `dir`, with an hgrc and a dirstate requirement.
+    fn backing_repository(dir: &Path) -> Result<Repository> {
+        let hg_dir = dir.join("backing").join(".hg");
+        fs::create_dir_all(&hg_dir).from_err()?;
+        fs::write(hg_dir.join("hgrc"), "[paths]\ndefault = test\n").from_err()?;
+        fs::write(hg_dir.join("requires"), "treedirstate\nstore\n").from_err()?;
+        Ok(Repository {
+            path: dir.join("backing"),
+            ..repository()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_clone_checkout() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let repository = backing_repository(dir.path())?;
+        let mount_point = dir.path().join("repo");
+        let mounted = Calls::default();
+        let client = MockClient::new().record_mount(&mounted).build();
+
+        let client_dir = clone_checkout(
+            &instance,
+            &client,
+            repository.clone(),
+            &mount_point,
+            COMMIT,
+            OsStr::new("true"),
+        )
+        .await?;
+        assert_eq!(client_dir, instance.clients_dir().join("repo"));
+        assert_eq!(CheckoutConfig::load(&client_dir)?.repository, repository);
+        assert!(client_dir.join("SNAPSHOT").exists());
+        assert!(client_dir.join(CLONE_SUCCEEDED).exists());
+        assert!(mount_point.join(README_FILE).exists());
+        assert_eq!(
+            instance.client_dir_for_mount_point(&mount_point)?,
+            client_dir
+        );
+        let mounted = mounted.lock().unwrap();
+        assert_eq!(mounted.len(), 1);
+        assert_eq!(mounted[0].mountPoint, bytes_from_path(&mount_point));
+        assert_eq!(mounted[0].edenClientPath, bytes_from_path(&client_dir));
+
+        let hg_dir = mount_point.join(".hg");
+        let hgrc = fs::read_to_string(hg_dir.join("hgrc")).from_err()?;
+        assert!(hgrc.starts_with("[paths]\ndefault = test\n\n[extensions]\neden =\n"));
+        assert_eq!(
+            fs::read_to_string(hg_dir.join("requires")).from_err()?,
+            "eden\nstore\n"
+        );
+        assert_eq!(
+            fs::read(hg_dir.join("sharedpath")).from_err()?,
+            bytes_from_path(&repository.path.join(".hg"))
+        );
+        assert_eq!(fs::read(hg_dir.join("dirstate")).from_err()?.len(), 77);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_dirstate() -> Result<()> {
+        let dirstate = empty_dirstate(COMMIT)?;
+        assert_eq!(dirstate[..20], [1; 20]);
+        assert_eq!(dirstate[20..40], [0; 20]);
+        assert_eq!(dirstate[40..45], [0, 0, 0, 1, 0xFF]);
+        // Checksum written by eden/fs/py/eden/dirstate.py for the same parents.
+        assert_eq!(
+            hex::encode(&dirstate[45..]),
+            "865ccf4a48b99983eb1c40e7a9cc3c51ae4185407a1a18fc26d2d173bd7e95e9"
+        );
+        assert!(empty_dirstate("abcd").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clone_same_name() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let repository = backing_repository(dir.path())?;
+        let mount_point = dir.path().join("repo");
+        let client = MockClient::new().record_mount(&Calls::default()).build();
+        let hg = OsStr::new("true");
+        clone_checkout(
+            &instance,
+            &client,
+            repository.clone(),
+            &mount_point,
+            COMMIT,
+            hg,
+        )
+        .await?;
+
+        // The same mount point can't be configured twice.
+        let result = clone_checkout(
+            &instance,
+            &client,
+            repository.clone(),
+            &mount_point,
+            COMMIT,
+            hg,
+        )
+        .await;
+        assert!(result.is_err());
+
+        // Another mount point with the same name gets a new client directory.
+        let other = dir.path().join("other").join("repo");
+        let client_dir = clone_checkout(&instance, &client, repository, &other, COMMIT, hg).await?;
+        assert_eq!(client_dir, instance.clients_dir().join("repo-1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clone_into_non_empty_directory() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let mount_point = dir.path().join("repo");
+        fs::create_dir_all(&mount_point).from_err()?;
+        fs::write(mount_point.join("file"), "").from_err()?;
+
+        let client = MockClient::new().build();
+        let result = clone_checkout(
+            &instance,
+            &client,
+            repository(),
+            &mount_point,
+            COMMIT,
+            OsStr::new("true"),
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(instance.get_configured_mounts_map()?.is_empty());
+        assert!(mount_point.join("file").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_clone_failure_cleans_up() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let mount_point = dir.path().join("repo");
+        let unmounted = Calls::default();
+        let client = MockClient::new()
+            .record_mount(&Calls::default())
+            .record_unmount(&unmounted)
+            .build();
+
+        // The backing repository doesn't exist, so the .hg directory can't be set up.
+        let result = clone_checkout(
+            &instance,
+            &client,
+            repository(),
+            &mount_point,
+            COMMIT,
+            OsStr::new("true"),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            *unmounted.lock().unwrap(),
+            vec![bytes_from_path(&mount_point)]
+        );
+        assert!(!instance.clients_dir().join("repo").exists());
+        assert!(!mount_point.exists());
+        assert!(instance.get_configured_mounts_map()?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl doctor
+
+use std::fmt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use serde_json::json;
+use structopt::StructOpt;
+use thrift_types::edenfs::types::{DaemonInfo, MountInfo};
+use tracing::{event, Level};
+
+use edenfs_client::{EdenFsClient, EdenFsInstance};
+use edenfs_error::{Result, ResultExt};
+
+use crate::ExitCode;
+
+mod bind_mounts;
+mod daemon;
+mod hg_parents;
+mod mounts;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Debug and fix issues with EdenFS")]
+pub struct DoctorCmd {
+    /// Try to fix the problems that are found
+    #[structopt(long)]
+    fix: bool,
+
+    /// Print the output in JSON format
+    #[structopt(long)]
+    json: bool,
+}
+
+/// State shared by all the checks, gathered once before running them.
+pub(crate) struct CheckContext<'a> {
+    pub instance: &'a EdenFsInstance,
+    /// Result of `EdenFsInstance::get_health`.
+    pub health: std::result::Result<DaemonInfo, String>,
+    /// `None` when EdenFS isn't running.
+    pub client: Option<EdenFsClient>,
+    /// Mounts known to the daemon, empty when it isn't running.
+    pub mounts: Vec<MountInfo>,
+    /// The kernel mount table, `None` on platforms where it isn't available.
+    pub kernel_mounts: Option<Vec<KernelMount>>,
+}
+
+impl CheckContext<'_> {
+    pub fn client(&self) -> Result<&EdenFsClient> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| anyhow!("EdenFS is not running").into())
+    }
+}
+
+/// A health check, run by `edenfsctl doctor`.
+#[async_trait]
+pub(crate) trait Check: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Look for problems. An error means that the check couldn't be performed.
+    async fn check(&self, ctx: &CheckContext<'_>) -> Result<Vec<Problem>>;
+}
+
+/// Automatic remediation of a problem, applied with `--fix`.
+#[async_trait]
+pub(crate) trait Fix: fmt::Debug + Send + Sync {
+    /// What the fix does, e.g. "Remount /data/repo".
+    fn description(&self) -> String;
+
+    async fn apply(&self, ctx: &CheckContext<'_>) -> Result<()>;
+}
+
+#[derive(Debug)]
+pub(crate) struct Problem {
+    pub description: String,
+    pub mount_point: Option<PathBuf>,
+    /// `None` when the problem has to be fixed manually.
+    pub fix: Option<Box<dyn Fix>>,
+}
+
+impl Problem {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            mount_point: None,
+            fix: None,
+        }
+    }
+
+    pub fn mount_point(mut self, mount_point: impl Into<PathBuf>) -> Self {
+        self.mount_point = Some(mount_point.into());
+        self
+    }
+
+    pub fn fix(mut self, fix: impl Fix + 'static) -> Self {
+        self.fix = Some(Box::new(fix));
+        self
+    }
+}
+
+/// An entry of the kernel mount table.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KernelMount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+/// Parse `/proc/mounts`. Spaces and other special characters in paths are octal escaped.
+pub(crate) fn parse_proc_mounts(content: &str) -> Vec<KernelMount> {
+    fn unescape(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            let octal = bytes.get(i + 1..i + 4).and_then(|digits| {
+                std::str::from_utf8(digits)
+                    .ok()
+                    .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            });
+            match (bytes[i], octal) {
+                (b'\\', Some(byte)) => {
+                    out.push(byte);
+                    i += 4;
+                }
+                (byte, _) => {
+                    out.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            let mount_point = fields.next()?;
+            let fs_type = fields.next()?;
+            Some(KernelMount {
+                mount_point: unescape(mount_point).into(),
+                fs_type: fs_type.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn read_kernel_mounts() -> Option<Vec<KernelMount>> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    match std::fs::read_to_string("/proc/mounts") {
+        Ok(content) => Some(parse_proc_mounts(&content)),
+        Err(e) => {
+            event!(Level::DEBUG, ?e, "Unable to read the mount table");
+            None
+        }
+    }
+}
+
+/// Run another `edenfsctl` command, for the fixes that are only implemented by the Python CLI.
+/// Unknown commands are forwarded to it.
+pub(crate) fn run_edenfsctl(args: &[&str]) -> Result<()> {
+    let exe = std::env::current_exe().context("Unable to locate edenfsctl")?;
+    let status = Command::new(&exe)
+        .args(args)
+        .status()
+        .with_context(|| format!("Unable to run {}", exe.display()))?;
+    if !status.success() {
+        return Err(anyhow!("`edenfsctl {}` failed: {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+fn all_checks() -> Vec<Box<dyn Check>> {
+    vec![
+        Box::new(daemon::DaemonHealthCheck),
+        Box::new(mounts::MountTableCheck),
+        Box::new(bind_mounts::BindMountsCheck),
+        Box::new(hg_parents::HgParentsCheck),
+    ]
+}
+
+#[derive(Debug)]
+struct ProblemReport {
+    problem: Problem,
+    /// `None` when no fix was attempted.
+    fix_result: Option<std::result::Result<(), String>>,
+}
+
+#[derive(Debug)]
+struct CheckReport {
+    name: &'static str,
+    /// Set when the check itself failed.
+    error: Option<String>,
+    problems: Vec<ProblemReport>,
+}
+
+impl CheckReport {
+    fn unresolved(&self) -> usize {
+        let problems = self
+            .problems
+            .iter()
+            .filter(|p| !matches!(p.fix_result, Some(Ok(()))))
+            .count();
+        problems + self.error.is_some() as usize
+    }
+}
+
+async fn run_checks(
+    checks: &[Box<dyn Check>],
+    ctx: &CheckContext<'_>,
+    fix: bool,
+) -> Vec<CheckReport> {
+    let mut reports = Vec::new();
+    for check in checks {
+        let (problems, error) = match check.check(ctx).await {
+            Ok(problems) => (problems, None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+
+        let mut problem_reports = Vec::new();
+        for problem in problems {
+            let fix_result = match &problem.fix {
+                Some(problem_fix) if fix => {
+                    Some(problem_fix.apply(ctx).await.map_err(|e| e.to_string()))
+                }
+                _ => None,
+            };
+            problem_reports.push(ProblemReport {
+                problem,
+                fix_result,
+            });
+        }
+
+        reports.push(CheckReport {
+            name: check.name(),
+            error,
+            problems: problem_reports,
+        });
+    }
+    reports
+}
+
+fn reports_to_json(reports: &[CheckReport]) -> serde_json::Value {
+    let checks = reports
+        .iter()
+        .map(|report| {
+            let problems = report
+                .problems
+                .iter()
+                .map(|p| {
+                    json!({
+                        "description": p.problem.description,
+                        "mount_point": p.problem.mount_point,
+                        "fix": p.problem.fix.as_ref().map(|fix| fix.description()),
+                        "fixed": matches!(p.fix_result, Some(Ok(()))),
+                        "fix_error": p.fix_result.as_ref().and_then(|r| r.clone().err()),
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "name": report.name,
+                "error": report.error,
+                "problems": problems,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "checks": checks,
+        "unresolved": reports.iter().map(CheckReport::unresolved).sum::<usize>(),
+    })
+}
+
+fn print_reports(reports: &[CheckReport], fix: bool) {
+    for report in reports {
+        if let Some(error) = &report.error {
+            println!(
+                "Checking {}: failed to run the check: {}",
+                report.name, error
+            );
+            continue;
+        }
+        if report.problems.is_empty() {
+            println!("Checking {}: OK", report.name);
+            continue;
+        }
+        println!("Checking {}:", report.name);
+        for p in &report.problems {
+            println!("  - {}", p.problem.description);
+            match (&p.problem.fix, &p.fix_result) {
+                (Some(problem_fix), Some(Ok(()))) => {
+                    println!("    Fixed: {}", problem_fix.description())
+                }
+                (Some(problem_fix), Some(Err(e))) => {
+                    println!("    Failed to {}: {}", problem_fix.description(), e)
+                }
+                (Some(problem_fix), None) => println!("    Fix: {}", problem_fix.description()),
+                (None, _) => println!("    This problem has to be fixed manually"),
+            }
+        }
+    }
+
+    let unresolved: usize = reports.iter().map(CheckReport::unresolved).sum();
+    let fixable = reports
+        .iter()
+        .flat_map(|r| &r.problems)
+        .any(|p| p.problem.fix.is_some() && p.fix_result.is_none());
+    if unresolved == 0 {
+        println!("No issues detected.");
+    } else {
+        println!("{} issue(s) remaining.", unresolved);
+        if fixable && !fix {
+            println!("Run `edenfsctl doctor --fix` to try to fix them.");
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Subcommand for DoctorCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let health = instance.get_health(None).await.map_err(|e| e.to_string());
+        let client = match instance.connect(Some(Duration::from_secs(3))).await {
+            Ok(client) => Some(client),
+            Err(e) => {
+                event!(Level::DEBUG, ?e, "Unable to connect to EdenFS daemon");
+                None
+            }
+        };
+        let mounts = match &client {
+            Some(client) => client.listMounts().await.from_err()?,
+            None => Vec::new(),
+        };
+        let ctx = CheckContext {
+            instance: &instance,
+            health,
+            client,
+            mounts,
+            kernel_mounts: read_kernel_mounts(),
+        };
+
+        let reports = run_checks(&all_checks(), &ctx, self.fix).await;
+        if self.json {
+            println!("{}", reports_to_json(&reports));
+        } else {
+            print_reports(&reports, self.fix);
+        }
+
+        let unresolved: usize = reports.iter().map(CheckReport::unresolved).sum();
+        Ok(if unresolved == 0 { 0 } else { 1 })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_utils::{instance, MockClient};
+
+    pub(crate) fn context<'a>(
+        instance: &'a EdenFsInstance,
+        client: Option<EdenFsClient>,
+        mounts: Vec<MountInfo>,
+    ) -> CheckContext<'a> {
+        CheckContext {
+            instance,
+            health: Ok(DaemonInfo::default()),
+            client,
+            mounts,
+            kernel_mounts: Some(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_parse_proc_mounts() {
+        let content = "\
+proc /proc proc rw,nosuid,nodev,noexec,relatime 0 0
+edenfs: /data/users/me/my\\040repo fuse.edenfs rw,nosuid,relatime 0 0
+/dev/sda1 /data/users/me/my\\040repo/buck-out ext4 rw 0 0
+";
+        assert_eq!(
+            parse_proc_mounts(content),
+            vec![
+                KernelMount {
+                    mount_point: "/proc".into(),
+                    fs_type: "proc".to_string(),
+                },
+                KernelMount {
+                    mount_point: "/data/users/me/my repo".into(),
+                    fs_type: "fuse.edenfs".to_string(),
+                },
+                KernelMount {
+                    mount_point: "/data/users/me/my repo/buck-out".into(),
+                    fs_type: "ext4".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[derive(Debug)]
+    struct FakeFix(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl Fix for FakeFix {
+        fn description(&self) -> String {
+            "Do nothing".to_string()
+        }
+
+        async fn apply(&self, _ctx: &CheckContext<'_>) -> Result<()> {
+            self.0.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct FakeCheck(Arc<AtomicBool>);
+
+    #[async_trait]
+    impl Check for FakeCheck {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn check(&self, _ctx: &CheckContext<'_>) -> Result<Vec<Problem>> {
+            Ok(vec![
+                Problem::new("fixable").fix(FakeFix(self.0.clone())),
+                Problem::new("manual").mount_point("/mnt/repo"),
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_checks() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        let ctx = context(&instance, Some(MockClient::new().build()), Vec::new());
+
+        let fixed = Arc::new(AtomicBool::new(false));
+        let checks: Vec<Box<dyn Check>> = vec![Box::new(FakeCheck(fixed.clone()))];
+
+        let reports = run_checks(&checks, &ctx, false).await;
+        assert!(!fixed.load(Ordering::SeqCst));
+        assert_eq!(reports[0].unresolved(), 2);
+
+        let reports = run_checks(&checks, &ctx, true).await;
+        assert!(fixed.load(Ordering::SeqCst));
+        assert_eq!(reports[0].unresolved(), 1);
+
+        let json = reports_to_json(&reports);
+        assert_eq!(json["unresolved"], 1);
+        assert_eq!(json["checks"][0]["problems"][0]["fixed"], true);
+        assert_eq!(
+            json["checks"][0]["problems"][1]["fix"],
+            serde_json::Value::Null
+        );
+        assert_eq!(json["checks"][0]["problems"][1]["mount_point"], "/mnt/repo");
+        Ok(())
+    }
+}
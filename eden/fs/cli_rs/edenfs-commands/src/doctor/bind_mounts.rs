@@ -0,0 +1,143 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Check that the bind mounts of every checkout are mounted.
+
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use thrift_types::edenfs::types::MountState;
+
+use edenfs_client::path_from_bytes;
+use edenfs_error::{Result, ResultExt};
+
+use super::{run_edenfsctl, Check, CheckContext, Fix, Problem};
+
+pub(crate) struct BindMountsCheck;
+
+#[derive(Debug)]
+struct FixupRedirections {
+    mount_point: PathBuf,
+}
+
+#[async_trait]
+impl Fix for FixupRedirections {
+    fn description(&self) -> String {
+        format!("Fix up the redirections of {}", self.mount_point.display())
+    }
+
+    async fn apply(&self, _ctx: &CheckContext<'_>) -> Result<()> {
+        // Bind mounts are created from the redirections configuration, which only the Python
+        // CLI knows how to apply.
+        let mount_point = self.mount_point.to_string_lossy();
+        run_edenfsctl(&["redirect", "fixup", "--mount", &mount_point])
+    }
+}
+
+#[async_trait]
+impl Check for BindMountsCheck {
+    fn name(&self) -> &'static str {
+        "bind mounts"
+    }
+
+    async fn check(&self, ctx: &CheckContext<'_>) -> Result<Vec<Problem>> {
+        let kernel_mounts = match &ctx.kernel_mounts {
+            Some(kernel_mounts) => kernel_mounts,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut problems = Vec::new();
+        for mount in &ctx.mounts {
+            if mount.state != MountState::RUNNING {
+                continue;
+            }
+            let mount_point = path_from_bytes(&mount.mountPoint);
+            let missing = ctx
+                .client()?
+                .getBindMounts(&mount.mountPoint)
+                .await
+                .from_err()?
+                .iter()
+                .map(|path| path_from_bytes(path))
+                .filter(|path| {
+                    let path = mount_point.join(path);
+                    !kernel_mounts.iter().any(|m| m.mount_point == path)
+                })
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>();
+            if missing.is_empty() {
+                continue;
+            }
+            problems.push(
+                Problem::new(format!(
+                    "Bind mounts of {} are not mounted: {}",
+                    mount_point.display(),
+                    missing.join(", ")
+                ))
+                .mount_point(&mount_point)
+                .fix(FixupRedirections { mount_point }),
+            );
+        }
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doctor::tests::context;
+    use crate::doctor::KernelMount;
+    use crate::test_utils::{instance, mount_info, MockClient};
+
+    fn kernel_mount(mount_point: &str) -> KernelMount {
+        KernelMount {
+            mount_point: mount_point.into(),
+            fs_type: "ext4".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_mounts() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        let client = MockClient::new()
+            .with(|mock| {
+                mock.getBindMounts.mock(|mount_point| {
+                    if mount_point == b"/mnt/a" {
+                        vec![b"buck-out".to_vec(), b"fbcode/buck-out".to_vec()]
+                    } else {
+                        vec![b"buck-out".to_vec()]
+                    }
+                });
+            })
+            .build();
+        let mounts = vec![
+            mount_info("/mnt/a", MountState::RUNNING),
+            mount_info("/mnt/b", MountState::RUNNING),
+        ];
+        let mut ctx = context(&instance, Some(client), mounts);
+        ctx.kernel_mounts = Some(vec![
+            kernel_mount("/mnt/a/buck-out"),
+            kernel_mount("/mnt/b/buck-out"),
+        ]);
+
+        let problems = BindMountsCheck.check(&ctx).await?;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].description,
+            "Bind mounts of /mnt/a are not mounted: fbcode/buck-out"
+        );
+        assert_eq!(
+            problems[0].fix.as_ref().unwrap().description(),
+            "Fix up the redirections of /mnt/a"
+        );
+
+        // Without a mount table, there is nothing to compare with.
+        ctx.kernel_mounts = None;
+        assert!(BindMountsCheck.check(&ctx).await?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Check that the daemon is running and reports itself healthy.
+
+use async_trait::async_trait;
+
+use edenfs_client::DaemonHealthy;
+use edenfs_error::Result;
+
+use super::{run_edenfsctl, Check, CheckContext, Fix, Problem};
+
+pub(crate) struct DaemonHealthCheck;
+
+#[derive(Debug)]
+struct StartDaemon {
+    restart: bool,
+}
+
+#[async_trait]
+impl Fix for StartDaemon {
+    fn description(&self) -> String {
+        if self.restart {
+            "Restart EdenFS".to_string()
+        } else {
+            "Start EdenFS".to_string()
+        }
+    }
+
+    async fn apply(&self, _ctx: &CheckContext<'_>) -> Result<()> {
+        run_edenfsctl(&[if self.restart { "restart" } else { "start" }])
+    }
+}
+
+#[async_trait]
+impl Check for DaemonHealthCheck {
+    fn name(&self) -> &'static str {
+        "daemon health"
+    }
+
+    async fn check(&self, ctx: &CheckContext<'_>) -> Result<Vec<Problem>> {
+        Ok(match &ctx.health {
+            Ok(info) if info.is_healthy() => Vec::new(),
+            Ok(info) => {
+                let status = match info.status {
+                    Some(status) => status.to_string(),
+                    None => "unknown".to_string(),
+                };
+                vec![Problem::new(format!(
+                    "EdenFS (pid {}) is not healthy, its status is {}",
+                    info.pid, status
+                ))
+                .fix(StartDaemon { restart: true })]
+            }
+            Err(e) => vec![Problem::new(format!("EdenFS is not running: {}", e))
+                .fix(StartDaemon { restart: false })],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thrift_types::edenfs::types::DaemonInfo;
+    use thrift_types::fb303_core::types::fb303_status;
+
+    use super::*;
+    use crate::doctor::tests::context;
+    use crate::test_utils::instance;
+
+    #[tokio::test]
+    async fn test_daemon_health() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        let mut ctx = context(&instance, None, Vec::new());
+
+        ctx.health = Ok(DaemonInfo {
+            pid: 42,
+            status: Some(fb303_status::ALIVE),
+            ..Default::default()
+        });
+        assert!(DaemonHealthCheck.check(&ctx).await?.is_empty());
+
+        ctx.health = Ok(DaemonInfo {
+            pid: 42,
+            status: Some(fb303_status::STOPPING),
+            ..Default::default()
+        });
+        let problems = DaemonHealthCheck.check(&ctx).await?;
+        assert_eq!(problems.len(), 1);
+        assert_eq!(
+            problems[0].description,
+            "EdenFS (pid 42) is not healthy, its status is STOPPING"
+        );
+        assert_eq!(
+            problems[0].fix.as_ref().unwrap().description(),
+            "Restart EdenFS"
+        );
+
+        ctx.health = Err("connection refused".to_string());
+        let problems = DaemonHealthCheck.check(&ctx).await?;
+        assert_eq!(
+            problems[0].fix.as_ref().unwrap().description(),
+            "Start EdenFS"
+        );
+        Ok(())
+    }
+}
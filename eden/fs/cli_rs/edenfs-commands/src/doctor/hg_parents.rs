@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Check that the parent commit recorded by Mercurial in the dirstate matches the parent
+//! commit of the checkout recorded by EdenFS.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use thrift_types::edenfs::types::{MountState, ResetParentCommitsParams, WorkingDirectoryParents};
+
+use edenfs_client::{bytes_from_path, path_from_bytes};
+use edenfs_config::checkout::load_snapshot;
+use edenfs_error::{Result, ResultExt};
+
+use super::{Check, CheckContext, Fix, Problem};
+
+pub(crate) struct HgParentsCheck;
+
+/// Read the first parent from `.hg/dirstate`, which starts with the binary hashes of both
+/// parents.
+fn read_dirstate_parent(mount_point: &Path) -> anyhow::Result<String> {
+    let path = mount_point.join(".hg").join("dirstate");
+    let mut parent = [0u8; 20];
+    File::open(&path)
+        .and_then(|mut f| f.read_exact(&mut parent))
+        .with_context(|| format!("Unable to read the parents from {}", path.display()))?;
+    Ok(parent.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+#[derive(Debug)]
+struct ResetParent {
+    mount_point: PathBuf,
+    commit: String,
+}
+
+#[async_trait]
+impl Fix for ResetParent {
+    fn description(&self) -> String {
+        format!(
+            "Reset the EdenFS parent commit of {} to {}",
+            self.mount_point.display(),
+            self.commit
+        )
+    }
+
+    async fn apply(&self, ctx: &CheckContext<'_>) -> Result<()> {
+        let parents = WorkingDirectoryParents {
+            parent1: self.commit.as_bytes().to_vec(),
+            parent2: None,
+            ..Default::default()
+        };
+        ctx.client()?
+            .resetParentCommits(
+                &bytes_from_path(&self.mount_point),
+                &parents,
+                &ResetParentCommitsParams::default(),
+            )
+            .await
+            .from_err()
+    }
+}
+
+#[async_trait]
+impl Check for HgParentsCheck {
+    fn name(&self) -> &'static str {
+        "hg parents"
+    }
+
+    async fn check(&self, ctx: &CheckContext<'_>) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+        for mount in &ctx.mounts {
+            if mount.state != MountState::RUNNING {
+                continue;
+            }
+            let mount_point = path_from_bytes(&mount.mountPoint);
+            if !mount_point.join(".hg").is_dir() {
+                // Not a Mercurial checkout.
+                continue;
+            }
+
+            let hg_parent = read_dirstate_parent(&mount_point);
+            let eden_parent = load_snapshot(&path_from_bytes(&mount.edenClientPath));
+            match (hg_parent, eden_parent) {
+                (Ok(hg_parent), Ok(eden_parent)) if hg_parent == eden_parent => {}
+                (Ok(hg_parent), Ok(eden_parent)) => problems.push(
+                    Problem::new(format!(
+                        "Mercurial's parent commit of {} is {}, but EdenFS's is {}",
+                        mount_point.display(),
+                        hg_parent,
+                        eden_parent
+                    ))
+                    .mount_point(&mount_point)
+                    .fix(ResetParent {
+                        mount_point: mount_point.clone(),
+                        commit: hg_parent,
+                    }),
+                ),
+                (Err(e), _) | (_, Err(e)) => {
+                    problems.push(Problem::new(e.to_string()).mount_point(&mount_point))
+                }
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use thrift_types::edenfs::types::MountInfo;
+
+    use edenfs_config::checkout::save_snapshot;
+
+    use super::*;
+    use crate::doctor::tests::context;
+    use crate::test_utils::{instance, mount_info, Calls, MockClient};
+
+    #[tokio::test]
+    async fn test_hg_parents() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let mount_point = dir.path().join("repo");
+        let client_dir = instance.clients_dir().join("repo");
+        fs::create_dir_all(mount_point.join(".hg")).from_err()?;
+        fs::create_dir_all(&client_dir).from_err()?;
+
+        let mut dirstate = vec![0x11; 20];
+        dirstate.extend_from_slice(&[0; 20]);
+        fs::write(mount_point.join(".hg").join("dirstate"), &dirstate).from_err()?;
+        save_snapshot(&client_dir, &"11".repeat(20))?;
+
+        let reset = Calls::default();
+        let client = MockClient::new()
+            .with(|mock| {
+                let reset = reset.clone();
+                mock.resetParentCommits
+                    .mock(move |mount_point, parents, _params| {
+                        reset.lock().unwrap().push((mount_point, parents.parent1))
+                    });
+            })
+            .build();
+        let mounts = vec![MountInfo {
+            edenClientPath: bytes_from_path(&client_dir),
+            ..mount_info(&mount_point, MountState::RUNNING)
+        }];
+        let ctx = context(&instance, Some(client), mounts);
+
+        assert!(HgParentsCheck.check(&ctx).await?.is_empty());
+
+        save_snapshot(&client_dir, &"22".repeat(20))?;
+        let problems = HgParentsCheck.check(&ctx).await?;
+        assert_eq!(problems.len(), 1);
+        problems[0].fix.as_ref().unwrap().apply(&ctx).await?;
+        assert_eq!(
+            *reset.lock().unwrap(),
+            vec![(bytes_from_path(&mount_point), "11".repeat(20).into_bytes())]
+        );
+
+        fs::remove_file(mount_point.join(".hg").join("dirstate")).from_err()?;
+        let problems = HgParentsCheck.check(&ctx).await?;
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].fix.is_none());
+        Ok(())
+    }
+}
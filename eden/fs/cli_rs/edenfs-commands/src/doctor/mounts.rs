@@ -0,0 +1,293 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Check that the mounts known to the daemon match `config.json` and the kernel mount table.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use thrift_types::edenfs::types::MountState;
+use tokio::sync::oneshot;
+
+use edenfs_client::path_from_bytes;
+use edenfs_error::Result;
+
+use super::{Check, CheckContext, Fix, Problem};
+use crate::mount::mount_checkout;
+use crate::unmount::unmount_checkout;
+
+/// Kernel file system type of the EdenFS FUSE mounts.
+const EDENFS_FS_TYPE: &str = "fuse.edenfs";
+
+/// How long to wait for a mount point to be stat'ed. Stat never returns on a hung FUSE mount.
+const STAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Whether `path` can be stat'ed within `timeout`. The stat runs on its own thread, which is
+/// left behind if it hangs.
+async fn is_accessible(path: &Path, timeout: Duration) -> bool {
+    let (sender, receiver) = oneshot::channel();
+    let path = path.to_path_buf();
+    let spawned = thread::Builder::new()
+        .name("stat".to_string())
+        .spawn(move || {
+            let _ = sender.send(path.metadata().is_ok());
+        });
+    if spawned.is_err() {
+        return false;
+    }
+    matches!(tokio::time::timeout(timeout, receiver).await, Ok(Ok(true)))
+}
+
+pub(crate) struct MountTableCheck;
+
+#[derive(Debug)]
+struct Remount {
+    mount_point: PathBuf,
+    client_dir: PathBuf,
+    /// Whether the daemon still knows the mount, in a broken state.
+    unmount_first: bool,
+}
+
+#[async_trait]
+impl Fix for Remount {
+    fn description(&self) -> String {
+        format!("Remount {}", self.mount_point.display())
+    }
+
+    async fn apply(&self, ctx: &CheckContext<'_>) -> Result<()> {
+        let client = ctx.client()?;
+        if self.unmount_first {
+            unmount_checkout(client, &self.mount_point).await?;
+        }
+        mount_checkout(client, &self.mount_point, &self.client_dir, false).await
+    }
+}
+
+#[derive(Debug)]
+struct UnmountStale {
+    mount_point: PathBuf,
+}
+
+#[async_trait]
+impl Fix for UnmountStale {
+    fn description(&self) -> String {
+        format!("Unmount stale mount {}", self.mount_point.display())
+    }
+
+    async fn apply(&self, _ctx: &CheckContext<'_>) -> Result<()> {
+        let status = Command::new("sudo")
+            .args(["umount", "-l"])
+            .arg(&self.mount_point)
+            .status()
+            .context("Unable to run umount")?;
+        if !status.success() {
+            return Err(anyhow!("umount failed: {}", status).into());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Check for MountTableCheck {
+    fn name(&self) -> &'static str {
+        "mount table"
+    }
+
+    async fn check(&self, ctx: &CheckContext<'_>) -> Result<Vec<Problem>> {
+        let mut problems = Vec::new();
+        let configured = ctx.instance.get_configured_mounts_map()?;
+        let clients_dir = ctx.instance.clients_dir();
+
+        let mounts = ctx
+            .mounts
+            .iter()
+            .map(|mount| (path_from_bytes(&mount.mountPoint), mount))
+            .collect::<Vec<_>>();
+
+        for (mount_point, mount) in &mounts {
+            if !configured.contains_key(mount_point) {
+                problems.push(
+                    Problem::new(format!(
+                        "{} is mounted but is missing from the configured checkouts",
+                        mount_point.display()
+                    ))
+                    .mount_point(mount_point),
+                );
+            }
+            if mount.state != MountState::RUNNING {
+                problems.push(
+                    Problem::new(format!(
+                        "{} is in state {}",
+                        mount_point.display(),
+                        mount.state
+                    ))
+                    .mount_point(mount_point)
+                    .fix(Remount {
+                        mount_point: mount_point.clone(),
+                        client_dir: path_from_bytes(&mount.edenClientPath),
+                        unmount_first: true,
+                    }),
+                );
+            }
+        }
+
+        // The daemon check already reports that nothing is mounted when EdenFS isn't running.
+        if ctx.client.is_some() {
+            for (mount_point, name) in &configured {
+                if !mounts.iter().any(|(path, _)| path == mount_point) {
+                    problems.push(
+                        Problem::new(format!("{} is not mounted", mount_point.display()))
+                            .mount_point(mount_point)
+                            .fix(Remount {
+                                mount_point: mount_point.clone(),
+                                client_dir: clients_dir.join(name),
+                                unmount_first: false,
+                            }),
+                    );
+                }
+            }
+        }
+
+        // A FUSE mount that the daemon doesn't know about, and that can't be accessed, was left
+        // behind by a daemon that died.
+        for kernel_mount in ctx.kernel_mounts.iter().flatten() {
+            if kernel_mount.fs_type != EDENFS_FS_TYPE
+                || mounts
+                    .iter()
+                    .any(|(path, _)| path == &kernel_mount.mount_point)
+                || is_accessible(&kernel_mount.mount_point, STAT_TIMEOUT).await
+            {
+                continue;
+            }
+            problems.push(
+                Problem::new(format!(
+                    "{} is a stale EdenFS mount",
+                    kernel_mount.mount_point.display()
+                ))
+                .mount_point(&kernel_mount.mount_point)
+                .fix(UnmountStale {
+                    mount_point: kernel_mount.mount_point.clone(),
+                }),
+            );
+        }
+
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use thrift_types::edenfs::types::MountInfo;
+
+    use edenfs_client::bytes_from_path;
+    use edenfs_error::ResultExt;
+
+    use super::*;
+    use crate::doctor::tests::context;
+    use crate::doctor::KernelMount;
+    use crate::test_utils::{instance, mount_info, Calls, MockClient};
+
+    fn kernel_mount(mount_point: impl Into<PathBuf>) -> KernelMount {
+        KernelMount {
+            mount_point: mount_point.into(),
+            fs_type: EDENFS_FS_TYPE.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mount_table() -> Result<()> {
+        let (dir, instance) = instance()?;
+        instance.add_configured_mount(Path::new("/mnt/ok"), "ok")?;
+        instance.add_configured_mount(Path::new("/mnt/unmounted"), "unmounted")?;
+        instance.add_configured_mount(Path::new("/mnt/broken"), "broken")?;
+
+        let mounts = vec![
+            mount_info("/mnt/ok", MountState::RUNNING),
+            MountInfo {
+                edenClientPath: bytes_from_path(&instance.clients_dir().join("broken")),
+                ..mount_info("/mnt/broken", MountState::FUSE_ERROR)
+            },
+            mount_info("/mnt/unknown", MountState::RUNNING),
+        ];
+        let mut ctx = context(&instance, Some(MockClient::new().build()), mounts);
+        // Accessible EdenFS mounts aren't stale, even when the daemon doesn't know them.
+        ctx.kernel_mounts = Some(vec![
+            kernel_mount("/mnt/ok"),
+            kernel_mount(dir.path().join("stale")),
+            kernel_mount(dir.path()),
+        ]);
+
+        let problems = MountTableCheck.check(&ctx).await?;
+        let descriptions = problems
+            .iter()
+            .map(|p| p.description.as_str())
+            .collect::<Vec<_>>();
+        let stale = format!(
+            "{} is a stale EdenFS mount",
+            dir.path().join("stale").display()
+        );
+        assert_eq!(
+            descriptions,
+            vec![
+                "/mnt/broken is in state FUSE_ERROR",
+                "/mnt/unknown is mounted but is missing from the configured checkouts",
+                "/mnt/unmounted is not mounted",
+                stale.as_str(),
+            ]
+        );
+        assert!(problems[1].fix.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_is_accessible() -> Result<()> {
+        let dir = tempfile::tempdir().from_err()?;
+        assert!(is_accessible(dir.path(), STAT_TIMEOUT).await);
+        assert!(!is_accessible(&dir.path().join("missing"), STAT_TIMEOUT).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remount() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        instance.add_configured_mount(Path::new("/mnt/unmounted"), "unmounted")?;
+
+        let mounted = Calls::default();
+        let client = MockClient::new()
+            .record_mount(&mounted)
+            .with(|mock| {
+                mock.unmount.ret(());
+            })
+            .build();
+        let mounts = vec![MountInfo {
+            edenClientPath: bytes_from_path(&instance.clients_dir().join("broken")),
+            ..mount_info("/mnt/broken", MountState::FUSE_ERROR)
+        }];
+        let ctx = context(&instance, Some(client), mounts);
+
+        for problem in MountTableCheck.check(&ctx).await? {
+            if let Some(fix) = problem.fix {
+                fix.apply(&ctx).await?;
+            }
+        }
+        let mounted = mounted.lock().unwrap();
+        assert_eq!(mounted.len(), 2);
+        assert_eq!(mounted[0].mountPoint, b"/mnt/broken");
+        assert_eq!(mounted[1].mountPoint, b"/mnt/unmounted");
+        assert_eq!(
+            mounted[1].edenClientPath,
+            bytes_from_path(&instance.clients_dir().join("unmounted"))
+        );
+        Ok(())
+    }
+}
@@ -7,34 +7,374 @@
 
 //! edenfsctl gc
 
+use std::fs;
 use std::io::{stderr, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use serde_json::json;
 use structopt::StructOpt;
+use thrift_types::edenfs::consts::STATS_MOUNTS_STATS;
+use thrift_types::edenfs::types::{GetStatInfoParams, MountState, TimeSpec};
 
-use edenfs_client::EdenFsInstance;
+use edenfs_client::{bytes_from_path, path_from_bytes, EdenFsClient, EdenFsInstance};
 use edenfs_error::{Result, ResultExt};
 
 use crate::ExitCode;
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Minimize disk and memory usage by freeing caches")]
-pub struct GcCmd {}
+pub struct GcCmd {
+    /// Only unload inodes that haven't been accessed for this many seconds
+    #[structopt(long, default_value = "3600")]
+    age: u64,
+
+    /// Report what would be freed without freeing anything
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Print the output in JSON format
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct MountGcResult {
+    pub mount_point: PathBuf,
+    /// Number of unloaded inodes, always 0 in dry-run mode.
+    pub unloaded_inodes: i64,
+    /// Number of currently loaded inodes, only counted in dry-run mode.
+    pub loaded_inodes: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Running mounts, the others have no inodes loaded.
+async fn running_mounts(client: &EdenFsClient) -> Result<Vec<PathBuf>> {
+    Ok(client
+        .listMounts()
+        .await
+        .from_err()?
+        .into_iter()
+        .filter(|mount| mount.state == MountState::RUNNING)
+        .map(|mount| path_from_bytes(&mount.mountPoint))
+        .collect())
+}
+
+/// Ask the kernel to drop its caches of every mount, then unload the inodes that weren't
+/// accessed for `age`: inodes referenced by the kernel can't be unloaded. In dry-run mode, only
+/// count the loaded inodes, not all of them can necessarily be unloaded.
+pub(crate) async fn unload_inodes(
+    client: &EdenFsClient,
+    age: Duration,
+    dry_run: bool,
+) -> Result<Vec<MountGcResult>> {
+    let mounts = running_mounts(client).await?;
+
+    if dry_run {
+        let params = GetStatInfoParams {
+            statsMask: STATS_MOUNTS_STATS,
+            ..Default::default()
+        };
+        let info = client
+            .getStatInfo(&params)
+            .await
+            .from_err()?
+            .mountPointInfo
+            .unwrap_or_default();
+        return Ok(mounts
+            .into_iter()
+            .map(|mount_point| {
+                let loaded_inodes = info
+                    .get(&bytes_from_path(&mount_point))
+                    .map_or(0, |info| info.loadedFileCount + info.loadedTreeCount);
+                MountGcResult {
+                    mount_point,
+                    unloaded_inodes: 0,
+                    loaded_inodes: Some(loaded_inodes),
+                    error: None,
+                }
+            })
+            .collect());
+    }
+
+    let age = TimeSpec {
+        seconds: age.as_secs() as i64,
+        nanoSeconds: age.subsec_nanos() as i64,
+        ..Default::default()
+    };
+    let mut results = Vec::new();
+    for mount_point in mounts {
+        let mount_point_bytes = bytes_from_path(&mount_point);
+        let mut error = None;
+        if let Err(e) = client
+            .invalidateKernelInodeCache(&mount_point_bytes, &Vec::new())
+            .await
+        {
+            error = Some(e.to_string());
+        }
+        let unloaded_inodes = match client
+            .unloadInodeForPath(&mount_point_bytes, &Vec::new(), &age)
+            .await
+        {
+            Ok(inodes) => inodes,
+            Err(e) => {
+                error.get_or_insert_with(|| e.to_string());
+                0
+            }
+        };
+        results.push(MountGcResult {
+            mount_point,
+            unloaded_inodes,
+            loaded_inodes: None,
+            error,
+        });
+    }
+    Ok(results)
+}
+
+/// Total size of the files in `path`. Files can be deleted while walking the directory, so
+/// errors are ignored.
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+impl GcCmd {
+    fn print_report(&self, results: &[MountGcResult], store_before: u64, store_after: u64) {
+        let reclaimed = store_before.saturating_sub(store_after);
+        if self.json {
+            let mounts = results
+                .iter()
+                .map(|result| {
+                    json!({
+                        "mount_point": result.mount_point,
+                        "unloaded_inodes": result.unloaded_inodes,
+                        "loaded_inodes": result.loaded_inodes,
+                        "error": result.error,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let report = json!({
+                "dry_run": self.dry_run,
+                "mounts": mounts,
+                "local_store_bytes": store_before,
+                "local_store_bytes_reclaimed": reclaimed,
+            });
+            println!("{}", report);
+            return;
+        }
+
+        for result in results {
+            match (&result.error, result.loaded_inodes) {
+                (Some(error), _) => {
+                    println!("{}: error: {}", result.mount_point.display(), error)
+                }
+                (None, Some(loaded_inodes)) => println!(
+                    "{}: {} inodes loaded",
+                    result.mount_point.display(),
+                    loaded_inodes
+                ),
+                (None, None) => println!(
+                    "{}: {} inodes unloaded",
+                    result.mount_point.display(),
+                    result.unloaded_inodes
+                ),
+            }
+        }
+        if self.dry_run {
+            println!("Local store: {} bytes", store_before);
+        } else {
+            println!("Local store: {} bytes reclaimed", reclaimed);
+        }
+    }
+}
 
 #[async_trait]
 impl crate::Subcommand for GcCmd {
     async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
         let client = instance.connect(None).await?;
+        let storage_dir = instance.storage_dir();
+        let store_before = dir_size(&storage_dir);
+
+        let results = unload_inodes(&client, Duration::from_secs(self.age), self.dry_run).await?;
+
+        let store_after = if self.dry_run {
+            store_before
+        } else {
+            if !self.json {
+                eprint!("Clearing and compacting local caches...");
+                stderr().flush().from_err()?;
+            }
+            client.clearAndCompactLocalStore().await.from_err()?;
+            if !self.json {
+                eprintln!();
+            }
+            dir_size(&storage_dir)
+        };
 
-        // TODO: unload inodes
+        self.print_report(&results, store_before, store_after);
+        Ok(if results.iter().any(|r| r.error.is_some()) {
+            1
+        } else {
+            0
+        })
+    }
+}
 
-        eprint!("Clearing and compacting local caches...");
-        stderr().flush().from_err()?;
-        client.clearAndCompactLocalStore().await.from_err()?;
-        eprintln!();
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use thrift_types::edenfs::errors::eden_service::InvalidateKernelInodeCacheError;
+    use thrift_types::edenfs::types::{EdenError, InternalStats, MountInodeInfo};
+
+    use super::*;
+    use crate::test_utils::{mount_info, Calls, MockClient};
+
+    fn mock_client() -> MockClient {
+        MockClient::new().mounts(vec![
+            mount_info("/mnt/a", MountState::RUNNING),
+            mount_info("/mnt/b", MountState::RUNNING),
+            mount_info("/mnt/stopping", MountState::SHUTTING_DOWN),
+        ])
+    }
 
-        // TODO: clear kernel caches here
+    #[tokio::test]
+    async fn test_unload_inodes() -> Result<()> {
+        let calls = Calls::default();
+        let client = mock_client()
+            .with(|mock| {
+                let calls = calls.clone();
+                mock.invalidateKernelInodeCache
+                    .mock(move |mount_point, path| {
+                        assert!(path.is_empty());
+                        calls
+                            .lock()
+                            .unwrap()
+                            .push(("invalidate", mount_point.clone()));
+                    });
+            })
+            .with(|mock| {
+                let calls = calls.clone();
+                mock.unloadInodeForPath.mock(move |mount_point, path, age| {
+                    assert!(path.is_empty());
+                    assert_eq!(age.seconds, 60);
+                    calls.lock().unwrap().push(("unload", mount_point.clone()));
+                    if mount_point == b"/mnt/a" {
+                        10
+                    } else {
+                        20
+                    }
+                });
+            })
+            .build();
+
+        let results = unload_inodes(&client, Duration::from_secs(60), false).await?;
+        // The kernel caches of a mount are dropped before its inodes are unloaded.
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                ("invalidate", b"/mnt/a".to_vec()),
+                ("unload", b"/mnt/a".to_vec()),
+                ("invalidate", b"/mnt/b".to_vec()),
+                ("unload", b"/mnt/b".to_vec()),
+            ]
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].unloaded_inodes, 10);
+        assert_eq!(results[1].unloaded_inodes, 20);
+        assert_eq!(results[0].loaded_inodes, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unload_inodes_invalidate_error() -> Result<()> {
+        let client = mock_client()
+            .with(|mock| {
+                mock.invalidateKernelInodeCache
+                    .mock_result(|mount_point, _path| {
+                        if mount_point == b"/mnt/b" {
+                            Err(InvalidateKernelInodeCacheError::ex(EdenError {
+                                message: "not mounted".to_string(),
+                                ..Default::default()
+                            }))
+                        } else {
+                            Ok(())
+                        }
+                    });
+                mock.unloadInodeForPath.ret(5);
+            })
+            .build();
+
+        let results = unload_inodes(&client, Duration::from_secs(60), false).await?;
+        assert_eq!(results[0].error, None);
+        assert!(results[1].error.is_some());
+        // The inodes that aren't referenced by the kernel are still unloaded.
+        assert_eq!(results[1].unloaded_inodes, 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unload_inodes_dry_run() -> Result<()> {
+        let mut info = BTreeMap::new();
+        info.insert(
+            b"/mnt/a".to_vec(),
+            MountInodeInfo {
+                loadedFileCount: 3,
+                loadedTreeCount: 4,
+                ..Default::default()
+            },
+        );
+        // unloadInodeForPath is left unimplemented: nothing is unloaded in dry-run mode.
+        let client = mock_client()
+            .with(|mock| {
+                mock.getStatInfo.ret(InternalStats {
+                    mountPointInfo: Some(info),
+                    ..Default::default()
+                });
+            })
+            .build();
+
+        let results = unload_inodes(&client, Duration::from_secs(60), true).await?;
+        assert_eq!(
+            results,
+            vec![
+                MountGcResult {
+                    mount_point: "/mnt/a".into(),
+                    unloaded_inodes: 0,
+                    loaded_inodes: Some(7),
+                    error: None,
+                },
+                MountGcResult {
+                    mount_point: "/mnt/b".into(),
+                    unloaded_inodes: 0,
+                    loaded_inodes: Some(0),
+                    error: None,
+                },
+            ]
+        );
+        Ok(())
+    }
 
-        Ok(0)
+    #[test]
+    fn test_dir_size() -> Result<()> {
+        let dir = tempfile::tempdir().from_err()?;
+        fs::create_dir(dir.path().join("sub")).from_err()?;
+        fs::write(dir.path().join("a"), b"abc").from_err()?;
+        fs::write(dir.path().join("sub").join("b"), b"de").from_err()?;
+        assert_eq!(dir_size(dir.path()), 5);
+        assert_eq!(dir_size(&dir.path().join("missing")), 0);
+        Ok(())
     }
 }
@@ -16,14 +16,24 @@ use edenfs_client::EdenFsInstance;
 use edenfs_error::Result;
 use util::path::expand_path;
 
+mod clone;
 mod config;
 mod debug;
+mod doctor;
 mod gc;
 mod humantime;
+mod list;
 mod minitop;
+mod mount;
 mod pid;
+mod prefetch;
+mod rage;
+mod remove;
 mod status;
+#[cfg(test)]
+mod test_utils;
 mod top;
+mod unmount;
 mod uptime;
 
 #[cfg(unix)]
@@ -38,6 +48,10 @@ const DEFAULT_ETC_EDEN_DIR: &str = "C:\\ProgramData\\facebook\\eden";
 
 type ExitCode = i32;
 
+/// Comma-separated subcommands that run in Rust while they are still being rolled out. The
+/// others are handed to the Python edenfsctl.
+const ROLLOUT_ENV: &str = "EDENFSCTL_RUST_ROLLOUT";
+
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "edenfsctl",
@@ -80,11 +94,40 @@ pub enum TopLevelSubcommand {
     Status(crate::status::StatusCmd),
     Pid(crate::pid::PidCmd),
     Uptime(crate::uptime::UptimeCmd),
-    // Gc(crate::gc::GcCmd),
+    Gc(crate::gc::GcCmd),
     Config(crate::config::ConfigCmd),
     Debug(crate::debug::DebugCmd),
+    Doctor(crate::doctor::DoctorCmd),
     // Top(crate::top::TopCmd),
     Minitop(crate::minitop::MinitopCmd),
+    List(crate::list::ListCmd),
+    Clone(crate::clone::CloneCmd),
+    Mount(crate::mount::MountCmd),
+    Unmount(crate::unmount::UnmountCmd),
+    #[structopt(alias = "rm")]
+    Remove(crate::remove::RemoveCmd),
+    Prefetch(crate::prefetch::PrefetchCmd),
+    Rage(crate::rage::RageCmd),
+}
+
+impl TopLevelSubcommand {
+    /// Name of the subcommand if it is still being rolled out, in which case it only runs in
+    /// Rust when listed in `EDENFSCTL_RUST_ROLLOUT`.
+    fn rollout_name(&self) -> Option<&'static str> {
+        use TopLevelSubcommand::*;
+        match self {
+            Gc(_) => Some("gc"),
+            Doctor(_) => Some("doctor"),
+            List(_) => Some("list"),
+            Clone(_) => Some("clone"),
+            Mount(_) => Some("mount"),
+            Unmount(_) => Some("unmount"),
+            Remove(_) => Some("remove"),
+            Prefetch(_) => Some("prefetch"),
+            Rage(_) => Some("rage"),
+            _ => None,
+        }
+    }
 }
 
 #[async_trait]
@@ -95,11 +138,19 @@ impl Subcommand for TopLevelSubcommand {
             Status(cmd) => cmd,
             Pid(cmd) => cmd,
             Uptime(cmd) => cmd,
-            // Gc(cmd) => cmd,
+            Gc(cmd) => cmd,
             Config(cmd) => cmd,
             Debug(cmd) => cmd,
+            Doctor(cmd) => cmd,
             // Top(cmd) => cmd,
             Minitop(cmd) => cmd,
+            List(cmd) => cmd,
+            Clone(cmd) => cmd,
+            Mount(cmd) => cmd,
+            Unmount(cmd) => cmd,
+            Remove(cmd) => cmd,
+            Prefetch(cmd) => cmd,
+            Rage(cmd) => cmd,
         };
         sc.run(instance).await
     }
@@ -138,6 +189,16 @@ impl MainCommand {
         )
     }
 
+    /// Whether the subcommand runs in Rust, rather than falling back to the Python edenfsctl.
+    pub fn is_enabled(&self) -> bool {
+        match self.subcommand.rollout_name() {
+            Some(name) => std::env::var(ROLLOUT_ENV).map_or(false, |enabled| {
+                enabled.split(',').any(|c| c.trim() == name)
+            }),
+            None => true,
+        }
+    }
+
     pub fn run(self) -> Result<ExitCode> {
         // For command line program, we don't really need concurrency. Schedule everything in
         // current thread should be sufficient.
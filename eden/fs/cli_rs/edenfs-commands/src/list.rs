@@ -0,0 +1,260 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl list
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::json;
+use structopt::StructOpt;
+use thrift_types::edenfs::types::MountState;
+use tracing::{event, Level};
+
+use edenfs_client::{path_from_bytes, EdenFsClient, EdenFsInstance};
+use edenfs_config::checkout::CheckoutConfig;
+use edenfs_error::{Result, ResultExt};
+
+use crate::ExitCode;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "List available checkouts")]
+pub struct ListCmd {
+    /// Print the output in JSON format
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct MountListItem {
+    pub data_dir: PathBuf,
+    /// `None` when the checkout isn't mounted.
+    pub state: Option<MountState>,
+    pub configured: bool,
+    pub backing_repo: Option<PathBuf>,
+}
+
+/// Merge the mounts known to the daemon with the checkouts listed in `config.json`. `client`
+/// is `None` when EdenFS isn't running, in which case no checkout is mounted.
+pub(crate) async fn get_mounts(
+    instance: &EdenFsInstance,
+    client: Option<&EdenFsClient>,
+) -> Result<BTreeMap<PathBuf, MountListItem>> {
+    let mut mounts = BTreeMap::new();
+
+    if let Some(client) = client {
+        for mount in client.listMounts().await.from_err()? {
+            mounts.insert(
+                path_from_bytes(&mount.mountPoint),
+                MountListItem {
+                    data_dir: path_from_bytes(&mount.edenClientPath),
+                    state: Some(mount.state),
+                    configured: false,
+                    backing_repo: mount.backingRepoPath.as_deref().map(path_from_bytes),
+                },
+            );
+        }
+    }
+
+    let clients_dir = instance.clients_dir();
+    for (path, name) in instance.get_configured_mounts_map()? {
+        let data_dir = clients_dir.join(name);
+        let item = mounts.entry(path).or_insert_with(|| MountListItem {
+            data_dir: data_dir.clone(),
+            state: None,
+            configured: true,
+            backing_repo: None,
+        });
+        item.configured = true;
+        if item.backing_repo.is_none() {
+            item.backing_repo = read_backing_repo(&data_dir);
+        }
+    }
+
+    Ok(mounts)
+}
+
+fn read_backing_repo(data_dir: &Path) -> Option<PathBuf> {
+    match CheckoutConfig::load(data_dir) {
+        Ok(config) => Some(config.repository.path),
+        Err(e) => {
+            event!(Level::DEBUG, ?e, "Unable to read checkout config");
+            None
+        }
+    }
+}
+
+fn format_mount(path: &Path, item: &MountListItem) -> String {
+    let mut line = path.display().to_string();
+    match item.state {
+        Some(MountState::RUNNING) => {}
+        Some(state) => line.push_str(&format!(" ({})", state)),
+        None => line.push_str(" (not mounted)"),
+    }
+    if !item.configured {
+        line.push_str(" (unconfigured)");
+    }
+    line
+}
+
+pub(crate) fn mounts_to_json(mounts: &BTreeMap<PathBuf, MountListItem>) -> serde_json::Value {
+    let mounts = mounts
+        .iter()
+        .map(|(path, item)| {
+            let state = match item.state {
+                Some(state) => state.to_string(),
+                None => "NOT_RUNNING".to_string(),
+            };
+            (
+                path.display().to_string(),
+                json!({
+                    "data_dir": item.data_dir,
+                    "state": state,
+                    "configured": item.configured,
+                    "backing_repo": item.backing_repo,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    serde_json::Value::Object(mounts)
+}
+
+#[async_trait]
+impl crate::Subcommand for ListCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let client = match instance.connect(Some(Duration::from_secs(3))).await {
+            Ok(client) => Some(client),
+            Err(e) => {
+                event!(Level::DEBUG, ?e, "Unable to connect to EdenFS daemon");
+                None
+            }
+        };
+        let mounts = get_mounts(&instance, client.as_ref()).await?;
+
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&mounts_to_json(&mounts)).from_err()?
+            );
+        } else {
+            for (path, item) in &mounts {
+                println!("{}", format_mount(path, item));
+            }
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thrift_types::edenfs::types::MountInfo;
+
+    use edenfs_client::bytes_from_path;
+
+    use super::*;
+    use crate::test_utils::{instance, mount_info, repository, MockClient};
+
+    fn item(state: Option<MountState>, configured: bool) -> MountListItem {
+        MountListItem {
+            data_dir: PathBuf::new(),
+            state,
+            configured,
+            backing_repo: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_mounts() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        let stopped_dir = instance.clients_dir().join("stopped");
+        std::fs::create_dir_all(&stopped_dir).from_err()?;
+        CheckoutConfig::new(repository()).save(&stopped_dir)?;
+        instance.add_configured_mount(Path::new("/mnt/running"), "running")?;
+        instance.add_configured_mount(Path::new("/mnt/stopped"), "stopped")?;
+
+        let running_dir = instance.clients_dir().join("running");
+        let client = MockClient::new()
+            .mounts(vec![
+                MountInfo {
+                    edenClientPath: bytes_from_path(&running_dir),
+                    backingRepoPath: Some(b"/data/repo".to_vec()),
+                    ..mount_info("/mnt/running", MountState::RUNNING)
+                },
+                mount_info("/mnt/other", MountState::STARTING),
+            ])
+            .build();
+
+        let mounts = get_mounts(&instance, Some(&client)).await?;
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(
+            mounts[Path::new("/mnt/running")],
+            MountListItem {
+                data_dir: running_dir,
+                backing_repo: Some("/data/repo".into()),
+                ..item(Some(MountState::RUNNING), true)
+            }
+        );
+        assert_eq!(
+            mounts[Path::new("/mnt/stopped")],
+            MountListItem {
+                data_dir: stopped_dir,
+                backing_repo: Some("/data/repo".into()),
+                ..item(None, true)
+            }
+        );
+        assert_eq!(
+            mounts[Path::new("/mnt/other")],
+            item(Some(MountState::STARTING), false)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_mounts_not_running() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        instance.add_configured_mount(Path::new("/mnt/repo"), "repo")?;
+
+        let mounts = get_mounts(&instance, None).await?;
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[Path::new("/mnt/repo")].state, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_mount() {
+        let path = Path::new("/mnt/repo");
+        assert_eq!(
+            format_mount(path, &item(Some(MountState::RUNNING), true)),
+            "/mnt/repo"
+        );
+        assert_eq!(
+            format_mount(path, &item(None, true)),
+            "/mnt/repo (not mounted)"
+        );
+        assert_eq!(
+            format_mount(path, &item(Some(MountState::STARTING), false)),
+            "/mnt/repo (STARTING) (unconfigured)"
+        );
+    }
+
+    #[test]
+    fn test_mounts_to_json() {
+        let mut mounts = BTreeMap::new();
+        mounts.insert(
+            PathBuf::from("/mnt/repo"),
+            MountListItem {
+                backing_repo: Some("/data/repo".into()),
+                ..item(None, true)
+            },
+        );
+        let json = mounts_to_json(&mounts);
+        assert_eq!(json["/mnt/repo"]["state"], "NOT_RUNNING");
+        assert_eq!(json["/mnt/repo"]["backing_repo"], "/data/repo");
+    }
+}
@@ -68,7 +68,7 @@ const COLUMN_TITLES: &[&str] = &[
     "CMD",
 ];
 
-trait GetAccessCountsResultExt {
+pub(crate) trait GetAccessCountsResultExt {
     fn get_cmd_for_pid(&self, pid: &i32) -> Result<String>;
 }
 
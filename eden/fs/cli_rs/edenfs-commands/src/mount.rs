@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl mount
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use structopt::StructOpt;
+use thrift_types::edenfs::types::MountArgument;
+
+use edenfs_client::{bytes_from_path, EdenFsClient, EdenFsInstance};
+use edenfs_error::{Result, ResultExt};
+
+use crate::ExitCode;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Remount an existing checkout (for instance, after it was unmounted)")]
+pub struct MountCmd {
+    /// The checkout mount path
+    #[structopt(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Mount the checkout in read-only mode
+    #[structopt(long)]
+    read_only: bool,
+}
+
+/// Make `path` absolute. The path is canonicalized when possible, but a broken mount point
+/// can't be stat'ed, in which case it is only joined to the current directory.
+pub(crate) fn normalize_path(path: &Path) -> Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    let cwd = std::env::current_dir().context("Unable to get the current directory")?;
+    Ok(cwd.join(path))
+}
+
+pub(crate) async fn mount_checkout(
+    client: &EdenFsClient,
+    mount_point: &Path,
+    client_dir: &Path,
+    read_only: bool,
+) -> Result<()> {
+    let argument = MountArgument {
+        mountPoint: bytes_from_path(mount_point),
+        edenClientPath: bytes_from_path(client_dir),
+        readOnly: read_only,
+        ..Default::default()
+    };
+    client.mount(&argument).await.from_err()
+}
+
+#[async_trait]
+impl crate::Subcommand for MountCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let client = instance.connect(None).await?;
+
+        let mut exit_code = 0;
+        for path in &self.paths {
+            let mount_point = normalize_path(path)?;
+            let result = match instance.client_dir_for_mount_point(&mount_point) {
+                Ok(client_dir) => {
+                    mount_checkout(&client, &mount_point, &client_dir, self.read_only).await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("error mounting {}: {}", mount_point.display(), e);
+                exit_code = 1;
+            }
+        }
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{Calls, MockClient};
+
+    #[tokio::test]
+    async fn test_mount_checkout() -> Result<()> {
+        let mounted = Calls::default();
+        let client = MockClient::new().record_mount(&mounted).build();
+
+        mount_checkout(
+            &client,
+            Path::new("/mnt/repo"),
+            Path::new("/eden/clients/repo"),
+            true,
+        )
+        .await?;
+
+        let mounted = mounted.lock().unwrap();
+        assert_eq!(mounted.len(), 1);
+        assert_eq!(mounted[0].mountPoint, b"/mnt/repo");
+        assert_eq!(mounted[0].edenClientPath, b"/eden/clients/repo");
+        assert!(mounted[0].readOnly);
+        Ok(())
+    }
+}
@@ -0,0 +1,478 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl prefetch
+
+use std::fs;
+use std::io::{stderr, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use crossterm::tty::IsTty;
+use progress_model::ProgressBar;
+use structopt::StructOpt;
+use thrift_types::edenfs::types::{GlobParams, PredictiveFetch};
+
+use edenfs_client::{bytes_from_path, path_from_bytes, EdenFsClient, EdenFsInstance};
+use edenfs_error::{Result, ResultExt};
+
+use crate::minitop::GetAccessCountsResultExt;
+use crate::mount::normalize_path;
+use crate::ExitCode;
+
+/// Location of the named prefetch profiles, relative to the root of the checkout.
+const PROFILES_DIR: &str = "xplat/scm/prefetch_profiles/profiles";
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Prefetch content for matching file patterns")]
+pub struct PrefetchCmd {
+    /// Filename patterns (relative to the repository root) to match via glob
+    patterns: Vec<String>,
+
+    /// Name of a prefetch profile stored in the checkout, can be repeated
+    #[structopt(long = "profile")]
+    profiles: Vec<String>,
+
+    /// Path to a file that lists patterns to match, one per line
+    #[structopt(long, parse(from_os_str))]
+    pattern_file: Option<PathBuf>,
+
+    /// Path to the checkout (default: the checkout containing the current directory)
+    #[structopt(long, parse(from_os_str))]
+    repo: Option<PathBuf>,
+
+    /// Also prefetch the directories that are the most accessed in this repository
+    #[structopt(long)]
+    predictive: bool,
+
+    /// Number of directories to prefetch with --predictive (default: set by the daemon)
+    #[structopt(long, requires = "predictive")]
+    predictive_num_dirs: Option<i32>,
+
+    /// Run the prefetch in the background
+    #[structopt(long)]
+    background: bool,
+
+    /// Do not prefetch, only match names
+    #[structopt(long)]
+    no_prefetch: bool,
+
+    /// Prefetch the metadata (sha1 and size) of every file in the fetched trees
+    #[structopt(long)]
+    prefetch_metadata: bool,
+
+    /// Include hidden files in the matching files
+    #[structopt(long)]
+    include_dot_files: bool,
+
+    /// Exclude directories from the matching files
+    #[structopt(long)]
+    list_only_files: bool,
+
+    /// Do not print the names of the matching files, nor the progress
+    #[structopt(long)]
+    silent: bool,
+}
+
+/// A set of patterns sent to the daemon in a single request.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Batch {
+    Globs { name: String, patterns: Vec<String> },
+    Predictive,
+}
+
+impl Batch {
+    fn name(&self) -> &str {
+        match self {
+            Batch::Globs { name, .. } => name,
+            Batch::Predictive => "predictive",
+        }
+    }
+}
+
+// \ is a path separator on Windows, while it escapes special characters in patterns
+// elsewhere. Windows tools commonly print paths with \, so they are accepted there.
+fn clean_pattern(pattern: &str) -> String {
+    if cfg!(windows) {
+        pattern.replace('\\', "/")
+    } else {
+        pattern.to_string()
+    }
+}
+
+fn read_patterns(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Unable to read patterns from {}", path.display()))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(clean_pattern)
+        .collect())
+}
+
+/// The root of the configured checkout that contains `path`.
+fn find_checkout(instance: &EdenFsInstance, path: &Path) -> Result<PathBuf> {
+    instance
+        .get_configured_mounts_map()?
+        .into_keys()
+        .filter(|mount_point| path.starts_with(mount_point))
+        .max_by_key(|mount_point| mount_point.components().count())
+        .ok_or_else(|| anyhow!("{} is not in an EdenFS checkout", path.display()).into())
+}
+
+/// Number of objects fetched from the backing store for `mount_point` in the last `duration`,
+/// by command, most fetches first.
+pub(crate) async fn fetch_counts(
+    client: &EdenFsClient,
+    mount_point: &Path,
+    duration: Duration,
+) -> Result<Vec<(String, i64)>> {
+    // Round up, a prefetch shorter than a second should still be accounted for.
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    let counts = client.getAccessCounts(seconds as i64).await.from_err()?;
+    let mut fetches = Vec::new();
+    if let Some(accesses) = counts.accessesByMount.get(&bytes_from_path(mount_point)) {
+        for (pid, count) in &accesses.fetchCountsByPid {
+            if *count > 0 {
+                fetches.push((counts.get_cmd_for_pid(pid)?, *count));
+            }
+        }
+    }
+    fetches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(fetches)
+}
+
+fn render_progress(bar: &ProgressBar) {
+    let (position, total) = bar.position_total();
+    let message = bar.message().map_or_else(String::new, |m| m.to_string());
+    eprint!(
+        "\r\x1b[K{} {}/{} {} {}",
+        bar.topic(),
+        position,
+        total,
+        bar.unit(),
+        message
+    );
+    let _ = stderr().flush();
+}
+
+impl PrefetchCmd {
+    pub(crate) fn batches(&self, checkout: &Path) -> Result<Vec<Batch>> {
+        let mut batches = Vec::new();
+        if !self.patterns.is_empty() {
+            batches.push(Batch::Globs {
+                name: "command line".to_string(),
+                patterns: self.patterns.iter().map(|p| clean_pattern(p)).collect(),
+            });
+        }
+        if let Some(pattern_file) = &self.pattern_file {
+            batches.push(Batch::Globs {
+                name: pattern_file.display().to_string(),
+                patterns: read_patterns(pattern_file)?,
+            });
+        }
+        for profile in &self.profiles {
+            let path = checkout.join(PROFILES_DIR).join(profile);
+            if !path.is_file() {
+                return Err(anyhow!(
+                    "Prefetch profile {} not found in {}",
+                    profile,
+                    checkout.display()
+                )
+                .into());
+            }
+            batches.push(Batch::Globs {
+                name: format!("profile {}", profile),
+                patterns: read_patterns(&path)?,
+            });
+        }
+        if self.predictive {
+            batches.push(Batch::Predictive);
+        }
+        Ok(batches)
+    }
+
+    fn glob_params(&self, checkout: &Path, globs: Vec<String>) -> GlobParams {
+        GlobParams {
+            mountPoint: bytes_from_path(checkout),
+            globs,
+            includeDotfiles: self.include_dot_files,
+            prefetchFiles: !self.no_prefetch,
+            suppressFileList: self.silent,
+            prefetchMetadata: self.prefetch_metadata,
+            background: self.background,
+            listOnlyFiles: self.list_only_files,
+            ..Default::default()
+        }
+    }
+
+    /// Send every batch to the daemon, and return the matching files.
+    pub(crate) async fn prefetch(
+        &self,
+        client: &EdenFsClient,
+        checkout: &Path,
+        batches: &[Batch],
+        bar: &ProgressBar,
+    ) -> Result<Vec<PathBuf>> {
+        let show_progress = !self.silent && stderr().is_tty();
+        let mut matching_files = Vec::new();
+        for batch in batches {
+            bar.set_message(batch.name().to_string());
+            if show_progress {
+                render_progress(bar);
+            }
+            let glob = match batch {
+                Batch::Globs { patterns, .. } => {
+                    let params = self.glob_params(checkout, patterns.clone());
+                    client.globFiles(&params).await.from_err()
+                }
+                Batch::Predictive => {
+                    let params = GlobParams {
+                        predictiveGlob: Some(PredictiveFetch {
+                            numTopDirectories: self.predictive_num_dirs,
+                            ..Default::default()
+                        }),
+                        ..self.glob_params(checkout, Vec::new())
+                    };
+                    client.predictiveGlobFiles(&params).await.from_err()
+                }
+            }
+            .with_context(|| format!("Unable to prefetch {}", batch.name()))?;
+            matching_files.extend(glob.matchingFiles.iter().map(|f| path_from_bytes(f)));
+            bar.increase_position(1);
+        }
+        if show_progress {
+            render_progress(bar);
+            eprintln!();
+        }
+        Ok(matching_files)
+    }
+}
+
+#[async_trait]
+impl crate::Subcommand for PrefetchCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let path = match &self.repo {
+            Some(repo) => normalize_path(repo)?,
+            None => std::env::current_dir().context("Unable to get the current directory")?,
+        };
+        let checkout = find_checkout(&instance, &path)?;
+        if self.repo.is_some() && checkout != path {
+            eprintln!("{} is not the root of an EdenFS checkout", path.display());
+            return Ok(1);
+        }
+
+        let batches = self.batches(&checkout)?;
+        if batches.is_empty() {
+            eprintln!("No patterns, profiles or --predictive were specified");
+            return Ok(1);
+        }
+
+        let client = instance.connect(None).await?;
+        let bar = ProgressBar::register_new("prefetching", batches.len() as u64, "batches");
+        let start = Instant::now();
+        let matching_files = self.prefetch(&client, &checkout, &batches, &bar).await?;
+
+        if self.background {
+            if !self.silent {
+                println!("Prefetching {} in the background", checkout.display());
+            }
+            return Ok(0);
+        }
+        if self.silent {
+            return Ok(0);
+        }
+
+        if matching_files.is_empty() {
+            eprintln!(
+                "No files were matched by the patterns specified.\n\
+                See `edenfsctl prefetch --help` for docs on pattern matching."
+            );
+        }
+        for file in &matching_files {
+            println!("{}", file.display());
+        }
+
+        let fetches = fetch_counts(&client, &checkout, start.elapsed()).await?;
+        let total: i64 = fetches.iter().map(|(_, count)| count).sum();
+        println!(
+            "{} objects fetched from the backing store in {:.1}s",
+            total,
+            start.elapsed().as_secs_f64()
+        );
+        for (cmd, count) in &fetches {
+            println!("{:>10}  {}", count, cmd);
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use thrift_types::edenfs::types::{GetAccessCountsResult, Glob, MountAccesses};
+
+    use super::*;
+    use crate::test_utils::{Calls, MockClient};
+
+    #[test]
+    fn test_batches() -> Result<()> {
+        let dir = tempfile::tempdir().from_err()?;
+        let profiles = dir.path().join(PROFILES_DIR);
+        fs::create_dir_all(&profiles).from_err()?;
+        fs::write(profiles.join("android"), "java/**\n\n  res/** \n").from_err()?;
+        let pattern_file = dir.path().join("patterns");
+        fs::write(&pattern_file, "docs/*.md\n").from_err()?;
+
+        let cmd = PrefetchCmd::from_iter(&[
+            "prefetch",
+            "src/**",
+            "--profile",
+            "android",
+            "--pattern-file",
+            pattern_file.to_str().unwrap(),
+            "--predictive",
+        ]);
+        assert_eq!(
+            cmd.batches(dir.path())?,
+            vec![
+                Batch::Globs {
+                    name: "command line".to_string(),
+                    patterns: vec!["src/**".to_string()],
+                },
+                Batch::Globs {
+                    name: pattern_file.display().to_string(),
+                    patterns: vec!["docs/*.md".to_string()],
+                },
+                Batch::Globs {
+                    name: "profile android".to_string(),
+                    patterns: vec!["java/**".to_string(), "res/**".to_string()],
+                },
+                Batch::Predictive,
+            ]
+        );
+
+        let cmd = PrefetchCmd::from_iter(&["prefetch", "--profile", "missing"]);
+        assert!(cmd.batches(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() -> Result<()> {
+        let params = Calls::default();
+        let client = MockClient::new()
+            .with(|mock| {
+                mock.globFiles.mock({
+                    let params = params.clone();
+                    move |p| {
+                        let files = p.globs.iter().map(|g| g.as_bytes().to_vec()).collect();
+                        params.lock().unwrap().push(p);
+                        Glob {
+                            matchingFiles: files,
+                            ..Default::default()
+                        }
+                    }
+                });
+                mock.predictiveGlobFiles.mock({
+                    let params = params.clone();
+                    move |p| {
+                        params.lock().unwrap().push(p);
+                        Glob {
+                            matchingFiles: vec![b"hot".to_vec()],
+                            ..Default::default()
+                        }
+                    }
+                });
+            })
+            .build();
+
+        let cmd = PrefetchCmd::from_iter(&[
+            "prefetch",
+            "--background",
+            "--predictive-num-dirs",
+            "10",
+            "--predictive",
+        ]);
+        let batches = vec![
+            Batch::Globs {
+                name: "command line".to_string(),
+                patterns: vec!["a".to_string(), "b".to_string()],
+            },
+            Batch::Predictive,
+        ];
+        let bar = ProgressBar::new("prefetching", batches.len() as u64, "batches");
+        let files = cmd
+            .prefetch(&client, Path::new("/mnt/repo"), &batches, &bar)
+            .await?;
+
+        assert_eq!(
+            files,
+            vec![PathBuf::from("a"), PathBuf::from("b"), PathBuf::from("hot")]
+        );
+        assert_eq!(bar.position_total(), (2, 2));
+        let params = params.lock().unwrap();
+        assert_eq!(params[0].mountPoint, b"/mnt/repo");
+        assert!(params[0].prefetchFiles);
+        assert!(params[0].background);
+        assert_eq!(params[0].predictiveGlob, None);
+        assert_eq!(
+            params[1].predictiveGlob.as_ref().unwrap().numTopDirectories,
+            Some(10)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_counts() -> Result<()> {
+        let client = MockClient::new()
+            .with(|mock| {
+                mock.getAccessCounts.mock(|duration| {
+                    assert_eq!(duration, 2);
+                    let mut cmds = BTreeMap::new();
+                    cmds.insert(1, b"/usr/bin/edenfsctl\0prefetch\0".to_vec());
+                    cmds.insert(2, b"hg\0status\0".to_vec());
+                    let mut fetches = BTreeMap::new();
+                    fetches.insert(1, 40);
+                    fetches.insert(2, 2);
+                    fetches.insert(3, 0);
+                    let mut mounts = BTreeMap::new();
+                    mounts.insert(
+                        b"/mnt/repo".to_vec(),
+                        MountAccesses {
+                            fetchCountsByPid: fetches,
+                            ..Default::default()
+                        },
+                    );
+                    GetAccessCountsResult {
+                        cmdsByPid: cmds,
+                        accessesByMount: mounts,
+                        ..Default::default()
+                    }
+                });
+            })
+            .build();
+
+        let fetches =
+            fetch_counts(&client, Path::new("/mnt/repo"), Duration::from_millis(1500)).await?;
+        assert_eq!(
+            fetches,
+            vec![
+                ("edenfsctl prefetch".to_string(), 40),
+                ("hg status".to_string(), 2),
+            ]
+        );
+
+        let fetches =
+            fetch_counts(&client, Path::new("/mnt/other"), Duration::from_secs(2)).await?;
+        assert!(fetches.is_empty());
+        Ok(())
+    }
+}
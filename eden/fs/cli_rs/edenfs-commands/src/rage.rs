@@ -0,0 +1,571 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl rage
+
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use regex::Regex;
+use structopt::StructOpt;
+use thrift_types::edenfs::consts::{STATS_COUNTERS, STATS_MOUNTS_STATS};
+use thrift_types::edenfs::types::{
+    DebugGetRawJournalParams, DebugGetRawJournalResponse, GetStatInfoParams, InternalStats,
+    MountState,
+};
+
+use edenfs_client::{bytes_from_path, path_from_bytes, EdenFsClient, EdenFsInstance};
+use edenfs_config::EdenFsConfig;
+use edenfs_error::{Result, ResultExt};
+
+use crate::list::{get_mounts, mounts_to_json};
+use crate::ExitCode;
+
+/// Amount of the end of the daemon log included in the archive.
+const LOG_TAIL_BYTES: u64 = 1024 * 1024;
+
+/// Number of journal entries included for each checkout.
+const JOURNAL_LIMIT: i32 = 100;
+
+const REDACTED: &str = "<redacted>";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long each call to the daemon may take. A stuck daemon is one of the reasons to rage.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Gather diagnostic information about EdenFS into an archive")]
+pub struct RageCmd {
+    /// Path of the archive to write (default: a new file in the temporary directory)
+    #[structopt(long, short, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Redact the text matching this regular expression, in addition to the patterns of the
+    /// `rage.redact-patterns` configuration. Can be repeated
+    #[structopt(long = "redact")]
+    redact_patterns: Vec<String>,
+}
+
+/// A file of the archive.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Entry {
+    pub name: String,
+    pub content: String,
+}
+
+impl Entry {
+    /// The content of an entry that couldn't be collected is the error.
+    fn new(name: &str, content: Result<String>) -> Self {
+        Entry {
+            name: name.to_string(),
+            content: content.unwrap_or_else(|e| format!("Error: {}\n", e)),
+        }
+    }
+}
+
+/// Replaces the text matching any of the patterns before it is written to the archive.
+pub(crate) struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid redaction pattern {:?}", pattern))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Redactor { patterns })
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            let redacted = match pattern.replace_all(&text, REDACTED) {
+                Cow::Owned(redacted) => Some(redacted),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(redacted) = redacted {
+                text = Cow::Owned(redacted);
+            }
+        }
+        text
+    }
+}
+
+/// Redaction patterns set in the `rage.redact-patterns` configuration.
+fn configured_redact_patterns(config: &EdenFsConfig) -> Result<Vec<String>> {
+    let value = match config.get_value("rage", "redact-patterns") {
+        Some(value) => value,
+        None => return Ok(Vec::new()),
+    };
+    value
+        .as_array()
+        .and_then(|patterns| {
+            patterns
+                .iter()
+                .map(|pattern| pattern.as_str().map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| anyhow!("rage.redact-patterns must be a list of strings").into())
+}
+
+/// The last `bytes` of the file at `path`.
+fn tail(path: &Path, bytes: u64) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+    let size = file.metadata().from_err()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(bytes)))
+        .from_err()?;
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).from_err()?;
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Await a call to the daemon, failing after `CALL_TIMEOUT`.
+async fn call<T, E>(future: impl Future<Output = std::result::Result<T, E>>) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    tokio::time::timeout(CALL_TIMEOUT, future)
+        .await
+        .with_context(|| format!("EdenFS did not respond within {:?}", CALL_TIMEOUT))?
+        .from_err()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn format_journal(journal: &DebugGetRawJournalResponse) -> String {
+    let mut out = String::new();
+    for delta in &journal.allDeltas {
+        let _ = writeln!(
+            out,
+            "{}-{} {} -> {}",
+            delta.fromPosition.sequenceNumber,
+            delta.toPosition.sequenceNumber,
+            hex(&delta.fromPosition.snapshotHash),
+            hex(&delta.toPosition.snapshotHash),
+        );
+        for (path, info) in &delta.changedPaths {
+            let status = match (info.existedBefore, info.existedAfter) {
+                (false, true) => 'A',
+                (true, false) => 'R',
+                (true, true) => 'M',
+                (false, false) => '?',
+            };
+            let _ = writeln!(out, "  {} {}", status, path_from_bytes(path).display());
+        }
+        for path in &delta.uncleanPaths {
+            let _ = writeln!(out, "  X {}", path_from_bytes(path).display());
+        }
+    }
+    out
+}
+
+fn format_stats(stats: &InternalStats) -> String {
+    let mut out = String::new();
+    if let Some(count) = stats.periodicUnloadCount {
+        let _ = writeln!(out, "periodic_unload_count: {}", count);
+    }
+    for (mount_point, info) in stats.mountPointInfo.iter().flatten() {
+        let _ = writeln!(
+            out,
+            "{}: {} loaded files, {} loaded trees, {} unloaded inodes",
+            path_from_bytes(mount_point).display(),
+            info.loadedFileCount,
+            info.loadedTreeCount,
+            info.unloadedInodeCount
+        );
+    }
+    for (name, value) in stats.counters.iter().flatten() {
+        let _ = writeln!(out, "{}: {}", name, value);
+    }
+    out
+}
+
+async fn journals(client: &EdenFsClient, mount_points: &[PathBuf]) -> String {
+    let mut out = String::new();
+    for mount_point in mount_points {
+        let params = DebugGetRawJournalParams {
+            mountPoint: bytes_from_path(mount_point),
+            limit: Some(JOURNAL_LIMIT),
+            ..Default::default()
+        };
+        let _ = writeln!(out, "== {} ==", mount_point.display());
+        match call(client.debugGetRawJournal(&params)).await {
+            Ok(journal) => out.push_str(&format_journal(&journal)),
+            Err(e) => {
+                let _ = writeln!(out, "Error: {}", e);
+            }
+        }
+    }
+    out
+}
+
+async fn outstanding_calls(client: &EdenFsClient, mount_points: &[PathBuf]) -> String {
+    let mut out = String::new();
+    for mount_point in mount_points {
+        let mount_point_bytes = bytes_from_path(mount_point);
+        let _ = writeln!(out, "== {} ==", mount_point.display());
+        match call(client.debugOutstandingFuseCalls(&mount_point_bytes)).await {
+            Ok(calls) => {
+                for call in calls {
+                    let _ = writeln!(
+                        out,
+                        "FUSE {} unique={} nodeid={} pid={} process={}",
+                        call.opcodeName,
+                        call.unique,
+                        call.nodeid,
+                        call.pid,
+                        call.processName.as_deref().unwrap_or("unknown")
+                    );
+                }
+            }
+            Err(e) => {
+                let _ = writeln!(out, "Error listing FUSE calls: {}", e);
+            }
+        }
+        match call(client.debugOutstandingNfsCalls(&mount_point_bytes)).await {
+            Ok(calls) => {
+                for call in calls {
+                    let _ = writeln!(out, "NFS {} xid={}", call.procName, call.xid);
+                }
+            }
+            Err(e) => {
+                let _ = writeln!(out, "Error listing NFS calls: {}", e);
+            }
+        }
+    }
+    out
+}
+
+/// Entries that can only be collected from a running daemon.
+pub(crate) async fn collect_daemon_entries(
+    client: &EdenFsClient,
+    mount_points: &[PathBuf],
+) -> Vec<Entry> {
+    let daemon_info = call(client.getDaemonInfo())
+        .await
+        .map(|info| format!("{:#?}\n", info));
+    let params = GetStatInfoParams {
+        statsMask: STATS_MOUNTS_STATS | STATS_COUNTERS,
+        ..Default::default()
+    };
+    let stats = call(client.getStatInfo(&params))
+        .await
+        .map(|stats| format_stats(&stats));
+
+    vec![
+        Entry::new("daemon_info.txt", daemon_info),
+        Entry::new("stats.txt", stats),
+        Entry::new("journal.txt", Ok(journals(client, mount_points).await)),
+        Entry::new(
+            "outstanding_calls.txt",
+            Ok(outstanding_calls(client, mount_points).await),
+        ),
+    ]
+}
+
+async fn collect_entries(
+    instance: &EdenFsInstance,
+    config: &EdenFsConfig,
+    client: Result<&EdenFsClient, String>,
+) -> Vec<Entry> {
+    let mut entries = Vec::new();
+
+    let config = toml::to_string(config).context("Unable to serialize the configuration");
+    entries.push(Entry::new("config.toml", config.map_err(Into::into)));
+
+    let mounts = call(get_mounts(instance, client.as_ref().ok().copied())).await;
+    let running = match &mounts {
+        Ok(mounts) => mounts
+            .iter()
+            .filter(|(_, item)| item.state == Some(MountState::RUNNING))
+            .map(|(path, _)| path.clone())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let mounts = mounts.and_then(|mounts| {
+        serde_json::to_string_pretty(&mounts_to_json(&mounts))
+            .from_err()
+            .map(|json| json + "\n")
+    });
+    entries.push(Entry::new("mounts.json", mounts));
+
+    match client {
+        Ok(client) => entries.extend(collect_daemon_entries(client, &running).await),
+        Err(e) => entries.push(Entry::new(
+            "daemon_info.txt",
+            Ok(format!("EdenFS is not running: {}\n", e)),
+        )),
+    }
+
+    entries.push(Entry::new(
+        "edenfs.log",
+        tail(&instance.log_path(), LOG_TAIL_BYTES),
+    ));
+    entries
+}
+
+/// Create the archive file: `output` if given, otherwise a new file with a unique name in the
+/// temporary directory.
+fn create_output(output: Option<&Path>) -> Result<(File, PathBuf)> {
+    match output {
+        Some(output) => {
+            let file = File::create(output)
+                .with_context(|| format!("Unable to create {}", output.display()))?;
+            Ok((file, output.to_path_buf()))
+        }
+        None => tempfile::Builder::new()
+            .prefix("edenfs-rage-")
+            .suffix(".tar.zst")
+            .tempfile()
+            .context("Unable to create the archive in the temporary directory")?
+            .keep()
+            .from_err(),
+    }
+}
+
+/// Write the redacted entries into `file`, as a zstd compressed tar archive.
+pub(crate) fn write_archive(file: File, entries: &[Entry], redactor: &Redactor) -> Result<()> {
+    let encoder = zstd::Encoder::new(file, 0).from_err()?;
+    let mut builder = tar::Builder::new(encoder);
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for entry in entries {
+        let content = redactor.redact(&entry.content);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder
+            .append_data(&mut header, &entry.name, content.as_bytes())
+            .from_err()?;
+    }
+    builder.into_inner().from_err()?.finish().from_err()?;
+    Ok(())
+}
+
+#[async_trait]
+impl crate::Subcommand for RageCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        // Nothing is collected without the configured redaction patterns.
+        let config = instance.get_config().map_err(|e| {
+            anyhow!(
+                "Unable to load the configuration, which holds the redaction patterns: {}",
+                e
+            )
+        })?;
+        let mut patterns = configured_redact_patterns(&config)?;
+        patterns.extend(self.redact_patterns.iter().cloned());
+        let redactor = Redactor::new(&patterns)?;
+
+        let client = instance.connect(Some(CONNECT_TIMEOUT)).await;
+        let entries = collect_entries(
+            &instance,
+            &config,
+            client.as_ref().map_err(|e| e.to_string()),
+        )
+        .await;
+
+        let (file, output) = create_output(self.output.as_deref())?;
+        write_archive(file, &entries, &redactor)?;
+        println!("Wrote the diagnostic archive to {}", output.display());
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use thrift_types::edenfs::types::{
+        DaemonInfo, DebugJournalDelta, DebugPathChangeInfo, FuseCall, JournalPosition,
+    };
+
+    use super::*;
+    use crate::test_utils::MockClient;
+
+    #[test]
+    fn test_redactor() -> Result<()> {
+        let redactor = Redactor::new(&["/home/[^/]+".to_string(), "secret\\S*".to_string()])?;
+        assert_eq!(
+            redactor.redact("open /home/alice/secret.txt failed"),
+            "open <redacted>/<redacted> failed"
+        );
+        assert!(matches!(redactor.redact("nothing"), Cow::Borrowed(_)));
+        assert!(Redactor::new(&["(".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_journal() {
+        let mut changed = BTreeMap::new();
+        changed.insert(
+            b"added".to_vec(),
+            DebugPathChangeInfo {
+                existedBefore: false,
+                existedAfter: true,
+                ..Default::default()
+            },
+        );
+        changed.insert(
+            b"modified".to_vec(),
+            DebugPathChangeInfo {
+                existedBefore: true,
+                existedAfter: true,
+                ..Default::default()
+            },
+        );
+        let mut unclean = BTreeSet::new();
+        unclean.insert(b"unclean".to_vec());
+        let journal = DebugGetRawJournalResponse {
+            allDeltas: vec![DebugJournalDelta {
+                fromPosition: JournalPosition {
+                    sequenceNumber: 3,
+                    snapshotHash: vec![0xab],
+                    ..Default::default()
+                },
+                toPosition: JournalPosition {
+                    sequenceNumber: 4,
+                    snapshotHash: vec![0xcd],
+                    ..Default::default()
+                },
+                changedPaths: changed,
+                uncleanPaths: unclean,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            format_journal(&journal),
+            "3-4 ab -> cd\n  A added\n  M modified\n  X unclean\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_daemon_entries() -> Result<()> {
+        let mut counters = BTreeMap::new();
+        counters.insert("fuse.read_us.avg".to_string(), 12);
+        let client = MockClient::new()
+            .with(|mock| {
+                mock.getDaemonInfo.ret(DaemonInfo {
+                    pid: 42,
+                    ..Default::default()
+                });
+                mock.getStatInfo.ret(InternalStats {
+                    counters: Some(counters),
+                    ..Default::default()
+                });
+                mock.debugGetRawJournal
+                    .ret(DebugGetRawJournalResponse::default());
+                mock.debugOutstandingFuseCalls.ret(vec![FuseCall {
+                    opcodeName: "FUSE_LOOKUP".to_string(),
+                    unique: 7,
+                    nodeid: 1,
+                    pid: 100,
+                    processName: Some("hg".to_string()),
+                    ..Default::default()
+                }]);
+                mock.debugOutstandingNfsCalls.ret(Vec::new());
+            })
+            .build();
+
+        let entries = collect_daemon_entries(&client, &[PathBuf::from("/mnt/repo")]).await;
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "daemon_info.txt",
+                "stats.txt",
+                "journal.txt",
+                "outstanding_calls.txt"
+            ]
+        );
+        assert!(entries[0].content.contains("pid: 42"));
+        assert_eq!(entries[1].content, "fuse.read_us.avg: 12\n");
+        assert_eq!(entries[2].content, "== /mnt/repo ==\n");
+        assert_eq!(
+            entries[3].content,
+            "== /mnt/repo ==\nFUSE FUSE_LOOKUP unique=7 nodeid=1 pid=100 process=hg\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_archive() -> Result<()> {
+        let dir = tempfile::tempdir().from_err()?;
+        let path = dir.path().join("rage.tar.zst");
+        let entries = vec![
+            Entry::new("a.txt", Ok("mounted /home/alice/repo\n".to_string())),
+            Entry::new("b.txt", Err(anyhow!("not running").into())),
+        ];
+        let (file, output) = create_output(Some(&path))?;
+        assert_eq!(output, path);
+        write_archive(file, &entries, &Redactor::new(&["alice".to_string()])?)?;
+
+        let decoder = zstd::Decoder::new(File::open(&path).from_err()?).from_err()?;
+        let mut archive = tar::Archive::new(decoder);
+        let mut files = Vec::new();
+        for file in archive.entries().from_err()? {
+            let mut file = file.from_err()?;
+            let name = file.path().from_err()?.display().to_string();
+            let mut content = String::new();
+            file.read_to_string(&mut content).from_err()?;
+            files.push((name, content));
+        }
+        assert_eq!(
+            files,
+            vec![
+                (
+                    "a.txt".to_string(),
+                    "mounted /home/<redacted>/repo\n".to_string()
+                ),
+                ("b.txt".to_string(), "Error: not running\n".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_output_default() -> Result<()> {
+        // Every archive gets a new file, never an existing one.
+        let (_, first) = create_output(None)?;
+        let (_, second) = create_output(None)?;
+        assert_ne!(first, second);
+        for path in [first, second] {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            assert!(name.starts_with("edenfs-rage-") && name.ends_with(".tar.zst"));
+            std::fs::remove_file(&path).from_err()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail() -> Result<()> {
+        let dir = tempfile::tempdir().from_err()?;
+        let path = dir.path().join("edenfs.log");
+        std::fs::write(&path, "first\nsecond\n").from_err()?;
+        assert_eq!(tail(&path, 7)?, "second\n");
+        assert_eq!(tail(&path, 100)?, "first\nsecond\n");
+        assert!(tail(&dir.path().join("missing"), 10).is_err());
+        Ok(())
+    }
+}
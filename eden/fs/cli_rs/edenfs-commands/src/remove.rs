@@ -0,0 +1,181 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl remove
+
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use structopt::StructOpt;
+use tracing::{event, Level};
+
+use edenfs_client::{bytes_from_path, EdenFsClient, EdenFsInstance};
+use edenfs_error::{Result, ResultExt};
+
+use crate::clone::README_FILE;
+use crate::mount::normalize_path;
+use crate::unmount::unmount_checkout;
+use crate::ExitCode;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Remove an EdenFS checkout")]
+pub struct RemoveCmd {
+    /// The EdenFS checkout(s) to remove
+    #[structopt(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Do not prompt for confirmation before removing the checkouts
+    #[structopt(short = "y", long = "yes", alias = "no-prompt")]
+    yes: bool,
+}
+
+fn confirm(paths: &[PathBuf]) -> Result<bool> {
+    println!("Warning: this operation will permanently delete the following checkouts:");
+    for path in paths {
+        println!("  {}", path.display());
+    }
+    print!("Any uncommitted changes and shelves in these checkouts will be lost forever.\nProceed? [y/N] ");
+    io::stdout().flush().from_err()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).from_err()?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Unmount the checkout if it is currently mounted, delete its state and remove it from
+/// `config.json`. `client` is `None` when EdenFS isn't running.
+pub(crate) async fn remove_checkout(
+    instance: &EdenFsInstance,
+    client: Option<&EdenFsClient>,
+    mount_point: &Path,
+) -> Result<()> {
+    let client_dir = instance.client_dir_for_mount_point(mount_point)?;
+
+    if let Some(client) = client {
+        let mount_point_bytes = bytes_from_path(mount_point);
+        let mounts = client.listMounts().await.from_err()?;
+        if mounts.iter().any(|m| m.mountPoint == mount_point_bytes) {
+            unmount_checkout(client, mount_point).await?;
+        }
+    }
+
+    match fs::remove_dir_all(&client_dir) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            return Err(e)
+                .with_context(|| format!("Unable to delete {}", client_dir.display()))
+                .map_err(Into::into);
+        }
+        _ => {}
+    }
+    instance.remove_configured_mount(mount_point)?;
+
+    // The mount point is now an empty directory, with only the README explaining that the
+    // checkout isn't mounted.
+    for result in [
+        fs::remove_file(mount_point.join(README_FILE)),
+        fs::remove_dir(mount_point),
+    ] {
+        if let Err(e) = result {
+            event!(
+                Level::DEBUG,
+                ?e,
+                ?mount_point,
+                "Unable to clean up mount point"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl crate::Subcommand for RemoveCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let paths = self
+            .paths
+            .iter()
+            .map(|path| normalize_path(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        if !self.yes && !confirm(&paths)? {
+            println!("Not confirmed");
+            return Ok(2);
+        }
+
+        let client = match instance.connect(Some(Duration::from_secs(3))).await {
+            Ok(client) => Some(client),
+            Err(e) => {
+                event!(Level::DEBUG, ?e, "Unable to connect to EdenFS daemon");
+                None
+            }
+        };
+
+        let mut exit_code = 0;
+        for path in &paths {
+            println!("Removing {}...", path.display());
+            if let Err(e) = remove_checkout(&instance, client.as_ref(), path).await {
+                eprintln!("error removing {}: {}", path.display(), e);
+                exit_code = 1;
+            }
+        }
+        if exit_code == 0 {
+            println!("Success");
+        }
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use thrift_types::edenfs::types::{MountInfo, MountState};
+
+    use super::*;
+    use crate::test_utils::{instance, mount_info, Calls, MockClient};
+
+    #[tokio::test]
+    async fn test_remove_checkout() -> Result<()> {
+        let (dir, instance) = instance()?;
+        let mount_point = dir.path().join("repo");
+        let client_dir = instance.clients_dir().join("repo");
+        fs::create_dir_all(&client_dir).from_err()?;
+        fs::create_dir_all(&mount_point).from_err()?;
+        fs::write(mount_point.join(README_FILE), "").from_err()?;
+        instance.add_configured_mount(&mount_point, "repo")?;
+
+        let unmounted = Calls::default();
+        let client = MockClient::new()
+            .mounts(vec![MountInfo {
+                edenClientPath: bytes_from_path(&client_dir),
+                ..mount_info(&mount_point, MountState::RUNNING)
+            }])
+            .record_unmount(&unmounted)
+            .build();
+
+        remove_checkout(&instance, Some(&client), &mount_point).await?;
+
+        assert_eq!(
+            *unmounted.lock().unwrap(),
+            vec![bytes_from_path(&mount_point)]
+        );
+        assert!(!client_dir.exists());
+        assert!(!mount_point.exists());
+        assert!(instance.get_configured_mounts_map()?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_remove_unknown_checkout() -> Result<()> {
+        let (_dir, instance) = instance()?;
+        assert!(remove_checkout(&instance, None, Path::new("/mnt/unknown"))
+            .await
+            .is_err());
+        Ok(())
+    }
+}
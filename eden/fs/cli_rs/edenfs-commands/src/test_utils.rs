@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Fixtures shared by the subcommand tests.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use tempfile::TempDir;
+use thrift_types::edenfs::client::EdenService;
+use thrift_types::edenfs::mock;
+use thrift_types::edenfs::types::{MountArgument, MountInfo, MountState};
+
+use edenfs_client::{bytes_from_path, EdenFsClient, EdenFsInstance};
+use edenfs_config::checkout::Repository;
+use edenfs_error::{Result, ResultExt};
+
+/// Arguments of the calls to a mocked method, in call order.
+pub(crate) type Calls<T> = Arc<Mutex<Vec<T>>>;
+
+/// Create an `EdenFsInstance` in a new temporary directory. Its config directory is `eden`,
+/// the rest of the temporary directory can be used for checkouts.
+pub(crate) fn instance() -> Result<(TempDir, EdenFsInstance)> {
+    let dir = tempfile::tempdir().from_err()?;
+    let config_dir = dir.path().join("eden");
+    fs::create_dir(&config_dir).from_err()?;
+    let instance = EdenFsInstance::new(config_dir, dir.path().into(), None);
+    Ok((dir, instance))
+}
+
+pub(crate) fn repository() -> Repository {
+    Repository {
+        path: "/data/repo".into(),
+        repo_type: "hg".to_string(),
+        guid: None,
+        protocol: Some("fuse".to_string()),
+        case_sensitive: Some(true),
+        require_utf8_path: Some(true),
+    }
+}
+
+pub(crate) fn mount_info(mount_point: impl AsRef<Path>, state: MountState) -> MountInfo {
+    MountInfo {
+        mountPoint: bytes_from_path(mount_point.as_ref()),
+        state,
+        ..Default::default()
+    }
+}
+
+/// Builder of mocked EdenFS clients. Calling a method that isn't mocked panics.
+pub(crate) struct MockClient {
+    mock: mock::EdenService<'static>,
+}
+
+impl MockClient {
+    pub(crate) fn new() -> Self {
+        Self {
+            mock: <dyn EdenService>::mock(),
+        }
+    }
+
+    /// `listMounts` returns `mounts`.
+    pub(crate) fn mounts(self, mounts: Vec<MountInfo>) -> Self {
+        self.mock.listMounts.ret(mounts);
+        self
+    }
+
+    /// Record the arguments of the `mount` calls in `calls`.
+    pub(crate) fn record_mount(self, calls: &Calls<MountArgument>) -> Self {
+        let calls = calls.clone();
+        self.mock
+            .mount
+            .mock(move |argument| calls.lock().unwrap().push(argument));
+        self
+    }
+
+    /// Record the mount points of the `unmount` calls in `calls`.
+    pub(crate) fn record_unmount(self, calls: &Calls<Vec<u8>>) -> Self {
+        let calls = calls.clone();
+        self.mock
+            .unmount
+            .mock(move |mount_point| calls.lock().unwrap().push(mount_point));
+        self
+    }
+
+    /// Mock the methods that have no dedicated helper.
+    pub(crate) fn with(self, f: impl FnOnce(&mock::EdenService<'static>)) -> Self {
+        f(&self.mock);
+        self
+    }
+
+    pub(crate) fn build(self) -> EdenFsClient {
+        Arc::new(self.mock)
+    }
+}
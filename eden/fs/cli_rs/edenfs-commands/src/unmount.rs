@@ -0,0 +1,67 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! edenfsctl unmount
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use structopt::StructOpt;
+
+use edenfs_client::{bytes_from_path, EdenFsClient, EdenFsInstance};
+use edenfs_error::{Result, ResultExt};
+
+use crate::mount::normalize_path;
+use crate::ExitCode;
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "Unmount a specific checkout")]
+pub struct UnmountCmd {
+    /// Path where checkout should be unmounted from
+    #[structopt(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+pub(crate) async fn unmount_checkout(client: &EdenFsClient, mount_point: &Path) -> Result<()> {
+    client
+        .unmount(&bytes_from_path(mount_point))
+        .await
+        .from_err()
+}
+
+#[async_trait]
+impl crate::Subcommand for UnmountCmd {
+    async fn run(&self, instance: EdenFsInstance) -> Result<ExitCode> {
+        let client = instance.connect(None).await?;
+
+        let mut exit_code = 0;
+        for path in &self.paths {
+            let mount_point = normalize_path(path)?;
+            if let Err(e) = unmount_checkout(&client, &mount_point).await {
+                eprintln!("error unmounting {}: {}", mount_point.display(), e);
+                exit_code = 1;
+            }
+        }
+        Ok(exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{Calls, MockClient};
+
+    #[tokio::test]
+    async fn test_unmount_checkout() -> Result<()> {
+        let unmounted = Calls::default();
+        let client = MockClient::new().record_unmount(&unmounted).build();
+
+        unmount_checkout(&client, Path::new("/mnt/repo")).await?;
+        assert_eq!(*unmounted.lock().unwrap(), vec![b"/mnt/repo".to_vec()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Per-checkout configuration files, stored in the EdenFS state directory.
+//!
+//! * `config.json` maps every configured mount point to the name of its client directory.
+//! * `clients/<name>/config.toml` describes the repository backing the checkout.
+//! * `clients/<name>/SNAPSHOT` records the commit the checkout is based on.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const CLIENTS_DIR: &str = "clients";
+const CONFIG_JSON: &str = "config.json";
+const MOUNT_CONFIG: &str = "config.toml";
+const SNAPSHOT: &str = "SNAPSHOT";
+const SNAPSHOT_MAGIC_1: &[u8] = b"eden\x00\x00\x00\x01";
+const SNAPSHOT_MAGIC_2: &[u8] = b"eden\x00\x00\x00\x02";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Repository {
+    pub path: PathBuf,
+
+    #[serde(rename = "type")]
+    pub repo_type: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guid: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub require_utf8_path: Option<bool>,
+}
+
+/// Content of `clients/<name>/config.toml`. Only the `[repository]` section is interpreted,
+/// the other sections are preserved as is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckoutConfig {
+    pub repository: Repository,
+
+    #[serde(flatten)]
+    pub other: toml::value::Table,
+}
+
+impl CheckoutConfig {
+    pub fn new(repository: Repository) -> Self {
+        Self {
+            repository,
+            other: Default::default(),
+        }
+    }
+
+    /// Read the config of the checkout whose state is stored in `client_dir`.
+    pub fn load(client_dir: &Path) -> Result<Self> {
+        let path = client_dir.join(MOUNT_CONFIG);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read checkout config {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("Unable to parse checkout config {}", path.display()))
+    }
+
+    pub fn save(&self, client_dir: &Path) -> Result<()> {
+        let content = toml::to_string(self)?;
+        write_file_atomically(&client_dir.join(MOUNT_CONFIG), content.as_bytes())
+    }
+}
+
+/// Write the commit the checkout is based on to its `SNAPSHOT` file.
+pub fn save_snapshot(client_dir: &Path, commit: &str) -> Result<()> {
+    let mut content = SNAPSHOT_MAGIC_2.to_vec();
+    content.extend_from_slice(&(commit.len() as u32).to_be_bytes());
+    content.extend_from_slice(commit.as_bytes());
+    write_file_atomically(&client_dir.join(SNAPSHOT), &content)
+}
+
+/// Read the commit the checkout is based on from its `SNAPSHOT` file. Old files store a binary
+/// hash, it is returned in hex.
+pub fn load_snapshot(client_dir: &Path) -> Result<String> {
+    let path = client_dir.join(SNAPSHOT);
+    let content = fs::read(&path).with_context(|| format!("Unable to read {}", path.display()))?;
+    let (header, body) = content.split_at(content.len().min(SNAPSHOT_MAGIC_2.len()));
+    if header == SNAPSHOT_MAGIC_1 && body.len() >= 20 {
+        Ok(body[..20].iter().map(|b| format!("{:02x}", b)).collect())
+    } else if header == SNAPSHOT_MAGIC_2 && body.len() >= 4 {
+        let (len, commit) = body.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if commit.len() < len {
+            return Err(anyhow!("{} is too short", path.display()));
+        }
+        Ok(String::from_utf8(commit[..len].to_vec())
+            .with_context(|| format!("Invalid commit in {}", path.display()))?)
+    } else {
+        Err(anyhow!("{} has an invalid header", path.display()))
+    }
+}
+
+/// Read `config.json`, the mapping of mount points to client directory names. A missing file
+/// means that there are no configured checkouts.
+pub fn load_directory_map(config_dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let path = config_dir.join(CONFIG_JSON);
+    let content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Unable to read {}", path.display()));
+        }
+    };
+    serde_json::from_slice(&content)
+        .map_err(|e| anyhow!("invalid data found in {}: {}", path.display(), e))
+}
+
+pub fn save_directory_map(config_dir: &Path, map: &BTreeMap<PathBuf, String>) -> Result<()> {
+    let mut content = serde_json::to_string_pretty(map)?;
+    content.push('\n');
+    write_file_atomically(&config_dir.join(CONFIG_JSON), content.as_bytes())
+}
+
+fn write_file_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, content).with_context(|| format!("Unable to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("Unable to write {}", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkout_config_roundtrip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let content = r#"
+[repository]
+path = "/data/repo"
+type = "hg"
+protocol = "fuse"
+case-sensitive = true
+
+[redirections]
+"buck-out" = "bind"
+"#;
+        fs::write(dir.path().join(MOUNT_CONFIG), content)?;
+
+        let config = CheckoutConfig::load(dir.path())?;
+        assert_eq!(config.repository.path, PathBuf::from("/data/repo"));
+        assert_eq!(config.repository.repo_type, "hg");
+        assert_eq!(config.repository.case_sensitive, Some(true));
+        assert!(config.other.contains_key("redirections"));
+
+        config.save(dir.path())?;
+        assert_eq!(CheckoutConfig::load(dir.path())?, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_map() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        assert!(load_directory_map(dir.path())?.is_empty());
+
+        let mut map = BTreeMap::new();
+        map.insert(PathBuf::from("/home/user/repo"), "repo".to_string());
+        save_directory_map(dir.path(), &map)?;
+        assert_eq!(load_directory_map(dir.path())?, map);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_snapshot() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        save_snapshot(dir.path(), "abcd")?;
+        assert_eq!(
+            fs::read(dir.path().join(SNAPSHOT))?,
+            b"eden\x00\x00\x00\x02\x00\x00\x00\x04abcd"
+        );
+        assert_eq!(load_snapshot(dir.path())?, "abcd");
+
+        let mut content = SNAPSHOT_MAGIC_1.to_vec();
+        content.extend_from_slice(&[0xab; 20]);
+        fs::write(dir.path().join(SNAPSHOT), content)?;
+        assert_eq!(load_snapshot(dir.path())?, "ab".repeat(20));
+
+        fs::write(dir.path().join(SNAPSHOT), b"garbage")?;
+        assert!(load_snapshot(dir.path()).is_err());
+        Ok(())
+    }
+}
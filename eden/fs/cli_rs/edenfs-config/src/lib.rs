@@ -14,6 +14,8 @@ use tracing::{event, trace, Level};
 
 use edenfs_error::EdenFsError;
 
+pub mod checkout;
+
 #[derive(Serialize, Deserialize, StackConfig, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Core {
@@ -31,6 +33,13 @@ pub struct EdenFsConfig {
     other: toml::value::Table,
 }
 
+impl EdenFsConfig {
+    /// Look up `section.key` among the settings that don't have a dedicated field.
+    pub fn get_value(&self, section: &str, key: &str) -> Option<&toml::Value> {
+        self.other.get(section)?.get(key)
+    }
+}
+
 fn merge_table(lhs: &mut toml::value::Table, rhs: toml::value::Table) {
     for (key, value) in rhs.into_iter() {
         if let Some(lhs_value) = lhs.get_mut(&key) {
@@ -99,7 +99,9 @@ fn main() -> Result<()> {
         fallback()
     } else {
         match edenfs_commands::MainCommand::from_args_safe() {
-            Ok(cmd) => rust_main(cmd),
+            Ok(cmd) if cmd.is_enabled() => rust_main(cmd),
+            // Still being rolled out, see `EDENFSCTL_RUST_ROLLOUT`.
+            Ok(_) => fallback(),
             Err(e) if e.kind == clap::ErrorKind::HelpDisplayed => {
                 // If we get a help message, we don't want to fallback to the Python version. The
                 // help flag has been disabled for the main command and debug subcommand so they
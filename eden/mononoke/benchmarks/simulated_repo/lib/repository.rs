@@ -258,6 +258,7 @@ impl BenchmarkRepoFactory {
         repo_config: &ArcRepoConfig,
         changesets: &ArcChangesets,
         bonsai_hg_mapping: &ArcBonsaiHgMapping,
+        bonsai_git_mapping: &ArcBonsaiGitMapping,
         filenodes: &ArcFilenodes,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcRepoDerivedData> {
@@ -266,6 +267,7 @@ impl BenchmarkRepoFactory {
             repo_identity.name().to_string(),
             changesets.clone(),
             bonsai_hg_mapping.clone(),
+            bonsai_git_mapping.clone(),
             filenodes.clone(),
             repo_blobstore.as_ref().clone(),
             Arc::new(DummyLease {}),
@@ -412,36 +412,20 @@ fn make_blobstore_put_ops<'a>(
                 region_name,
                 endpoint,
                 num_concurrent_operations,
-            } => {
-                #[cfg(fbcode_build)]
-                {
-                    ::s3blob::S3Blob::new(
-                        fb,
-                        bucket,
-                        keychain_group,
-                        region_name,
-                        endpoint,
-                        blobstore_options.put_behaviour,
-                        logger,
-                        num_concurrent_operations,
-                    )
-                    .watched(logger)
-                    .await
-                    .context(ErrorKind::StateOpen)
-                    .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?
-                }
-                #[cfg(not(fbcode_build))]
-                {
-                    let _ = (
-                        bucket,
-                        keychain_group,
-                        region_name,
-                        endpoint,
-                        num_concurrent_operations,
-                    );
-                    unimplemented!("This is implemented only for fbcode_build")
-                }
-            }
+            } => ::s3blob::S3Blob::new(
+                fb,
+                bucket,
+                keychain_group,
+                region_name,
+                endpoint,
+                blobstore_options.put_behaviour,
+                logger,
+                num_concurrent_operations,
+            )
+            .watched(logger)
+            .await
+            .context(ErrorKind::StateOpen)
+            .map(|store| Arc::new(store) as Arc<dyn BlobstorePutOps>)?,
 
             // Special case
             Disabled => {
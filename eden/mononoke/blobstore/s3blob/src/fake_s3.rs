@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! In-process fake of the parts of the S3 API that `S3Blob` uses. It sits below the rusoto
+//! client, so requests are built, signed and parsed by the real client.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::Utc;
+use http::{HeaderMap, StatusCode};
+use rusoto_core::request::{
+    DispatchSignedRequest, DispatchSignedRequestFuture, HttpDispatchError, HttpResponse,
+};
+use rusoto_core::signature::{SignedRequest, SignedRequestPayload};
+use rusoto_core::{ByteStream, Region};
+use rusoto_credential::StaticProvider;
+use rusoto_s3::S3Client;
+use tokio::io::AsyncReadExt;
+
+#[derive(Default)]
+pub struct FakeS3State {
+    /// Objects by request path, i.e. `/bucket/key`.
+    pub objects: HashMap<String, Vec<u8>>,
+    /// Parts of the multipart uploads in progress, by upload id.
+    pub uploads: HashMap<String, BTreeMap<u32, Vec<u8>>>,
+    /// Fail every part upload.
+    pub fail_parts: bool,
+    next_upload_id: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct FakeS3 {
+    state: Arc<Mutex<FakeS3State>>,
+}
+
+impl FakeS3 {
+    pub fn client(&self) -> S3Client {
+        S3Client::new_with(
+            self.clone(),
+            StaticProvider::new_minimal("access_key".to_string(), "secret_key".to_string()),
+            Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: "http://fake-s3".to_string(),
+            },
+        )
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, FakeS3State> {
+        self.state.lock().expect("lock poisoned")
+    }
+}
+
+impl DispatchSignedRequest for FakeS3 {
+    fn dispatch(
+        &self,
+        request: SignedRequest,
+        _timeout: Option<Duration>,
+    ) -> DispatchSignedRequestFuture {
+        let this = self.clone();
+        Box::pin(async move {
+            let body = match request.payload {
+                Some(SignedRequestPayload::Buffer(bytes)) => bytes.to_vec(),
+                Some(SignedRequestPayload::Stream(stream)) => {
+                    let mut body = Vec::new();
+                    stream
+                        .into_async_read()
+                        .read_to_end(&mut body)
+                        .await
+                        .map_err(|e| HttpDispatchError::new(e.to_string()))?;
+                    body
+                }
+                None => Vec::new(),
+            };
+            let param = |name: &str| request.params.get(name).cloned().flatten();
+            let upload_id = param("uploadId");
+            let part_number = param("partNumber");
+            let initiate = request.params.contains_key("uploads");
+
+            let mut state = this.state();
+            Ok(match (request.method.as_str(), upload_id) {
+                ("HEAD", None) | ("GET", None) => match state.objects.get(&request.path) {
+                    Some(data) => {
+                        let mut headers = HeaderMap::default();
+                        headers.insert(
+                            "last-modified",
+                            Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+                        );
+                        let data = if request.method == "GET" {
+                            data.clone()
+                        } else {
+                            Vec::new()
+                        };
+                        response(StatusCode::OK, headers, data)
+                    }
+                    None => empty_response(StatusCode::NOT_FOUND),
+                },
+                ("PUT", None) => {
+                    state.objects.insert(request.path.clone(), body);
+                    empty_response(StatusCode::OK)
+                }
+                ("POST", None) if initiate => {
+                    state.next_upload_id += 1;
+                    let upload_id = format!("upload{}", state.next_upload_id);
+                    state.uploads.insert(upload_id.clone(), BTreeMap::new());
+                    xml_response(format!(
+                        "<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                        upload_id
+                    ))
+                }
+                ("PUT", Some(upload_id)) => {
+                    let fail_parts = state.fail_parts;
+                    let part_number = part_number.and_then(|n| n.parse().ok());
+                    match (state.uploads.get_mut(&upload_id), part_number) {
+                        (Some(_), _) if fail_parts => {
+                            empty_response(StatusCode::INTERNAL_SERVER_ERROR)
+                        }
+                        (Some(parts), Some(part_number)) => {
+                            parts.insert(part_number, body);
+                            let mut headers = HeaderMap::default();
+                            headers.insert("etag", format!("\"part{}\"", part_number));
+                            response(StatusCode::OK, headers, Vec::new())
+                        }
+                        _ => empty_response(StatusCode::NOT_FOUND),
+                    }
+                }
+                // The parts listed in the request are assumed to be all the parts uploaded.
+                ("POST", Some(upload_id)) => match state.uploads.remove(&upload_id) {
+                    Some(parts) => {
+                        let data = parts.into_iter().flat_map(|(_, part)| part).collect();
+                        state.objects.insert(request.path.clone(), data);
+                        xml_response(
+                            "<CompleteMultipartUploadResult></CompleteMultipartUploadResult>"
+                                .to_string(),
+                        )
+                    }
+                    None => empty_response(StatusCode::NOT_FOUND),
+                },
+                ("DELETE", Some(upload_id)) => {
+                    state.uploads.remove(&upload_id);
+                    empty_response(StatusCode::NO_CONTENT)
+                }
+                _ => empty_response(StatusCode::BAD_REQUEST),
+            })
+        })
+    }
+}
+
+fn response(status: StatusCode, headers: HeaderMap<String>, body: Vec<u8>) -> HttpResponse {
+    HttpResponse {
+        status,
+        body: ByteStream::from(body),
+        headers,
+    }
+}
+
+fn empty_response(status: StatusCode) -> HttpResponse {
+    response(status, HeaderMap::default(), Vec::new())
+}
+
+fn xml_response(body: String) -> HttpResponse {
+    response(StatusCode::OK, HeaderMap::default(), body.into_bytes())
+}
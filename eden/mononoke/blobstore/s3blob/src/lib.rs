@@ -0,0 +1,478 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Blobstore backed by any storage service speaking the S3 API (AWS S3, MinIO, Ceph, ...).
+
+#![deny(warnings)]
+
+use std::fmt;
+
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use chrono::DateTime;
+use fbinit::FacebookInit;
+use rusoto_core::{request::BufferedHttpResponse, HttpClient, Region, RusotoError};
+use rusoto_credential::{ChainProvider, ProfileProvider};
+use rusoto_s3::{
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest, CompletedMultipartUpload,
+    CompletedPart, CreateMultipartUploadRequest, GetObjectError, GetObjectRequest, HeadObjectError,
+    HeadObjectRequest, PutObjectRequest, S3Client, UploadPartRequest, S3,
+};
+use slog::{debug, Logger};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use blobstore::{
+    Blobstore, BlobstoreBytes, BlobstoreGetData, BlobstoreIsPresent, BlobstoreMetadata,
+    BlobstorePutOps, OverwriteStatus, PutBehaviour,
+};
+use context::CoreContext;
+
+#[cfg(test)]
+mod fake_s3;
+
+/// Blobs larger than this are uploaded in parts.
+const MULTIPART_THRESHOLD: usize = 32 * 1024 * 1024;
+/// Size of each part of a multipart upload. S3 requires all parts but the last to be at least
+/// 5MiB.
+const MULTIPART_PART_SIZE: usize = 16 * 1024 * 1024;
+
+pub struct S3Blob {
+    client: S3Client,
+    bucket: String,
+    put_behaviour: PutBehaviour,
+    semaphore: Option<Semaphore>,
+}
+
+impl S3Blob {
+    /// Connect to `bucket` on the S3 endpoint at `endpoint`. The endpoint defaults to HTTPS if it
+    /// doesn't specify a scheme. Credentials are read from the profile named `keychain_group` in
+    /// the shared AWS credentials file, or from the default AWS credential chain (environment,
+    /// credentials file, instance metadata) if it is empty.
+    pub async fn new(
+        _fb: FacebookInit,
+        bucket: String,
+        keychain_group: String,
+        region_name: String,
+        endpoint: String,
+        put_behaviour: PutBehaviour,
+        logger: &Logger,
+        num_concurrent_operations: Option<usize>,
+    ) -> Result<Self> {
+        let endpoint = if endpoint.contains("://") {
+            endpoint
+        } else {
+            format!("https://{}", endpoint)
+        };
+        debug!(
+            logger,
+            "Connecting to S3 bucket {} at {} ({})", bucket, endpoint, region_name
+        );
+
+        let region = Region::Custom {
+            name: region_name,
+            endpoint,
+        };
+        let dispatcher = HttpClient::new().context("Failed to create S3 HTTP client")?;
+        let client = if keychain_group.is_empty() {
+            S3Client::new_with(dispatcher, ChainProvider::new(), region)
+        } else {
+            let mut provider =
+                ProfileProvider::new().context("Failed to load AWS credentials profiles")?;
+            provider.set_profile(keychain_group);
+            S3Client::new_with(dispatcher, provider, region)
+        };
+
+        Ok(Self::from_client(
+            client,
+            bucket,
+            put_behaviour,
+            num_concurrent_operations,
+        ))
+    }
+
+    pub fn from_client(
+        client: S3Client,
+        bucket: String,
+        put_behaviour: PutBehaviour,
+        num_concurrent_operations: Option<usize>,
+    ) -> Self {
+        Self {
+            client,
+            bucket,
+            put_behaviour,
+            semaphore: num_concurrent_operations.map(Semaphore::new),
+        }
+    }
+
+    async fn permit(&self) -> Result<Option<SemaphorePermit<'_>>> {
+        match &self.semaphore {
+            Some(semaphore) => Ok(Some(semaphore.acquire().await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn head(&self, key: &str) -> Result<bool> {
+        let _permit = self.permit().await?;
+        let request = HeadObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        match self.client.head_object(request).await {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(false),
+            Err(RusotoError::Unknown(ref response)) if is_not_found(response) => Ok(false),
+            Err(e) => Err(Error::from(e).context(format!("Failed to check S3 key {}", key))),
+        }
+    }
+
+    async fn upload(&self, key: String, value: BlobstoreBytes) -> Result<()> {
+        if value.len() > MULTIPART_THRESHOLD {
+            return self.upload_multipart(key, value).await;
+        }
+
+        let _permit = self.permit().await?;
+        let len = value.len();
+        let request = PutObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.clone(),
+            content_length: Some(len as i64),
+            body: Some(value.into_bytes().to_vec().into()),
+            ..Default::default()
+        };
+
+        self.client
+            .put_object(request)
+            .await
+            .with_context(|| format!("Failed to put S3 key {}", key))?;
+        Ok(())
+    }
+
+    async fn upload_multipart(&self, key: String, value: BlobstoreBytes) -> Result<()> {
+        let _permit = self.permit().await?;
+        let upload = self
+            .client
+            .create_multipart_upload(CreateMultipartUploadRequest {
+                bucket: self.bucket.clone(),
+                key: key.clone(),
+                ..Default::default()
+            })
+            .await
+            .with_context(|| format!("Failed to start multipart upload of S3 key {}", key))?;
+        let upload_id = upload
+            .upload_id
+            .with_context(|| format!("No upload id for multipart upload of S3 key {}", key))?;
+
+        let res = self.upload_parts(&key, &upload_id, value).await;
+        if res.is_err() {
+            // Don't leave the parts that were uploaded around. The original error is more
+            // interesting than a failure to abort, so ignore the latter.
+            let _ = self
+                .client
+                .abort_multipart_upload(AbortMultipartUploadRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.clone(),
+                    upload_id,
+                    ..Default::default()
+                })
+                .await;
+        }
+        res
+    }
+
+    async fn upload_parts(&self, key: &str, upload_id: &str, value: BlobstoreBytes) -> Result<()> {
+        let bytes = value.into_bytes();
+        let mut parts = Vec::new();
+
+        for (idx, chunk) in bytes.chunks(MULTIPART_PART_SIZE).enumerate() {
+            // Part numbers are 1-based.
+            let part_number = idx as i64 + 1;
+            let part = self
+                .client
+                .upload_part(UploadPartRequest {
+                    bucket: self.bucket.clone(),
+                    key: key.to_string(),
+                    upload_id: upload_id.to_string(),
+                    part_number,
+                    content_length: Some(chunk.len() as i64),
+                    body: Some(chunk.to_vec().into()),
+                    ..Default::default()
+                })
+                .await
+                .with_context(|| {
+                    format!("Failed to upload part {} of S3 key {}", part_number, key)
+                })?;
+
+            parts.push(CompletedPart {
+                e_tag: part.e_tag,
+                part_number: Some(part_number),
+            });
+        }
+
+        self.client
+            .complete_multipart_upload(CompleteMultipartUploadRequest {
+                bucket: self.bucket.clone(),
+                key: key.to_string(),
+                upload_id: upload_id.to_string(),
+                multipart_upload: Some(CompletedMultipartUpload { parts: Some(parts) }),
+                ..Default::default()
+            })
+            .await
+            .with_context(|| format!("Failed to complete multipart upload of S3 key {}", key))?;
+        Ok(())
+    }
+}
+
+fn is_not_found(response: &BufferedHttpResponse) -> bool {
+    response.status.as_u16() == 404
+}
+
+fn parse_ctime(last_modified: Option<&str>) -> Option<i64> {
+    DateTime::parse_from_rfc2822(last_modified?)
+        .ok()
+        .map(|t| t.timestamp())
+}
+
+impl fmt::Display for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S3Blob")
+    }
+}
+
+impl fmt::Debug for S3Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Blob")
+            .field("bucket", &self.bucket)
+            .field("put_behaviour", &self.put_behaviour)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Blobstore for S3Blob {
+    async fn get<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let _permit = self.permit().await?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            key: key.to_string(),
+            ..Default::default()
+        };
+
+        let output = match self.client.get_object(request).await {
+            Ok(output) => output,
+            Err(RusotoError::Service(GetObjectError::NoSuchKey(_))) => return Ok(None),
+            Err(RusotoError::Unknown(ref response)) if is_not_found(response) => return Ok(None),
+            Err(e) => {
+                return Err(Error::from(e).context(format!("Failed to get S3 key {}", key)));
+            }
+        };
+
+        let mut data = Vec::new();
+        if let Some(body) = output.body {
+            body.into_async_read()
+                .read_to_end(&mut data)
+                .await
+                .with_context(|| format!("Failed to read S3 key {}", key))?;
+        }
+
+        Ok(Some(BlobstoreGetData::new(
+            BlobstoreMetadata::new(parse_ctime(output.last_modified.as_deref()), None),
+            BlobstoreBytes::from_bytes(data),
+        )))
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        Ok(if self.head(key).await? {
+            BlobstoreIsPresent::Present
+        } else {
+            BlobstoreIsPresent::Absent
+        })
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for S3Blob {
+    async fn put_explicit<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        // S3 has no conditional puts, so checking for presence first is racy: two concurrent
+        // writers may both see the key as absent. Blobs are content addressed, so in practice
+        // both would write the same data.
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => OverwriteStatus::NotChecked,
+            PutBehaviour::IfAbsent | PutBehaviour::OverwriteAndLog => {
+                if !self.head(&key).await? {
+                    OverwriteStatus::New
+                } else if put_behaviour.should_overwrite() {
+                    OverwriteStatus::Overwrote
+                } else {
+                    return Ok(OverwriteStatus::Prevented);
+                }
+            }
+        };
+
+        self.upload(key, value).await?;
+        Ok(status)
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.put_explicit(ctx, key, value, self.put_behaviour).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use bytes::Bytes;
+
+    use crate::fake_s3::FakeS3;
+
+    fn test_blobstore(s3: &FakeS3, put_behaviour: PutBehaviour) -> S3Blob {
+        S3Blob::from_client(s3.client(), "bucket".to_string(), put_behaviour, Some(4))
+    }
+
+    fn multipart_value() -> BlobstoreBytes {
+        let data: Vec<u8> = (0..MULTIPART_THRESHOLD + MULTIPART_PART_SIZE / 2)
+            .map(|i| i as u8)
+            .collect();
+        BlobstoreBytes::from_bytes(data)
+    }
+
+    #[test]
+    fn test_parse_ctime() {
+        assert_eq!(
+            parse_ctime(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(1445412480)
+        );
+        assert_eq!(parse_ctime(Some("yesterday")), None);
+        assert_eq!(parse_ctime(None), None);
+    }
+
+    #[fbinit::test]
+    async fn test_roundtrip(fb: FacebookInit) -> Result<()> {
+        let s3 = FakeS3::default();
+        let blob = test_blobstore(&s3, PutBehaviour::IfAbsent);
+        let ctx = CoreContext::test_mock(fb);
+        let key = "key".to_string();
+        let value = BlobstoreBytes::from_bytes(Bytes::from_static(b"value"));
+
+        assert!(blob.get(&ctx, &key).await?.is_none());
+        assert!(!blob.is_present(&ctx, &key).await?.fail_if_unsure()?);
+
+        let status = blob
+            .put_with_status(&ctx, key.clone(), value.clone())
+            .await?;
+        assert_eq!(status, OverwriteStatus::New);
+        assert!(blob.is_present(&ctx, &key).await?.fail_if_unsure()?);
+
+        let fetched = blob.get(&ctx, &key).await?.expect("blob is missing");
+        assert!(fetched.as_meta().ctime().is_some());
+        assert_eq!(fetched.into_bytes(), value);
+
+        let other = BlobstoreBytes::from_bytes(Bytes::from_static(b"other"));
+        let status = blob.put_with_status(&ctx, key.clone(), other).await?;
+        assert_eq!(status, OverwriteStatus::Prevented);
+        assert_eq!(
+            blob.get(&ctx, &key).await?.map(|data| data.into_bytes()),
+            Some(value)
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_overwrite(fb: FacebookInit) -> Result<()> {
+        let s3 = FakeS3::default();
+        let blob = test_blobstore(&s3, PutBehaviour::OverwriteAndLog);
+        let ctx = CoreContext::test_mock(fb);
+        let key = "key".to_string();
+        let value = BlobstoreBytes::from_bytes(Bytes::from_static(b"value"));
+        let other = BlobstoreBytes::from_bytes(Bytes::from_static(b"other"));
+
+        let status = blob.put_with_status(&ctx, key.clone(), value).await?;
+        assert_eq!(status, OverwriteStatus::New);
+        let status = blob
+            .put_with_status(&ctx, key.clone(), other.clone())
+            .await?;
+        assert_eq!(status, OverwriteStatus::Overwrote);
+        assert_eq!(
+            blob.get(&ctx, &key).await?.map(|data| data.into_bytes()),
+            Some(other)
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_multipart(fb: FacebookInit) -> Result<()> {
+        let s3 = FakeS3::default();
+        let blob = test_blobstore(&s3, PutBehaviour::Overwrite);
+        let ctx = CoreContext::test_mock(fb);
+        let key = "key".to_string();
+        let value = multipart_value();
+
+        let status = blob
+            .put_with_status(&ctx, key.clone(), value.clone())
+            .await?;
+        assert_eq!(status, OverwriteStatus::NotChecked);
+        assert_eq!(
+            blob.get(&ctx, &key).await?.map(|data| data.into_bytes()),
+            Some(value)
+        );
+        assert!(s3.state().uploads.is_empty());
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_multipart_failure_aborts(fb: FacebookInit) -> Result<()> {
+        let s3 = FakeS3::default();
+        let blob = test_blobstore(&s3, PutBehaviour::Overwrite);
+        let ctx = CoreContext::test_mock(fb);
+        let key = "key".to_string();
+        s3.state().fail_parts = true;
+
+        let res = blob
+            .put_with_status(&ctx, key.clone(), multipart_value())
+            .await;
+        assert!(res.is_err());
+        assert!(s3.state().uploads.is_empty());
+        assert!(blob.get(&ctx, &key).await?.is_none());
+
+        Ok(())
+    }
+}
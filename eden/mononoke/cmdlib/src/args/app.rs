@@ -89,6 +89,7 @@ pub const NO_DEFAULT_SCUBA_DATASET_ARG: &str = "no-default-scuba-dataset";
 pub const WITH_DYNAMIC_OBSERVABILITY: &str = "with-dynamic-observability";
 
 pub const LOCAL_CONFIGERATOR_PATH_ARG: &str = "local-configerator-path";
+pub const ACL_FILE_ARG: &str = "acl-file";
 pub const WITH_TEST_MEGAREPO_CONFIGS_CLIENT: &str = "with-test-megarepo-configs-client";
 pub const CRYPTO_PATH_REGEX_ARG: &str = "crypto-path-regex";
 pub const DERIVE_REMOTELY: &str = "derive-remotely";
@@ -475,6 +476,13 @@ impl MononokeAppBuilder {
                     .long(LOCAL_CONFIGERATOR_PATH_ARG)
                     .takes_value(true)
                     .help("local path to fetch configerator configs from, instead of normal configerator"),
+            )
+            .arg(
+                Arg::with_name(ACL_FILE_ARG)
+                    .long(ACL_FILE_ARG)
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("TOML or JSON file with repo and tier ACLs and group definitions, reloaded when it changes"),
             );
         }
 
@@ -17,8 +17,10 @@ use std::time::Duration;
 use anyhow::{bail, format_err, Context, Error, Result};
 use cached_config::{ConfigHandle, ConfigStore};
 use clap::{ArgMatches, Values};
+use context::CoreContext;
 use derived_data_remote::RemoteDerivationOptions;
 use fbinit::FacebookInit;
+use futures::future;
 use maybe_owned::MaybeOwned;
 use megarepo_config::MononokeMegarepoConfigsOptions;
 use panichandler::{self, Fate};
@@ -26,7 +28,7 @@ use rendezvous::RendezVousOptions;
 use slog::{debug, o, Level, Logger, Never, SendSyncRefUnwindSafeDrain};
 use slog_glog_fmt::{kv_categorizer::FacebookCategorizer, kv_defaults::FacebookKV, GlogFormat};
 use slog_term::TermDecorator;
-use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::panic::{AssertUnwindSafe, RefUnwindSafe, UnwindSafe};
 use tokio::runtime::{Handle, Runtime};
 
 use blobstore_factory::{
@@ -36,6 +38,8 @@ use blobstore_factory::{
 use environment::{Caching, MononokeEnvironment};
 use metaconfig_types::PackFormat;
 use observability::{DynamicLevelDrain, ObservabilityContext};
+use permission_checker::{set_acl_provider, AclFileWatcher, FileAclProvider};
+use reloader::Reloader;
 use repo_factory::ReadOnlyStorage;
 use scuba_ext::MononokeScubaSampleBuilder;
 use slog_ext::make_tag_filter_drain;
@@ -49,8 +53,8 @@ use crate::log;
 use super::parse_config_spec_to_path;
 use super::{
     app::{
-        ArgType, MononokeAppData, BLOBSTORE_BYTES_MIN_THROTTLE_ARG, BLOBSTORE_PUT_BEHAVIOUR_ARG,
-        BLOBSTORE_SCRUB_ACTION_ARG, BLOBSTORE_SCRUB_GRACE_ARG,
+        ArgType, MononokeAppData, ACL_FILE_ARG, BLOBSTORE_BYTES_MIN_THROTTLE_ARG,
+        BLOBSTORE_PUT_BEHAVIOUR_ARG, BLOBSTORE_SCRUB_ACTION_ARG, BLOBSTORE_SCRUB_GRACE_ARG,
         BLOBSTORE_SCRUB_QUEUE_PEEK_BOUND_ARG, BLOBSTORE_SCRUB_WRITE_MOSTLY_MISSING_ARG,
         CACHELIB_ATTEMPT_ZSTD_ARG, CRYPTO_PATH_REGEX_ARG, DERIVE_REMOTELY, DERIVE_REMOTELY_TIER,
         DISABLE_TUNABLES, ENABLE_MCROUTER, GET_MEAN_DELAY_SECS_ARG, GET_STDDEV_DELAY_SECS_ARG,
@@ -78,6 +82,7 @@ const CRYPTO_PROJECT: &str = "SCM";
 
 const CONFIGERATOR_POLL_INTERVAL: Duration = Duration::from_secs(1);
 const CONFIGERATOR_REFRESH_TIMEOUT: Duration = Duration::from_secs(1);
+const ACL_FILE_POLL_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct MononokeMatches<'a> {
     matches: MaybeOwned<'a, ArgMatches<'a>>,
@@ -120,6 +125,9 @@ impl<'a> MononokeMatches<'a> {
         init_tunables(&matches, &config_store, logger.clone())
             .context("Failed to initialize tunables")?;
 
+        init_acl_provider(fb, &matches, &runtime, logger.clone())
+            .context("Failed to initialize ACL provider")?;
+
         let mysql_options =
             parse_mysql_options(&matches, &app_data).context("Failed to parse MySQL options")?;
         let blobstore_options = parse_blobstore_options(&matches, &app_data, &arg_types)
@@ -796,6 +804,31 @@ fn init_tunables<'a>(
     init_tunables_worker(logger, config_handle)
 }
 
+fn init_acl_provider<'a>(
+    fb: FacebookInit,
+    matches: &'a ArgMatches<'a>,
+    runtime: &Runtime,
+    logger: Logger,
+) -> Result<()> {
+    let path = match matches.value_of(ACL_FILE_ARG) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let ctx = CoreContext::new_with_logger(fb, logger);
+    let mut watcher = AclFileWatcher::new(path);
+    let reloader = runtime.block_on(Reloader::reload_periodically(
+        ctx,
+        || ACL_FILE_POLL_INTERVAL,
+        move || future::ready(watcher.load_if_modified()),
+    ))?;
+
+    // Loading from the reloader can't observe a half-updated state, so it is fine to share
+    // across a panic.
+    let reloader = AssertUnwindSafe(reloader);
+    set_acl_provider(FileAclProvider::from_fn(move || reloader.load_full()))
+}
+
 /// Initialize a new `Runtime` with thread number parsed from the CLI
 fn init_runtime(matches: &ArgMatches<'_>) -> Result<Runtime> {
     let core_threads = matches
@@ -10,6 +10,7 @@ use std::sync::Arc;
 
 use anyhow::{anyhow, Context, Result};
 use blobstore::Blobstore;
+use bonsai_git_mapping::BonsaiGitMapping;
 use bonsai_hg_mapping::BonsaiHgMapping;
 use cacheblob::MemWritesBlobstore;
 use context::CoreContext;
@@ -204,6 +205,10 @@ impl DerivationContext {
         self.manager.bonsai_hg_mapping()
     }
 
+    pub fn bonsai_git_mapping(&self) -> Result<&dyn BonsaiGitMapping> {
+        self.manager.bonsai_git_mapping()
+    }
+
     pub fn filenodes(&self) -> Result<&dyn Filenodes> {
         self.manager.filenodes()
     }
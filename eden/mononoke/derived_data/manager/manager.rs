@@ -8,6 +8,7 @@
 use anyhow::{Context, Result};
 use std::sync::Arc;
 
+use bonsai_git_mapping::BonsaiGitMapping;
 use bonsai_hg_mapping::BonsaiHgMapping;
 use cacheblob::LeaseOps;
 use changesets::Changesets;
@@ -42,6 +43,7 @@ pub struct DerivedDataManagerInner {
     repo_name: String,
     changesets: Arc<dyn Changesets>,
     bonsai_hg_mapping: Option<Arc<dyn BonsaiHgMapping>>,
+    bonsai_git_mapping: Option<Arc<dyn BonsaiGitMapping>>,
     filenodes: Option<Arc<dyn Filenodes>>,
     repo_blobstore: RepoBlobstore,
     lease: DerivedDataLease,
@@ -84,6 +86,7 @@ impl DerivedDataManager {
         repo_name: String,
         changesets: Arc<dyn Changesets>,
         bonsai_hg_mapping: Arc<dyn BonsaiHgMapping>,
+        bonsai_git_mapping: Arc<dyn BonsaiGitMapping>,
         filenodes: Arc<dyn Filenodes>,
         repo_blobstore: RepoBlobstore,
         lease: Arc<dyn LeaseOps>,
@@ -99,6 +102,7 @@ impl DerivedDataManager {
                 config,
                 changesets,
                 bonsai_hg_mapping: Some(bonsai_hg_mapping),
+                bonsai_git_mapping: Some(bonsai_git_mapping),
                 filenodes: Some(filenodes),
                 repo_blobstore,
                 lease,
@@ -206,6 +210,13 @@ impl DerivedDataManager {
             .context("Missing BonsaiHgMapping")
     }
 
+    pub fn bonsai_git_mapping(&self) -> Result<&dyn BonsaiGitMapping> {
+        self.inner
+            .bonsai_git_mapping
+            .as_deref()
+            .context("Missing BonsaiGitMapping")
+    }
+
     pub fn filenodes(&self) -> Result<&dyn Filenodes> {
         self.inner.filenodes.as_deref().context("Missing filenodes")
     }
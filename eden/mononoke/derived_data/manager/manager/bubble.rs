@@ -58,6 +58,7 @@ impl DerivedDataManager {
                                 .wrap_repo_blobstore(self.inner.repo_blobstore.clone()),
                             filenodes: None,
                             bonsai_hg_mapping: None,
+                            bonsai_git_mapping: None,
                             ..self.inner.as_ref().clone()
                         }),
                     },
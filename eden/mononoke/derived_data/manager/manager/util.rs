@@ -94,6 +94,7 @@ pub mod derived_data_service {
 
     use super::DerivedDataManager;
     use anyhow::Result;
+    use bonsai_git_mapping::BonsaiGitMapping;
     use bonsai_hg_mapping::BonsaiHgMapping;
     use cacheblob::LeaseOps;
     use changesets::Changesets;
@@ -132,6 +133,7 @@ pub mod derived_data_service {
             repo_name: String,
             changesets: Arc<dyn Changesets>,
             bonsai_hg_mapping: Arc<dyn BonsaiHgMapping>,
+            bonsai_git_mapping: Arc<dyn BonsaiGitMapping>,
             filenodes: Arc<dyn Filenodes>,
             repo_blobstore: RepoBlobstore,
             lease: Arc<dyn LeaseOps>,
@@ -144,6 +146,7 @@ pub mod derived_data_service {
                 repo_name,
                 changesets,
                 bonsai_hg_mapping,
+                bonsai_git_mapping,
                 filenodes,
                 repo_blobstore,
                 lease,
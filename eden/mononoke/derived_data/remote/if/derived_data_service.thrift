@@ -37,6 +37,7 @@ union DerivedData {
   8: DerivedDataDeletedManifest deleted_manifest;
   9: DerivedDataSkeletonManifest skeleton_manifest;
   10: DerivedDataTreeHandle tree_handle;
+  11: DerivedDataCommitHandle commit_handle;
 }
 
 union DerivedDataFsnode {
@@ -94,6 +95,10 @@ union DerivedDataTreeHandle {
   1: git_types_thrift.TreeHandle tree_handle;
 }
 
+union DerivedDataCommitHandle {
+  1: git_types_thrift.CommitHandle commit_handle;
+}
+
 struct DerivedDataTypeNotEnabled {
   1: string reason;
 } (rust.exhaustive)
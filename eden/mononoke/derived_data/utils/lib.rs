@@ -11,6 +11,7 @@ use anyhow::{anyhow, format_err, Error};
 use async_trait::async_trait;
 use blame::{BlameRoot, RootBlameV2};
 use blobrepo::BlobRepo;
+use bonsai_git_mapping::BonsaiGitMappingArc;
 use bonsai_hg_mapping::BonsaiHgMappingArc;
 use changeset_info::ChangesetInfo;
 use changesets::ChangesetsArc;
@@ -33,7 +34,7 @@ use futures::{
     Future, Stream, TryFutureExt, TryStreamExt,
 };
 use futures_stats::TimedTryFutureExt;
-use git_types::TreeHandle;
+use git_types::{CommitHandle, TreeHandle};
 use lazy_static::lazy_static;
 use lock_ext::LockExt;
 use mercurial_derived_data::MappedHgChangesetId;
@@ -64,6 +65,7 @@ pub const POSSIBLE_DERIVED_TYPES: &[&str] = &[
     FilenodesOnlyPublic::NAME,
     RootSkeletonManifestId::NAME,
     TreeHandle::NAME,
+    CommitHandle::NAME,
 ];
 
 lazy_static! {
@@ -78,6 +80,8 @@ lazy_static! {
         let deleted_mf = RootDeletedManifestId::NAME;
         let filenodes = FilenodesOnlyPublic::NAME;
         let skeleton_mf = RootSkeletonManifestId::NAME;
+        let git_trees = TreeHandle::NAME;
+        let git_commits = CommitHandle::NAME;
 
         let mut dag = HashMap::new();
 
@@ -90,6 +94,8 @@ lazy_static! {
         dag.insert(fsnodes, vec![]);
         dag.insert(deleted_mf, vec![unodes]);
         dag.insert(skeleton_mf, vec![]);
+        dag.insert(git_trees, vec![]);
+        dag.insert(git_commits, vec![git_trees]);
 
         dag
     };
@@ -214,6 +220,7 @@ impl<Derivable> DerivedUtilsFromManager<Derivable> {
             repo.name().clone(),
             repo.changesets_arc(),
             repo.bonsai_hg_mapping_arc(),
+            repo.bonsai_git_mapping_arc(),
             repo.filenodes_arc(),
             repo.repo_blobstore().clone(),
             lease,
@@ -434,6 +441,9 @@ fn derived_data_utils_impl(
         TreeHandle::NAME => Ok(Arc::new(DerivedUtilsFromManager::<TreeHandle>::new(
             repo, config,
         ))),
+        CommitHandle::NAME => Ok(Arc::new(DerivedUtilsFromManager::<CommitHandle>::new(
+            repo, config,
+        ))),
         name => Err(format_err!("Unsupported derived data type: {}", name)),
     }
 }
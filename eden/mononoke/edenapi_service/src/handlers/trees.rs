@@ -5,18 +5,21 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
+
 use anyhow::{Context, Error};
 use async_trait::async_trait;
 use bytes::Bytes;
 use context::PerfCounterType;
-use futures::{stream, Future, FutureExt, Stream, StreamExt, TryStreamExt};
+use futures::{stream, Future, FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
 use gotham::state::{FromState, State};
 use gotham_derive::{StateData, StaticResponseExtender};
 use serde::Deserialize;
 
 use edenapi_types::{
-    wire::WireTreeRequest, AnyId, Batch, EdenApiServerError, FileMetadata, TreeChildEntry,
-    TreeEntry, TreeRequest, UploadToken, UploadTreeRequest, UploadTreeResponse,
+    wire::WireTreeRequest, AnyId, Batch, DirectoryMetadata, EdenApiServerError, FileMetadata,
+    TreeAttributes, TreeChildEntry, TreeEntry, TreeRequest, UploadToken, UploadTreeRequest,
+    UploadTreeResponse,
 };
 use gotham_ext::{
     error::HttpError, middleware::scuba::ScubaMiddlewareState, response::TryIntoResponse,
@@ -30,7 +33,7 @@ use types::{Key, RepoPathBuf};
 use crate::context::ServerContext;
 use crate::errors::ErrorKind;
 use crate::middleware::RequestContext;
-use crate::utils::{custom_cbor_stream, get_repo, parse_wire_request};
+use crate::utils::{custom_cbor_stream, get_repo, parse_wire_request, to_mpath};
 
 use super::{EdenApiHandler, EdenApiMethod, HandlerInfo, HandlerResult};
 
@@ -60,7 +63,8 @@ pub async fn trees(state: &mut State) -> Result<impl TryIntoResponse, HttpError>
         .add_to_counter(PerfCounterType::EdenapiTrees, request.keys.len() as i64);
 
     // Sample trivial requests
-    if request.keys.len() == 1 && !request.attributes.child_metadata {
+    let attributes = &request.attributes;
+    if request.keys.len() == 1 && !attributes.child_metadata && !attributes.aux_data {
         ScubaMiddlewareState::try_set_sampling_rate(state, nonzero_ext::nonzero!(100_u64));
     }
 
@@ -77,17 +81,31 @@ fn fetch_all_trees(
 ) -> impl Stream<Item = Result<TreeEntry, EdenApiServerError>> {
     let ctx = repo.ctx().clone();
 
-    let fetch_metadata = request.attributes.child_metadata;
-    let fetches = request.keys.into_iter().map(move |key| {
-        fetch_tree(repo.clone(), key.clone(), fetch_metadata)
-            .map(|r| r.map_err(|e| EdenApiServerError::with_key(key, e)))
-    });
+    let attributes = request.attributes;
+    let keys = request.keys;
+    async move {
+        // Aux data is looked up for all of the keys at once.
+        let mut aux_data = HashMap::new();
+        if attributes.aux_data {
+            aux_data = fetch_all_tree_aux_data(&repo, &keys)
+                .await
+                .map_err(EdenApiServerError::new)?;
+        }
 
-    stream::iter(fetches)
-        .buffer_unordered(MAX_CONCURRENT_TREE_FETCHES_PER_REQUEST)
-        .inspect_ok(move |_| {
-            ctx.session().bump_load(Metric::TotalManifests, 1.0);
-        })
+        let fetches = keys.into_iter().map(move |key| {
+            let id = HgManifestId::from_node_hash(HgNodeHash::from(key.hgid));
+            let tree_aux_data = aux_data.get(&id).copied();
+            fetch_tree(repo.clone(), key.clone(), attributes, tree_aux_data)
+                .map(|r| r.map_err(|e| EdenApiServerError::with_key(key, e)))
+        });
+        Ok::<_, EdenApiServerError>(
+            stream::iter(fetches).buffer_unordered(MAX_CONCURRENT_TREE_FETCHES_PER_REQUEST),
+        )
+    }
+    .try_flatten_stream()
+    .inspect_ok(move |_| {
+        ctx.session().bump_load(Metric::TotalManifests, 1.0);
+    })
 }
 
 /// Fetch requested tree for a single key.
@@ -96,7 +114,8 @@ fn fetch_all_trees(
 async fn fetch_tree(
     repo: HgRepoContext,
     key: Key,
-    fetch_metadata: bool,
+    attributes: TreeAttributes,
+    tree_aux_data: Option<DirectoryMetadata>,
 ) -> Result<TreeEntry, Error> {
     let id = HgManifestId::from_node_hash(HgNodeHash::from(key.hgid));
 
@@ -114,7 +133,7 @@ async fn fetch_tree(
 
     let mut entry = TreeEntry::new(key.clone(), data, parents);
 
-    if fetch_metadata {
+    if attributes.child_metadata {
         let children: Vec<Result<TreeChildEntry, EdenApiServerError>> =
             fetch_child_metadata_entries(&repo, &ctx)
                 .await?
@@ -126,9 +145,46 @@ async fn fetch_tree(
         entry.with_children(Some(children));
     }
 
+    if attributes.aux_data {
+        entry.with_tree_aux_data(tree_aux_data);
+    }
+
     Ok(entry)
 }
 
+/// Fetch the digests and sizes of the requested trees, using the fsnodes
+/// already derived for their linknodes. Trees without a linknode, or whose
+/// linknode does not have fsnodes yet, have no aux data.
+async fn fetch_all_tree_aux_data(
+    repo: &HgRepoContext,
+    keys: &[Key],
+) -> Result<HashMap<HgManifestId, DirectoryMetadata>, Error> {
+    let trees = keys
+        .iter()
+        .map(|key| {
+            let id = HgManifestId::from_node_hash(HgNodeHash::from(key.hgid));
+            Ok((id, to_mpath(&key.path)?))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let summaries = repo.tree_fsnode_summaries(trees).await?;
+    Ok(summaries
+        .into_iter()
+        .map(|(id, (fsnode_id, summary))| {
+            let metadata = DirectoryMetadata {
+                fsnode_id: Some(fsnode_id.into()),
+                simple_format_sha1: Some(summary.simple_format_sha1.into()),
+                simple_format_sha256: Some(summary.simple_format_sha256.into()),
+                child_files_count: Some(summary.child_files_count),
+                child_files_total_size: Some(summary.child_files_total_size),
+                child_dirs_count: Some(summary.child_dirs_count),
+                descendant_files_count: Some(summary.descendant_files_count),
+                descendant_files_total_size: Some(summary.descendant_files_total_size),
+            };
+            (id, metadata)
+        })
+        .collect())
+}
+
 async fn fetch_child_metadata_entries<'a>(
     repo: &'a HgRepoContext,
     ctx: &'a HgTreeContext,
@@ -0,0 +1,120 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use crate::errors::ErrorKind;
+use crate::pktline::{Packet, PacketReader};
+
+/// A protocol v2 command request:
+///
+/// ```text
+/// command=<name>
+/// <capability>*
+/// delim-pkt
+/// <argument>*
+/// flush-pkt
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Command {
+    pub name: String,
+    pub capabilities: Vec<String>,
+    pub args: Vec<Vec<u8>>,
+}
+
+impl Command {
+    pub fn parse(data: &[u8]) -> Result<Self, ErrorKind> {
+        let mut packets = PacketReader::new(data);
+
+        let name = match packets.next().transpose()? {
+            Some(packet) => packet
+                .text()
+                .and_then(|line| line.strip_prefix(b"command="))
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .ok_or_else(|| ErrorKind::InvalidCommand("expected command".to_string()))?,
+            None => return Err(ErrorKind::InvalidCommand("empty request".to_string())),
+        };
+
+        let mut capabilities = Vec::new();
+        let mut args = Vec::new();
+        let mut in_args = false;
+
+        for packet in packets {
+            match packet? {
+                Packet::Delim if !in_args => in_args = true,
+                Packet::Flush => {
+                    return Ok(Self {
+                        name,
+                        capabilities,
+                        args,
+                    });
+                }
+                packet @ Packet::Data(_) => {
+                    let line = packet.text().expect("Data packets have text");
+                    if in_args {
+                        args.push(line.to_vec());
+                    } else {
+                        capabilities.push(String::from_utf8_lossy(line).into_owned());
+                    }
+                }
+                packet => {
+                    return Err(ErrorKind::InvalidCommand(format!(
+                        "unexpected {:?}",
+                        packet
+                    )));
+                }
+            }
+        }
+
+        Err(ErrorKind::InvalidCommand(
+            "request is not terminated".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pktline::{write_delim, write_flush, write_text};
+
+    #[test]
+    fn test_parse() {
+        let mut buf = Vec::new();
+        write_text(&mut buf, "command=fetch");
+        write_text(&mut buf, "agent=git/2.33.0");
+        write_text(&mut buf, "object-format=sha1");
+        write_delim(&mut buf);
+        write_text(&mut buf, "want 0000000000000000000000000000000000000000");
+        write_text(&mut buf, "done");
+        write_flush(&mut buf);
+
+        assert_eq!(
+            Command::parse(&buf).unwrap(),
+            Command {
+                name: "fetch".to_string(),
+                capabilities: vec![
+                    "agent=git/2.33.0".to_string(),
+                    "object-format=sha1".to_string()
+                ],
+                args: vec![
+                    b"want 0000000000000000000000000000000000000000".to_vec(),
+                    b"done".to_vec()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut buf = Vec::new();
+        write_text(&mut buf, "command=ls-refs");
+        assert!(Command::parse(&buf).is_err());
+
+        let mut buf = Vec::new();
+        write_text(&mut buf, "ls-refs");
+        write_flush(&mut buf);
+        assert!(Command::parse(&buf).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use gotham::state::{FromState, State};
+use gotham_derive::StateData;
+use gotham_ext::middleware::ClientIdentity;
+use permission_checker::{ArcPermissionChecker, MononokeIdentitySet};
+
+use blobrepo::BlobRepo;
+use context::CoreContext;
+use metaconfig_types::RepoConfig;
+use skiplist::SkiplistIndex;
+
+use crate::errors::GitServerContextErrorKind;
+use crate::middleware::{GitMethod, RequestContext};
+
+// Reading a repository over git requires the same permission as reading it over hg.
+const ACL_CHECK_ACTION: &str = "read";
+
+/// The repo attributes used to serve git clients.
+#[facet::container]
+#[derive(Clone)]
+pub struct GitRepo {
+    #[delegate()]
+    pub blob_repo: BlobRepo,
+
+    #[facet]
+    pub skiplist_index: SkiplistIndex,
+
+    #[facet]
+    pub repo_config: RepoConfig,
+}
+
+#[derive(Clone, StateData)]
+pub struct GitServerContext {
+    repositories: Arc<HashMap<String, (GitRepo, ArcPermissionChecker)>>,
+    will_exit: Arc<AtomicBool>,
+}
+
+impl GitServerContext {
+    pub fn new(
+        repositories: HashMap<String, (GitRepo, ArcPermissionChecker)>,
+        will_exit: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            repositories: Arc::new(repositories),
+            will_exit,
+        }
+    }
+
+    pub async fn request(
+        &self,
+        ctx: CoreContext,
+        repository: String,
+        identities: Option<&MononokeIdentitySet>,
+    ) -> Result<RepositoryRequestContext, GitServerContextErrorKind> {
+        let (repo, aclchecker) = self.repositories.get(&repository).ok_or(
+            GitServerContextErrorKind::RepositoryDoesNotExist(repository),
+        )?;
+
+        let empty = MononokeIdentitySet::new();
+        let allowed = aclchecker
+            .check_set(identities.unwrap_or(&empty), &[ACL_CHECK_ACTION])
+            .await
+            .map_err(GitServerContextErrorKind::PermissionCheckFailed)?;
+
+        if !allowed {
+            return Err(GitServerContextErrorKind::Forbidden);
+        }
+
+        Ok(RepositoryRequestContext {
+            ctx,
+            repo: repo.clone(),
+        })
+    }
+
+    pub fn will_exit(&self) -> bool {
+        self.will_exit.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct RepositoryRequestContext {
+    pub ctx: CoreContext,
+    pub repo: GitRepo,
+}
+
+impl RepositoryRequestContext {
+    pub async fn instantiate(
+        state: &mut State,
+        repository: String,
+        method: GitMethod,
+    ) -> Result<Self, GitServerContextErrorKind> {
+        let req_ctx = state.borrow_mut::<RequestContext>();
+        req_ctx.set_request(repository.clone(), method);
+
+        let ctx = req_ctx.ctx.clone();
+
+        let identities = if let Some(client_ident) = state.try_borrow::<ClientIdentity>() {
+            client_ident.identities().as_ref()
+        } else {
+            None
+        };
+
+        let git_ctx = GitServerContext::borrow_from(state);
+        git_ctx.request(ctx, repository, identities).await
+    }
+}
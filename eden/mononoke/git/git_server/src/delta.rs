@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Git's binary delta format. A delta starts with the sizes of the base and of the result, and
+//! is followed by instructions that either copy a range of the base, or insert literal data.
+
+use std::collections::HashMap;
+
+/// Size of the blocks of the base that are indexed. Matches shorter than this aren't found.
+const BLOCK_SIZE: usize = 16;
+
+/// Literal data is inserted at most this many bytes at a time.
+const MAX_INSERT: usize = 0x7f;
+
+/// Largest range that a single copy instruction can describe.
+const MAX_COPY: usize = 0xffffff;
+
+/// Compute a delta that turns `base` into `target`.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let mut index: HashMap<&[u8], usize> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE) {
+        index
+            .entry(&base[offset..offset + BLOCK_SIZE])
+            .or_insert(offset);
+    }
+
+    let mut pos = 0;
+    let mut insert_start = 0;
+    while pos + BLOCK_SIZE <= target.len() {
+        let base_offset = match index.get(&target[pos..pos + BLOCK_SIZE]) {
+            Some(base_offset) => *base_offset,
+            None => {
+                pos += 1;
+                continue;
+            }
+        };
+
+        let len = base[base_offset..]
+            .iter()
+            .zip(&target[pos..])
+            .take(MAX_COPY)
+            .take_while(|(b, t)| b == t)
+            .count();
+
+        write_insert(&mut delta, &target[insert_start..pos]);
+        write_copy(&mut delta, base_offset, len);
+        pos += len;
+        insert_start = pos;
+    }
+    write_insert(&mut delta, &target[insert_start..]);
+
+    delta
+}
+
+fn write_size(buf: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_insert(buf: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        buf.push(chunk.len() as u8);
+        buf.extend_from_slice(chunk);
+    }
+}
+
+/// A copy instruction has a header byte with the top bit set, followed by the non-zero bytes of
+/// the offset (4 bytes) and length (3 bytes). The low bits of the header say which are present.
+fn write_copy(buf: &mut Vec<u8>, offset: usize, len: usize) {
+    let header_pos = buf.len();
+    let mut header = 0x80u8;
+    buf.push(header);
+
+    for i in 0..4 {
+        let byte = ((offset >> (i * 8)) & 0xff) as u8;
+        if byte != 0 {
+            header |= 1 << i;
+            buf.push(byte);
+        }
+    }
+    for i in 0..3 {
+        let byte = ((len >> (i * 8)) & 0xff) as u8;
+        if byte != 0 {
+            header |= 1 << (4 + i);
+            buf.push(byte);
+        }
+    }
+
+    buf[header_pos] = header;
+}
+
+#[cfg(test)]
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    fn read_size(delta: &[u8], pos: &mut usize) -> usize {
+        let mut size = 0;
+        let mut shift = 0;
+        loop {
+            let byte = delta[*pos];
+            *pos += 1;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return size;
+            }
+        }
+    }
+
+    let mut pos = 0;
+    assert_eq!(read_size(delta, &mut pos), base.len());
+    let target_len = read_size(delta, &mut pos);
+
+    let mut target = Vec::with_capacity(target_len);
+    while pos < delta.len() {
+        let header = delta[pos];
+        pos += 1;
+        if header & 0x80 == 0 {
+            let len = header as usize;
+            target.extend_from_slice(&delta[pos..pos + len]);
+            pos += len;
+            continue;
+        }
+
+        let mut offset = 0;
+        let mut len = 0;
+        for i in 0..4 {
+            if header & (1 << i) != 0 {
+                offset |= (delta[pos] as usize) << (i * 8);
+                pos += 1;
+            }
+        }
+        for i in 0..3 {
+            if header & (1 << (4 + i)) != 0 {
+                len |= (delta[pos] as usize) << (i * 8);
+                pos += 1;
+            }
+        }
+        if len == 0 {
+            len = 0x10000;
+        }
+        target.extend_from_slice(&base[offset..offset + len]);
+    }
+
+    assert_eq!(target.len(), target_len);
+    target
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let base: Vec<u8> = (0..10000u32).flat_map(|i| i.to_le_bytes()).collect();
+
+        let mut target = base.clone();
+        target.splice(100..100, b"inserted".iter().copied());
+        target.drain(5000..5200);
+        target.extend_from_slice(&base[..300]);
+
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < target.len() / 10);
+        assert_eq!(apply_delta(&base, &delta), target);
+    }
+
+    #[test]
+    fn test_unrelated() {
+        let base = b"short base";
+        let target: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+
+        let delta = create_delta(base, &target);
+        assert_eq!(apply_delta(base, &delta), target);
+        assert_eq!(apply_delta(&target, &create_delta(&target, b"")), b"");
+    }
+}
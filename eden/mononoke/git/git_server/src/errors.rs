@@ -0,0 +1,54 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use thiserror::Error;
+
+use gotham_ext::error::HttpError;
+use mononoke_types::hash::GitSha1;
+
+#[derive(Debug, Error)]
+pub enum ErrorKind {
+    #[error("Client cancelled the request")]
+    ClientCancelled,
+    #[error("Only git protocol version 2 is supported")]
+    UnsupportedProtocolVersion,
+    #[error("Unsupported service: {0}")]
+    UnsupportedService(String),
+    #[error("Invalid pkt-line: {0}")]
+    InvalidPktLine(String),
+    #[error("Invalid command request: {0}")]
+    InvalidCommand(String),
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+    #[error("Invalid argument for {0}: {1}")]
+    InvalidArgument(&'static str, String),
+    #[error("Shallow fetches are not supported")]
+    ShallowNotSupported,
+    #[error("Not our ref: {0}")]
+    UnknownWant(GitSha1),
+}
+
+#[derive(Debug, Error)]
+pub enum GitServerContextErrorKind {
+    #[error("Operation not permitted")]
+    Forbidden,
+    #[error("Permission check failed: {0}")]
+    PermissionCheckFailed(anyhow::Error),
+    #[error("Repository does not exist: {0}")]
+    RepositoryDoesNotExist(String),
+}
+
+impl From<GitServerContextErrorKind> for HttpError {
+    fn from(e: GitServerContextErrorKind) -> HttpError {
+        use GitServerContextErrorKind::*;
+        match e {
+            Forbidden => HttpError::e403(e),
+            RepositoryDoesNotExist(_) => HttpError::e404(e),
+            PermissionCheckFailed(_) => HttpError::e500(e),
+        }
+    }
+}
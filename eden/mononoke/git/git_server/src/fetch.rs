@@ -0,0 +1,686 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use anyhow::Error;
+use bytes::Bytes;
+use futures::{
+    compat::Stream01CompatExt,
+    future::{self, try_join, try_join_all, Future, TryFutureExt},
+    stream::{self, BoxStream, StreamExt, TryStreamExt},
+};
+
+use blobrepo::BlobRepo;
+use blobstore::Loadable;
+use context::CoreContext;
+use derived_data::BonsaiDerived;
+use filestore::{Alias, FetchKey};
+use git_types::{BlobHandle, CommitHandle, ObjectKind, Tree, TreeHandle, TreeMember, Treeish};
+use mononoke_types::{hash::GitSha1, ChangesetId};
+use revset::DifferenceOfUnionsOfAncestorsNodeStream;
+
+use crate::context::RepositoryRequestContext;
+use crate::errors::ErrorKind;
+use crate::pack::{DeltaBase, PackWriter};
+use crate::pktline::{write_data, write_delim, write_flush, write_text, MAX_PKT_DATA_LEN};
+
+/// Sideband channel that carries the packfile.
+const SIDEBAND_PACK: u8 = 1;
+
+/// Sideband channel that carries fatal errors.
+const SIDEBAND_ERROR: u8 = 3;
+
+/// The largest part of the pack that fits in a single sideband packet.
+const MAX_SIDEBAND_DATA_LEN: usize = MAX_PKT_DATA_LEN - 1;
+
+/// How many changesets to prepare (derive and load) ahead of the one being walked.
+const CHANGESET_CONCURRENCY: usize = 100;
+
+/// How many objects to load ahead of the one being written to the pack.
+const OBJECT_CONCURRENCY: usize = 100;
+
+/// How many trees to load at once while listing the objects of a changeset.
+const TREE_CONCURRENCY: usize = 100;
+
+/// Objects larger than this are always sent whole, as computing deltas for them is expensive.
+const MAX_DELTA_OBJECT_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct FetchArgs {
+    wants: Vec<GitSha1>,
+    haves: Vec<GitSha1>,
+    done: bool,
+    thin_pack: bool,
+    ofs_delta: bool,
+}
+
+impl FetchArgs {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, ErrorKind> {
+        let mut res = Self::default();
+        for arg in args {
+            let arg = String::from_utf8_lossy(arg);
+            let (name, value) = match arg.split_once(' ') {
+                Some((name, value)) => (name, Some(value)),
+                None => (arg.as_ref(), None),
+            };
+
+            match (name, value) {
+                ("want", Some(oid)) => res.wants.push(parse_oid(oid)?),
+                ("have", Some(oid)) => res.haves.push(parse_oid(oid)?),
+                ("done", None) => res.done = true,
+                ("thin-pack", None) => res.thin_pack = true,
+                ("ofs-delta", None) => res.ofs_delta = true,
+                // Progress is never sent, and there are no tags.
+                ("no-progress", None) | ("include-tag", None) => {}
+                ("shallow", _)
+                | ("deepen", _)
+                | ("deepen-relative", _)
+                | ("deepen-since", _)
+                | ("deepen-not", _) => return Err(ErrorKind::ShallowNotSupported),
+                _ => return Err(ErrorKind::InvalidArgument("fetch", arg.to_string())),
+            }
+        }
+
+        if res.wants.is_empty() {
+            return Err(ErrorKind::InvalidArgument("fetch", "no wants".to_string()));
+        }
+
+        Ok(res)
+    }
+}
+
+fn parse_oid(oid: &str) -> Result<GitSha1, ErrorKind> {
+    GitSha1::from_str(oid).map_err(|_| ErrorKind::InvalidArgument("fetch", oid.to_string()))
+}
+
+/// Respond to a fetch. The negotiation always completes in a single round: the server ACKs the
+/// haves it knows about and sends a pack of everything reachable from the wants but not from
+/// those haves.
+///
+/// The objects to send are listed before responding, so that invalid requests are still
+/// reported as such. The pack itself is streamed as its objects are loaded. Failures past that
+/// point are reported to the client on the error sideband, and end the response.
+pub async fn fetch(
+    ctx: &RepositoryRequestContext,
+    args: &[Vec<u8>],
+) -> Result<BoxStream<'static, Result<Bytes, Error>>, Error> {
+    let args = FetchArgs::parse(args)?;
+    let repo = &ctx.repo.blob_repo;
+    let mapping = repo.bonsai_git_mapping();
+
+    let wants = try_join_all(args.wants.iter().map(|oid| async move {
+        mapping
+            .get_bonsai_from_git_sha1(&ctx.ctx, *oid)
+            .await?
+            .ok_or_else(|| Error::from(ErrorKind::UnknownWant(*oid)))
+    }))
+    .await?;
+
+    let common: Vec<(GitSha1, ChangesetId)> =
+        try_join_all(args.haves.iter().map(|oid| async move {
+            let cs_id = mapping.get_bonsai_from_git_sha1(&ctx.ctx, *oid).await?;
+            Ok::<_, Error>(cs_id.map(|cs_id| (*oid, cs_id)))
+        }))
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let mut preamble = Vec::new();
+    if !args.done {
+        write_text(&mut preamble, "acknowledgments");
+        if common.is_empty() {
+            write_text(&mut preamble, "NAK");
+        }
+        for (oid, _) in &common {
+            write_text(&mut preamble, format!("ACK {}", oid));
+        }
+        write_text(&mut preamble, "ready");
+        write_delim(&mut preamble);
+    }
+    write_text(&mut preamble, "packfile");
+
+    let excludes = common.into_iter().map(|(_, cs_id)| cs_id).collect();
+    let objects = list_objects(ctx, wants, excludes).await?;
+    let pack = stream_pack(ctx.clone(), objects, args.thin_pack, args.ofs_delta);
+
+    let mut flush = Vec::new();
+    write_flush(&mut flush);
+
+    Ok(stream::once(future::ready(Ok(Bytes::from(preamble))))
+        .chain(pack)
+        .chain(stream::once(future::ready(Ok(Bytes::from(flush)))))
+        .flat_map(|res| {
+            // Tell the client why the response ends early. The error is passed on, so that it
+            // ends the response and gets logged.
+            let items = match res {
+                Ok(data) => vec![Ok(data)],
+                Err(e) => vec![Ok(error_packet(&e)), Err(e)],
+            };
+            stream::iter(items)
+        })
+        .boxed())
+}
+
+/// Wrap part of the pack in sideband packets.
+fn sideband_packets(pack: &[u8]) -> Bytes {
+    let mut buf = Vec::with_capacity(pack.len() + pack.len() / MAX_SIDEBAND_DATA_LEN * 5 + 5);
+    for chunk in pack.chunks(MAX_SIDEBAND_DATA_LEN) {
+        let mut data = Vec::with_capacity(chunk.len() + 1);
+        data.push(SIDEBAND_PACK);
+        data.extend_from_slice(chunk);
+        write_data(&mut buf, &data);
+    }
+    Bytes::from(buf)
+}
+
+fn error_packet(e: &Error) -> Bytes {
+    let mut data = vec![SIDEBAND_ERROR];
+    data.extend_from_slice(format!("error: {:#}\n", e).as_bytes());
+    data.truncate(MAX_PKT_DATA_LEN);
+    let mut buf = Vec::new();
+    write_data(&mut buf, &data);
+    Bytes::from(buf)
+}
+
+/// An object to send, with the object to store it as a delta against, if any. Contents are only
+/// loaded when the object is written to the pack.
+enum PackObject {
+    Commit(CommitHandle),
+    Tree(TreeHandle, Option<TreeHandle>),
+    Blob(BlobHandle, Option<BlobHandle>),
+}
+
+struct LoadedObject {
+    kind: ObjectKind,
+    oid: GitSha1,
+    content: Bytes,
+    base: Option<(GitSha1, Vec<u8>)>,
+}
+
+/// A changeset to pack, with the git objects needed to do so.
+struct PreparedChangeset {
+    commit: CommitHandle,
+    tree: TreeHandle,
+    parent_trees: Vec<TreeHandle>,
+}
+
+async fn prepare_changeset(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    cs_id: ChangesetId,
+) -> Result<PreparedChangeset, Error> {
+    let (commit, tree, parents) = futures::try_join!(
+        CommitHandle::derive(ctx, repo, cs_id).map_err(Error::from),
+        TreeHandle::derive(ctx, repo, cs_id).map_err(Error::from),
+        repo.get_changeset_parents_by_bonsai(ctx.clone(), cs_id),
+    )?;
+
+    let parent_trees = try_join_all(
+        parents
+            .into_iter()
+            .map(|parent| TreeHandle::derive(ctx, repo, parent).map_err(Error::from)),
+    )
+    .await?;
+
+    Ok(PreparedChangeset {
+        commit,
+        tree,
+        parent_trees,
+    })
+}
+
+/// List the objects of the changesets that are ancestors of `wants` but not of `excludes`, in
+/// pack order. Changesets are listed oldest first, so that objects can be stored as deltas
+/// against their version in the parent commit.
+async fn list_objects(
+    ctx: &RepositoryRequestContext,
+    wants: Vec<ChangesetId>,
+    excludes: Vec<ChangesetId>,
+) -> Result<Vec<PackObject>, Error> {
+    let repo = &ctx.repo.blob_repo;
+
+    let mut changesets: Vec<ChangesetId> =
+        DifferenceOfUnionsOfAncestorsNodeStream::new_with_excludes(
+            ctx.ctx.clone(),
+            &repo.get_changeset_fetcher(),
+            ctx.repo.skiplist_index.clone(),
+            wants,
+            excludes,
+        )
+        .compat()
+        .try_collect()
+        .await?;
+    changesets.reverse();
+
+    let mut seen = HashSet::new();
+    let mut objects = Vec::new();
+    let mut prepared = stream::iter(changesets)
+        .map(|cs_id| prepare_changeset(&ctx.ctx, repo, cs_id))
+        .buffered(CHANGESET_CONCURRENCY);
+
+    while let Some(prepared) = prepared.try_next().await? {
+        if seen.insert(prepared.commit.oid().sha1()) {
+            objects.push(PackObject::Commit(prepared.commit));
+        }
+        list_tree_objects(
+            &ctx.ctx,
+            repo,
+            &mut seen,
+            &mut objects,
+            prepared.tree,
+            prepared.parent_trees,
+        )
+        .await?;
+    }
+
+    Ok(objects)
+}
+
+/// List a tree and everything it references, except for what is also in one of the parent trees
+/// at the same path: the client has those already, or they are in the pack. Trees are walked a
+/// level at a time, loading the trees of a level concurrently. Only handles are kept: the pack
+/// header needs the number of objects, and their contents are loaded again as the pack is
+/// streamed.
+async fn list_tree_objects(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    seen: &mut HashSet<GitSha1>,
+    objects: &mut Vec<PackObject>,
+    tree: TreeHandle,
+    parent_trees: Vec<TreeHandle>,
+) -> Result<(), Error> {
+    let blobstore = &repo.get_blobstore();
+    let mut level = vec![(tree, parent_trees)];
+
+    while !level.is_empty() {
+        let mut to_load = Vec::new();
+        for (tree, parent_trees) in level {
+            if parent_trees.iter().any(|p| p.oid() == tree.oid()) || !seen.insert(tree.oid().sha1())
+            {
+                continue;
+            }
+            objects.push(PackObject::Tree(tree, parent_trees.first().copied()));
+            to_load.push((tree, parent_trees));
+        }
+
+        let mut loaded = stream::iter(to_load)
+            .map(|(tree, parent_trees)| async move {
+                try_join(
+                    tree.load(ctx, blobstore).map_err(Error::from),
+                    try_join_all(parent_trees.iter().map(|p| p.load(ctx, blobstore)))
+                        .map_err(Error::from),
+                )
+                .await
+            })
+            .buffered(TREE_CONCURRENCY);
+
+        let mut next = Vec::new();
+        while let Some((tree, parent_trees)) = loaded.try_next().await? {
+            list_members(seen, objects, &mut next, &tree, &parent_trees);
+        }
+        level = next;
+    }
+
+    Ok(())
+}
+
+/// List the blobs of a loaded tree, and queue its subtrees, skipping the members that are the
+/// same in a parent tree.
+fn list_members(
+    seen: &mut HashSet<GitSha1>,
+    objects: &mut Vec<PackObject>,
+    queue: &mut Vec<(TreeHandle, Vec<TreeHandle>)>,
+    tree: &Tree,
+    parent_trees: &[Tree],
+) {
+    for (name, member) in tree.members() {
+        let parent_members: Vec<&TreeMember> = parent_trees
+            .iter()
+            .filter_map(|parent| parent.members().get(name))
+            .collect();
+        if parent_members.iter().any(|m| m.oid() == member.oid()) {
+            continue;
+        }
+
+        match member {
+            TreeMember::Tree(subtree) => {
+                let parent_subtrees = parent_members
+                    .into_iter()
+                    .filter_map(|m| match m {
+                        TreeMember::Tree(t) => Some(*t),
+                        TreeMember::Blob(_) => None,
+                    })
+                    .collect();
+                queue.push((*subtree, parent_subtrees));
+            }
+            TreeMember::Blob(blob) => {
+                if !seen.insert(blob.oid().sha1()) {
+                    continue;
+                }
+                let parent_blob = parent_members
+                    .into_iter()
+                    .find_map(|m| match m {
+                        TreeMember::Blob(b) => Some(*b),
+                        TreeMember::Tree(_) => None,
+                    })
+                    .filter(|parent| {
+                        blob.oid().size() <= MAX_DELTA_OBJECT_SIZE
+                            && parent.oid().size() <= MAX_DELTA_OBJECT_SIZE
+                    });
+                objects.push(PackObject::Blob(*blob, parent_blob));
+            }
+        }
+    }
+}
+
+/// Stream the pack of `objects`, loading them ahead of the one being written.
+fn stream_pack(
+    ctx: RepositoryRequestContext,
+    objects: Vec<PackObject>,
+    thin_pack: bool,
+    ofs_delta: bool,
+) -> BoxStream<'static, Result<Bytes, Error>> {
+    let writer = PackWriter::new(objects.len() as u32, ofs_delta);
+    let objects = stream::iter(objects)
+        .map(move |object| {
+            let ctx = ctx.clone();
+            async move { load_object(&ctx.ctx, &ctx.repo.blob_repo, object).await }
+        })
+        .buffered(OBJECT_CONCURRENCY)
+        .boxed();
+
+    stream::try_unfold(
+        (objects, Some(writer)),
+        move |(mut objects, writer)| async move {
+            let mut writer = match writer {
+                Some(writer) => writer,
+                None => return Ok(None),
+            };
+
+            // Send the pack in chunks of at least a packet.
+            while writer.pending_len() < MAX_SIDEBAND_DATA_LEN {
+                match objects.try_next().await? {
+                    Some(object) => write_object(&mut writer, object, thin_pack),
+                    None => return Ok(Some((sideband_packets(&writer.finish()), (objects, None)))),
+                }
+            }
+            let data = sideband_packets(&writer.take_output());
+            Ok(Some((data, (objects, Some(writer)))))
+        },
+    )
+    .boxed()
+}
+
+fn write_object(writer: &mut PackWriter, object: LoadedObject, thin_pack: bool) {
+    let base = delta_base(writer, object.base.as_ref(), thin_pack);
+    writer.add(object.kind, object.oid, &object.content, base);
+}
+
+async fn load_object(
+    ctx: &CoreContext,
+    repo: &BlobRepo,
+    object: PackObject,
+) -> Result<LoadedObject, Error> {
+    let blobstore = repo.get_blobstore();
+    match object {
+        PackObject::Commit(commit) => {
+            let loaded = commit.load(ctx, &blobstore).await?;
+            Ok(LoadedObject {
+                kind: ObjectKind::Commit,
+                oid: commit.oid().sha1(),
+                content: Bytes::copy_from_slice(loaded.object()),
+                base: None,
+            })
+        }
+        PackObject::Tree(tree, base) => {
+            let (content, base) = try_join(
+                load_tree(ctx, repo, tree),
+                load_base(base.map(|b| (b.oid().sha1(), b)), |b| {
+                    load_tree(ctx, repo, b)
+                }),
+            )
+            .await?;
+            Ok(LoadedObject {
+                kind: ObjectKind::Tree,
+                oid: tree.oid().sha1(),
+                content: Bytes::from(content),
+                base,
+            })
+        }
+        PackObject::Blob(blob, base) => {
+            let (content, base) = try_join(
+                fetch_blob(ctx, repo, blob),
+                load_base(base.map(|b| (b.oid().sha1(), b)), |b| async move {
+                    Ok(fetch_blob(ctx, repo, b).await?.to_vec())
+                }),
+            )
+            .await?;
+            Ok(LoadedObject {
+                kind: ObjectKind::Blob,
+                oid: blob.oid().sha1(),
+                content,
+                base,
+            })
+        }
+    }
+}
+
+/// Load the object to store another one as a delta against, if any.
+async fn load_base<H, F, Fut>(
+    base: Option<(GitSha1, H)>,
+    load: F,
+) -> Result<Option<(GitSha1, Vec<u8>)>, Error>
+where
+    F: FnOnce(H) -> Fut,
+    Fut: Future<Output = Result<Vec<u8>, Error>>,
+{
+    match base {
+        Some((oid, base)) => Ok(Some((oid, load(base).await?))),
+        None => Ok(None),
+    }
+}
+
+async fn load_tree(ctx: &CoreContext, repo: &BlobRepo, tree: TreeHandle) -> Result<Vec<u8>, Error> {
+    let tree = tree.load(ctx, &repo.get_blobstore()).await?;
+    Ok(serialize_tree(&tree))
+}
+
+fn serialize_tree(tree: &Tree) -> Vec<u8> {
+    let mut object = Vec::new();
+    tree.write_serialized_object(&mut object)
+        .expect("Writes to Vec cannot fail");
+    object
+}
+
+/// Pick how to store an object relative to `base`: as a delta against a copy in the pack, as a
+/// delta against a copy the client has if the client accepts thin packs, or not at all.
+fn delta_base<'a>(
+    pack: &PackWriter,
+    base: Option<&'a (GitSha1, Vec<u8>)>,
+    thin_pack: bool,
+) -> Option<DeltaBase<'a>> {
+    let (oid, object) = base?;
+    if pack.contains(oid) {
+        Some(DeltaBase::InPack(*oid, object))
+    } else if thin_pack {
+        Some(DeltaBase::External(*oid, object))
+    } else {
+        None
+    }
+}
+
+async fn fetch_blob(ctx: &CoreContext, repo: &BlobRepo, blob: BlobHandle) -> Result<Bytes, Error> {
+    let key = FetchKey::Aliased(Alias::GitSha1(blob.oid().sha1()));
+    filestore::fetch_concat(&repo.get_blobstore(), ctx, key).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::io::Write;
+
+    use fbinit::FacebookInit;
+    use git2::{Oid, Repository};
+    use tempdir::TempDir;
+
+    use crate::context::GitRepo;
+    use crate::ls_refs::ls_refs;
+    use crate::pktline::{Packet, PacketReader};
+
+    const OID: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+    #[test]
+    fn test_parse_args() {
+        let args = FetchArgs::parse(&[
+            format!("want {}", OID).into_bytes(),
+            format!("have {}", OID).into_bytes(),
+            b"thin-pack".to_vec(),
+            b"ofs-delta".to_vec(),
+            b"done".to_vec(),
+        ])
+        .unwrap();
+
+        let oid = GitSha1::from_str(OID).unwrap();
+        assert_eq!(
+            args,
+            FetchArgs {
+                wants: vec![oid],
+                haves: vec![oid],
+                done: true,
+                thin_pack: true,
+                ofs_delta: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_args_invalid() {
+        assert!(FetchArgs::parse(&[]).is_err());
+        assert!(FetchArgs::parse(&[b"want nothex".to_vec()]).is_err());
+        assert!(matches!(
+            FetchArgs::parse(&[format!("want {}", OID).into_bytes(), b"deepen 1".to_vec()]),
+            Err(ErrorKind::ShallowNotSupported)
+        ));
+    }
+
+    /// Read the refs a client would see, as `(oid, name)` pairs.
+    async fn list_refs(ctx: &RepositoryRequestContext) -> Result<Vec<(String, String)>, Error> {
+        let res = ls_refs(ctx, &[]).await?;
+        let mut refs = Vec::new();
+        for packet in PacketReader::new(&res) {
+            if let Some(text) = packet?.text() {
+                let text = String::from_utf8(text.to_vec())?;
+                let (oid, name) = text.split_once(' ').expect("invalid ref line");
+                refs.push((oid.to_string(), name.to_string()));
+            }
+        }
+        Ok(refs)
+    }
+
+    /// Fetch `want` and return the pack sent on the sideband.
+    async fn fetch_pack(
+        ctx: &RepositoryRequestContext,
+        want: &str,
+        ofs_delta: bool,
+    ) -> Result<Vec<u8>, Error> {
+        let mut args = vec![format!("want {}", want).into_bytes(), b"done".to_vec()];
+        if ofs_delta {
+            args.push(b"ofs-delta".to_vec());
+        }
+        let res: Vec<Bytes> = fetch(ctx, &args).await?.try_collect().await?;
+        let res = res.concat();
+
+        let mut packets = PacketReader::new(&res);
+        assert_eq!(
+            packets.next().transpose()?.and_then(|p| p.text()),
+            Some(b"packfile".as_ref())
+        );
+        let mut pack = Vec::new();
+        for packet in packets {
+            match packet? {
+                Packet::Data(data) => {
+                    assert_eq!(data[0], SIDEBAND_PACK);
+                    pack.extend_from_slice(&data[1..]);
+                }
+                Packet::Flush => break,
+                packet => panic!("unexpected packet {:?}", packet),
+            }
+        }
+        Ok(pack)
+    }
+
+    #[fbinit::test]
+    async fn test_ls_refs_and_fetch(fb: FacebookInit) -> Result<(), Error> {
+        let repo: GitRepo = test_repo_factory::build_empty()?;
+        fixtures::linear::initrepo(fb, &repo.blob_repo).await;
+        let ctx = RepositoryRequestContext {
+            ctx: CoreContext::test_mock(fb),
+            repo,
+        };
+
+        // Bookmarks are only listed once their git commits are derived.
+        assert_eq!(list_refs(&ctx).await?, vec![]);
+
+        // Clients that ask for it are told where HEAD will point.
+        let res = ls_refs(&ctx, &[b"symrefs".to_vec(), b"unborn".to_vec()]).await?;
+        let lines = PacketReader::new(&res)
+            .filter_map(|packet| packet.ok()?.text().map(<[u8]>::to_vec))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![b"unborn HEAD symref-target:refs/heads/master".to_vec()]
+        );
+
+        let master = ctx
+            .repo
+            .blob_repo
+            .get_bonsai_bookmark(ctx.ctx.clone(), &"master".try_into()?)
+            .await?
+            .expect("no master");
+        let commit = CommitHandle::derive(&ctx.ctx, &ctx.repo.blob_repo, master).await?;
+        let oid = commit.oid().sha1().to_string();
+
+        assert_eq!(
+            list_refs(&ctx).await?,
+            vec![
+                (oid.clone(), "HEAD".to_string()),
+                (oid.clone(), "refs/heads/master".to_string()),
+            ]
+        );
+
+        for ofs_delta in [true, false] {
+            let pack = fetch_pack(&ctx, &oid, ofs_delta).await?;
+
+            let tmp_dir = TempDir::new("git_server_test")?;
+            let git = Repository::init_bare(tmp_dir.path())?;
+            let odb = git.odb()?;
+            let mut writer = odb.packwriter()?;
+            writer.write_all(&pack)?;
+            writer.commit()?;
+
+            // The pack has the whole history, with every tree and blob in it.
+            let mut walk = git.revwalk()?;
+            walk.push(Oid::from_str(&oid)?)?;
+            let mut commits = 0;
+            for commit_id in walk {
+                let tree = git.find_commit(commit_id?)?.tree()?;
+                tree.walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+                    entry.to_object(&git).expect("object missing from the pack");
+                    git2::TreeWalkResult::Ok
+                })?;
+                commits += 1;
+            }
+            assert_eq!(commits, 11);
+        }
+
+        Ok(())
+    }
+}
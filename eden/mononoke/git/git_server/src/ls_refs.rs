@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use futures::stream::TryStreamExt;
+
+use git_types::CommitHandle;
+use repo_derived_data::RepoDerivedDataRef;
+use slog::warn;
+
+use crate::context::RepositoryRequestContext;
+use crate::errors::ErrorKind;
+use crate::pktline::{write_flush, write_text};
+
+#[derive(Debug, Default, Eq, PartialEq)]
+struct LsRefsArgs {
+    symrefs: bool,
+    unborn: bool,
+    prefixes: Vec<String>,
+}
+
+impl LsRefsArgs {
+    fn parse(args: &[Vec<u8>]) -> Result<Self, ErrorKind> {
+        let mut res = Self::default();
+        for arg in args {
+            let arg = String::from_utf8_lossy(arg);
+            match arg.as_ref() {
+                "symrefs" => res.symrefs = true,
+                "unborn" => res.unborn = true,
+                // There are no annotated tags to peel.
+                "peel" => {}
+                arg => match arg.strip_prefix("ref-prefix ") {
+                    Some(prefix) => res.prefixes.push(prefix.to_string()),
+                    None => return Err(ErrorKind::InvalidArgument("ls-refs", arg.to_string())),
+                },
+            }
+        }
+        Ok(res)
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|p| name.starts_with(p.as_str()))
+    }
+}
+
+fn bookmark_ref(bookmark: impl std::fmt::Display) -> String {
+    format!("refs/heads/{}", bookmark)
+}
+
+/// List the repository's refs: every publishing bookmark as a branch, and HEAD pointing to the
+/// repo's main bookmark, as configured for segmented changelog. Only bookmarks whose git commits
+/// are derived already are listed: deriving is left to the derivation service, rather than done
+/// on the read path. If HEAD's bookmark isn't listed, HEAD is only sent, as unborn, to clients
+/// that ask for it.
+pub async fn ls_refs(ctx: &RepositoryRequestContext, args: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+    let args = LsRefsArgs::parse(args)?;
+    let repo = &ctx.repo.blob_repo;
+
+    let bookmarks: Vec<_> = repo
+        .get_bonsai_publishing_bookmarks_maybe_stale(ctx.ctx.clone())
+        .try_collect()
+        .await?;
+
+    let cs_ids = bookmarks.iter().map(|(_, cs_id)| *cs_id).collect();
+    let commits = repo
+        .repo_derived_data()
+        .manager()
+        .fetch_derived_batch::<CommitHandle>(&ctx.ctx, cs_ids, None)
+        .await?;
+
+    let mut refs = BTreeMap::new();
+    for (bookmark, cs_id) in bookmarks {
+        match commits.get(&cs_id) {
+            Some(handle) => {
+                refs.insert(bookmark_ref(bookmark.name()), *handle);
+            }
+            None => warn!(
+                ctx.ctx.logger(),
+                "Not listing {}: git commit for {} is not derived yet",
+                bookmark.name(),
+                cs_id
+            ),
+        }
+    }
+
+    let head_target = bookmark_ref(
+        &ctx.repo
+            .repo_config
+            .segmented_changelog_config
+            .master_bookmark,
+    );
+
+    let mut buf = Vec::new();
+    if args.matches("HEAD") {
+        let oid = match refs.get(&head_target) {
+            Some(handle) => Some(handle.oid().to_string()),
+            None if args.unborn => Some("unborn".to_string()),
+            None => None,
+        };
+        if let Some(oid) = oid {
+            let mut line = format!("{} HEAD", oid);
+            if args.symrefs {
+                line.push_str(&format!(" symref-target:{}", head_target));
+            }
+            write_text(&mut buf, line);
+        }
+    }
+    for (name, handle) in refs {
+        if args.matches(&name) {
+            write_text(&mut buf, format!("{} {}", handle.oid(), name));
+        }
+    }
+    write_flush(&mut buf);
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        let args = LsRefsArgs::parse(&[
+            b"symrefs".to_vec(),
+            b"peel".to_vec(),
+            b"unborn".to_vec(),
+            b"ref-prefix HEAD".to_vec(),
+            b"ref-prefix refs/heads/".to_vec(),
+        ])
+        .unwrap();
+
+        assert!(args.symrefs);
+        assert!(args.unborn);
+        assert!(args.matches("HEAD"));
+        assert!(args.matches("refs/heads/master"));
+        assert!(!args.matches("refs/tags/v1"));
+
+        assert!(LsRefsArgs::parse(&[b"bogus".to_vec()]).is_err());
+        assert!(LsRefsArgs::default().matches("refs/tags/v1"));
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+
+use context::{CoreContext, SessionContainer};
+use fbinit::FacebookInit;
+use gotham::state::State;
+use gotham_derive::StateData;
+use gotham_ext::{middleware::Middleware, state_ext::StateExt};
+use hyper::{body::Body, Response};
+use scuba_ext::MononokeScubaSampleBuilder;
+use slog::{o, Logger};
+
+#[derive(Copy, Clone)]
+pub enum GitMethod {
+    InfoRefs,
+    LsRefs,
+    Fetch,
+}
+
+impl fmt::Display for GitMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::InfoRefs => "info_refs",
+            Self::LsRefs => "ls_refs",
+            Self::Fetch => "fetch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(StateData, Clone)]
+pub struct RequestContext {
+    pub ctx: CoreContext,
+    pub repository: Option<String>,
+    pub method: Option<GitMethod>,
+}
+
+impl RequestContext {
+    fn new(ctx: CoreContext) -> Self {
+        Self {
+            ctx,
+            repository: None,
+            method: None,
+        }
+    }
+
+    pub fn set_request(&mut self, repository: String, method: GitMethod) {
+        self.repository = Some(repository);
+        self.method = Some(method);
+    }
+
+    pub fn set_method(&mut self, method: GitMethod) {
+        self.method = Some(method);
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestContextMiddleware {
+    fb: FacebookInit,
+    logger: Logger,
+}
+
+impl RequestContextMiddleware {
+    pub fn new(fb: FacebookInit, logger: Logger) -> Self {
+        Self { fb, logger }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RequestContextMiddleware {
+    async fn inbound(&self, state: &mut State) -> Option<Response<Body>> {
+        let request_id = state.short_request_id();
+
+        let logger = self.logger.new(o!("request_id" => request_id.to_string()));
+        let session = SessionContainer::new_with_defaults(self.fb);
+        let ctx = session.new_context(logger, MononokeScubaSampleBuilder::with_discard());
+
+        state.put(RequestContext::new(ctx));
+
+        None
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Writer for version 2 packfiles: a header, a sequence of zlib-compressed objects (possibly
+//! stored as deltas), and a trailing SHA-1 of everything before it.
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use digest::Digest;
+use flate2::{write::ZlibEncoder, Compression};
+use git_types::ObjectKind;
+use mononoke_types::hash::GitSha1;
+use sha1::Sha1;
+
+use crate::delta::create_delta;
+
+const PACK_VERSION: u32 = 2;
+
+/// Deltas are only worth storing if they are substantially smaller than the object.
+const MAX_DELTA_RATIO: usize = 2;
+
+/// Git's default limit on the length of delta chains.
+const MAX_DELTA_DEPTH: usize = 50;
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// A delta base for an object being added to the pack.
+pub enum DeltaBase<'a> {
+    /// An object that was already added to this pack.
+    InPack(GitSha1, &'a [u8]),
+    /// An object the client already has. Deltas against those make the pack thin.
+    External(GitSha1, &'a [u8]),
+}
+
+struct Entry {
+    offset: usize,
+    depth: usize,
+}
+
+/// Writes a pack incrementally. The pack is produced in pieces, which callers collect with
+/// `take_output` as objects are added, so that it can be sent as it is built. Since the header
+/// holds the number of objects, that must be known up front, and every object must be added
+/// exactly once.
+pub struct PackWriter {
+    output: Vec<u8>,
+    sha1: Sha1,
+    offset: usize,
+    count: u32,
+    expected_count: u32,
+    ofs_delta: bool,
+    entries: HashMap<GitSha1, Entry>,
+}
+
+impl PackWriter {
+    /// Start a pack of `count` objects. Deltas against objects in the pack refer to their base
+    /// by offset if `ofs_delta` is set, which requires the client to have advertised the
+    /// `ofs-delta` capability, and by object id otherwise.
+    pub fn new(count: u32, ofs_delta: bool) -> Self {
+        let mut writer = Self {
+            output: Vec::new(),
+            sha1: Sha1::new(),
+            offset: 0,
+            count: 0,
+            expected_count: count,
+            ofs_delta,
+            entries: Default::default(),
+        };
+        writer.write(b"PACK");
+        writer.write(&PACK_VERSION.to_be_bytes());
+        writer.write(&count.to_be_bytes());
+        writer
+    }
+
+    pub fn contains(&self, oid: &GitSha1) -> bool {
+        self.entries.contains_key(oid)
+    }
+
+    /// How much of the pack was written since the output was last taken.
+    pub fn pending_len(&self) -> usize {
+        self.output.len()
+    }
+
+    /// Take the part of the pack written since the output was last taken.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Add an object to the pack, as a delta against `base` if that's worth it.
+    pub fn add(
+        &mut self,
+        kind: ObjectKind,
+        oid: GitSha1,
+        object: &[u8],
+        base: Option<DeltaBase<'_>>,
+    ) {
+        debug_assert!(!self.contains(&oid), "{} was added to the pack twice", oid);
+
+        let offset = self.offset;
+        let mut depth = 0;
+
+        let delta = base.and_then(|base| {
+            let (base_oid, base_object, base_offset) = match base {
+                DeltaBase::InPack(base_oid, base_object) => {
+                    let entry = self.entries.get(&base_oid)?;
+                    if entry.depth >= MAX_DELTA_DEPTH {
+                        return None;
+                    }
+                    depth = entry.depth + 1;
+                    (base_oid, base_object, Some(entry.offset))
+                }
+                DeltaBase::External(base_oid, base_object) => {
+                    depth = 1;
+                    (base_oid, base_object, None)
+                }
+            };
+
+            let delta = create_delta(base_object, object);
+            if delta.len() * MAX_DELTA_RATIO > object.len() {
+                return None;
+            }
+            Some((base_oid, base_offset, delta))
+        });
+
+        match delta {
+            Some((_, Some(base_offset), delta)) if self.ofs_delta => {
+                self.write_header(OBJ_OFS_DELTA, delta.len());
+                self.write(&encode_offset(offset - base_offset));
+                self.write_compressed(&delta);
+            }
+            Some((base_oid, _, delta)) => {
+                self.write_header(OBJ_REF_DELTA, delta.len());
+                self.write(base_oid.as_ref());
+                self.write_compressed(&delta);
+            }
+            None => {
+                depth = 0;
+                self.write_header(object_type(kind), object.len());
+                self.write_compressed(object);
+            }
+        }
+
+        self.count += 1;
+        self.entries.insert(oid, Entry { offset, depth });
+    }
+
+    /// Append the checksum, and return the rest of the pack.
+    pub fn finish(mut self) -> Vec<u8> {
+        debug_assert_eq!(
+            self.count, self.expected_count,
+            "The pack header has the wrong object count"
+        );
+
+        let hash: [u8; 20] = self.sha1.clone().result().into();
+        self.write(&hash);
+        self.output
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        self.sha1.input(data);
+        self.offset += data.len();
+        self.output.extend_from_slice(data);
+    }
+
+    /// The object header holds the type and the uncompressed size. The first byte has the type
+    /// in bits 4-6 and the low 4 bits of the size; further bytes hold 7 bits of size each.
+    fn write_header(&mut self, ty: u8, size: usize) {
+        let mut header = Vec::new();
+        let mut byte = (ty << 4) | (size & 0x0f) as u8;
+        let mut size = size >> 4;
+        while size != 0 {
+            header.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        header.push(byte);
+        self.write(&header);
+    }
+
+    fn write_compressed(&mut self, data: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(data)
+            .and_then(|_| encoder.finish())
+            .expect("Writes to Vec cannot fail");
+        self.write(&compressed);
+    }
+}
+
+fn object_type(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => OBJ_COMMIT,
+        ObjectKind::Tree => OBJ_TREE,
+        ObjectKind::Blob => OBJ_BLOB,
+    }
+}
+
+/// Offsets of delta bases are big-endian, 7 bits per byte, with one added to every byte but
+/// the last so that each length has a distinct range.
+fn encode_offset(mut offset: usize) -> Vec<u8> {
+    let mut bytes = vec![(offset & 0x7f) as u8];
+    offset >>= 7;
+    while offset != 0 {
+        offset -= 1;
+        bytes.push(0x80 | (offset & 0x7f) as u8);
+        offset >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::{ObjectType, Oid, Repository};
+    use tempdir::TempDir;
+
+    fn oid(kind: ObjectKind, object: &[u8]) -> GitSha1 {
+        kind.create_oid(object).sha1()
+    }
+
+    fn check_pack_readable_by_git(ofs_delta: bool) -> Result<(), anyhow::Error> {
+        let base: Vec<u8> = (0..2000u32)
+            .flat_map(|i| format!("line {}\n", i).into_bytes())
+            .collect();
+        let mut modified = base.clone();
+        modified.extend_from_slice(b"one more line\n");
+        let mut modified_again = modified.clone();
+        modified_again.drain(..100);
+
+        let objects = [
+            (ObjectKind::Blob, &base),
+            (ObjectKind::Blob, &modified),
+            (ObjectKind::Blob, &modified_again),
+        ];
+
+        let mut writer = PackWriter::new(objects.len() as u32, ofs_delta);
+        let mut previous: Option<(GitSha1, &[u8])> = None;
+        for (kind, object) in objects {
+            let id = oid(kind, object);
+            let base = previous.map(|(base_oid, base)| DeltaBase::InPack(base_oid, base));
+            writer.add(kind, id, object, base);
+            previous = Some((id, object.as_slice()));
+        }
+
+        // Everything but the first object was stored as a delta, of the negotiated type.
+        let depths: Vec<_> = objects
+            .iter()
+            .map(|(kind, object)| writer.entries[&oid(*kind, object)].depth)
+            .collect();
+        assert_eq!(depths, vec![0, 1, 2]);
+        let second = writer.entries[&oid(ObjectKind::Blob, &modified)].offset;
+
+        let pack = writer.finish();
+        assert_eq!(&pack[8..12], &3u32.to_be_bytes());
+        let expected_type = if ofs_delta {
+            OBJ_OFS_DELTA
+        } else {
+            OBJ_REF_DELTA
+        };
+        assert_eq!((pack[second] >> 4) & 0x7, expected_type);
+
+        let tmp_dir = TempDir::new("git_server_test")?;
+        let git = Repository::init_bare(tmp_dir.path())?;
+        let odb = git.odb()?;
+        let mut packwriter = odb.packwriter()?;
+        packwriter.write_all(&pack)?;
+        packwriter.commit()?;
+
+        for (_, object) in objects {
+            let id = Oid::from_bytes(oid(ObjectKind::Blob, object).as_ref())?;
+            let read = odb.read(id)?;
+            assert_eq!(read.kind(), ObjectType::Blob);
+            assert_eq!(read.data(), &object[..]);
+        }
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_with_ofs_deltas() -> Result<(), anyhow::Error> {
+        check_pack_readable_by_git(true)
+    }
+
+    #[test]
+    fn test_pack_with_ref_deltas() -> Result<(), anyhow::Error> {
+        check_pack_readable_by_git(false)
+    }
+
+    #[test]
+    fn test_encode_offset() {
+        assert_eq!(encode_offset(0x7f), vec![0x7f]);
+        assert_eq!(encode_offset(0x80), vec![0x80, 0x00]);
+    }
+}
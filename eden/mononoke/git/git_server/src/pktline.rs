@@ -0,0 +1,162 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Git's pkt-line framing. Every packet starts with its length (including the 4 bytes of the
+//! length itself) as 4 hex digits. Lengths below 4 are special packets that carry no data.
+
+use crate::errors::ErrorKind;
+
+/// The largest packet git accepts, including the length prefix.
+const MAX_PKT_LEN: usize = 65520;
+
+/// The largest amount of data that fits in a single packet.
+pub const MAX_PKT_DATA_LEN: usize = MAX_PKT_LEN - 4;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Packet<'a> {
+    /// `0000`: ends a message.
+    Flush,
+    /// `0001`: separates sections of a message.
+    Delim,
+    /// `0002`: ends a response in stateless connections.
+    ResponseEnd,
+    Data(&'a [u8]),
+}
+
+impl<'a> Packet<'a> {
+    /// The packet's data, without the trailing newline git adds to text packets.
+    pub fn text(&self) -> Option<&'a [u8]> {
+        match self {
+            Self::Data(data) => Some(data.strip_suffix(b"\n").unwrap_or(data)),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_flush(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(b"0000");
+}
+
+pub fn write_delim(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(b"0001");
+}
+
+/// Append a data packet. Panics if `data` doesn't fit in a single packet, as callers are
+/// expected to split large payloads themselves.
+pub fn write_data(buf: &mut Vec<u8>, data: &[u8]) {
+    assert!(
+        data.len() <= MAX_PKT_DATA_LEN,
+        "pkt-line data is too long: {}",
+        data.len()
+    );
+    buf.extend_from_slice(format!("{:04x}", data.len() + 4).as_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Append a text packet, terminated by a newline.
+pub fn write_text(buf: &mut Vec<u8>, text: impl AsRef<str>) {
+    let text = text.as_ref();
+    let mut data = Vec::with_capacity(text.len() + 1);
+    data.extend_from_slice(text.as_bytes());
+    data.push(b'\n');
+    write_data(buf, &data);
+}
+
+/// Iterates over the packets in a buffer.
+pub struct PacketReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for PacketReader<'a> {
+    type Item = Result<Packet<'a>, ErrorKind>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let res = read_packet(self.data);
+        match res {
+            Ok((packet, rest)) => {
+                self.data = rest;
+                Some(Ok(packet))
+            }
+            Err(e) => {
+                self.data = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+fn read_packet(data: &[u8]) -> Result<(Packet<'_>, &[u8]), ErrorKind> {
+    let len = data
+        .get(..4)
+        .and_then(|len| std::str::from_utf8(len).ok())
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| ErrorKind::InvalidPktLine("invalid length".to_string()))?;
+
+    let packet = match len {
+        0 => Packet::Flush,
+        1 => Packet::Delim,
+        2 => Packet::ResponseEnd,
+        3 => return Err(ErrorKind::InvalidPktLine("invalid length".to_string())),
+        len if len > MAX_PKT_LEN || len > data.len() => {
+            return Err(ErrorKind::InvalidPktLine(format!(
+                "truncated packet of {}",
+                len
+            )));
+        }
+        len => Packet::Data(&data[4..len]),
+    };
+
+    Ok((packet, &data[len.max(4)..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut buf = Vec::new();
+        write_text(&mut buf, "command=ls-refs");
+        write_delim(&mut buf);
+        write_data(&mut buf, b"\x01PACK");
+        write_flush(&mut buf);
+
+        assert_eq!(&buf[..4], b"0014");
+
+        let packets = PacketReader::new(&buf)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            packets,
+            vec![
+                Packet::Data(b"command=ls-refs\n"),
+                Packet::Delim,
+                Packet::Data(b"\x01PACK"),
+                Packet::Flush,
+            ]
+        );
+        assert_eq!(packets[0].text(), Some(&b"command=ls-refs"[..]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        for data in [&b"00"[..], b"zzzz", b"0003", b"0010short"] {
+            let res: Result<Vec<_>, _> = PacketReader::new(data).collect();
+            assert!(res.is_err(), "{:?} should not parse", data);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use gotham::state::State;
+
+use gotham_ext::middleware::{ClientIdentity, PostResponseInfo, ScubaHandler};
+use scuba_ext::MononokeScubaSampleBuilder;
+
+use crate::middleware::RequestContext;
+
+#[derive(Copy, Clone, Debug)]
+pub enum GitScubaKey {
+    Repo,
+    Method,
+    User,
+    Error,
+    ErrorCount,
+}
+
+impl AsRef<str> for GitScubaKey {
+    fn as_ref(&self) -> &'static str {
+        match self {
+            Self::Repo => "repo",
+            Self::Method => "git_method",
+            Self::User => "git_user",
+            Self::Error => "git_error",
+            Self::ErrorCount => "git_error_count",
+        }
+    }
+}
+
+impl Into<String> for GitScubaKey {
+    fn into(self) -> String {
+        self.as_ref().to_string()
+    }
+}
+
+#[derive(Clone)]
+pub struct GitScubaHandler {
+    request_context: Option<RequestContext>,
+    client_username: Option<String>,
+}
+
+impl ScubaHandler for GitScubaHandler {
+    fn from_state(state: &State) -> Self {
+        Self {
+            request_context: state.try_borrow::<RequestContext>().cloned(),
+            client_username: state
+                .try_borrow::<ClientIdentity>()
+                .and_then(|id| id.username())
+                .map(ToString::to_string),
+        }
+    }
+
+    fn populate_scuba(self, info: &PostResponseInfo, scuba: &mut MononokeScubaSampleBuilder) {
+        scuba.add_opt(GitScubaKey::User, self.client_username);
+
+        if let Some(ctx) = self.request_context {
+            scuba.add_opt(GitScubaKey::Repo, ctx.repository.clone());
+            scuba.add_opt(GitScubaKey::Method, ctx.method.map(|m| m.to_string()));
+            ctx.ctx.perf_counters().insert_perf_counters(scuba);
+        }
+
+        if let Some(err) = info.first_error() {
+            scuba.add(GitScubaKey::Error, format!("{:?}", err));
+        }
+
+        scuba.add(GitScubaKey::ErrorCount, info.error_count());
+    }
+}
@@ -0,0 +1,23 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use gotham::state::State;
+use gotham_ext::{error::ErrorFormatter, state_ext::StateExt};
+use mime::Mime;
+
+/// Git prints the body of failed requests, so errors are sent as plain text.
+pub struct GitErrorFormatter;
+
+impl ErrorFormatter for GitErrorFormatter {
+    type Body = String;
+
+    fn format(&self, error: &Error, state: &State) -> Result<(Self::Body, Mime), Error> {
+        let message = format!("{:#}\nRequest ID: {}\n", error, state.short_request_id());
+        Ok((message, mime::TEXT_PLAIN_UTF_8))
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use futures::FutureExt;
+use gotham::{
+    handler::HandlerFuture,
+    middleware::state::StateMiddleware,
+    pipeline::{new_pipeline, single::single_pipeline},
+    router::{
+        builder::{build_router as gotham_build_router, DefineSingleRoute, DrawRoutes},
+        Router,
+    },
+    state::{FromState, State},
+};
+use gotham_ext::response::build_response;
+use std::pin::Pin;
+
+use crate::context::GitServerContext;
+use crate::upload_pack;
+
+use super::error_formatter::GitErrorFormatter;
+
+// These methods are wrappers to go from async fn's to the implementations Gotham expects,
+// as well as creating HTTP responses using build_response().
+fn info_refs_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let res = upload_pack::info_refs(&mut state).await;
+        build_response(res, state, &GitErrorFormatter)
+    }
+    .boxed()
+}
+
+fn upload_pack_handler(mut state: State) -> Pin<Box<HandlerFuture>> {
+    async move {
+        let res = upload_pack::upload_pack(&mut state).await;
+        build_response(res, state, &GitErrorFormatter)
+    }
+    .boxed()
+}
+
+fn health_handler(state: State) -> (State, &'static str) {
+    let git_ctx = GitServerContext::borrow_from(&state);
+    let res = if git_ctx.will_exit() {
+        "EXITING"
+    } else {
+        "I_AM_ALIVE"
+    };
+    (state, res)
+}
+
+pub fn build_router(git_ctx: GitServerContext) -> Router {
+    let pipeline = new_pipeline().add(StateMiddleware::new(git_ctx)).build();
+
+    let (chain, pipelines) = single_pipeline(pipeline);
+
+    gotham_build_router(chain, pipelines, |route| {
+        route
+            .get("/:repository/info/refs")
+            .with_path_extractor::<upload_pack::RepositoryParams>()
+            .with_query_string_extractor::<upload_pack::InfoRefsQuery>()
+            .to(info_refs_handler);
+
+        route
+            .post("/:repository/git-upload-pack")
+            .with_path_extractor::<upload_pack::RepositoryParams>()
+            .to(upload_pack_handler);
+
+        route.get("/health_check").to(health_handler);
+    })
+}
@@ -0,0 +1,169 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::io::Read;
+
+use anyhow::{Context, Error};
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
+use gotham::state::{FromState, State};
+use gotham_derive::{StateData, StaticResponseExtender};
+use gotham_ext::{
+    body_ext::BodyExt,
+    error::HttpError,
+    response::{BytesBody, ResponseStream, ResponseTryStreamExt, StreamBody, TryIntoResponse},
+};
+use http::header::{HeaderMap, CONTENT_ENCODING};
+use hyper::Body;
+use mime::Mime;
+use serde::Deserialize;
+
+use crate::command::Command;
+use crate::context::RepositoryRequestContext;
+use crate::errors::ErrorKind;
+use crate::fetch::fetch;
+use crate::ls_refs::ls_refs;
+use crate::middleware::GitMethod;
+use crate::pktline::{write_flush, write_text};
+
+const SERVICE: &str = "git-upload-pack";
+const GIT_PROTOCOL_HEADER: &str = "Git-Protocol";
+const AGENT: &str = "mononoke-git-server";
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct RepositoryParams {
+    repository: String,
+}
+
+#[derive(Deserialize, StateData, StaticResponseExtender)]
+pub struct InfoRefsQuery {
+    service: Option<String>,
+}
+
+fn git_mime(kind: &str) -> Mime {
+    format!("application/x-{}-{}", SERVICE, kind)
+        .parse()
+        .expect("Invalid git mime type")
+}
+
+/// Clients request protocol v2 through a header. Version 0 and 1 aren't supported, so
+/// requests without it are rejected rather than being answered in a format the client would
+/// misinterpret.
+fn check_protocol_version(state: &State) -> Result<(), HttpError> {
+    let is_v2 = HeaderMap::try_borrow_from(state)
+        .and_then(|headers| headers.get(GIT_PROTOCOL_HEADER))
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.split(':').any(|p| p == "version=2"));
+
+    if is_v2 {
+        Ok(())
+    } else {
+        Err(HttpError::e400(ErrorKind::UnsupportedProtocolVersion))
+    }
+}
+
+fn capability_advertisement() -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_text(&mut buf, "version 2");
+    write_text(&mut buf, format!("agent={}", AGENT));
+    write_text(&mut buf, "ls-refs=unborn");
+    write_text(&mut buf, "fetch");
+    write_text(&mut buf, "object-format=sha1");
+    write_flush(&mut buf);
+    buf
+}
+
+/// `GET /:repository/info/refs?service=git-upload-pack`: the first request of every fetch.
+/// With protocol v2 it only advertises the server's capabilities.
+pub async fn info_refs(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let RepositoryParams { repository } = RepositoryParams::take_from(state);
+    let InfoRefsQuery { service } = InfoRefsQuery::take_from(state);
+
+    match service {
+        Some(service) if service == SERVICE => {}
+        Some(service) => return Err(HttpError::e403(ErrorKind::UnsupportedService(service))),
+        // Without a service, the client is using the dumb HTTP protocol.
+        None => return Err(HttpError::e400(ErrorKind::UnsupportedProtocolVersion)),
+    }
+    check_protocol_version(state)?;
+
+    RepositoryRequestContext::instantiate(state, repository, GitMethod::InfoRefs).await?;
+
+    Ok(BytesBody::new(
+        Bytes::from(capability_advertisement()),
+        git_mime("advertisement"),
+    ))
+}
+
+/// `POST /:repository/git-upload-pack`: runs a single protocol v2 command. Errors found before
+/// the response starts are reported with an HTTP error status. The response to a fetch is
+/// streamed, so later errors end it early instead.
+pub async fn upload_pack(state: &mut State) -> Result<impl TryIntoResponse, HttpError> {
+    let RepositoryParams { repository } = RepositoryParams::take_from(state);
+    check_protocol_version(state)?;
+
+    let body = read_body(state).await?;
+    let command = Command::parse(&body).map_err(HttpError::e400)?;
+    let method = match command.name.as_str() {
+        "ls-refs" => GitMethod::LsRefs,
+        "fetch" => GitMethod::Fetch,
+        _ => return Err(HttpError::e400(ErrorKind::UnknownCommand(command.name))),
+    };
+
+    let ctx = RepositoryRequestContext::instantiate(state, repository, method).await?;
+
+    let res = match method {
+        GitMethod::LsRefs => ls_refs(&ctx, &command.args)
+            .await
+            .map(|res| stream::once(future::ready(Ok(Bytes::from(res)))).boxed()),
+        GitMethod::Fetch => fetch(&ctx, &command.args).await,
+        GitMethod::InfoRefs => unreachable!(),
+    };
+
+    let res = res.map_err(|e| match e.downcast_ref::<ErrorKind>() {
+        Some(_) => HttpError::e400(e),
+        None => HttpError::e500(e),
+    })?;
+
+    Ok(StreamBody::new(
+        ResponseStream::new(res).end_on_err(),
+        git_mime("result"),
+    ))
+}
+
+/// Read the request body. Git compresses large requests, such as fetches with many haves.
+async fn read_body(state: &mut State) -> Result<Bytes, HttpError> {
+    let body = Body::take_from(state);
+    let headers = HeaderMap::try_borrow_from(state);
+
+    let gzip = headers
+        .and_then(|headers| headers.get(CONTENT_ENCODING))
+        .map_or(false, |encoding| encoding == "gzip");
+
+    let body = body
+        .try_concat_body_opt(headers)
+        .map_err(HttpError::e400)?
+        .await
+        .context(ErrorKind::ClientCancelled)
+        .map_err(HttpError::e400)?;
+
+    if !gzip {
+        return Ok(body);
+    }
+
+    let mut decoded = Vec::new();
+    GzDecoder::new(body.as_ref())
+        .read_to_end(&mut decoded)
+        .context("Invalid gzip request body")
+        .map_err(|e: Error| HttpError::e400(e))?;
+
+    Ok(Bytes::from(decoded))
+}
@@ -27,3 +27,16 @@ struct Tree {
   1: TreeHandle handle;
   2: map<mononoke_types_thrift.MPathElement, TreeMember> members;
 } (rust.exhaustive)
+
+struct CommitHandle {
+  1: mononoke_types_thrift.GitSha1 oid;
+  2: i64 size;
+} (rust.exhaustive)
+
+// A git commit object. The object is stored verbatim (without the
+// "commit <size>\0" header git hashes it with), so it can be served to git
+// clients as-is.
+struct Commit {
+  1: CommitHandle handle;
+  2: binary object;
+} (rust.exhaustive)
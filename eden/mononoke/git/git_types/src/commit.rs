@@ -0,0 +1,335 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Error;
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+use bonsai_git_mapping::{CONVERT_REVISION_EXTRA, HGGIT_SOURCE_EXTRA};
+use mononoke_types::{hash::RichGitSha1, BonsaiChangeset, DateTime};
+
+use crate::errors::ErrorKind;
+use crate::thrift;
+use crate::{ObjectKind, TreeHandle};
+
+/// Headers that git writes itself from the tree, parents and signatures. Extras with these names
+/// (hg-git records the original `committer` line as an extra, for instance) are skipped.
+const RESERVED_HEADERS: &[&str] = &["tree", "parent", "author", "committer"];
+
+/// Extras that only record where a commit was converted from. They don't describe the commit
+/// itself, so they don't become headers.
+const CONVERSION_EXTRAS: &[&str] = &[CONVERT_REVISION_EXTRA, HGGIT_SOURCE_EXTRA];
+
+#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+pub struct CommitHandle {
+    oid: RichGitSha1,
+}
+
+impl CommitHandle {
+    pub fn oid(&self) -> &RichGitSha1 {
+        &self.oid
+    }
+
+    pub fn blobstore_key(&self) -> String {
+        format!("git.commit.{}", self.oid)
+    }
+}
+
+impl TryFrom<thrift::CommitHandle> for CommitHandle {
+    type Error = Error;
+
+    fn try_from(t: thrift::CommitHandle) -> Result<Self, Error> {
+        let size = t.size.try_into()?;
+        let oid = RichGitSha1::from_bytes(&t.oid.0, ObjectKind::Commit.as_str(), size)?;
+        Ok(Self { oid })
+    }
+}
+
+impl Into<thrift::CommitHandle> for CommitHandle {
+    fn into(self) -> thrift::CommitHandle {
+        let size = self.oid.size();
+
+        thrift::CommitHandle {
+            oid: self.oid.into_thrift(),
+            size: size.try_into().expect("Commit size must fit in a i64"),
+        }
+    }
+}
+
+/// A git commit object, as it would be stored by git.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    handle: CommitHandle,
+    object: Vec<u8>,
+}
+
+impl Commit {
+    pub fn handle(&self) -> &CommitHandle {
+        &self.handle
+    }
+
+    /// The serialized commit object, without the header git prepends to it when hashing.
+    pub fn object(&self) -> &[u8] {
+        &self.object
+    }
+}
+
+impl TryFrom<thrift::Commit> for Commit {
+    type Error = Error;
+
+    fn try_from(t: thrift::Commit) -> Result<Self, Error> {
+        let handle: CommitHandle = t.handle.try_into()?;
+        if ObjectKind::Commit.create_oid(&t.object) != handle.oid {
+            return Err(ErrorKind::InvalidThrift.into());
+        }
+        Ok(Self {
+            handle,
+            object: t.object,
+        })
+    }
+}
+
+impl Into<thrift::Commit> for Commit {
+    fn into(self) -> thrift::Commit {
+        let Commit { handle, object } = self;
+
+        thrift::Commit {
+            handle: handle.into(),
+            object,
+        }
+    }
+}
+
+impl Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.object))
+    }
+}
+
+/// Contents of a git commit object. Converting a `CommitBuilder` into a `Commit` serializes and
+/// hashes it.
+#[derive(Debug, Clone)]
+pub struct CommitBuilder {
+    tree: TreeHandle,
+    parents: Vec<CommitHandle>,
+    author: Vec<u8>,
+    committer: Vec<u8>,
+    headers: Vec<(String, Vec<u8>)>,
+    message: Vec<u8>,
+}
+
+impl CommitBuilder {
+    /// Build the git commit for a bonsai changeset. `tree` is the changeset's git tree, and
+    /// `parents` the git commits of its parents, in the same order.
+    ///
+    /// The committer defaults to the author if the changeset doesn't record one. Extras become
+    /// additional headers, in key order, after the committer, except for those named after a
+    /// header git writes itself. Multi-line values are continued on
+    /// lines starting with a space, which is how git stores headers such as `gpgsig` and
+    /// `mergetag`.
+    pub fn from_bonsai(
+        bonsai: &BonsaiChangeset,
+        tree: TreeHandle,
+        parents: Vec<CommitHandle>,
+    ) -> Result<Self, Error> {
+        let author = signature(bonsai.author(), bonsai.author_date())?;
+        let committer = match (bonsai.committer(), bonsai.committer_date()) {
+            (Some(committer), Some(date)) => signature(committer, date)?,
+            (Some(committer), None) => signature(committer, bonsai.author_date())?,
+            (None, Some(date)) => signature(bonsai.author(), date)?,
+            (None, None) => author.clone(),
+        };
+
+        let headers = bonsai
+            .extra()
+            .filter(|(key, _)| !CONVERSION_EXTRAS.contains(key) && !RESERVED_HEADERS.contains(key))
+            .map(|(key, value)| {
+                if key.is_empty() || key.contains(&[' ', '\n'][..]) {
+                    return Err(ErrorKind::InvalidCommitHeader(key.to_string()).into());
+                }
+                Ok((key.to_string(), value.to_vec()))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self {
+            tree,
+            parents,
+            author,
+            committer,
+            headers,
+            message: bonsai.message().as_bytes().to_vec(),
+        })
+    }
+
+    fn write_serialized_object(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        writeln!(writer, "tree {}", self.tree.oid())?;
+        for parent in &self.parents {
+            writeln!(writer, "parent {}", parent.oid())?;
+        }
+        write_header(writer, "author", &self.author)?;
+        write_header(writer, "committer", &self.committer)?;
+        for (key, value) in &self.headers {
+            write_header(writer, key, value)?;
+        }
+        writer.write_all(b"\n")?;
+        writer.write_all(&self.message)
+    }
+}
+
+impl Into<Commit> for CommitBuilder {
+    fn into(self) -> Commit {
+        let mut object = Vec::new();
+        self.write_serialized_object(&mut object)
+            .expect("Writes to Vec cannot fail");
+
+        let oid = ObjectKind::Commit.create_oid(&object);
+
+        Commit {
+            handle: CommitHandle { oid },
+            object,
+        }
+    }
+}
+
+fn write_header(writer: &mut impl Write, key: &str, value: &[u8]) -> Result<(), io::Error> {
+    write!(writer, "{} ", key)?;
+    for (idx, line) in value.split(|b| *b == b'\n').enumerate() {
+        if idx > 0 {
+            writer.write_all(b"\n ")?;
+        }
+        writer.write_all(line)?;
+    }
+    writer.write_all(b"\n")
+}
+
+/// Format an author or committer line: `Name <email> <seconds> <+/-HHMM>`. Mercurial authors may
+/// not have an email address, in which case an empty one is used.
+fn signature(user: &str, date: &DateTime) -> Result<Vec<u8>, Error> {
+    if user.contains('\n') {
+        return Err(ErrorKind::InvalidCommitSignature(user.to_string()).into());
+    }
+
+    let user = if user.ends_with('>') && user.contains('<') {
+        user.to_string()
+    } else {
+        format!("{} <>", user)
+    };
+
+    // Bonsai stores the offset in seconds west of UTC, git in hours and minutes east of it.
+    let offset = -date.tz_offset_secs();
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+
+    Ok(format!(
+        "{} {} {}{:02}{:02}",
+        user,
+        date.timestamp_secs(),
+        sign,
+        offset / 3600,
+        (offset % 3600) / 60
+    )
+    .into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::{ObjectType, Oid, Repository};
+    use mononoke_types::BonsaiChangesetMut;
+    use sorted_vector_map::sorted_vector_map;
+    use tempdir::TempDir;
+
+    use crate::{Tree, TreeBuilder};
+
+    fn tree() -> TreeHandle {
+        let tree: Tree = TreeBuilder::default().into();
+        *tree.handle()
+    }
+
+    #[test]
+    fn test_signature() -> Result<(), Error> {
+        let date = DateTime::from_timestamp(1600000000, -3600)?;
+        assert_eq!(
+            signature("Alice <alice@example.com>", &date)?,
+            b"Alice <alice@example.com> 1600000000 +0100"
+        );
+
+        let date = DateTime::from_timestamp(1600000000, 5 * 3600 + 30 * 60)?;
+        assert_eq!(signature("bob", &date)?, b"bob <> 1600000000 -0530");
+
+        assert!(signature("eve\ncommitter mallory", &date).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_object() -> Result<(), Error> {
+        let bonsai = BonsaiChangesetMut {
+            parents: vec![],
+            author: "Alice <alice@example.com>".to_string(),
+            author_date: DateTime::from_timestamp(1600000000, 0)?,
+            committer: None,
+            committer_date: None,
+            message: "Initial commit\n".to_string(),
+            extra: sorted_vector_map! {
+                "convert_revision".to_string() => b"0000000000000000000000000000000000000000".to_vec(),
+                "gpgsig".to_string() => b"-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----".to_vec(),
+            },
+            file_changes: Default::default(),
+            is_snapshot: false,
+        }
+        .freeze()?;
+
+        let commit: Commit = CommitBuilder::from_bonsai(&bonsai, tree(), vec![])?.into();
+        let expected = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+             author Alice <alice@example.com> 1600000000 +0000\n\
+             committer Alice <alice@example.com> 1600000000 +0000\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n abc\n -----END PGP SIGNATURE-----\n\
+             \n\
+             Initial commit\n";
+        assert_eq!(String::from_utf8_lossy(commit.object()), expected);
+
+        // libgit2 must agree on the hash, and be able to parse the commit back.
+        let tmp_dir = TempDir::new("git_types_test")?;
+        let git = Repository::init(tmp_dir.path())?;
+        let oid = git.odb()?.write(ObjectType::Commit, commit.object())?;
+        assert_eq!(oid, Oid::from_bytes(commit.handle().oid().as_ref())?);
+
+        let git_commit = git.find_commit(oid)?;
+        assert_eq!(git_commit.author().email(), Some("alice@example.com"));
+        assert_eq!(git_commit.committer().when().seconds(), 1600000000);
+        assert_eq!(git_commit.message(), Some("Initial commit\n"));
+        assert_eq!(git_commit.parent_count(), 0);
+
+        tmp_dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserved_extra() -> Result<(), Error> {
+        let bonsai = BonsaiChangesetMut {
+            parents: vec![],
+            author: "alice".to_string(),
+            author_date: DateTime::from_timestamp(0, 0)?,
+            committer: None,
+            committer_date: None,
+            message: String::new(),
+            extra: sorted_vector_map! {
+                "committer".to_string() => b"bob <bob> 0 +0000".to_vec(),
+                "parent".to_string() => b"0000000000000000000000000000000000000000".to_vec(),
+            },
+            file_changes: Default::default(),
+            is_snapshot: false,
+        }
+        .freeze()?;
+
+        let builder = CommitBuilder::from_bonsai(&bonsai, tree(), vec![])?;
+        assert!(builder.parents.is_empty());
+        assert!(builder.headers.is_empty());
+        assert_eq!(builder.committer, builder.author);
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use context::CoreContext;
+use slog::warn;
+
+use blobstore::{Blobstore, Storable};
+use bonsai_git_mapping::{AddGitMappingErrorKind, BonsaiGitMappingEntry};
+use derived_data::impl_bonsai_derived_via_manager;
+use derived_data_manager::{dependencies, BonsaiDerivable, DerivationContext};
+use mononoke_types::{BonsaiChangeset, ChangesetId};
+
+use crate::{Commit, CommitBuilder, CommitHandle, TreeHandle};
+
+use derived_data_service_if::types as thrift;
+
+fn format_key(derivation_ctx: &DerivationContext, changeset_id: ChangesetId) -> String {
+    let root_prefix = "git.derived_commit.";
+    let key_prefix = derivation_ctx.mapping_key_prefix::<CommitHandle>();
+    format!("{}{}{}", root_prefix, key_prefix, changeset_id)
+}
+
+#[async_trait]
+impl BonsaiDerivable for CommitHandle {
+    const NAME: &'static str = "git_commits";
+
+    type Dependencies = dependencies![TreeHandle];
+
+    async fn derive_single(
+        ctx: &CoreContext,
+        derivation_ctx: &DerivationContext,
+        bonsai: BonsaiChangeset,
+        parents: Vec<Self>,
+    ) -> Result<Self> {
+        if bonsai.is_snapshot() {
+            bail!("Can't derive CommitHandle for snapshot")
+        }
+        let changeset_id = bonsai.get_changeset_id();
+        let tree = derivation_ctx
+            .derive_dependency::<TreeHandle>(ctx, changeset_id)
+            .await?;
+
+        let commit: Commit = CommitBuilder::from_bonsai(&bonsai, tree, parents)?.into();
+        commit.store(ctx, derivation_ctx.blobstore()).await
+    }
+
+    async fn store_mapping(
+        self,
+        ctx: &CoreContext,
+        derivation_ctx: &DerivationContext,
+        changeset_id: ChangesetId,
+    ) -> Result<()> {
+        // Like the bonsai-hg mapping for hg changesets, the bonsai-git mapping that git clients
+        // resolve commits with is recorded along with the derived data mapping. It goes first, so
+        // that a failure is retried by the next derivation.
+        add_git_mapping(ctx, derivation_ctx, changeset_id, &self).await?;
+        let key = format_key(derivation_ctx, changeset_id);
+        derivation_ctx.blobstore().put(ctx, key, self.into()).await
+    }
+
+    async fn fetch(
+        ctx: &CoreContext,
+        derivation_ctx: &DerivationContext,
+        changeset_id: ChangesetId,
+    ) -> Result<Option<Self>> {
+        let key = format_key(derivation_ctx, changeset_id);
+        Ok(derivation_ctx
+            .blobstore()
+            .get(ctx, &key)
+            .await?
+            .map(TryInto::try_into)
+            .transpose()?)
+    }
+
+    fn from_thrift(data: thrift::DerivedData) -> Result<Self> {
+        if let thrift::DerivedData::commit_handle(thrift::DerivedDataCommitHandle::commit_handle(
+            id,
+        )) = data
+        {
+            Self::try_from(id)
+        } else {
+            Err(anyhow!(
+                "Can't convert {} from provided thrift::DerivedData",
+                Self::NAME.to_string(),
+            ))
+        }
+    }
+
+    fn into_thrift(data: Self) -> Result<thrift::DerivedData> {
+        Ok(thrift::DerivedData::commit_handle(
+            thrift::DerivedDataCommitHandle::commit_handle(data.into()),
+        ))
+    }
+}
+
+impl_bonsai_derived_via_manager!(CommitHandle);
+
+/// Record the derived commit in the bonsai-git mapping. Adding an identical entry again is a
+/// no-op. An existing entry that differs is left alone, as it records the commit the changeset
+/// was imported from, which is the hash git clients already know it by.
+async fn add_git_mapping(
+    ctx: &CoreContext,
+    derivation_ctx: &DerivationContext,
+    changeset_id: ChangesetId,
+    handle: &CommitHandle,
+) -> Result<()> {
+    // Managers for bubbles don't have a mapping, as their changesets aren't public yet.
+    let mapping = match derivation_ctx.bonsai_git_mapping() {
+        Ok(mapping) => mapping,
+        Err(_) => return Ok(()),
+    };
+
+    let git_sha1 = handle.oid().sha1();
+    let entry = BonsaiGitMappingEntry::new(git_sha1, changeset_id);
+    match mapping.bulk_add(ctx, &[entry]).await {
+        Ok(()) => Ok(()),
+        Err(AddGitMappingErrorKind::Conflict(existing, _)) => {
+            warn!(
+                ctx.logger(),
+                "Derived git commit {} for {} conflicts with mapping {:?}",
+                git_sha1,
+                changeset_id,
+                existing
+            );
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::format_err;
+    use blobrepo::BlobRepo;
+    use blobstore::Loadable;
+    use derived_data::BonsaiDerived;
+    use fbinit::FacebookInit;
+    use git2::{Oid, Repository};
+    use mononoke_types::hash::GitSha1;
+    use repo_derived_data::RepoDerivedDataRef;
+    use std::collections::HashSet;
+    use tempdir::TempDir;
+
+    /// Derive the git commit for the fixture's master bookmark, and check that libgit2 accepts it
+    /// and all of its ancestors, and computes the same hashes for them.
+    async fn run_commit_derivation_for_fixture(
+        fb: FacebookInit,
+        repo: BlobRepo,
+    ) -> Result<(), anyhow::Error> {
+        let ctx = CoreContext::test_mock(fb);
+
+        let bcs_id = repo
+            .get_bonsai_bookmark(ctx.clone(), &("master".try_into()?))
+            .await?
+            .ok_or(format_err!("no master"))?;
+
+        let handle = CommitHandle::derive(&ctx, &repo, bcs_id).await?;
+
+        let tmp_dir = TempDir::new("git_types_test")?;
+        let git = Repository::init_bare(tmp_dir.path())?;
+        let odb = git.odb()?;
+
+        let mut queue = vec![handle];
+        let mut seen = HashSet::new();
+        while let Some(handle) = queue.pop() {
+            if !seen.insert(handle) {
+                continue;
+            }
+            let commit = handle.load(&ctx, &repo.get_blobstore()).await?;
+            let oid = odb.write(git2::ObjectType::Commit, commit.object())?;
+            assert_eq!(oid, Oid::from_bytes(handle.oid().as_ref())?);
+
+            let git_commit = git.find_commit(oid)?;
+            for parent_id in git_commit.parent_ids() {
+                let parent_handle = queue_parent(&ctx, &repo, parent_id).await?;
+                queue.push(parent_handle);
+            }
+        }
+
+        let mapped = repo
+            .bonsai_git_mapping()
+            .get_git_sha1_from_bonsai(&ctx, bcs_id)
+            .await?;
+        assert_eq!(mapped, Some(handle.oid().sha1()));
+
+        tmp_dir.close()?;
+
+        Ok(())
+    }
+
+    async fn queue_parent(
+        ctx: &CoreContext,
+        repo: &BlobRepo,
+        parent_id: Oid,
+    ) -> Result<CommitHandle, anyhow::Error> {
+        let sha1 = GitSha1::from_bytes(parent_id.as_bytes())?;
+        let parent_bcs_id = repo
+            .bonsai_git_mapping()
+            .get_bonsai_from_git_sha1(ctx, sha1)
+            .await?
+            .ok_or(format_err!("parent {} is not mapped", parent_id))?;
+        Ok(CommitHandle::derive(ctx, repo, parent_bcs_id).await?)
+    }
+
+    #[fbinit::test]
+    async fn test_git_mapping_is_idempotent(fb: FacebookInit) -> Result<(), anyhow::Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let repo = fixtures::linear::getrepo(fb).await;
+        let bcs_id = repo
+            .get_bonsai_bookmark(ctx.clone(), &("master".try_into()?))
+            .await?
+            .ok_or(format_err!("no master"))?;
+        let parent_id = repo
+            .get_changeset_parents_by_bonsai(ctx.clone(), bcs_id)
+            .await?[0];
+        let handle = CommitHandle::derive(&ctx, &repo, bcs_id).await?;
+        let parent_handle = CommitHandle::derive(&ctx, &repo, parent_id).await?;
+        let derivation_ctx = repo.repo_derived_data().manager().derivation_context(None);
+
+        // Deriving recorded the mapping, recording it again succeeds.
+        add_git_mapping(&ctx, &derivation_ctx, bcs_id, &handle).await?;
+
+        // A conflicting entry leaves the existing one in place.
+        add_git_mapping(&ctx, &derivation_ctx, bcs_id, &parent_handle).await?;
+        let mapped = repo
+            .bonsai_git_mapping()
+            .get_git_sha1_from_bonsai(&ctx, bcs_id)
+            .await?;
+        assert_eq!(mapped, Some(handle.oid().sha1()));
+
+        Ok(())
+    }
+
+    macro_rules! impl_test {
+        ($fixture:ident) => {
+            #[fbinit::test]
+            fn $fixture(fb: FacebookInit) -> Result<(), anyhow::Error> {
+                let runtime = tokio::runtime::Runtime::new()?;
+                runtime.block_on(async move {
+                    let repo = fixtures::$fixture::getrepo(fb).await;
+                    run_commit_derivation_for_fixture(fb, repo).await
+                })
+            }
+        };
+    }
+
+    impl_test!(linear);
+    impl_test!(branch_even);
+    impl_test!(merge_even);
+    impl_test!(merge_uneven);
+    impl_test!(many_diamonds);
+}
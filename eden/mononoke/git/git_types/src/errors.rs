@@ -16,4 +16,8 @@ pub enum ErrorKind {
     TreeDerivationFailed,
     #[error("Invalid Thrift")]
     InvalidThrift,
+    #[error("Invalid git commit header: {0:?}")]
+    InvalidCommitHeader(String),
+    #[error("Invalid git commit author or committer: {0:?}")]
+    InvalidCommitSignature(String),
 }
@@ -12,6 +12,8 @@ mod thrift {
 }
 
 mod blob;
+mod commit;
+mod derive_commit;
 mod derive_tree;
 mod errors;
 mod manifest;
@@ -20,5 +22,6 @@ mod store;
 mod tree;
 
 pub use crate::blob::BlobHandle;
+pub use crate::commit::{Commit, CommitBuilder, CommitHandle};
 pub use crate::tree::{Tree, TreeBuilder, TreeHandle, TreeMember, Treeish};
 pub use object::ObjectKind;
@@ -7,8 +7,11 @@
 
 use blobstore::impl_loadable_storable;
 
-use crate::thrift::{Tree as ThriftTree, TreeHandle as ThriftTreeHandle};
-use crate::{Tree, TreeHandle};
+use crate::thrift::{
+    Commit as ThriftCommit, CommitHandle as ThriftCommitHandle, Tree as ThriftTree,
+    TreeHandle as ThriftTreeHandle,
+};
+use crate::{Commit, CommitHandle, Tree, TreeHandle};
 
 impl_loadable_storable! {
     handle_type => TreeHandle,
@@ -16,3 +19,10 @@ impl_loadable_storable! {
     value_type => Tree,
     value_thrift_type => ThriftTree,
 }
+
+impl_loadable_storable! {
+    handle_type => CommitHandle,
+    handle_thrift_type => ThriftCommitHandle,
+    value_type => Commit,
+    value_thrift_type => ThriftCommit,
+}
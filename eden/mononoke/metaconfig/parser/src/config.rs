@@ -1647,4 +1647,51 @@ mod test {
             panic!("Multiplexed config is not a multiplexed blobstore");
         }
     }
+
+    #[test]
+    fn test_s3_blobstore() {
+        const STORAGE: &str = r#"
+        [s3_store.metadata.local]
+        local_db_path = "/tmp/s3_store"
+
+        [s3_store.blobstore.s3]
+        bucket = "mononoke"
+        keychain_group = "minio"
+        region_name = "us-east-1"
+        endpoint = "http://localhost:9000"
+        num_concurrent_operations = 16
+        "#;
+
+        const REPO: &str = r#"
+        storage_config = "s3_store"
+        "#;
+
+        const REPO_DEF: &str = r#"
+        repo_id = 123
+        repo_name = "test"
+        repo_config = "test"
+        "#;
+
+        let paths = btreemap! {
+            "common/storage.toml" => STORAGE,
+            "common/commitsyncmap.toml" => "",
+            "repos/test/server.toml" => REPO,
+            "repo_definitions/test/server.toml" => REPO_DEF,
+        };
+
+        let config_store = ConfigStore::new(Arc::new(TestSource::new()), None, None);
+        let tmp_dir = write_files(&paths);
+        let res = load_repo_configs(tmp_dir.path(), &config_store).expect("Read configs failed");
+
+        assert_eq!(
+            res.repos["test"].storage_config.blobstore,
+            BlobConfig::S3 {
+                bucket: "mononoke".to_string(),
+                keychain_group: "minio".to_string(),
+                region_name: "us-east-1".to_string(),
+                endpoint: "http://localhost:9000".to_string(),
+                num_concurrent_operations: Some(16),
+            }
+        );
+    }
 }
@@ -16,28 +16,35 @@ use bookmarks::Freshness;
 use bytes::Bytes;
 use changesets::{ChangesetInsert, Changesets};
 use context::{CoreContext, SessionClass};
+use derived_data_manager::DerivationError;
 use edenapi_types::{AnyId, UploadToken};
 use ephemeral_blobstore::{Bubble, BubbleId, RepoEphemeralBlobstore, StorageLocation};
 use filestore::{self, Alias, FetchKey, StoreRequest};
+use fsnodes::RootFsnodeId;
 use futures::compat::{Future01CompatExt, Stream01CompatExt};
 use futures::{future, stream, Stream, StreamExt, TryStream, TryStreamExt};
 use futures_util::try_join;
 use hgproto::GettreepackArgs;
+use manifest::{Entry, ManifestOps};
 use mercurial_mutation::HgMutationEntry;
 use mercurial_types::blobs::{RevlogChangeset, UploadHgNodeHash, UploadHgTreeEntry};
 use mercurial_types::{HgChangesetId, HgFileEnvelopeMut, HgFileNodeId, HgManifestId, HgNodeHash};
 use metaconfig_types::RepoConfig;
 use mononoke_api::RepoWriteContext;
-use mononoke_api::{errors::MononokeError, path::MononokePath, repo::RepoContext};
+use mononoke_api::{
+    errors::MononokeError, path::MononokePath, repo::RepoContext, TreeId, TreeSummary,
+};
 use mononoke_types::{
     hash::{Sha1, Sha256},
-    BonsaiChangeset, ChangesetId, ContentId, ContentMetadata, MPath, MononokeId, RepoPath,
+    BonsaiChangeset, ChangesetId, ContentId, ContentMetadata, FsnodeId, MPath, MononokeId,
+    RepoPath,
 };
 use repo_blobstore::RepoBlobstore;
 use repo_client::{
     find_commits_to_send, find_new_draft_commits_and_derive_filenodes_for_public_roots,
     gettreepack_entries,
 };
+use repo_derived_data::RepoDerivedDataRef;
 
 use segmented_changelog::{CloneData, Location};
 use std::collections::HashSet;
@@ -49,6 +56,10 @@ use reachabilityindex::LeastCommonAncestorsHint;
 
 use super::{HgFileContext, HgTreeContext};
 
+/// XXX: These numbers were chosen arbitrarily.
+const MAX_CONCURRENT_FILENODE_FETCHES: usize = 100;
+const MAX_CONCURRENT_FSNODE_FETCHES: usize = 100;
+
 #[derive(Clone)]
 pub struct HgRepoContext {
     repo: RepoContext,
@@ -309,6 +320,114 @@ impl HgRepoContext {
         HgTreeContext::new_check_exists(self.clone(), manifest_id).await
     }
 
+    /// Get the fsnode ids and summaries of many trees, which contain the
+    /// digests and sizes of the directories and their descendants.
+    ///
+    /// Each tree is given with its path. Fsnodes are derived for changesets,
+    /// so trees are looked up at their paths in their linknodes. Trees are
+    /// left out of the result if they have no linknode, which is the case for
+    /// trees only introduced by draft commits, or if fsnodes have not been
+    /// derived for their linknodes. This never derives fsnodes.
+    pub async fn tree_fsnode_summaries(
+        &self,
+        trees: Vec<(HgManifestId, Option<MPath>)>,
+    ) -> Result<HashMap<HgManifestId, (TreeId, TreeSummary)>, MononokeError> {
+        let ctx = self.ctx();
+        let blob_repo = self.blob_repo();
+        let blobstore = blob_repo.blobstore();
+
+        let linknodes: Vec<(HgManifestId, Option<MPath>, HgChangesetId)> = stream::iter(trees)
+            .map(|(manifest_id, path)| async move {
+                let repo_path = match &path {
+                    Some(path) => RepoPath::DirectoryPath(path.clone()),
+                    None => RepoPath::RootPath,
+                };
+                let filenode_id = HgFileNodeId::new(manifest_id.into_nodehash());
+                let filenode = blob_repo
+                    .get_filenode_opt(ctx.clone(), &repo_path, filenode_id)
+                    .await?
+                    .do_not_handle_disabled_filenodes()?;
+                Ok::<_, Error>(filenode.map(|filenode| (manifest_id, path, filenode.linknode)))
+            })
+            .buffer_unordered(MAX_CONCURRENT_FILENODE_FETCHES)
+            .try_filter_map(future::ok)
+            .try_collect()
+            .await?;
+
+        let hg_cs_ids: HashSet<HgChangesetId> =
+            linknodes.iter().map(|(_, _, linknode)| *linknode).collect();
+        let hg_to_bonsai: HashMap<HgChangesetId, ChangesetId> = blob_repo
+            .get_hg_bonsai_mapping(ctx.clone(), hg_cs_ids.into_iter().collect::<Vec<_>>())
+            .await?
+            .into_iter()
+            .collect();
+        let root_fsnode_ids = match blob_repo
+            .repo_derived_data()
+            .manager()
+            .fetch_derived_batch::<RootFsnodeId>(
+                ctx,
+                hg_to_bonsai.values().copied().collect(),
+                None,
+            )
+            .await
+        {
+            Ok(root_fsnode_ids) => root_fsnode_ids,
+            Err(DerivationError::Disabled(..)) => return Ok(HashMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Trees to look up at each path, grouped by the root fsnode of their
+        // linknode.
+        let mut trees_by_root: HashMap<FsnodeId, HashMap<Option<MPath>, Vec<HgManifestId>>> =
+            HashMap::new();
+        for (manifest_id, path, linknode) in linknodes {
+            let root_fsnode_id = hg_to_bonsai
+                .get(&linknode)
+                .and_then(|cs_id| root_fsnode_ids.get(cs_id));
+            if let Some(root_fsnode_id) = root_fsnode_id {
+                trees_by_root
+                    .entry(*root_fsnode_id.fsnode_id())
+                    .or_default()
+                    .entry(path)
+                    .or_default()
+                    .push(manifest_id);
+            }
+        }
+
+        let entries = stream::select_all(trees_by_root.into_iter().map(
+            |(root_fsnode_id, mut trees)| {
+                let paths = trees.keys().cloned().collect::<Vec<_>>();
+                root_fsnode_id
+                    .find_entries(ctx.clone(), blobstore.clone(), paths)
+                    .try_filter_map(move |(path, entry)| {
+                        let found = match entry {
+                            Entry::Tree(fsnode_id) => {
+                                trees.remove(&path).map(|ids| (fsnode_id, ids))
+                            }
+                            Entry::Leaf(_) => None,
+                        };
+                        future::ok(found)
+                    })
+            },
+        ));
+        let summaries: Vec<(FsnodeId, TreeSummary, Vec<HgManifestId>)> = entries
+            .map_ok(|(fsnode_id, manifest_ids)| async move {
+                let fsnode = fsnode_id.load(ctx, blobstore).await?;
+                Ok::<_, Error>((fsnode_id, fsnode.summary().clone(), manifest_ids))
+            })
+            .try_buffer_unordered(MAX_CONCURRENT_FSNODE_FETCHES)
+            .try_collect()
+            .await?;
+
+        let mut result = HashMap::new();
+        for (fsnode_id, summary, manifest_ids) in summaries {
+            for manifest_id in manifest_ids {
+                result.insert(manifest_id, (fsnode_id, summary.clone()));
+            }
+        }
+        Ok(result)
+    }
+
 
     /// Store HgFilenode into blobstore
     pub async fn store_hg_filenode(
@@ -826,6 +945,7 @@ mod tests {
 
     use anyhow::Error;
     use blobstore::Loadable;
+    use derived_data_filenodes::FilenodesOnlyPublic;
     use fbinit::FacebookInit;
     use mononoke_api::repo::Repo;
     use mononoke_types::ChangesetId;
@@ -896,6 +1016,61 @@ mod tests {
         Ok(())
     }
 
+    #[fbinit::test]
+    async fn test_tree_fsnode_summaries(fb: FacebookInit) -> Result<(), MononokeError> {
+        let ctx = CoreContext::test_mock(fb);
+        let blob_repo: BlobRepo = test_repo_factory::build_empty()?;
+
+        let commit = CreateCommitContext::new_root(&ctx, &blob_repo)
+            .add_file("a", "1")
+            .add_file("dir/b", "22")
+            .add_file("dir/c", "333")
+            .commit()
+            .await?;
+        blob_repo
+            .repo_derived_data()
+            .derive::<FilenodesOnlyPublic>(&ctx, commit)
+            .await?;
+        let root_mfid = root_manifest_id(ctx.clone(), &blob_repo, commit).await?;
+
+        let repo = Repo::new_test(ctx.clone(), blob_repo.clone()).await?;
+        let repo_ctx = RepoContext::new(ctx.clone(), Arc::new(repo)).await?;
+        let hg = repo_ctx.hg();
+
+        let dir_mfid = HgTreeContext::new(hg.clone(), root_mfid)
+            .await?
+            .entries()?
+            .find_map(|(name, entry)| match entry {
+                Entry::Tree(id) if name.as_ref() == b"dir" => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        let trees = vec![(root_mfid, None), (dir_mfid, Some(MPath::new("dir")?))];
+
+        // Fsnodes are not derived for the linknode yet.
+        assert!(hg.tree_fsnode_summaries(trees.clone()).await?.is_empty());
+
+        let root_fsnode_id = blob_repo
+            .repo_derived_data()
+            .derive::<RootFsnodeId>(&ctx, commit)
+            .await?;
+        let summaries = hg.tree_fsnode_summaries(trees).await?;
+
+        let (fsnode_id, summary) = &summaries[&root_mfid];
+        assert_eq!(fsnode_id, root_fsnode_id.fsnode_id());
+        assert_eq!(summary.child_files_count, 1);
+        assert_eq!(summary.child_dirs_count, 1);
+        assert_eq!(summary.descendant_files_count, 3);
+        assert_eq!(summary.descendant_files_total_size, 6);
+
+        let (_, summary) = &summaries[&dir_mfid];
+        assert_eq!(summary.child_files_count, 2);
+        assert_eq!(summary.child_files_total_size, 5);
+        assert_eq!(summary.child_dirs_count, 0);
+
+        Ok(())
+    }
+
     /// Get the HgManifestId of the root tree manifest for the given commit.
     async fn root_manifest_id(
         ctx: CoreContext,
@@ -0,0 +1,398 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::panic::RefUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::checker::{BoxPermissionChecker, PermissionChecker};
+use crate::identity::{MononokeIdentity, MononokeIdentitySet};
+use crate::membership::{BoxMembershipChecker, MembershipChecker};
+use crate::provider::AclProvider;
+
+const GROUP_IDENTITY_TYPE: &str = "GROUP";
+
+/// ACLs and group definitions read from a local file, in TOML or (for files with a `.json`
+/// extension) JSON. Every member is either an identity in `TYPE:data` form, or a reference to
+/// another group in `GROUP:name` form:
+///
+/// ```toml
+/// [repos.fbsource]
+/// read = ["GROUP:engineers", "X509_SUBJECT_NAME:CN=ci.example.com"]
+/// write = ["USER:alice"]
+///
+/// [tiers.mononoke]
+/// read = ["GROUP:engineers"]
+///
+/// [groups]
+/// engineers = ["USER:alice", "USER:bob"]
+/// ```
+///
+/// Repos and tiers that aren't listed are not restricted. Actions that aren't listed for a repo or
+/// tier that is are denied to everyone.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AclFile {
+    #[serde(default)]
+    repos: HashMap<String, Acl>,
+    #[serde(default)]
+    tiers: HashMap<String, Acl>,
+    #[serde(default)]
+    groups: HashMap<String, Vec<Member>>,
+}
+
+/// Members allowed to perform each action.
+type Acl = HashMap<String, Vec<Member>>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Member {
+    Identity(MononokeIdentity),
+    Group(String),
+}
+
+impl<'de> Deserialize<'de> for Member {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        match raw.split_once(':') {
+            Some((GROUP_IDENTITY_TYPE, name)) => Ok(Member::Group(name.to_string())),
+            Some((id_type, id_data)) => MononokeIdentity::new(id_type, id_data)
+                .map(Member::Identity)
+                .map_err(|e| D::Error::custom(format!("{:?}", e))),
+            None => Err(D::Error::custom(format!(
+                "Invalid ACL member {:?}, expected TYPE:data",
+                raw
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AclKind {
+    Repo,
+    Tier,
+}
+
+impl AclFile {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read ACL file {}", path.display()))?;
+
+        let parsed = if path.extension().map_or(false, |ext| ext == "json") {
+            serde_json::from_str(&content).map_err(Error::from)
+        } else {
+            toml::from_str(&content).map_err(Error::from)
+        };
+
+        parsed.with_context(|| format!("Failed to parse ACL file {}", path.display()))
+    }
+
+    fn acl(&self, kind: AclKind, name: &str) -> Option<&Acl> {
+        match kind {
+            AclKind::Repo => self.repos.get(name),
+            AclKind::Tier => self.tiers.get(name),
+        }
+    }
+
+    fn check(
+        &self,
+        kind: AclKind,
+        name: &str,
+        accessors: &MononokeIdentitySet,
+        actions: &[&str],
+    ) -> bool {
+        let acl = match self.acl(kind, name) {
+            Some(acl) => acl,
+            None => return true,
+        };
+
+        actions
+            .iter()
+            .filter_map(|action| acl.get(*action))
+            .flatten()
+            .any(|member| self.matches(member, accessors, &mut HashSet::new()))
+    }
+
+    fn is_member(&self, group: &str, identities: &MononokeIdentitySet) -> bool {
+        self.matches(
+            &Member::Group(group.to_string()),
+            identities,
+            &mut HashSet::new(),
+        )
+    }
+
+    fn matches<'a>(
+        &'a self,
+        member: &'a Member,
+        identities: &MononokeIdentitySet,
+        visited: &mut HashSet<&'a str>,
+    ) -> bool {
+        match member {
+            Member::Identity(identity) => identities.contains(identity),
+            Member::Group(group) => {
+                // Groups may include each other, so guard against cycles.
+                if !visited.insert(group.as_str()) {
+                    return false;
+                }
+                self.groups.get(group).map_or(false, |members| {
+                    members
+                        .iter()
+                        .any(|member| self.matches(member, identities, visited))
+                })
+            }
+        }
+    }
+}
+
+/// Watches an ACL file for modifications, to only parse it again once it changed. This is meant
+/// to be driven by a `Reloader`.
+pub struct AclFileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl AclFileWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            modified: None,
+        }
+    }
+
+    /// Parse the file if it was modified since it was last parsed, or return `None` if it wasn't.
+    /// The modification time is only recorded once the file parsed successfully, so a file that
+    /// fails to parse is tried again on the next call.
+    pub fn load_if_modified(&mut self) -> Result<Option<AclFile>> {
+        let modified = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Failed to stat ACL file {}", self.path.display()))?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+
+        let acls = AclFile::from_path(&self.path)?;
+        self.modified = Some(modified);
+        Ok(Some(acls))
+    }
+}
+
+type AclSource = Arc<dyn Fn() -> Arc<AclFile> + Send + Sync + RefUnwindSafe>;
+
+/// `AclProvider` serving the contents of an `AclFile`. Checkers handed out by the provider
+/// consult the ACLs returned by its source on every check, so they follow reloads.
+pub struct FileAclProvider {
+    acls: AclSource,
+}
+
+impl FileAclProvider {
+    pub fn new(acls: AclFile) -> Arc<Self> {
+        let acls = Arc::new(acls);
+        Self::from_fn(move || acls.clone())
+    }
+
+    /// Serve whatever `acls` returns at the time of each check, e.g. the latest ACLs loaded by a
+    /// `Reloader`.
+    pub fn from_fn(
+        acls: impl Fn() -> Arc<AclFile> + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            acls: Arc::new(acls),
+        })
+    }
+
+    fn checker(&self, kind: AclKind, name: &str) -> BoxPermissionChecker {
+        Box::new(FileAclChecker {
+            acls: self.acls.clone(),
+            kind,
+            name: name.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl AclProvider for FileAclProvider {
+    async fn repo_acl(&self, name: &str) -> Result<BoxPermissionChecker> {
+        Ok(self.checker(AclKind::Repo, name))
+    }
+
+    async fn tier_acl(&self, name: &str) -> Result<BoxPermissionChecker> {
+        Ok(self.checker(AclKind::Tier, name))
+    }
+
+    async fn group(&self, name: &str) -> Result<BoxMembershipChecker> {
+        Ok(Box::new(FileGroupChecker {
+            acls: self.acls.clone(),
+            name: name.to_string(),
+        }))
+    }
+}
+
+struct FileAclChecker {
+    acls: AclSource,
+    kind: AclKind,
+    name: String,
+}
+
+#[async_trait]
+impl PermissionChecker for FileAclChecker {
+    async fn check_set(&self, accessors: &MononokeIdentitySet, actions: &[&str]) -> Result<bool> {
+        Ok((self.acls)().check(self.kind, &self.name, accessors, actions))
+    }
+}
+
+struct FileGroupChecker {
+    acls: AclSource,
+    name: String,
+}
+
+#[async_trait]
+impl MembershipChecker for FileGroupChecker {
+    async fn is_member(&self, identities: &MononokeIdentitySet) -> Result<bool> {
+        Ok((self.acls)().is_member(&self.name, identities))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    const ACLS: &str = r#"
+        [repos.restricted]
+        read = ["GROUP:engineers"]
+        write = ["USER:alice"]
+
+        [tiers.mononoke]
+        read = ["X509_SUBJECT_NAME:CN=ci.example.com,O=Example"]
+
+        [groups]
+        engineers = ["USER:bob", "GROUP:admins"]
+        admins = ["USER:alice", "GROUP:engineers"]
+    "#;
+
+    fn idents(ids: &[&str]) -> MononokeIdentitySet {
+        ids.iter().map(|id| id.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_repo_acl() {
+        let acls: AclFile = toml::from_str(ACLS).unwrap();
+        let alice = idents(&["USER:alice"]);
+        let bob = idents(&["USER:bob"]);
+        let carol = idents(&["USER:carol"]);
+
+        assert!(acls.check(AclKind::Repo, "restricted", &alice, &["read"]));
+        assert!(acls.check(AclKind::Repo, "restricted", &alice, &["write"]));
+        assert!(acls.check(AclKind::Repo, "restricted", &bob, &["read"]));
+        assert!(!acls.check(AclKind::Repo, "restricted", &bob, &["write"]));
+        assert!(acls.check(AclKind::Repo, "restricted", &bob, &["write", "read"]));
+        assert!(!acls.check(AclKind::Repo, "restricted", &carol, &["read"]));
+        assert!(!acls.check(AclKind::Repo, "restricted", &alice, &["bypass_readonly"]));
+
+        // Repos that aren't listed are unrestricted.
+        assert!(acls.check(AclKind::Repo, "other", &carol, &["write"]));
+    }
+
+    #[test]
+    fn test_tier_acl() {
+        let acls: AclFile = toml::from_str(ACLS).unwrap();
+        let ci = idents(&["X509_SUBJECT_NAME:CN=ci.example.com,O=Example"]);
+
+        assert!(acls.check(AclKind::Tier, "mononoke", &ci, &["read"]));
+        assert!(!acls.check(AclKind::Tier, "mononoke", &idents(&["USER:bob"]), &["read"]));
+    }
+
+    #[test]
+    fn test_groups() {
+        let acls: AclFile = toml::from_str(ACLS).unwrap();
+
+        assert!(acls.is_member("engineers", &idents(&["USER:alice"])));
+        assert!(acls.is_member("admins", &idents(&["USER:bob"])));
+        assert!(!acls.is_member("engineers", &idents(&["USER:carol"])));
+        assert!(!acls.is_member("missing", &idents(&["USER:alice"])));
+    }
+
+    #[test]
+    fn test_invalid_member() {
+        assert!(toml::from_str::<AclFile>("[groups]\nbad = [\"alice\"]").is_err());
+    }
+
+    #[test]
+    fn test_watcher() -> Result<()> {
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile()?;
+        write!(
+            file,
+            r#"{{"repos": {{"repo": {{"write": ["USER:alice"]}}}}}}"#
+        )?;
+        let mut watcher = AclFileWatcher::new(file.path());
+        let alice = idents(&["USER:alice"]);
+        let bob = idents(&["USER:bob"]);
+
+        let acls = watcher.load_if_modified()?.expect("initial load");
+        assert!(acls.check(AclKind::Repo, "repo", &alice, &["write"]));
+        assert!(watcher.load_if_modified()?.is_none());
+
+        // The modification time may not change within the test, so pretend it did.
+        let rewrite = |watcher: &mut AclFileWatcher, content: &str| -> Result<()> {
+            let mut rewritten = file.reopen()?;
+            rewritten.set_len(0)?;
+            write!(rewritten, "{}", content)?;
+            rewritten.sync_all()?;
+            watcher.modified = Some(SystemTime::UNIX_EPOCH);
+            Ok(())
+        };
+
+        // A file that doesn't parse is retried until it does.
+        rewrite(&mut watcher, "{")?;
+        assert!(watcher.load_if_modified().is_err());
+        assert_eq!(watcher.modified, Some(SystemTime::UNIX_EPOCH));
+        assert!(watcher.load_if_modified().is_err());
+
+        rewrite(
+            &mut watcher,
+            r#"{"repos": {"repo": {"write": ["USER:bob"]}}}"#,
+        )?;
+        let acls = watcher.load_if_modified()?.expect("reload");
+        assert!(acls.check(AclKind::Repo, "repo", &bob, &["write"]));
+        assert!(watcher.load_if_modified()?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_provider_follows_source() -> Result<()> {
+        let current = Arc::new(std::sync::Mutex::new(Arc::new(toml::from_str::<AclFile>(
+            ACLS,
+        )?)));
+        let provider = FileAclProvider::from_fn({
+            let current = current.clone();
+            move || current.lock().unwrap().clone()
+        });
+        let checker = provider.repo_acl("restricted").await?;
+        let group = provider.group("engineers").await?;
+        let carol = idents(&["USER:carol"]);
+        assert!(!checker.check_set(&carol, &["read"]).await?);
+        assert!(!group.is_member(&carol).await?);
+
+        *current.lock().unwrap() = Arc::new(toml::from_str(
+            "[repos.restricted]\nread = [\"USER:carol\"]\n[groups]\nengineers = [\"USER:carol\"]",
+        )?);
+        assert!(checker.check_set(&carol, &["read"]).await?);
+        assert!(group.is_member(&carol).await?);
+
+        Ok(())
+    }
+}
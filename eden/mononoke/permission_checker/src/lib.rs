@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+mod acl_file;
 mod checker;
 #[cfg(fbcode_build)]
 mod facebook;
@@ -12,7 +13,9 @@ mod identity;
 mod membership;
 #[cfg(not(fbcode_build))]
 mod oss;
+mod provider;
 
+pub use acl_file::{AclFile, AclFileWatcher, FileAclProvider};
 pub use checker::{
     ArcPermissionChecker, BoxPermissionChecker, PermissionChecker, PermissionCheckerBuilder,
 };
@@ -20,3 +23,4 @@ pub use identity::{MononokeIdentity, MononokeIdentitySet, MononokeIdentitySetExt
 pub use membership::{
     ArcMembershipChecker, BoxMembershipChecker, MembershipChecker, MembershipCheckerBuilder,
 };
+pub use provider::{set_acl_provider, AclProvider, ArcAclProvider};
@@ -5,25 +5,55 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use fbinit::FacebookInit;
 use openssl::x509::X509;
 
 use crate::checker::{BoxPermissionChecker, PermissionCheckerBuilder};
 use crate::identity::{MononokeIdentity, MononokeIdentitySet, MononokeIdentitySetExt};
 use crate::membership::{BoxMembershipChecker, MembershipCheckerBuilder};
+use crate::provider::acl_provider;
+
+const SSH_PRINCIPAL_DEFAULT_TYPE: &str = "USER";
 
 impl MononokeIdentity {
     pub fn reviewer_identities(_username: &str) -> MononokeIdentitySet {
         MononokeIdentitySet::new()
     }
 
-    pub fn try_from_ssh_encoded(_encoded: &str) -> Result<MononokeIdentitySet> {
-        bail!("Decoding from SSH Principals is not yet implemented for MononokeIdentity")
+    /// Decode the principals of an SSH certificate, as found in the `SSH_CERT_PRINCIPALS`
+    /// variable sshrelay forwards in its preamble. This is a comma-separated list, where each
+    /// principal is either an identity in `TYPE:data` form, or a bare name that is taken to be a
+    /// `USER` identity (e.g. `alice,GROUP_MACHINE:ci`).
+    pub fn try_from_ssh_encoded(encoded: &str) -> Result<MononokeIdentitySet> {
+        let idents = encoded
+            .split(',')
+            .map(str::trim)
+            .filter(|principal| !principal.is_empty())
+            .map(|principal| match principal.split_once(':') {
+                Some((id_type, id_data)) => MononokeIdentity::new(id_type, id_data),
+                None => MononokeIdentity::new(SSH_PRINCIPAL_DEFAULT_TYPE, principal),
+            })
+            .collect::<Result<MononokeIdentitySet>>()?;
+
+        if idents.is_empty() {
+            bail!("No identities in SSH principals {:?}", encoded);
+        }
+        Ok(idents)
     }
 
-    pub fn try_from_json_encoded(_: &str) -> Result<MononokeIdentitySet> {
-        bail!("Decoding from JSON is not yet implemented for MononokeIdentity")
+    /// Decode identities forwarded by a trusted proxy as a JSON list of `TYPE:data` strings,
+    /// which is how `MononokeIdentity` serializes.
+    pub fn try_from_json_encoded(encoded: &str) -> Result<MononokeIdentitySet> {
+        let raw: Vec<String> =
+            serde_json::from_str(encoded).context("Identities must be a JSON list of strings")?;
+
+        raw.iter()
+            .map(|ident| match ident.split_once(':') {
+                Some((id_type, id_data)) => MononokeIdentity::new(id_type, id_data),
+                None => bail!("Invalid identity {:?}, expected TYPE:data", ident),
+            })
+            .collect()
     }
 
     pub fn try_from_x509(cert: &X509) -> Result<MononokeIdentitySet> {
@@ -65,12 +95,18 @@ impl MononokeIdentitySetExt for MononokeIdentitySet {
 }
 
 impl PermissionCheckerBuilder {
-    pub async fn acl_for_repo(_fb: FacebookInit, _name: &str) -> Result<BoxPermissionChecker> {
-        Ok(Self::always_allow())
+    pub async fn acl_for_repo(_fb: FacebookInit, name: &str) -> Result<BoxPermissionChecker> {
+        match acl_provider() {
+            Some(provider) => provider.repo_acl(name).await,
+            None => Ok(Self::always_allow()),
+        }
     }
 
-    pub async fn acl_for_tier(_fb: FacebookInit, _name: &str) -> Result<BoxPermissionChecker> {
-        Ok(Self::always_allow())
+    pub async fn acl_for_tier(_fb: FacebookInit, name: &str) -> Result<BoxPermissionChecker> {
+        match acl_provider() {
+            Some(provider) => provider.tier_acl(name).await,
+            None => Ok(Self::always_allow()),
+        }
     }
 }
 
@@ -83,7 +119,45 @@ impl MembershipCheckerBuilder {
         Ok(Self::never_member())
     }
 
-    pub async fn for_group(_fb: FacebookInit, _group_name: &str) -> Result<BoxMembershipChecker> {
-        Ok(Self::never_member())
+    pub async fn for_group(_fb: FacebookInit, group_name: &str) -> Result<BoxMembershipChecker> {
+        match acl_provider() {
+            Some(provider) => provider.group(group_name).await,
+            None => Ok(Self::never_member()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_try_from_ssh_encoded() -> Result<()> {
+        let idents = MononokeIdentity::try_from_ssh_encoded("alice, SERVICE_IDENTITY:ci,")?;
+        assert_eq!(
+            idents,
+            ["USER:alice", "SERVICE_IDENTITY:ci"]
+                .iter()
+                .map(|id| id.parse())
+                .collect::<Result<_>>()?
+        );
+
+        assert!(MononokeIdentity::try_from_ssh_encoded("").is_err());
+        assert!(MononokeIdentity::try_from_ssh_encoded(" , ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_from_json_encoded() -> Result<()> {
+        let mut expected = MononokeIdentitySet::new();
+        expected.insert(MononokeIdentity::new("USER", "alice")?);
+        expected.insert(MononokeIdentity::new("X509_SUBJECT_NAME", "CN=host:443")?);
+
+        let encoded = serde_json::to_string(&expected)?;
+        assert_eq!(MononokeIdentity::try_from_json_encoded(&encoded)?, expected);
+
+        assert!(MononokeIdentity::try_from_json_encoded(r#"["alice"]"#).is_err());
+        assert!(MononokeIdentity::try_from_json_encoded(r#"{"USER": "alice"}"#).is_err());
+        Ok(())
     }
 }
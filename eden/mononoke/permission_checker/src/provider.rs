@@ -0,0 +1,46 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use once_cell::sync::OnceCell;
+use std::sync::Arc;
+
+use crate::checker::BoxPermissionChecker;
+use crate::membership::BoxMembershipChecker;
+
+pub type ArcAclProvider = Arc<dyn AclProvider + Send + Sync + 'static>;
+
+/// Source of ACLs and group membership for builds that can't use the internal ACL service. The
+/// `PermissionCheckerBuilder` and `MembershipCheckerBuilder` constructors consult the provider
+/// registered with `set_acl_provider`, if any.
+#[async_trait]
+pub trait AclProvider {
+    async fn repo_acl(&self, name: &str) -> Result<BoxPermissionChecker>;
+
+    async fn tier_acl(&self, name: &str) -> Result<BoxPermissionChecker>;
+
+    async fn group(&self, name: &str) -> Result<BoxMembershipChecker>;
+}
+
+static ACL_PROVIDER: OnceCell<ArcAclProvider> = OnceCell::new();
+
+/// Register the process-wide ACL provider. This can only be done once, and fails in builds that
+/// check ACLs against the ACL service, since those would never consult the provider.
+pub fn set_acl_provider(provider: ArcAclProvider) -> Result<()> {
+    if cfg!(fbcode_build) {
+        bail!("ACL providers are not supported in this build, ACLs come from the ACL service");
+    }
+    ACL_PROVIDER
+        .set(provider)
+        .map_err(|_| anyhow!("ACL provider is already set"))
+}
+
+#[cfg_attr(fbcode_build, allow(dead_code))]
+pub(crate) fn acl_provider() -> Option<&'static ArcAclProvider> {
+    ACL_PROVIDER.get()
+}
@@ -108,7 +108,6 @@ pub struct RateLimit {
     metric: Metric,
 }
 
-#[cfg(fbcode_build)]
 impl RateLimit {
     fn applies_to_client(&self, identities: &MononokeIdentitySet) -> bool {
         match &self.target {
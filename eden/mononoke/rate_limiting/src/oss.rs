@@ -8,58 +8,310 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use async_trait::async_trait;
 use fbinit::FacebookInit;
+use once_cell::sync::OnceCell;
 use permission_checker::MononokeIdentitySet;
+use time_window_counter::{BoxGlobalTimeWindowCounter, GlobalTimeWindowCounterBuilder};
 
 use crate::{
-    BoxRateLimiter, LoadCost, Metric, MononokeRateLimitConfig, RateLimitBody, RateLimitReason,
-    RateLimiter,
+    BoxRateLimiter, LoadCost, Metric, MononokeRateLimitConfig, RateLimit, RateLimitBody,
+    RateLimitReason, RateLimitStatus, RateLimiter,
 };
 
+const UNKNOWN_CLIENT: &str = "unknown";
+
 pub fn get_region_capacity(_datacenter_capacity: &BTreeMap<String, i32>) -> Option<i32> {
     None
 }
 
 pub fn create_rate_limiter(
-    _fb: FacebookInit,
+    fb: FacebookInit,
     category: String,
-    _config: Arc<MononokeRateLimitConfig>,
+    config: Arc<MononokeRateLimitConfig>,
 ) -> BoxRateLimiter {
-    Box::new(FakeLimiter { category })
+    Box::new(LocalRateLimiter {
+        fb,
+        category,
+        config,
+        client: OnceCell::new(),
+    })
 }
 
-#[derive(Debug)]
-struct FakeLimiter {
+/// Rate limiter that accounts load in the process-wide counters of `time_window_counter`, with
+/// a counter per metric and per client, where a client is identified by its full identity set.
+/// A limit is exceeded once the load counted over its window reaches it. Since a limiter is
+/// created for every session, the client is recorded on the first check made through it and
+/// used to attribute any load bumped afterwards.
+struct LocalRateLimiter {
+    fb: FacebookInit,
     category: String,
+    config: Arc<MononokeRateLimitConfig>,
+    client: OnceCell<(String, MononokeIdentitySet)>,
+}
+
+impl LocalRateLimiter {
+    fn record_client(&self, identities: &MononokeIdentitySet) -> &str {
+        &self
+            .client
+            .get_or_init(|| (client_key(identities), identities.clone()))
+            .0
+    }
+
+    /// The counter of a metric for a client. It keeps enough history for the longest window
+    /// of the given limits, which all count the same load.
+    fn counter<'a>(
+        &self,
+        metric: Metric,
+        client: &str,
+        limits: impl IntoIterator<Item = &'a RateLimit>,
+    ) -> BoxGlobalTimeWindowCounter {
+        let max_window = limits
+            .into_iter()
+            .map(|limit| window_secs(&limit.body))
+            .max()
+            .unwrap_or(1);
+        GlobalTimeWindowCounterBuilder::build(
+            self.fb,
+            &self.category,
+            format!("{:?}.{}", metric, client),
+            1,
+            max_window,
+        )
+    }
+
+    fn capacity(&self, limit: &RateLimit) -> f64 {
+        limit.body.raw_config.limit * self.config.region_weight
+    }
+}
+
+fn window_secs(body: &RateLimitBody) -> u32 {
+    body.window.as_secs().clamp(1, u32::MAX as u64) as u32
+}
+
+fn client_key(identities: &MononokeIdentitySet) -> String {
+    if identities.is_empty() {
+        UNKNOWN_CLIENT.to_string()
+    } else {
+        identities
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+fn is_enforced(status: RateLimitStatus) -> Result<Option<bool>, Error> {
+    match status {
+        RateLimitStatus::Disabled => Ok(None),
+        RateLimitStatus::Tracked => Ok(Some(false)),
+        RateLimitStatus::Enforced => Ok(Some(true)),
+        // NOTE: Thrift enums aren't real enums once in Rust. We have to account for other values
+        // here.
+        _ => Err(anyhow!("Invalid rate limit status: {:?}", status)),
+    }
 }
 
 #[async_trait]
-impl RateLimiter for FakeLimiter {
+impl RateLimiter for LocalRateLimiter {
     async fn check_rate_limit(
         &self,
-        _metric: Metric,
-        _identities: &MononokeIdentitySet,
+        metric: Metric,
+        identities: &MononokeIdentitySet,
     ) -> Result<Result<(), RateLimitReason>, Error> {
+        let client = self.record_client(identities);
+
+        let mut enforced = Vec::new();
+        for limit in self.config.rate_limits.iter() {
+            if limit.metric != metric || !limit.applies_to_client(identities) {
+                continue;
+            }
+            // Tracked limits only count load.
+            if is_enforced(limit.body.raw_config.status)? == Some(true) {
+                enforced.push(limit);
+            }
+        }
+        if enforced.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let counter = self.counter(metric, client, enforced.iter().copied());
+        for limit in enforced {
+            if counter.get(window_secs(&limit.body)).await? >= self.capacity(limit) {
+                return Ok(Err(RateLimitReason::RateLimitedMetric(
+                    metric,
+                    limit.body.window,
+                )));
+            }
+        }
+
         Ok(Ok(()))
     }
 
-    fn check_load_shed(&self, _identities: &MononokeIdentitySet) -> Result<(), RateLimitReason> {
+    fn check_load_shed(&self, identities: &MononokeIdentitySet) -> Result<(), RateLimitReason> {
+        self.record_client(identities);
+
+        for limit in self.config.load_shed_limits.iter() {
+            match is_enforced(limit.raw_config.status) {
+                Ok(Some(true)) => limit.should_load_shed(self.fb, Some(identities))?,
+                _ => continue,
+            }
+        }
+
         Ok(())
     }
 
-    fn bump_load(&self, _metric: Metric, _load: LoadCost) {}
+    fn bump_load(&self, metric: Metric, load: LoadCost) {
+        let empty = MononokeIdentitySet::new();
+        let (client, identities) = match self.client.get() {
+            Some((client, identities)) => (client.as_str(), identities),
+            None => (UNKNOWN_CLIENT, &empty),
+        };
+
+        let limits = self
+            .config
+            .rate_limits
+            .iter()
+            .filter(|limit| {
+                limit.metric == metric
+                    && limit.applies_to_client(identities)
+                    && matches!(is_enforced(limit.body.raw_config.status), Ok(Some(_)))
+            })
+            .collect::<Vec<_>>();
+        if !limits.is_empty() {
+            self.counter(metric, client, limits).bump(load);
+        }
+    }
 
     fn category(&self) -> &str {
         &self.category
     }
 
     fn commits_per_author_limit(&self) -> Option<RateLimitBody> {
-        None
+        Some(self.config.commits_per_author.clone())
     }
 
     fn total_file_changes_limit(&self) -> Option<RateLimitBody> {
-        None
+        self.config.total_file_changes.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::Duration;
+
+    use permission_checker::MononokeIdentity;
+
+    use crate::{RateLimit, Target};
+
+    fn body(status: RateLimitStatus, limit: f64, window: u64) -> RateLimitBody {
+        RateLimitBody {
+            raw_config: rate_limiting_config::RateLimitBody {
+                status,
+                limit,
+                window: window as i64,
+            },
+            window: Duration::from_secs(window),
+        }
+    }
+
+    fn config(rate_limits: Vec<RateLimit>) -> Arc<MononokeRateLimitConfig> {
+        Arc::new(MononokeRateLimitConfig {
+            region_weight: 1.0,
+            rate_limits,
+            load_shed_limits: vec![],
+            commits_per_author: body(RateLimitStatus::Disabled, 0.0, 0),
+            total_file_changes: None,
+        })
+    }
+
+    fn identities(user: &str) -> MononokeIdentitySet {
+        let mut idents = MononokeIdentitySet::new();
+        idents.insert(MononokeIdentity::new("USER", user).unwrap());
+        idents
+    }
+
+    #[fbinit::test]
+    async fn test_rate_limit_per_client(fb: FacebookInit) -> Result<(), Error> {
+        let config = config(vec![RateLimit {
+            body: body(RateLimitStatus::Enforced, 10.0, 60),
+            target: None,
+            metric: Metric::GetpackFiles,
+        }]);
+        let foo = identities("foo");
+        let bar = identities("bar");
+
+        let limiter = create_rate_limiter(fb, "test_per_client".to_string(), config.clone());
+        assert!(
+            limiter
+                .check_rate_limit(Metric::GetpackFiles, &foo)
+                .await?
+                .is_ok()
+        );
+        limiter.bump_load(Metric::GetpackFiles, 11.0);
+
+        // The counter is shared between limiters for the same client.
+        let limiter = create_rate_limiter(fb, "test_per_client".to_string(), config.clone());
+        assert!(
+            limiter
+                .check_rate_limit(Metric::GetpackFiles, &foo)
+                .await?
+                .is_err()
+        );
+        assert!(
+            limiter
+                .check_rate_limit(Metric::EgressBytes, &foo)
+                .await?
+                .is_ok()
+        );
+
+        let limiter = create_rate_limiter(fb, "test_per_client".to_string(), config);
+        assert!(
+            limiter
+                .check_rate_limit(Metric::GetpackFiles, &bar)
+                .await?
+                .is_ok()
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn test_rate_limit_status_and_target(fb: FacebookInit) -> Result<(), Error> {
+        let foo = identities("foo");
+        let config = config(vec![
+            RateLimit {
+                body: body(RateLimitStatus::Tracked, 0.0, 60),
+                target: None,
+                metric: Metric::Commits,
+            },
+            RateLimit {
+                body: body(RateLimitStatus::Enforced, 0.0, 60),
+                target: Some(Target::Identity(
+                    MononokeIdentity::new("USER", "bar").unwrap(),
+                )),
+                metric: Metric::Commits,
+            },
+        ]);
+
+        let limiter = create_rate_limiter(fb, "test_status".to_string(), config);
+        assert!(
+            limiter
+                .check_rate_limit(Metric::Commits, &foo)
+                .await?
+                .is_ok()
+        );
+        limiter.bump_load(Metric::Commits, 5.0);
+        assert!(
+            limiter
+                .check_rate_limit(Metric::Commits, &foo)
+                .await?
+                .is_ok()
+        );
+
+        Ok(())
     }
 }
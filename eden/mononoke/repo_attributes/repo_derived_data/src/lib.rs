@@ -12,6 +12,7 @@
 use std::sync::Arc;
 
 use anyhow::Result;
+use bonsai_git_mapping::BonsaiGitMapping;
 use bonsai_hg_mapping::BonsaiHgMapping;
 use cacheblob::LeaseOps;
 use changesets::Changesets;
@@ -41,6 +42,7 @@ impl RepoDerivedData {
         repo_name: String,
         changesets: Arc<dyn Changesets>,
         bonsai_hg_mapping: Arc<dyn BonsaiHgMapping>,
+        bonsai_git_mapping: Arc<dyn BonsaiGitMapping>,
         filenodes: Arc<dyn Filenodes>,
         repo_blobstore: RepoBlobstore,
         lease: Arc<dyn LeaseOps>,
@@ -53,6 +55,7 @@ impl RepoDerivedData {
             repo_name,
             changesets,
             bonsai_hg_mapping,
+            bonsai_git_mapping,
             filenodes,
             repo_blobstore,
             lease,
@@ -743,6 +743,7 @@ impl RepoFactory {
         repo_config: &ArcRepoConfig,
         changesets: &ArcChangesets,
         bonsai_hg_mapping: &ArcBonsaiHgMapping,
+        bonsai_git_mapping: &ArcBonsaiGitMapping,
         filenodes: &ArcFilenodes,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcRepoDerivedData> {
@@ -760,6 +761,7 @@ impl RepoFactory {
             repo_identity.name().to_string(),
             changesets.clone(),
             bonsai_hg_mapping.clone(),
+            bonsai_git_mapping.clone(),
             filenodes.clone(),
             repo_blobstore.as_ref().clone(),
             lease,
@@ -863,6 +865,7 @@ impl RepoFactory {
         repo_config: &ArcRepoConfig,
         changesets: &ArcChangesets,
         bonsai_hg_mapping: &ArcBonsaiHgMapping,
+        bonsai_git_mapping: &ArcBonsaiGitMapping,
         filenodes: &ArcFilenodes,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcDerivedDataManagerSet> {
@@ -880,6 +883,7 @@ impl RepoFactory {
             repo_identity.name().to_string(),
             changesets.clone(),
             bonsai_hg_mapping.clone(),
+            bonsai_git_mapping.clone(),
             filenodes.clone(),
             repo_blobstore.as_ref().clone(),
             lease,
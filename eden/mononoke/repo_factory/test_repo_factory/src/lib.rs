@@ -34,7 +34,7 @@ use fastlog::RootFastlog;
 use filenodes::ArcFilenodes;
 use filestore::{ArcFilestoreConfig, FilestoreConfig};
 use fsnodes::RootFsnodeId;
-use git_types::TreeHandle;
+use git_types::{CommitHandle, TreeHandle};
 use maplit::hashset;
 use megarepo_mapping::MegarepoMapping;
 use memblob::Memblob;
@@ -104,6 +104,7 @@ pub fn default_test_repo_config() -> RepoConfig {
                     RootDeletedManifestId::NAME.to_string(),
                     RootUnodeManifestId::NAME.to_string(),
                     TreeHandle::NAME.to_string(),
+                    CommitHandle::NAME.to_string(),
                     MappedHgChangesetId::NAME.to_string(),
                 },
                 unode_version: UnodeVersion::V2,
@@ -383,6 +384,7 @@ impl TestRepoFactory {
         repo_config: &ArcRepoConfig,
         changesets: &ArcChangesets,
         bonsai_hg_mapping: &ArcBonsaiHgMapping,
+        bonsai_git_mapping: &ArcBonsaiGitMapping,
         filenodes: &ArcFilenodes,
         repo_blobstore: &ArcRepoBlobstore,
     ) -> Result<ArcRepoDerivedData> {
@@ -395,6 +397,7 @@ impl TestRepoFactory {
             repo_identity.name().to_string(),
             changesets.clone(),
             bonsai_hg_mapping.clone(),
+            bonsai_git_mapping.clone(),
             filenodes.clone(),
             repo_blobstore.as_ref().clone(),
             lease,
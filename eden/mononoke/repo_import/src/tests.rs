@@ -26,7 +26,7 @@ mod tests {
     use derived_data_utils::derived_data_utils;
     use fbinit::FacebookInit;
     use futures::{compat::Future01CompatExt, stream::TryStreamExt};
-    use git_types::TreeHandle;
+    use git_types::{CommitHandle, TreeHandle};
     use live_commit_sync_config::{
         CfgrLiveCommitSyncConfig, LiveCommitSyncConfig, TestLiveCommitSyncConfig,
         CONFIGERATOR_ALL_COMMIT_SYNC_CONFIGS, CONFIGERATOR_PUSHREDIRECT_ENABLE,
@@ -70,11 +70,9 @@ mod tests {
     fn create_repo(id: i32) -> Result<BlobRepo> {
         let repo: BlobRepo = TestRepoFactory::new()?
             .with_config_override(|config| {
-                config
-                    .derived_data_config
-                    .enabled
-                    .types
-                    .remove(TreeHandle::NAME);
+                let types = &mut config.derived_data_config.enabled.types;
+                types.remove(TreeHandle::NAME);
+                types.remove(CommitHandle::NAME);
             })
             .with_id(RepositoryId::new(id))
             .build()?;
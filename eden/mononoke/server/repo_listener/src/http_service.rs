@@ -6,7 +6,6 @@
  */
 
 use anyhow::{anyhow, Context, Error, Result};
-#[cfg(fbcode_build)]
 use clientinfo::{ClientInfo, CLIENT_INFO_HEADER};
 use futures::future::{BoxFuture, FutureExt};
 use gotham_ext::socket_data::TlsSocketData;
@@ -428,7 +427,6 @@ fn calculate_websocket_accept(headers: &HeaderMap<HeaderValue>) -> String {
     base64::encode(&hash)
 }
 
-#[cfg(fbcode_build)]
 async fn try_convert_headers_to_metadata(
     is_trusted: bool,
     headers: &HeaderMap<HeaderValue>,
@@ -503,11 +501,3 @@ async fn try_convert_headers_to_metadata(
         Ok(None)
     }
 }
-
-#[cfg(not(fbcode_build))]
-async fn try_convert_headers_to_metadata(
-    _is_trusted: bool,
-    _headers: &HeaderMap<HeaderValue>,
-) -> Result<Option<Metadata>> {
-    Ok(None)
-}
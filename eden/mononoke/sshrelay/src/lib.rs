@@ -226,6 +226,20 @@ impl Metadata {
 }
 
 // Common information for a connection
+//
+// The preamble is sent as JSON on stream 3 before any other data. Keys in `misc` that the server
+// understands:
+//
+//  - `session_uuid`: id of the session, generated by the relay.
+//  - `unix_username`: name of the connecting user. Used as a `USER` identity when no certificate
+//    principals are available.
+//  - `source_hostname`: hostname of the client.
+//  - `SSH_CERT_PRINCIPALS`: principals of the client's SSH certificate, as set by sshd. This is a
+//    comma-separated list where each principal is either an identity in `TYPE:data` form or a bare
+//    name, which is taken to be a `USER` identity (e.g. `alice,SERVICE_IDENTITY:ci`). These are
+//    decoded by `MononokeIdentity::try_from_ssh_encoded`.
+//  - `SSH_ORIGINAL_COMMAND`, `SSH_CLIENT`: forwarded verbatim from the relay's environment.
+//  - `client_debug`, `priority`: optional flags controlling how the session is handled.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Preamble {
     // Name of the repo to connect to
@@ -5,31 +5,217 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use fbinit::FacebookInit;
+use once_cell::sync::Lazy;
 
 use crate::{BoxGlobalTimeWindowCounter, GlobalTimeWindowCounter, GlobalTimeWindowCounterBuilder};
 
-struct AlwaysZeroCounter {}
+/// Idle counters are looked for at most this often, in seconds.
+const SWEEP_INTERVAL: u64 = 60;
+
+/// Counters are process-wide: every builder call with the same category and key observes (and
+/// bumps) the same set of buckets, regardless of which session or request built it.
+static COUNTERS: Lazy<Mutex<Counters>> = Lazy::new(|| {
+    Mutex::new(Counters {
+        map: HashMap::new(),
+        last_sweep: now_secs(),
+    })
+});
+
+struct Counters {
+    map: HashMap<(String, String), Arc<LocalCounterState>>,
+    last_sweep: u64,
+}
+
+impl Counters {
+    /// Drop the counters that nobody holds and whose buckets have all expired. Such a counter
+    /// reads as zero, exactly like a new one, so the keys of clients that went away don't
+    /// accumulate.
+    fn sweep(&mut self, now: u64) {
+        if now < self.last_sweep + SWEEP_INTERVAL {
+            return;
+        }
+        self.last_sweep = now;
+        self.map
+            .retain(|_, state| Arc::strong_count(state) > 1 || !state.is_idle(now));
+    }
+}
+
+/// In-process counter that keeps one bucket per second for the last `max_time_window` seconds.
+struct LocalTimeWindowCounter {
+    state: Arc<LocalCounterState>,
+}
+
+struct LocalCounterState {
+    max_time_window: Mutex<u32>,
+    buckets: Mutex<VecDeque<(u64, f64)>>,
+}
+
+impl LocalCounterState {
+    fn new(max_time_window: u32) -> Self {
+        Self {
+            max_time_window: Mutex::new(max_time_window),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn extend_window(&self, max_time_window: u32) {
+        let mut current = self.max_time_window.lock().expect("lock poisoned");
+        *current = (*current).max(max_time_window);
+    }
+
+    fn bump_at(&self, now: u64, value: f64) {
+        let max_time_window = *self.max_time_window.lock().expect("lock poisoned");
+        let mut buckets = self.buckets.lock().expect("lock poisoned");
+
+        match buckets.back_mut() {
+            Some((second, total)) if *second == now => *total += value,
+            _ => buckets.push_back((now, value)),
+        }
+
+        let cutoff = now.saturating_sub(max_time_window as u64);
+        while matches!(buckets.front(), Some((second, _)) if *second <= cutoff) {
+            buckets.pop_front();
+        }
+    }
+
+    fn is_idle(&self, now: u64) -> bool {
+        let max_time_window = *self.max_time_window.lock().expect("lock poisoned");
+        let cutoff = now.saturating_sub(max_time_window as u64);
+        match self.buckets.lock().expect("lock poisoned").back() {
+            Some((second, _)) => *second <= cutoff,
+            None => true,
+        }
+    }
+
+    fn get_at(&self, now: u64, time_window: u32) -> f64 {
+        let cutoff = now.saturating_sub(time_window as u64);
+        self.buckets
+            .lock()
+            .expect("lock poisoned")
+            .iter()
+            .rev()
+            .take_while(|(second, _)| *second > cutoff)
+            .map(|(_, value)| value)
+            .sum()
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
 
 #[async_trait]
-impl GlobalTimeWindowCounter for AlwaysZeroCounter {
-    async fn get(&self, _time_window: u32) -> Result<f64> {
-        Ok(0.0)
+impl GlobalTimeWindowCounter for LocalTimeWindowCounter {
+    async fn get(&self, time_window: u32) -> Result<f64> {
+        Ok(self.state.get_at(now_secs(), time_window))
     }
 
-    fn bump(&self, _value: f64) {}
+    fn bump(&self, value: f64) {
+        self.state.bump_at(now_secs(), value)
+    }
 }
 
 impl GlobalTimeWindowCounterBuilder {
     pub fn build(
         _fb: FacebookInit,
-        _category: impl AsRef<str>,
-        _key: impl AsRef<str>,
+        category: impl AsRef<str>,
+        key: impl AsRef<str>,
         _min_time_window: u32,
-        _max_time_window: u32,
+        max_time_window: u32,
     ) -> BoxGlobalTimeWindowCounter {
-        Box::new(AlwaysZeroCounter {})
+        let id = (category.as_ref().to_string(), key.as_ref().to_string());
+        let mut counters = COUNTERS.lock().expect("lock poisoned");
+        counters.sweep(now_secs());
+        let state = counters
+            .map
+            .entry(id)
+            .or_insert_with(|| Arc::new(LocalCounterState::new(max_time_window)))
+            .clone();
+        state.extend_window(max_time_window);
+
+        Box::new(LocalTimeWindowCounter { state })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window_sums() {
+        let state = LocalCounterState::new(10);
+        state.bump_at(100, 1.0);
+        state.bump_at(100, 2.0);
+        state.bump_at(105, 4.0);
+
+        assert_eq!(state.get_at(105, 1), 4.0);
+        assert_eq!(state.get_at(105, 5), 4.0);
+        assert_eq!(state.get_at(105, 6), 7.0);
+        assert_eq!(state.get_at(115, 10), 0.0);
+    }
+
+    #[test]
+    fn test_old_buckets_are_dropped() {
+        let state = LocalCounterState::new(10);
+        state.bump_at(100, 1.0);
+        state.bump_at(111, 1.0);
+
+        assert_eq!(state.buckets.lock().unwrap().len(), 1);
+        assert_eq!(state.get_at(111, 3600), 1.0);
+    }
+
+    #[test]
+    fn test_idle_counters_are_dropped() {
+        let mut counters = Counters {
+            map: HashMap::new(),
+            last_sweep: 0,
+        };
+        for key in ["idle", "active", "held"] {
+            let state = Arc::new(LocalCounterState::new(10));
+            state.bump_at(100, 1.0);
+            counters
+                .map
+                .insert(("test".to_string(), key.to_string()), state);
+        }
+        counters.map[&("test".to_string(), "active".to_string())].bump_at(155, 1.0);
+        let held = counters.map[&("test".to_string(), "held".to_string())].clone();
+
+        // Sweeps are rate limited.
+        counters.last_sweep = 100;
+        counters.sweep(105);
+        assert_eq!(counters.map.len(), 3);
+
+        counters.sweep(100 + SWEEP_INTERVAL);
+        let mut keys = counters
+            .map
+            .keys()
+            .map(|(_, key)| key.as_str())
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["active", "held"]);
+        drop(held);
+    }
+
+    #[fbinit::test]
+    async fn test_counters_are_shared(fb: FacebookInit) -> Result<()> {
+        let a = GlobalTimeWindowCounterBuilder::build(fb, "test", "shared", 1, 60);
+        let b = GlobalTimeWindowCounterBuilder::build(fb, "test", "shared", 1, 60);
+        let other = GlobalTimeWindowCounterBuilder::build(fb, "test", "other", 1, 60);
+
+        a.bump(3.0);
+        b.bump(2.0);
+
+        assert_eq!(b.get(60).await?, 5.0);
+        assert_eq!(other.get(60).await?, 0.0);
+        Ok(())
     }
 }
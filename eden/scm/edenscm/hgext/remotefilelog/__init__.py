@@ -100,6 +100,18 @@ Configs:
     ``remotefilelog.getpackversion`` version of the "getpack" wire protocol.
     Starting with 2, LFS blobs are supported.
 
+    ``remotefilelog.lfs.blobsmaxsize`` limit the size of the shared LFS blobs
+    store to this size. The oldest blobs are removed first, even if they were
+    read recently, when the store is flushed and by :hg:`debuglfsgc`.
+
+    ``remotefilelog.lfs.pointersmaxsize`` limit the size of the shared LFS
+    pointers store to this size. The oldest pointers are removed first, even if
+    they were read recently, when the store is flushed and by :hg:`debuglfsgc`.
+
+    ``remotefilelog.lfs.objectsmaxsize`` limit the size of the shared loose LFS
+    blobs to this size. The least recently used blobs are removed by
+    :hg:`debuglfsgc`.
+
     ``format.userustmutablestore`` switches to using the rust mutable stores.
 
     ``treemanifest.blocksendflat`` causes an exception to be thrown if the
@@ -1080,6 +1092,19 @@ def debugwaitonprefetch(ui, repo, **opts):
     return debugcommands.debugwaitonprefetch(repo)
 
 
+@command("debuglfsgc", [], _("hg debuglfsgc"))
+def debuglfsgc(ui, repo, **opts):
+    """evict data from the shared LFS store
+
+    The shared LFS pointers and blobs stores are shrunk to
+    ``remotefilelog.lfs.pointersmaxsize`` and ``remotefilelog.lfs.blobsmaxsize``,
+    oldest data first, regardless of when it was last read. Loose LFS blobs are shrunk to
+    ``remotefilelog.lfs.objectsmaxsize``, least recently used first, if it is set.
+    Reports the number of bytes reclaimed.
+    """
+    return debugcommands.debuglfsgc(ui, repo)
+
+
 def resolveprefetchopts(ui, opts):
     if not opts.get("rev"):
         revset = [".", "draft()"]
@@ -505,3 +505,12 @@ def debugwaitonprefetch(repo):
         _("prefetching in %s") % repo.origroot,
     ):
         pass
+
+
+def debuglfsgc(ui, repo):
+    sharedpath = os.path.join(shallowutil.getcachepath(ui), repo.name)
+    pointers, blobs, objects = revisionstore.lfsgc(sharedpath, ui._uiconfig._rcfg._rcfg)
+    ui.write(_("reclaimed %s from the LFS pointers store\n") % util.bytecount(pointers))
+    ui.write(_("reclaimed %s from the LFS blobs store\n") % util.bytecount(blobs))
+    ui.write(_("reclaimed %s from the LFS loose blobs\n") % util.bytecount(objects))
+    ui.write(_("reclaimed %s in total\n") % util.bytecount(pointers + blobs + objects))
@@ -550,6 +550,7 @@ class remotefileslog(filelog.fileslog):
             fileserverclient.getpackclient(repo)
         )
         memcachestore = self.memcachestore(repo)
+        peercachestore = shallowutil.peercachestore(repo)
         edenapistore = self.edenapistore(repo)
 
         correlator = clienttelemetry.correlator(repo.ui)
@@ -564,6 +565,7 @@ class remotefileslog(filelog.fileslog):
                     memcachestore,
                     edenapistore,
                     correlator=correlator,
+                    peercache=peercachestore,
                 )
             else:
                 sharedonlycontentstore = revisionstore.contentstore(
@@ -573,6 +575,7 @@ class remotefileslog(filelog.fileslog):
                     memcachestore,
                     edenapistore,
                     correlator=correlator,
+                    peercache=peercachestore,
                 )
             sharedonlymetadatastore = revisionstore.metadatastore(
                 None,
@@ -590,6 +593,7 @@ class remotefileslog(filelog.fileslog):
         remotestore = revisionstore.pyremotestore(fileserverclient.getpackclient(repo))
 
         memcachestore = self.memcachestore(repo)
+        peercachestore = shallowutil.peercachestore(repo)
         edenapistore = self.edenapistore(repo)
 
         correlator = clienttelemetry.correlator(repo.ui)
@@ -603,6 +607,7 @@ class remotefileslog(filelog.fileslog):
                 memcachestore,
                 edenapistore,
                 correlator=correlator,
+                peercache=peercachestore,
             )
             if repo.ui.configbool("scmstore", "enableshim"):
                 self.contentstore = self.filescmstore
@@ -17,6 +17,7 @@ import typing
 from collections import defaultdict
 from typing import IO, Any, Dict, Mapping, Tuple
 
+from bindings import revisionstore
 from edenscm.mercurial import error, filelog, pycompat, revlog, util
 from edenscm.mercurial.i18n import _
 from edenscm.mercurial.node import bin, hex, nullid
@@ -101,6 +102,21 @@ def getallcachepaths(ui):
             yield path
 
 
+def peercachestore(repo):
+    """Return the peer cache store configured with peercache.url, or None.
+
+    The store owns an uploader thread and an HTTP client, so a single instance
+    is shared by the file and tree stores of the repo.
+    """
+    if not repo.ui.config("peercache", "url"):
+        return None
+    store = getattr(repo, "_peercachestore", None)
+    if store is None:
+        store = revisionstore.peercachestore(repo.ui._rcfg._rcfg)
+        repo._peercachestore = store
+    return store
+
+
 def createrevlogtext(text, copyfrom=None, copyrev=None):
     """returns a string that matches the revlog contents in a
     traditional revlog
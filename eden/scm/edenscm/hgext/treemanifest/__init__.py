@@ -563,6 +563,7 @@ class basetreemanifestlog(object):
                 edenapistore,
                 "manifests",
                 correlator=correlator,
+                peercache=shallowutil.peercachestore(self._repo),
             )
             if self._repo.ui.configbool("scmstore", "enableshim"):
                 self.datastore = self.treescmstore
@@ -1004,6 +1004,7 @@ class basetreemanifestlog(object):
                 edenapistore,
                 "manifests",
                 correlator=correlator,
+                peercache=shallowutil.peercachestore(self._repo),
             )
             self.datastore = self.treescmstore.get_contentstore()
             self.historystore = revisionstore.metadatastore(
@@ -29,6 +29,7 @@ configtable = {}
 configitem = registrar.configitem(configtable)
 configitem("workingcopy", "enablerustwalker", default=False)
 configitem("workingcopy", "rustpendingchanges", default=False)
+configitem("workingcopy", "changesource", default=None)
 
 
 class physicalfilesystem(object):
@@ -164,12 +165,24 @@ class physicalfilesystem(object):
         or not.
         """
         if self.ui.configbool("workingcopy", "rustpendingchanges", False):
-            physicalfs = workingcopy.physicalfilesystem(self.opener.join(""))
+            changesource = self.ui.config("workingcopy", "changesource")
+            physicalfs = workingcopy.physicalfilesystem(
+                self.opener.join(""), changesource
+            )
+            oldid = self.dirstate.identity()
             pendingchanges = physicalfs.pendingchanges(
                 self.dirstate._map._tree, match, False, self.dirstate._lastnormaltime
             )
+            results = []
             for fn in pendingchanges:
+                results.append(fn[0])
                 yield fn
+
+            if changesource:
+                # The change source cursor, and the files it reported, were recorded in
+                # the treestate. Write it so the next query starts from there.
+                self.dirstate._dirty = True
+                self._postpendingfixup(oldid, results)
         else:
             results = []
             for fn in self._pendingchanges(match, listignored):
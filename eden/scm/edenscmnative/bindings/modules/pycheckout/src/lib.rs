@@ -31,6 +31,7 @@ use manifest_tree::TreeManifest;
 use pathmatcher::Matcher;
 use progress_model::ProgressBar;
 use pyconfigparser::config;
+use pycopytrace::copytracer;
 use pymanifest::treemanifest;
 use pypathmatcher::extract_matcher;
 use pypathmatcher::extract_option_matcher;
@@ -209,6 +210,8 @@ py_class!(class mergeresult |py| {
         src_manifest: &treemanifest,
         dst_manifest: &treemanifest,
         ancestor_manifest: &treemanifest,
+        // Follow renames between the ancestor and either side
+        copytracer: Option<copytracer> = None,
         // matcher: Option<PyObject> = None,
         // If sparse profile changes, contains Some((old_sparse_matcher, new_sparse_matcher))
         // sparse_change: Option<(PyObject, PyObject)> = None,
@@ -216,11 +219,18 @@ py_class!(class mergeresult |py| {
         let src_lock = src_manifest.get_underlying(py);
         let dst_lock = dst_manifest.get_underlying(py);
         let ancestor_lock = ancestor_manifest.get_underlying(py);
+        let tracer = copytracer.map(|t| t.extract_inner(py));
         let merge_result = py.allow_threads(move || -> Result<_> {
             let src = src_lock.read();
             let dst = dst_lock.read();
             let ancestor = ancestor_lock.read();
-            Merge{}.merge(&*src, &*dst, &*ancestor)
+            let mut merge = Merge::default();
+            if let Some(tracer) = tracer {
+                let src_copies = tracer.trace_copies(&*ancestor, &*src)?;
+                let dst_copies = tracer.trace_copies(&*ancestor, &*dst)?;
+                merge = merge.with_copies(&src_copies, &dst_copies);
+            }
+            merge.merge(&*src, &*dst, &*ancestor)
         }).map_pyerr(py)?;
         mergeresult::create_instance(py, merge_result)
     }
@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+#![allow(non_camel_case_types)]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use copytrace::CopyTraceConfig;
+use copytrace::CopyTracer;
+use cpython::*;
+use cpython_ext::ExtractInner;
+use cpython_ext::ExtractInnerRef;
+use cpython_ext::ResultPyErrExt;
+use pyconfigparser::config;
+use pymanifest::treemanifest;
+use pyrevisionstore::contentstore;
+use pyrevisionstore::filescmstore;
+use pyrevisionstore::metadatastore;
+use revisionstore::HgIdDataStore;
+
+pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
+    let name = [package, "copytrace"].join(".");
+    let m = PyModule::new(py, &name)?;
+    m.add_class::<copytracer>(py)?;
+    Ok(m)
+}
+
+py_class!(pub class copytracer |py| {
+    data tracer: Arc<CopyTracer>;
+
+    def __new__(
+        _cls,
+        config: &config,
+        historystore: &metadatastore,
+        // contentstore or filescmstore
+        datastore: &PyObject,
+    ) -> PyResult<copytracer> {
+        let config = CopyTraceConfig::from_config(&config.get_cfg(py)).map_pyerr(py)?;
+        let history = historystore.extract_inner(py);
+        let data = contentstore::downcast_from(py, datastore.clone_ref(py)).map(|s| s.extract_inner(py) as Arc<dyn HgIdDataStore>)
+            .or_else(|_| filescmstore::downcast_from(py, datastore.clone_ref(py)).map(|s| s.extract_inner(py) as Arc<dyn HgIdDataStore>))?;
+        let tracer = CopyTracer::new(history, data).with_config(config);
+        copytracer::create_instance(py, Arc::new(tracer))
+    }
+
+    /// tracecopies(old, new) -> {dest: (source, kind, renamed)}
+    ///
+    /// Files added between the old and new manifests that were copied or renamed from a file
+    /// in the old manifest.
+    def tracecopies(&self, old: &treemanifest, new: &treemanifest) -> PyResult<HashMap<String, (String, String, bool)>> {
+        let tracer = self.tracer(py).clone();
+        let old = old.get_underlying(py);
+        let new = new.get_underlying(py);
+        let copies = py.allow_threads(move || {
+            let old = old.read();
+            let new = new.read();
+            tracer.trace_copies(&*old, &*new)
+        }).map_pyerr(py)?;
+        Ok(copies
+            .into_iter()
+            .map(|(dest, copy)| {
+                let kind = copy.kind.to_string();
+                (dest.into_string(), (copy.source.into_string(), kind, copy.renamed))
+            })
+            .collect())
+    }
+});
+
+impl ExtractInnerRef for copytracer {
+    type Inner = Arc<CopyTracer>;
+
+    fn extract_inner_ref<'a>(&'a self, py: Python<'a>) -> &'a Self::Inner {
+        self.tracer(py)
+    }
+}
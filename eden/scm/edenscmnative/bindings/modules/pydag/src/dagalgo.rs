@@ -11,6 +11,8 @@ use async_runtime::try_block_unless_interrupted as block_on;
 use cpython::*;
 use cpython_ext::ResultPyErrExt;
 use cpython_ext::Str;
+use dag::bisect::Bisect;
+use dag::bisect::BisectStep;
 use dag::DagAlgorithm;
 use dag::Vertex;
 
@@ -138,6 +140,31 @@ py_class!(pub class dagalgo |py| {
         Ok(Names(result))
     }
 
+    /// Decide the next step of bisecting, given `good`, `bad` and `skip`
+    /// vertexes.
+    ///
+    /// Return one of:
+    /// - `("test", vertex, untested)`: `vertex` should be tested next.
+    ///   `untested` is the count of candidates that are not tested yet.
+    /// - `("found", vertex, 0)`: `vertex` is the first bad vertex.
+    /// - `("ambiguous", set, 0)`: skipped vertexes prevent finding the first
+    ///   bad vertex. It is one of the vertexes in `set`.
+    def bisect(&self, good: Names, bad: Names, skip: Names) -> PyResult<(Str, PyObject, u64)> {
+        let mut state = Bisect::new();
+        state.mark_good(good.0);
+        state.mark_bad(bad.0);
+        state.mark_skip(skip.0);
+        let step = block_on(state.next(self.dag(py).as_ref())).map_pyerr(py)?;
+        let result = match step {
+            BisectStep::Test { vertex, untested } => {
+                ("test", PyBytes::new(py, vertex.as_ref()).into_object(), untested)
+            }
+            BisectStep::Found(vertex) => ("found", PyBytes::new(py, vertex.as_ref()).into_object(), 0),
+            BisectStep::Ambiguous(set) => ("ambiguous", Names(set).to_py_object(py).into_object(), 0),
+        };
+        Ok((result.0.to_string().into(), result.1, result.2))
+    }
+
     /// Return true if the vertexes are lazily fetched from remote.
     def isvertexlazy(&self) -> PyResult<bool> {
         Ok(self.dag(py).is_vertex_lazy())
@@ -60,6 +60,7 @@ use revisionstore::HistoryPackVersion;
 use revisionstore::IndexedLogHgIdDataStore;
 use revisionstore::IndexedLogHgIdHistoryStore;
 use revisionstore::LegacyStore;
+use revisionstore::LfsStore;
 use revisionstore::LocalStore;
 use revisionstore::MemcacheStore;
 use revisionstore::Metadata;
@@ -67,6 +68,7 @@ use revisionstore::MetadataStore;
 use revisionstore::MetadataStoreBuilder;
 use revisionstore::MutableDataPack;
 use revisionstore::MutableHistoryPack;
+use revisionstore::PeerCacheStore;
 use revisionstore::RemoteDataStore;
 use revisionstore::RemoteHistoryStore;
 use revisionstore::RepackKind;
@@ -115,6 +117,7 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
     m.add_class::<contentstore>(py)?;
     m.add_class::<metadatastore>(py)?;
     m.add_class::<memcachestore>(py)?;
+    m.add_class::<peercachestore>(py)?;
     m.add_class::<filescmstore>(py)?;
     m.add_class::<treescmstore>(py)?;
     m.add(
@@ -144,6 +147,11 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
             )
         ),
     )?;
+    m.add(
+        py,
+        "lfsgc",
+        py_fn!(py, lfsgc(shared_path: &PyPath, config: config)),
+    )?;
     Ok(m)
 }
 
@@ -215,6 +223,16 @@ fn repair(
     .map(Into::into)
 }
 
+/// Evict data from the shared LFS store. Returns the bytes reclaimed from the pointers store,
+/// the blobs store and the loose blobs.
+fn lfsgc(py: Python, shared_path: &PyPath, config: config) -> PyResult<(u64, u64, u64)> {
+    let config = config.get_cfg(py);
+    let stats = py
+        .allow_threads(|| LfsStore::gc(shared_path.as_path(), &config))
+        .map_pyerr(py)?;
+    Ok((stats.pointers, stats.blobs, stats.objects))
+}
+
 py_class!(class datapack |py| {
     data store: Box<DataPack>;
 
@@ -951,7 +969,8 @@ py_class!(pub class contentstore |py| {
         memcache: Option<memcachestore>,
         edenapi: Option<edenapifilestore> = None,
         suffix: Option<String> = None,
-        correlator: Option<String> = None
+        correlator: Option<String> = None,
+        peercache: Option<peercachestore> = None
     ) -> PyResult<contentstore> {
         let remotestore = remote.extract_inner(py);
         let config = config.get_cfg(py);
@@ -976,6 +995,12 @@ py_class!(pub class contentstore |py| {
             builder
         };
 
+        builder = if let Some(peercache) = peercache {
+            builder.peercache(peercache.extract_inner(py))
+        } else {
+            builder
+        };
+
         builder = if let Some(suffix) = suffix {
             builder.suffix(suffix)
         } else {
@@ -1065,7 +1090,7 @@ impl ExtractInnerRef for contentstore {
     }
 }
 
-py_class!(class metadatastore |py| {
+py_class!(pub class metadatastore |py| {
     data store: Arc<MetadataStore>;
 
     def __new__(_cls,
@@ -1169,15 +1194,38 @@ impl ExtractInnerRef for memcachestore {
     }
 }
 
+py_class!(pub class peercachestore |py| {
+    data peercache: Arc<PeerCacheStore>;
+
+    def __new__(_cls, config: config) -> PyResult<peercachestore> {
+        let config = config.get_cfg(py);
+        let peercache = PeerCacheStore::from_config(&config).map_pyerr(py)?;
+        let peercache = peercache
+            .ok_or_else(|| format_err!("peercache.url is not set"))
+            .map_pyerr(py)?;
+        peercachestore::create_instance(py, Arc::new(peercache))
+    }
+});
+
+impl ExtractInnerRef for peercachestore {
+    type Inner = Arc<PeerCacheStore>;
+
+    fn extract_inner_ref<'a>(&'a self, py: Python<'a>) -> &'a Self::Inner {
+        self.peercache(py)
+    }
+}
+
 // TODO(meyer): Make this a `BoxedRwStore` (and introduce such a concept). Will need to implement write
 // for FallbackStore.
 /// Construct a file ReadStore using the provided config, optionally falling back
 /// to the provided legacy HgIdDataStore.
+#[allow(clippy::too_many_arguments)]
 fn make_filescmstore<'a>(
     path: Option<&'a Path>,
     config: &'a ConfigSet,
     remote: Arc<PyHgIdRemoteStore>,
     memcache: Option<Arc<MemcacheStore>>,
+    peercache: Option<Arc<PeerCacheStore>>,
     edenapi_filestore: Option<Arc<EdenApiFileStore>>,
     suffix: Option<String>,
     correlator: Option<String>,
@@ -1205,6 +1253,11 @@ fn make_filescmstore<'a>(
         builder = builder.memcachestore(memcache)
     };
 
+    if let Some(peercache) = peercache {
+        filestore_builder = filestore_builder.peercache(peercache.clone());
+        builder = builder.peercache(peercache);
+    };
+
     if let Some(ref suffix) = suffix {
         builder = builder.suffix(suffix);
         filestore_builder = filestore_builder.suffix(suffix);
@@ -1260,16 +1313,18 @@ py_class!(pub class filescmstore |py| {
         memcache: Option<memcachestore>,
         edenapi: Option<edenapifilestore> = None,
         suffix: Option<String> = None,
-        correlator: Option<String> = None
+        correlator: Option<String> = None,
+        peercache: Option<peercachestore> = None
     ) -> PyResult<filescmstore> {
         // Extract Rust Values
         let path = path.as_ref().map(|v| v.as_path());
         let config = config.get_cfg(py);
         let remote = remote.extract_inner(py);
         let memcache = memcache.map(|v| v.extract_inner(py));
+        let peercache = peercache.map(|v| v.extract_inner(py));
         let edenapi = edenapi.map(|v| v.extract_inner(py));
 
-        let (filestore, contentstore) = make_filescmstore(path, &config, remote, memcache, edenapi, suffix, correlator).map_pyerr(py)?;
+        let (filestore, contentstore) = make_filescmstore(path, &config, remote, memcache, peercache, edenapi, suffix, correlator).map_pyerr(py)?;
 
         filescmstore::create_instance(py, filestore, contentstore)
     }
@@ -1427,11 +1482,13 @@ impl ExtractInnerRef for filescmstore {
 // for FallbackStore.
 /// Construct a tree ReadStore using the provided config, optionally falling back
 /// to the provided legacy HgIdDataStore.
+#[allow(clippy::too_many_arguments)]
 fn make_treescmstore<'a>(
     path: Option<&'a Path>,
     config: &'a ConfigSet,
     remote: Arc<PyHgIdRemoteStore>,
     memcache: Option<Arc<MemcacheStore>>,
+    peercache: Option<Arc<PeerCacheStore>>,
     edenapi_treestore: Option<Arc<EdenApiTreeStore>>,
     suffix: Option<String>,
     correlator: Option<String>,
@@ -1451,6 +1508,11 @@ fn make_treescmstore<'a>(
         treestore_builder = treestore_builder.memcache(memcache);
     };
 
+    if let Some(peercache) = peercache {
+        builder = builder.peercache(peercache.clone());
+        treestore_builder = treestore_builder.peercache(peercache);
+    };
+
     if let Some(ref suffix) = suffix {
         builder = builder.suffix(suffix);
         treestore_builder = treestore_builder.suffix(suffix);
@@ -1495,16 +1557,18 @@ py_class!(pub class treescmstore |py| {
         memcache: Option<memcachestore>,
         edenapi: Option<edenapitreestore> = None,
         suffix: Option<String> = None,
-        correlator: Option<String> = None
+        correlator: Option<String> = None,
+        peercache: Option<peercachestore> = None
     ) -> PyResult<treescmstore> {
         // Extract Rust Values
         let path = path.as_ref().map(|v| v.as_path());
         let config = config.get_cfg(py);
         let remote = remote.extract_inner(py);
         let memcache = memcache.map(|v| v.extract_inner(py));
+        let peercache = peercache.map(|v| v.extract_inner(py));
         let edenapi = edenapi.map(|v| v.extract_inner(py));
 
-        let (treestore, contentstore) = make_treescmstore(path, &config, remote, memcache, edenapi, suffix, correlator).map_pyerr(py)?;
+        let (treestore, contentstore) = make_treescmstore(path, &config, remote, memcache, peercache, edenapi, suffix, correlator).map_pyerr(py)?;
 
         treescmstore::create_instance(py, treestore, contentstore)
     }
@@ -1597,6 +1661,19 @@ py_class!(pub class treescmstore |py| {
         Ok(Vec::new())
     }
 
+    def getmetrics(&self) -> PyResult<Vec<PyTuple>> {
+        let store = self.store(py);
+        Ok(store.metrics().into_iter().map(|(k, v)| {
+            PyTuple::new(
+                py,
+                &[
+                    k.to_py_object(py).into_object(),
+                    v.to_py_object(py).into_object(),
+                ],
+            )
+        }).collect::<Vec<PyTuple>>())
+    }
+
     def getsharedmutable(&self) -> PyResult<mutabledeltastore> {
         let store = self.store(py);
         mutabledeltastore::create_instance(py, store.get_shared_mutable())
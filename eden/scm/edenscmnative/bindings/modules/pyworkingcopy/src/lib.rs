@@ -8,6 +8,7 @@
 #![allow(non_camel_case_types)]
 
 use std::cell::RefCell;
+use std::sync::Arc;
 
 use anyhow::Error;
 use cpython::*;
@@ -19,8 +20,10 @@ use workingcopy::filesystem::ChangeType;
 use workingcopy::filesystem::PendingChangeResult;
 use workingcopy::filesystem::PendingChanges;
 use workingcopy::filesystem::PhysicalFileSystem;
+use workingcopy::mtimejournal::MtimeJournal;
 use workingcopy::walker::WalkError;
 use workingcopy::walker::Walker;
+use workingcopy::watchman::WatchmanChangeSource;
 
 pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
     let name = [package, "workingcopy"].join(".");
@@ -34,8 +37,18 @@ pub fn init_module(py: Python, package: &str) -> PyResult<PyModule> {
 py_class!(class physicalfilesystem |py| {
     data filesystem: RefCell<PhysicalFileSystem>;
 
-    def __new__(_cls, root: PyPathBuf) -> PyResult<physicalfilesystem> {
-        physicalfilesystem::create_instance(py, RefCell::new(PhysicalFileSystem::new(root.to_path_buf()).map_pyerr(py)?))
+    def __new__(_cls, root: PyPathBuf, changesource: Option<String> = None) -> PyResult<physicalfilesystem> {
+        let root = root.to_path_buf();
+        let mut fs = PhysicalFileSystem::new(root.clone()).map_pyerr(py)?;
+        match changesource.as_deref() {
+            None | Some("") => {}
+            Some("watchman") => fs = fs.with_change_source(Arc::new(WatchmanChangeSource::new(root))),
+            Some("mtimejournal") => fs = fs.with_change_source(Arc::new(MtimeJournal::new(root))),
+            Some(name) => {
+                return Err(PyErr::new::<exc::ValueError, _>(py, format!("unknown change source: {}", name)));
+            }
+        }
+        physicalfilesystem::create_instance(py, RefCell::new(fs))
     }
 
     def pendingchanges(&self, pytreestate: treestate, pymatcher: PyObject, include_directories: bool, last_write: u32) -> PyResult<pendingchanges> {
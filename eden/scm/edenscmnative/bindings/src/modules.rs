@@ -22,6 +22,7 @@ pub(crate) fn populate_module(py: Python<'_>, module: &PyModule) -> PyResult<PyN
     m.add(py, "checkout", pycheckout::init_module(py, &name)?)?;
     m.add(py, "cliparser", pycliparser::init_module(py, &name)?)?;
     m.add(py, "configparser", pyconfigparser::init_module(py, &name)?)?;
+    m.add(py, "copytrace", pycopytrace::init_module(py, &name)?)?;
     m.add(py, "dag", pydag::init_module(py, &name)?)?;
     m.add(py, "diffhelpers", pydiffhelpers::init_module(py, &name)?)?;
     m.add(py, "dirs", pydirs::init_module(py, &name)?)?;
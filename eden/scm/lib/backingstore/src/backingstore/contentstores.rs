@@ -23,6 +23,7 @@ use revisionstore::HgIdDataStore;
 use revisionstore::LegacyStore;
 use revisionstore::LocalStore;
 use revisionstore::MemcacheStore;
+use revisionstore::PeerCacheStore;
 use revisionstore::RemoteDataStore;
 use revisionstore::StoreKey;
 use revisionstore::StoreResult;
@@ -49,10 +50,16 @@ impl BackingContentStores {
 
         #[allow(unused_mut)]
         let mut blobstore = ContentStoreBuilder::new(&config).local_path(&store_path);
-        let treestore = ContentStoreBuilder::new(&config)
+        let mut treestore = ContentStoreBuilder::new(&config)
             .local_path(&store_path)
             .suffix(Path::new("manifests"));
 
+        // One peer cache for both stores, so they share its uploader thread and HTTP client.
+        if let Some(peercache) = PeerCacheStore::from_config(config)?.map(Arc::new) {
+            blobstore = blobstore.peercache(peercache.clone());
+            treestore = treestore.peercache(peercache);
+        }
+
         // Memcache takes 30s to initialize on debug builds slowing down tests significantly, let's
         // not even try to initialize it then.
         if !cfg!(debug_assertions) {
@@ -25,6 +25,7 @@ use revisionstore::scmstore::TreeStore;
 use revisionstore::scmstore::TreeStoreBuilder;
 use revisionstore::HgIdDataStore;
 use revisionstore::MemcacheStore;
+use revisionstore::PeerCacheStore;
 use tracing::event;
 use tracing::instrument;
 use tracing::Level;
@@ -56,11 +57,17 @@ impl BackingScmStores {
             filestore = filestore.store_aux_data();
         }
 
-        let treestore = TreeStoreBuilder::new(&config)
+        let mut treestore = TreeStoreBuilder::new(&config)
             .override_edenapi(use_edenapi)
             .local_path(&store_path)
             .suffix(Path::new("manifests"));
 
+        // One peer cache for both stores, so they share its uploader thread and HTTP client.
+        if let Some(peercache) = PeerCacheStore::from_config(config)?.map(Arc::new) {
+            filestore = filestore.peercache(peercache.clone());
+            treestore = treestore.peercache(peercache);
+        }
+
         // Memcache takes 30s to initialize on debug builds slowing down tests significantly, let's
         // not even try to initialize it then.
         if !cfg!(debug_assertions) {
@@ -14,6 +14,7 @@ use manifest::FileMetadata;
 use types::RepoPathBuf;
 
 use crate::actions::UpdateAction;
+use crate::filemerge::FileMerge;
 
 pub enum Conflict {
     // ("m", (f, f, f, False, pa.node()), "versions differ")
@@ -33,6 +34,25 @@ pub enum Conflict {
 #[derive(Default)]
 pub struct ConflictState {
     map: HashMap<RepoPathBuf, Conflict>,
+    // Result of merging the contents of BothChanged files, if it was attempted
+    text_merges: HashMap<RepoPathBuf, FileMerge>,
+}
+
+impl ConflictState {
+    pub fn text_merge(&self, path: &RepoPathBuf) -> Option<&FileMerge> {
+        self.text_merges.get(path)
+    }
+
+    pub fn set_text_merge(&mut self, path: RepoPathBuf, merge: FileMerge) {
+        self.text_merges.insert(path, merge);
+    }
+
+    /// Conflicts that were not resolved by merging file contents.
+    pub fn unresolved(&self) -> impl Iterator<Item = (&RepoPathBuf, &Conflict)> {
+        self.map.iter().filter(move |(path, _)| {
+            !matches!(self.text_merges.get(*path), Some(merge) if !merge.has_conflicts())
+        })
+    }
 }
 
 impl Deref for ConflictState {
@@ -56,7 +76,15 @@ impl fmt::Display for ConflictState {
                 Conflict::SrcRemovedDstChanged(up) => write!(f, "cd {}=>{}\n", path, up.to.hgid)?,
                 Conflict::DstRemovedSrcChanged(up) => write!(f, "dc {}=>{}\n", path, up.to.hgid)?,
                 Conflict::BothChanged { dest, src, .. } => {
-                    write!(f, "m  {} [src=>{}, dest=>{}]\n", path, src.hgid, dest.hgid)?
+                    write!(f, "m  {} [src=>{}, dest=>{}]", path, src.hgid, dest.hgid)?;
+                    match self.text_merges.get(path) {
+                        Some(merge) if merge.has_conflicts() => {
+                            write!(f, " ({} conflicting hunks)", merge.conflict_count())?
+                        }
+                        Some(_) => write!(f, " (merged)")?,
+                        None => {}
+                    }
+                    writeln!(f)?
                 }
             }
         }
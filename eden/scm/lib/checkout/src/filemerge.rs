@@ -0,0 +1,340 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Three-way merge of file contents.
+//!
+//! Both sides are diffed against the base. Changes that touch different parts of the base are
+//! applied together; changes that overlap (or are adjacent) are a conflict, unless both sides
+//! made the same change.
+
+use std::ops::Range;
+
+use xdiff::diff_hunks;
+use xdiff::Hunk;
+
+/// How conflicts are rendered in the merged file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Show both sides of the conflict.
+    Merge,
+    /// Show both sides of the conflict, and the base they were changed from.
+    Diff3,
+}
+
+/// Names shown next to the conflict markers.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeLabels<'a> {
+    pub dest: &'a str,
+    pub base: &'a str,
+    pub src: &'a str,
+}
+
+impl Default for MergeLabels<'static> {
+    fn default() -> Self {
+        Self {
+            dest: "local",
+            base: "base",
+            src: "other",
+        }
+    }
+}
+
+/// A region of the merged file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeHunk {
+    /// Content that merged cleanly.
+    Resolved(Vec<u8>),
+    /// Content that both sides changed differently, with the base it was changed from.
+    Conflict {
+        base: Vec<u8>,
+        dest: Vec<u8>,
+        src: Vec<u8>,
+    },
+}
+
+/// Result of merging the contents of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMerge {
+    hunks: Vec<MergeHunk>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Dest,
+    Src,
+}
+
+impl FileMerge {
+    pub fn hunks(&self) -> &[MergeHunk] {
+        &self.hunks
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.conflict_count() > 0
+    }
+
+    pub fn conflict_count(&self) -> usize {
+        self.hunks
+            .iter()
+            .filter(|hunk| matches!(hunk, MergeHunk::Conflict { .. }))
+            .count()
+    }
+
+    /// The merged content, if there were no conflicts.
+    pub fn resolved(&self) -> Option<Vec<u8>> {
+        if self.has_conflicts() {
+            return None;
+        }
+        Some(self.render(ConflictStyle::Merge, &MergeLabels::default()))
+    }
+
+    /// The merged content, with conflict markers around conflicting regions.
+    pub fn render(&self, style: ConflictStyle, labels: &MergeLabels) -> Vec<u8> {
+        let mut out = Vec::new();
+        for hunk in &self.hunks {
+            match hunk {
+                MergeHunk::Resolved(content) => out.extend_from_slice(content),
+                MergeHunk::Conflict { base, dest, src } => {
+                    write_marker(&mut out, b'<', Some(labels.dest));
+                    write_side(&mut out, dest);
+                    if style == ConflictStyle::Diff3 {
+                        write_marker(&mut out, b'|', Some(labels.base));
+                        write_side(&mut out, base);
+                    }
+                    write_marker(&mut out, b'=', None);
+                    write_side(&mut out, src);
+                    write_marker(&mut out, b'>', Some(labels.src));
+                }
+            }
+        }
+        out
+    }
+
+    fn push_resolved(&mut self, content: Vec<u8>) {
+        if content.is_empty() {
+            return;
+        }
+        match self.hunks.last_mut() {
+            Some(MergeHunk::Resolved(last)) => last.extend_from_slice(&content),
+            _ => self.hunks.push(MergeHunk::Resolved(content)),
+        }
+    }
+}
+
+fn write_marker(out: &mut Vec<u8>, marker: u8, label: Option<&str>) {
+    out.extend_from_slice(&[marker; 7]);
+    if let Some(label) = label {
+        out.push(b' ');
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+/// Conflict markers must start on their own line, even if the side doesn't end with a newline
+/// because it runs to the end of the file.
+fn write_side(out: &mut Vec<u8>, content: &[u8]) {
+    out.extend_from_slice(content);
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+}
+
+/// Lines as xdiff counts them: each line includes its newline, and the last line may not have
+/// one.
+fn split_lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|b| *b == b'\n').collect()
+}
+
+fn join_lines(lines: &[&[u8]]) -> Vec<u8> {
+    lines.concat()
+}
+
+/// Merge `dest` and `src`, which were both changed from `base`.
+pub fn merge_text(base: &[u8], dest: &[u8], src: &[u8]) -> FileMerge {
+    let base_lines = split_lines(base);
+    let dest_lines = split_lines(dest);
+    let src_lines = split_lines(src);
+
+    let dest_hunks = diff_hunks(base, dest);
+    let src_hunks = diff_hunks(base, src);
+
+    // Changes from both sides, in the order they apply to the base.
+    let mut changes: Vec<(Side, &Hunk)> = dest_hunks
+        .iter()
+        .map(|hunk| (Side::Dest, hunk))
+        .chain(src_hunks.iter().map(|hunk| (Side::Src, hunk)))
+        .collect();
+    changes.sort_by_key(|(_, hunk)| (hunk.remove.start, hunk.remove.end));
+
+    let mut result = FileMerge { hunks: Vec::new() };
+    let mut base_pos = 0;
+    // How far each side's line numbers have shifted from the base's, before the current group.
+    let mut dest_offset = 0isize;
+    let mut src_offset = 0isize;
+
+    let mut i = 0;
+    while i < changes.len() {
+        // Group the changes that overlap or touch: the base range they cover is changed by
+        // both sides, or by one side only.
+        let start = changes[i].1.remove.start;
+        let mut end = changes[i].1.remove.end;
+        let mut j = i + 1;
+        while j < changes.len() && changes[j].1.remove.start <= end {
+            end = end.max(changes[j].1.remove.end);
+            j += 1;
+        }
+        let group = &changes[i..j];
+
+        result.push_resolved(join_lines(&base_lines[base_pos..start]));
+
+        let base_range = start..end;
+        let (dest_range, dest_changed) =
+            side_range(group, Side::Dest, &base_range, &mut dest_offset);
+        let (src_range, src_changed) = side_range(group, Side::Src, &base_range, &mut src_offset);
+        let dest_content = join_lines(&dest_lines[dest_range]);
+        let src_content = join_lines(&src_lines[src_range]);
+
+        if !src_changed || dest_content == src_content {
+            result.push_resolved(dest_content);
+        } else if !dest_changed {
+            result.push_resolved(src_content);
+        } else {
+            result.hunks.push(MergeHunk::Conflict {
+                base: join_lines(&base_lines[base_range]),
+                dest: dest_content,
+                src: src_content,
+            });
+        }
+
+        base_pos = end;
+        i = j;
+    }
+    result.push_resolved(join_lines(&base_lines[base_pos..]));
+
+    result
+}
+
+/// The range of lines of one side that corresponds to `base_range`, and whether that side
+/// changed it. `offset` is updated to account for the side's changes in the group.
+fn side_range(
+    group: &[(Side, &Hunk)],
+    side: Side,
+    base_range: &Range<usize>,
+    offset: &mut isize,
+) -> (Range<usize>, bool) {
+    let start = (base_range.start as isize + *offset) as usize;
+
+    let mut changed = false;
+    for (hunk_side, hunk) in group {
+        if *hunk_side == side {
+            changed = true;
+            *offset += hunk.add.len() as isize - hunk.remove.len() as isize;
+        }
+    }
+
+    let end = (base_range.end as isize + *offset) as usize;
+    (start..end, changed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn merge(base: &str, dest: &str, src: &str) -> FileMerge {
+        merge_text(base.as_bytes(), dest.as_bytes(), src.as_bytes())
+    }
+
+    fn render(merge: &FileMerge, style: ConflictStyle) -> String {
+        String::from_utf8(merge.render(style, &MergeLabels::default())).unwrap()
+    }
+
+    #[test]
+    fn test_non_overlapping() {
+        let base = "a\nb\nc\nd\ne\n";
+        let dest = "A\nb\nc\nd\ne\n";
+        let src = "a\nb\nc\nd\nE\nf\n";
+
+        let m = merge(base, dest, src);
+        assert!(!m.has_conflicts());
+        assert_eq!(m.resolved().unwrap(), b"A\nb\nc\nd\nE\nf\n");
+    }
+
+    #[test]
+    fn test_one_side_changed() {
+        let base = "a\nb\n";
+        assert_eq!(merge(base, base, "x\n").resolved().unwrap(), b"x\n");
+        assert_eq!(merge(base, "a\n", base).resolved().unwrap(), b"a\n");
+        assert_eq!(merge("", "new\n", "").resolved().unwrap(), b"new\n");
+    }
+
+    #[test]
+    fn test_same_change() {
+        let m = merge("a\nb\nc\n", "a\nB\nc\n", "a\nB\nc\n");
+        assert_eq!(m.resolved().unwrap(), b"a\nB\nc\n");
+    }
+
+    #[test]
+    fn test_conflict_styles() {
+        let base = "a\nb\nc\n";
+        let dest = "a\nlocal\nc\n";
+        let src = "a\nother\nc\n";
+
+        let m = merge(base, dest, src);
+        assert_eq!(m.conflict_count(), 1);
+        assert_eq!(m.resolved(), None);
+        assert_eq!(
+            m.hunks()[1],
+            MergeHunk::Conflict {
+                base: b"b\n".to_vec(),
+                dest: b"local\n".to_vec(),
+                src: b"other\n".to_vec(),
+            }
+        );
+
+        assert_eq!(
+            render(&m, ConflictStyle::Merge),
+            "a\n<<<<<<< local\nlocal\n=======\nother\n>>>>>>> other\nc\n"
+        );
+        assert_eq!(
+            render(&m, ConflictStyle::Diff3),
+            "a\n<<<<<<< local\nlocal\n||||||| base\nb\n=======\nother\n>>>>>>> other\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_adjacent_changes_conflict() {
+        let m = merge("a\nb\nc\n", "a\nB\nc\n", "a\nb\nC\n");
+        assert_eq!(m.conflict_count(), 1);
+        assert_eq!(
+            render(&m, ConflictStyle::Merge),
+            "a\n<<<<<<< local\nB\nc\n=======\nb\nC\n>>>>>>> other\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_trailing_newline() {
+        let m = merge("a\nb", "a\nx", "a\ny");
+        assert_eq!(
+            render(&m, ConflictStyle::Merge),
+            "a\n<<<<<<< local\nx\n=======\ny\n>>>>>>> other\n"
+        );
+    }
+
+    #[test]
+    fn test_multiple_regions() {
+        let base = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let dest = "1\n2x\n3\n4\n5\n6\n7\n8y\n9\n";
+        let src = "1\n2\n3\n4\n5z\n6\n7\n8w\n9\n10\n";
+
+        let m = merge(base, dest, src);
+        assert_eq!(m.conflict_count(), 1);
+        assert_eq!(
+            render(&m, ConflictStyle::Merge),
+            "1\n2x\n3\n4\n5z\n6\n7\n<<<<<<< local\n8y\n=======\n8w\n>>>>>>> other\n9\n10\n"
+        );
+    }
+}
@@ -57,6 +57,7 @@ use vfs::VFS;
 mod actions;
 #[allow(dead_code)]
 mod conflict;
+mod filemerge;
 #[allow(dead_code)]
 mod merge;
 
@@ -65,6 +66,12 @@ pub use actions::ActionMap;
 use configmodel::Config;
 use configmodel::ConfigExt;
 pub use conflict::Conflict;
+pub use conflict::ConflictState;
+pub use filemerge::merge_text;
+pub use filemerge::ConflictStyle;
+pub use filemerge::FileMerge;
+pub use filemerge::MergeHunk;
+pub use filemerge::MergeLabels;
 pub use merge::Merge;
 pub use merge::MergeResult;
 use status::FileStatus;
@@ -419,7 +426,10 @@ impl CheckoutPlan {
         let mut conflicts = vec![];
         for file in self.all_files() {
             // Unknown files are handled separately in check_unknown_files
-            if !matches!(status.status(file), None | Some(FileStatus::Unknown)) {
+            if !matches!(
+                status.status(file),
+                None | Some(FileStatus::Unknown | FileStatus::Ignored | FileStatus::Clean)
+            ) {
                 conflicts.push(file.as_repo_path());
             }
         }
@@ -5,11 +5,14 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
 
 use anyhow::bail;
 use anyhow::Result;
+use copytrace::CopyMap;
+use manifest::FileMetadata;
 use manifest::FileType;
 use manifest::FsNodeMetadata;
 use manifest::Manifest;
@@ -21,15 +24,31 @@ use crate::actions::ActionMap;
 use crate::actions::UpdateAction;
 use crate::conflict::Conflict;
 use crate::conflict::ConflictState;
+use crate::filemerge::merge_text;
 
 /// Merge operation settings
-pub struct Merge {}
+#[derive(Default)]
+pub struct Merge {
+    // Files renamed from base to src / dest, as new path => old path
+    src_renames: HashMap<RepoPathBuf, RepoPathBuf>,
+    dest_renames: HashMap<RepoPathBuf, RepoPathBuf>,
+}
 
 /// Contains result of the mere, separated by update actions and conflicts
 pub struct MergeResult<M: Manifest> {
     dest: M,
     actions: ActionMap,
     conflicts: ConflictState,
+    // Paths of the versions of the files merged across a rename
+    renamed: HashMap<RepoPathBuf, RenamedPaths>,
+}
+
+/// For a file merged across a rename, the paths its ancestor, dest and src versions live at.
+#[derive(Debug, Clone, PartialEq)]
+struct RenamedPaths {
+    ancestor: RepoPathBuf,
+    dest: RepoPathBuf,
+    src: RepoPathBuf,
 }
 
 pub enum ActionOrConflict {
@@ -38,6 +57,14 @@ pub enum ActionOrConflict {
 }
 
 impl Merge {
+    /// Follow renames, so that changes made to a file on one side are merged into the file it
+    /// was renamed to on the other side. Copies are typically found with copytrace::CopyTracer.
+    pub fn with_copies(mut self, src_copies: &CopyMap, dest_copies: &CopyMap) -> Self {
+        self.src_renames = renames(src_copies);
+        self.dest_renames = renames(dest_copies);
+        self
+    }
+
     // dest          result
     // |             |
     // |  src   =>   dest
@@ -54,13 +81,19 @@ impl Merge {
     ) -> Result<MergeResult<M>> {
         let matcher = AlwaysMatcher::new();
         let diff = base.diff(dest, &matcher)?;
-        let dest_actions = ActionMap::from_diff(diff)?;
+        let mut dest_actions = ActionMap::from_diff(diff)?;
         let diff = base.diff(src, &matcher)?;
-        let src_actions = ActionMap::from_diff(diff)?;
+        let mut src_actions = ActionMap::from_diff(diff)?;
+        let mut result = MergeResult::new_empty(dest.clone());
+        let renamed = self.merge_renames(
+            base,
+            &mut src_actions,
+            &mut dest_actions,
+            &mut result.renamed,
+        )?;
         let dest_files: HashSet<_> = dest_actions.keys().collect();
         let src_files = src_actions.keys().collect();
         let union = dest_files.union(&src_files);
-        let mut result = MergeResult::new_empty(dest.clone());
         for file in union {
             let ac = match (src_actions.get(*file), dest_actions.get(*file)) {
                 (None, Some(_a)) => continue, // Already in destination
@@ -77,6 +110,109 @@ impl Merge {
             let file = (*file).clone();
             result.insert_new(file, ac);
         }
+        for (file, ac) in renamed {
+            result.insert_new(file, ac);
+        }
+        Ok(result)
+    }
+
+    /// Moves changes across renames. Files involved in a rename are taken out of the action
+    /// maps, and what to do with them is returned instead. The paths of the versions of the
+    /// files whose contents need merging are recorded in `renamed`.
+    fn merge_renames<M: Manifest>(
+        &self,
+        base: &M,
+        src_actions: &mut ActionMap,
+        dest_actions: &mut ActionMap,
+        renamed: &mut HashMap<RepoPathBuf, RenamedPaths>,
+    ) -> Result<Vec<(RepoPathBuf, ActionOrConflict)>> {
+        let mut result = vec![];
+        for (new, old) in self.src_renames.iter() {
+            let src_new = match src_actions.get(new) {
+                Some(Action::Update(up)) => up.to,
+                _ => continue,
+            };
+            if self.dest_renames.get(new) == Some(old) {
+                // Both sides renamed the file to the same place
+                let dest_new = match dest_actions.get(new) {
+                    Some(Action::Update(up)) => up.to,
+                    _ => continue,
+                };
+                src_actions.remove(new);
+                dest_actions.remove(new);
+                if src_new.hgid != dest_new.hgid {
+                    let ancestor = base.get_file(old)?;
+                    let conflict = Conflict::BothChanged {
+                        ancestor,
+                        dest: dest_new,
+                        src: src_new,
+                    };
+                    renamed.insert(new.clone(), RenamedPaths::new(old, new, new));
+                    result.push((new.clone(), ActionOrConflict::Conflict(conflict)));
+                }
+            } else if let (Some(Action::Update(dest_old)), false) = (
+                dest_actions.get(old).copied(),
+                dest_actions.contains_key(new),
+            ) {
+                // Renamed in src, changed in dest
+                src_actions.remove(new);
+                src_actions.remove(old);
+                dest_actions.remove(old);
+                let conflict = Conflict::BothChanged {
+                    ancestor: dest_old.from,
+                    dest: dest_old.to,
+                    src: src_new,
+                };
+                renamed.insert(new.clone(), RenamedPaths::new(old, old, new));
+                result.push((old.clone(), ActionOrConflict::Action(Action::Remove)));
+                result.push((new.clone(), ActionOrConflict::Conflict(conflict)));
+            } else if let (Some(Action::Remove), false) = (
+                dest_actions.get(old).copied(),
+                dest_actions.contains_key(new),
+            ) {
+                // Renamed in src, removed in dest
+                src_actions.remove(new);
+                src_actions.remove(old);
+                dest_actions.remove(old);
+                let up = UpdateAction::new(base.get_file(old)?, src_new);
+                let conflict = Conflict::DstRemovedSrcChanged(up);
+                result.push((new.clone(), ActionOrConflict::Conflict(conflict)));
+            }
+        }
+        for (new, old) in self.dest_renames.iter() {
+            if self.src_renames.get(new) == Some(old) {
+                continue;
+            }
+            let dest_new = match dest_actions.get(new) {
+                Some(Action::Update(up)) => up.to,
+                _ => continue,
+            };
+            if let (Some(Action::Update(src_old)), false) =
+                (src_actions.get(old).copied(), src_actions.contains_key(new))
+            {
+                // Renamed in dest, changed in src
+                src_actions.remove(old);
+                dest_actions.remove(old);
+                dest_actions.remove(new);
+                let conflict = Conflict::BothChanged {
+                    ancestor: src_old.from,
+                    dest: dest_new,
+                    src: src_old.to,
+                };
+                renamed.insert(new.clone(), RenamedPaths::new(old, new, old));
+                result.push((new.clone(), ActionOrConflict::Conflict(conflict)));
+            } else if let (Some(Action::Remove), false) =
+                (src_actions.get(old).copied(), src_actions.contains_key(new))
+            {
+                // Renamed in dest, removed in src
+                src_actions.remove(old);
+                dest_actions.remove(old);
+                dest_actions.remove(new);
+                let up = UpdateAction::new(base.get_file(old)?, dest_new);
+                let conflict = Conflict::SrcRemovedDstChanged(up);
+                result.push((new.clone(), ActionOrConflict::Conflict(conflict)));
+            }
+        }
         Ok(result)
     }
 
@@ -112,6 +248,24 @@ impl Merge {
     }
 }
 
+impl RenamedPaths {
+    fn new(ancestor: &RepoPathBuf, dest: &RepoPathBuf, src: &RepoPathBuf) -> Self {
+        Self {
+            ancestor: ancestor.clone(),
+            dest: dest.clone(),
+            src: src.clone(),
+        }
+    }
+}
+
+fn renames(copies: &CopyMap) -> HashMap<RepoPathBuf, RepoPathBuf> {
+    copies
+        .iter()
+        .filter(|(_, copy)| copy.renamed)
+        .map(|(new, copy)| (new.clone(), copy.source.clone()))
+        .collect()
+}
+
 fn both_changed(src: UpdateAction, dest: UpdateAction) -> ActionOrConflict {
     assert_eq!(dest.from, src.from);
     ActionOrConflict::Conflict(Conflict::BothChanged {
@@ -127,6 +281,7 @@ impl<M: Manifest> MergeResult<M> {
             dest,
             actions: Default::default(),
             conflicts: Default::default(),
+            renamed: Default::default(),
         }
     }
 
@@ -206,13 +361,297 @@ impl<M: Manifest> MergeResult<M> {
         &self.actions
     }
 
+    /// Try to merge the contents of files changed on both sides. `read` returns the content of
+    /// a file version, given the path it lives at in its commit: for files merged across a
+    /// rename, that is the path before the rename for the ancestor, and for the side that
+    /// didn't rename the file. Symlinks and binary files are left to the caller.
+    ///
+    /// Files that merged cleanly remain in the conflict state together with their merge result,
+    /// see ConflictState::unresolved.
+    pub fn merge_file_contents(
+        &mut self,
+        mut read: impl FnMut(&RepoPathBuf, &FileMetadata) -> Result<Vec<u8>>,
+    ) -> Result<()> {
+        let mut merges = vec![];
+        for (path, conflict) in self.conflicts.iter() {
+            let (ancestor, dest, src) = match conflict {
+                Conflict::BothChanged {
+                    ancestor,
+                    dest,
+                    src,
+                } => (ancestor, dest, src),
+                _ => continue,
+            };
+            let is_symlink = |meta: &FileMetadata| meta.file_type == FileType::Symlink;
+            if is_symlink(dest) || is_symlink(src) || matches!(ancestor, Some(a) if is_symlink(a)) {
+                continue;
+            }
+            let (ancestor_path, dest_path, src_path) = match self.renamed.get(path) {
+                Some(paths) => (&paths.ancestor, &paths.dest, &paths.src),
+                None => (path, path, path),
+            };
+            // "both created" conflicts are merged against an empty base.
+            let base = match ancestor {
+                Some(ancestor) => read(ancestor_path, ancestor)?,
+                None => vec![],
+            };
+            let dest = read(dest_path, dest)?;
+            let src = read(src_path, src)?;
+            if [&base, &dest, &src].iter().any(|c| is_binary(c)) {
+                continue;
+            }
+            merges.push((path.clone(), merge_text(&base, &dest, &src)));
+        }
+        for (path, merge) in merges {
+            self.conflicts.set_text_merge(path, merge);
+        }
+        Ok(())
+    }
+
     pub fn into_actions_and_conflicts(self) -> (ActionMap, ConflictState) {
         (self.actions, self.conflicts)
     }
 }
 
+// Same heuristic as mercurial's util.binary
+fn is_binary(content: &[u8]) -> bool {
+    content.contains(&0)
+}
+
 impl<T: Manifest> fmt::Display for MergeResult<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}\n{}", self.actions, self.conflicts)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use copytrace::Copy;
+    use copytrace::CopyKind;
+    use manifest_tree::testutil::make_tree_manifest_from_meta;
+    use manifest_tree::testutil::TestStore;
+    use manifest_tree::TreeManifest;
+    use types::HgId;
+
+    use super::*;
+    use crate::filemerge::ConflictStyle;
+    use crate::filemerge::MergeLabels;
+
+    /// Merge a single file "a", whose version in each commit has the given content.
+    fn merge_file(base: &[u8], dest: &[u8], src: &[u8]) -> Result<MergeResult<TreeManifest>> {
+        let store = Arc::new(TestStore::new());
+        let contents: HashMap<HgId, Vec<u8>> = vec![
+            (hgid(1), base.to_vec()),
+            (hgid(2), dest.to_vec()),
+            (hgid(3), src.to_vec()),
+        ]
+        .into_iter()
+        .collect();
+        let manifest = |id: HgId| {
+            make_tree_manifest_from_meta(store.clone(), vec![(rp("a"), FileMetadata::regular(id))])
+        };
+
+        let mut result =
+            Merge::default().merge(&manifest(hgid(3)), &manifest(hgid(2)), &manifest(hgid(1)))?;
+        result.merge_file_contents(|_, meta| Ok(contents[&meta.hgid].clone()))?;
+        Ok(result)
+    }
+
+    #[test]
+    fn test_merge_file_contents_clean() -> Result<()> {
+        let result = merge_file(b"a\nb\nc\n", b"A\nb\nc\n", b"a\nb\nC\n")?;
+
+        let merge = result.conflicts().text_merge(&rp("a")).unwrap();
+        assert_eq!(merge.resolved(), Some(b"A\nb\nC\n".to_vec()));
+        assert_eq!(result.conflicts().unresolved().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_file_contents_conflict() -> Result<()> {
+        let result = merge_file(b"a\nb\nc\n", b"a\nB1\nc\n", b"a\nB2\nc\n")?;
+
+        let merge = result.conflicts().text_merge(&rp("a")).unwrap();
+        assert_eq!(merge.conflict_count(), 1);
+        assert_eq!(merge.resolved(), None);
+        assert_eq!(
+            String::from_utf8(merge.render(ConflictStyle::Merge, &MergeLabels::default()))?,
+            "a\n<<<<<<< local\nB1\n=======\nB2\n>>>>>>> other\nc\n"
+        );
+        assert_eq!(result.conflicts().unresolved().count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_file_contents_binary() -> Result<()> {
+        let result = merge_file(b"a\nb\nc\n", b"A\nb\nc\n", b"a\nb\n\0\n")?;
+
+        assert!(result.conflicts().text_merge(&rp("a")).is_none());
+        assert_eq!(result.conflicts().unresolved().count(), 1);
+
+        Ok(())
+    }
+
+    fn manifest(store: &Arc<TestStore>, files: &[(&str, u8)]) -> TreeManifest {
+        let files = files
+            .iter()
+            .map(|(path, id)| (rp(path), FileMetadata::regular(hgid(*id))));
+        make_tree_manifest_from_meta(store.clone(), files)
+    }
+
+    fn renames(renames: &[(&str, &str)]) -> CopyMap {
+        renames
+            .iter()
+            .map(|(new, old)| {
+                let copy = Copy {
+                    source: rp(old),
+                    kind: CopyKind::Exact,
+                    renamed: true,
+                };
+                (rp(new), copy)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rename_on_one_side() -> Result<()> {
+        let store = Arc::new(TestStore::new());
+        let base = manifest(&store, &[("a", 1), ("c", 1)]);
+        let src = manifest(&store, &[("b", 1), ("c", 1)]);
+        let dest = manifest(&store, &[("a", 2), ("c", 1)]);
+
+        // The change made in dest is merged into the file src renamed it to.
+        let result = Merge::default()
+            .with_copies(&renames(&[("b", "a")]), &CopyMap::new())
+            .merge(&src, &dest, &base)?;
+        assert!(matches!(
+            result.actions().get(&rp("a")),
+            Some(Action::Remove)
+        ));
+        assert_eq!(result.actions().len(), 1);
+        assert!(matches!(
+            result.conflicts().get(&rp("b")),
+            Some(Conflict::BothChanged { ancestor: Some(ancestor), dest, src })
+                if ancestor.hgid == hgid(1) && dest.hgid == hgid(2) && src.hgid == hgid(1)
+        ));
+        assert_eq!(result.conflicts().len(), 1);
+
+        // Same for a change made in src to a file renamed in dest.
+        let result = Merge::default()
+            .with_copies(&CopyMap::new(), &renames(&[("b", "a")]))
+            .merge(&dest, &src, &base)?;
+        assert!(result.actions().is_empty());
+        assert!(matches!(
+            result.conflicts().get(&rp("b")),
+            Some(Conflict::BothChanged { ancestor: Some(ancestor), dest, src })
+                if ancestor.hgid == hgid(1) && dest.hgid == hgid(1) && src.hgid == hgid(2)
+        ));
+        assert_eq!(result.conflicts().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_file_contents_across_rename() -> Result<()> {
+        let store = Arc::new(TestStore::new());
+        let base = manifest(&store, &[("a", 1)]);
+        let src = manifest(&store, &[("b", 3)]);
+        let dest = manifest(&store, &[("a", 2)]);
+        // File versions are only found at the path they live at in their commit.
+        let contents: HashMap<(RepoPathBuf, HgId), &[u8]> = vec![
+            ((rp("a"), hgid(1)), &b"a\nb\nc\n"[..]),
+            ((rp("a"), hgid(2)), &b"A\nb\nc\n"[..]),
+            ((rp("b"), hgid(3)), &b"a\nb\nC\n"[..]),
+        ]
+        .into_iter()
+        .collect();
+        let read = |path: &RepoPathBuf, meta: &FileMetadata| match contents
+            .get(&(path.clone(), meta.hgid))
+        {
+            Some(content) => Ok(content.to_vec()),
+            None => bail!("{} {} not found", path, meta.hgid),
+        };
+
+        let mut result = Merge::default()
+            .with_copies(&renames(&[("b", "a")]), &CopyMap::new())
+            .merge(&src, &dest, &base)?;
+        result.merge_file_contents(read)?;
+        let merge = result.conflicts().text_merge(&rp("b")).unwrap();
+        assert_eq!(merge.resolved(), Some(b"A\nb\nC\n".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_on_both_sides() -> Result<()> {
+        let store = Arc::new(TestStore::new());
+        let base = manifest(&store, &[("a", 1)]);
+        let src = manifest(&store, &[("b", 3)]);
+        let dest = manifest(&store, &[("b", 2)]);
+        let merge = Merge::default().with_copies(&renames(&[("b", "a")]), &renames(&[("b", "a")]));
+
+        // Both sides changed the renamed file.
+        let result = merge.merge(&src, &dest, &base)?;
+        assert!(result.actions().is_empty());
+        assert!(matches!(
+            result.conflicts().get(&rp("b")),
+            Some(Conflict::BothChanged { ancestor: Some(ancestor), dest, src })
+                if ancestor.hgid == hgid(1) && dest.hgid == hgid(2) && src.hgid == hgid(3)
+        ));
+        assert_eq!(result.conflicts().len(), 1);
+
+        // Both sides made the same change.
+        let result = merge.merge(&dest, &dest, &base)?;
+        assert!(result.actions().is_empty());
+        assert!(!result.has_conflicts());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_delete_conflict() -> Result<()> {
+        let store = Arc::new(TestStore::new());
+        let base = manifest(&store, &[("a", 1), ("c", 1)]);
+        let src = manifest(&store, &[("b", 2), ("c", 1)]);
+        let dest = manifest(&store, &[("c", 1)]);
+
+        let result = Merge::default()
+            .with_copies(&renames(&[("b", "a")]), &CopyMap::new())
+            .merge(&src, &dest, &base)?;
+        assert!(result.actions().is_empty());
+        assert!(matches!(
+            result.conflicts().get(&rp("b")),
+            Some(Conflict::DstRemovedSrcChanged(up))
+                if up.from.map(|m| m.hgid) == Some(hgid(1)) && up.to.hgid == hgid(2)
+        ));
+        assert_eq!(result.conflicts().len(), 1);
+
+        // The file was renamed in dest and removed in src.
+        let result = Merge::default()
+            .with_copies(&CopyMap::new(), &renames(&[("b", "a")]))
+            .merge(&dest, &src, &base)?;
+        assert!(result.actions().is_empty());
+        assert!(matches!(
+            result.conflicts().get(&rp("b")),
+            Some(Conflict::SrcRemovedDstChanged(up))
+                if up.from.map(|m| m.hgid) == Some(hgid(1)) && up.to.hgid == hgid(2)
+        ));
+        assert_eq!(result.conflicts().len(), 1);
+
+        Ok(())
+    }
+
+    fn rp(p: &str) -> RepoPathBuf {
+        RepoPathBuf::from_string(p.to_string()).unwrap()
+    }
+
+    fn hgid(p: u8) -> HgId {
+        let mut r = HgId::default().into_byte_array();
+        r[0] = p;
+        HgId::from_byte_array(r)
+    }
+}
@@ -0,0 +1,517 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Detects files that were copied or renamed between two commits.
+//!
+//! Copies recorded in history (the `copyfrom` of a file revision) are found first. Added files
+//! that have no recorded copy are then compared with the removed files, and the most similar
+//! one above a threshold is considered their source.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::bail;
+use anyhow::format_err;
+use anyhow::Result;
+use configmodel::Config;
+use configmodel::ConfigExt;
+use manifest::DiffType;
+use manifest::FileMetadata;
+use manifest::FileType;
+use manifest::Manifest;
+use minibytes::Bytes;
+use pathmatcher::AlwaysMatcher;
+use revisionstore::datastore::strip_metadata;
+use revisionstore::HgIdDataStore;
+use revisionstore::HgIdHistoryStore;
+use revisionstore::StoreKey;
+use revisionstore::StoreResult;
+use types::Key;
+use types::RepoPath;
+use types::RepoPathBuf;
+
+use crate::similarity::count_lines;
+use crate::similarity::max_similarity;
+use crate::similarity::similarity;
+
+mod similarity;
+
+const DEFAULT_SIMILARITY_THRESHOLD: u8 = 50;
+const DEFAULT_MAX_HISTORY_DEPTH: usize = 100;
+const DEFAULT_MAX_CANDIDATES: usize = 100;
+const DEFAULT_MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+
+/// How the source of a copy was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyKind {
+    /// The copy is recorded in the file's history.
+    History,
+    /// The file has the same content as the source.
+    Exact,
+    /// The file has this percentage of lines in common with the source.
+    Similar(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Copy {
+    pub source: RepoPathBuf,
+    pub kind: CopyKind,
+    /// The source no longer exists, so the copy is a rename.
+    pub renamed: bool,
+}
+
+/// Copies between two commits, by destination path.
+pub type CopyMap = HashMap<RepoPathBuf, Copy>;
+
+#[derive(Debug, Clone)]
+pub struct CopyTraceConfig {
+    /// Minimum similarity (in percent) for a removed file to be the source of an added file.
+    pub similarity_threshold: u8,
+    /// How many revisions of a file to look at when searching its history for a copy.
+    pub max_history_depth: usize,
+    /// How many removed files to compare with each added file.
+    pub max_candidates: usize,
+    /// Files larger than this are not compared.
+    pub max_file_size: usize,
+}
+
+impl Default for CopyTraceConfig {
+    fn default() -> Self {
+        Self {
+            similarity_threshold: DEFAULT_SIMILARITY_THRESHOLD,
+            max_history_depth: DEFAULT_MAX_HISTORY_DEPTH,
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+        }
+    }
+}
+
+impl CopyTraceConfig {
+    pub fn from_config(config: &dyn Config) -> Result<Self> {
+        let default = Self::default();
+        let get = |name: &str, default: usize| -> Result<usize> {
+            let value = config
+                .get_opt("copytrace", name)
+                .map_err(|e| format_err!("Failed to parse copytrace.{}: {}", name, e))?;
+            Ok(value.unwrap_or(default))
+        };
+        let similarity_threshold =
+            get("similaritythreshold", default.similarity_threshold as usize)?;
+        if similarity_threshold > 100 {
+            bail!(
+                "copytrace.similaritythreshold must be a percentage, got {}",
+                similarity_threshold
+            );
+        }
+        Ok(Self {
+            similarity_threshold: similarity_threshold as u8,
+            max_history_depth: get("maxhistorydepth", default.max_history_depth)?,
+            max_candidates: get("maxcandidates", default.max_candidates)?,
+            max_file_size: get("maxfilesize", default.max_file_size)?,
+        })
+    }
+}
+
+pub struct CopyTracer {
+    history: Arc<dyn HgIdHistoryStore>,
+    data: Arc<dyn HgIdDataStore>,
+    config: CopyTraceConfig,
+}
+
+impl CopyTracer {
+    pub fn new(history: Arc<dyn HgIdHistoryStore>, data: Arc<dyn HgIdDataStore>) -> Self {
+        Self {
+            history,
+            data,
+            config: Default::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: CopyTraceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Find the files added between `old` and `new` that were copied or renamed from a file in
+    /// `old`.
+    pub fn trace_copies<M: Manifest>(&self, old: &M, new: &M) -> Result<CopyMap> {
+        let matcher = AlwaysMatcher::new();
+        let mut added = vec![];
+        let mut removed = HashMap::new();
+        for entry in old.diff(new, &matcher)? {
+            let entry = entry?;
+            match entry.diff_type {
+                DiffType::LeftOnly(meta) => {
+                    removed.insert(entry.path, meta);
+                }
+                DiffType::RightOnly(meta) => added.push((entry.path, meta)),
+                DiffType::Changed(_, _) => {}
+            }
+        }
+        // Deterministic results when several files could be the source.
+        added.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut copies = CopyMap::new();
+        let mut untraced = vec![];
+        for (path, meta) in added {
+            match self.history_source(old, &path, &meta)? {
+                Some(source) => {
+                    let renamed = removed.contains_key(&source);
+                    let copy = Copy {
+                        source,
+                        kind: CopyKind::History,
+                        renamed,
+                    };
+                    copies.insert(path, copy);
+                }
+                None => untraced.push((path, meta)),
+            }
+        }
+
+        // A removed file is the source of at most one rename found by content.
+        for copy in copies.values() {
+            removed.remove(&copy.source);
+        }
+        let mut contents = ContentCache::new(self);
+        for (path, meta) in untraced {
+            if removed.is_empty() {
+                break;
+            }
+            if let Some((source, kind)) =
+                self.content_source(&mut contents, &path, &meta, &removed)?
+            {
+                removed.remove(&source);
+                let copy = Copy {
+                    source,
+                    kind,
+                    renamed: true,
+                };
+                copies.insert(path, copy);
+            }
+        }
+
+        Ok(copies)
+    }
+
+    /// Follow the history of a file until it was copied from a file that exists in `old`.
+    /// Chained copies (a to b, then b to c) are followed.
+    fn history_source<M: Manifest>(
+        &self,
+        old: &M,
+        path: &RepoPathBuf,
+        meta: &FileMetadata,
+    ) -> Result<Option<RepoPathBuf>> {
+        let mut key = Key::new(path.clone(), meta.hgid);
+        for _ in 0..self.config.max_history_depth {
+            let info = match self.history.get_node_info(&key)? {
+                Some(info) => info,
+                None => return Ok(None),
+            };
+            let p1 = &info.parents[0];
+            if p1.hgid.is_null() {
+                return Ok(None);
+            }
+            if p1.path != key.path && old.get_file(&p1.path)?.is_some() {
+                return Ok(Some(p1.path.clone()));
+            }
+            key = p1.clone();
+        }
+        Ok(None)
+    }
+
+    /// Find the removed file most similar to an added file.
+    fn content_source(
+        &self,
+        contents: &mut ContentCache,
+        path: &RepoPathBuf,
+        meta: &FileMetadata,
+        removed: &HashMap<RepoPathBuf, FileMetadata>,
+    ) -> Result<Option<(RepoPathBuf, CopyKind)>> {
+        let content = contents.get(path, meta)?;
+        // Empty or large files are not worth comparing.
+        if content.is_empty() || content.len() > self.config.max_file_size {
+            return Ok(None);
+        }
+        let lines = count_lines(&content);
+
+        let mut candidates: Vec<_> = removed
+            .iter()
+            .filter(|(_, candidate)| is_symlink(candidate) == is_symlink(meta))
+            .collect();
+        candidates.sort_by_key(|(candidate, _)| (candidate_rank(path, candidate), *candidate));
+        candidates.truncate(self.config.max_candidates);
+
+        let mut best: Option<(&RepoPathBuf, u8)> = None;
+        for (candidate, candidate_meta) in candidates {
+            let candidate_content = contents.get(candidate, candidate_meta)?;
+            if candidate_content.is_empty() || candidate_content.len() > self.config.max_file_size {
+                continue;
+            }
+            if candidate_content == content {
+                return Ok(Some((candidate.clone(), CopyKind::Exact)));
+            }
+            let threshold = best.map_or(self.config.similarity_threshold, |(_, score)| score + 1);
+            if max_similarity(lines, count_lines(&candidate_content)) < threshold {
+                continue;
+            }
+            let score = similarity(&candidate_content, &content);
+            if score >= threshold {
+                best = Some((candidate, score));
+            }
+        }
+
+        Ok(best.map(|(source, score)| (source.clone(), CopyKind::Similar(score))))
+    }
+
+    fn read(&self, path: &RepoPathBuf, meta: &FileMetadata) -> Result<Bytes> {
+        let key = Key::new(path.clone(), meta.hgid);
+        match self.data.get(StoreKey::hgid(key))? {
+            StoreResult::Found(data) => Ok(strip_metadata(&data.into())?.0),
+            StoreResult::NotFound(key) => bail!("file content not found: {:?}", key),
+        }
+    }
+}
+
+/// Removed files are compared with every added file, so their content is only read once.
+struct ContentCache<'a> {
+    tracer: &'a CopyTracer,
+    contents: HashMap<RepoPathBuf, Bytes>,
+}
+
+impl<'a> ContentCache<'a> {
+    fn new(tracer: &'a CopyTracer) -> Self {
+        Self {
+            tracer,
+            contents: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, path: &RepoPathBuf, meta: &FileMetadata) -> Result<Bytes> {
+        if let Some(content) = self.contents.get(path) {
+            return Ok(content.clone());
+        }
+        let content = self.tracer.read(path, meta)?;
+        self.contents.insert(path.clone(), content.clone());
+        Ok(content)
+    }
+}
+
+fn is_symlink(meta: &FileMetadata) -> bool {
+    meta.file_type == FileType::Symlink
+}
+
+/// Candidates with the same file name, then the same extension, are compared first.
+fn candidate_rank(path: &RepoPath, candidate: &RepoPath) -> u8 {
+    let name = |path: &RepoPath| path.last_component().map(|c| c.as_str().to_string());
+    let extension = |name: &Option<String>| {
+        name.as_ref()
+            .and_then(|name| name.rsplit_once('.').map(|(_, ext)| ext.to_string()))
+    };
+    let (path_name, candidate_name) = (name(path), name(candidate));
+    if path_name == candidate_name {
+        0
+    } else if extension(&path_name).is_some() && extension(&path_name) == extension(&candidate_name)
+    {
+        1
+    } else {
+        2
+    }
+}
+
+impl fmt::Display for CopyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CopyKind::History => write!(f, "history"),
+            CopyKind::Exact => write!(f, "exact"),
+            CopyKind::Similar(score) => write!(f, "similar ({}%)", score),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use manifest::FileMetadata;
+    use manifest_tree::testutil::make_tree_manifest_from_meta;
+    use manifest_tree::testutil::TestStore;
+    use manifest_tree::TreeManifest;
+    use revisionstore::LocalStore;
+    use revisionstore::Metadata;
+    use types::testutil::*;
+    use types::HgId;
+    use types::NodeInfo;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct TestRepo {
+        history: Mutex<HashMap<Key, NodeInfo>>,
+        data: Mutex<HashMap<Key, Vec<u8>>>,
+        next_id: Mutex<u8>,
+    }
+
+    impl TestRepo {
+        /// Add a file revision, optionally copied from another file revision.
+        fn add(&self, path: &str, content: &str, p1: Option<&Key>) -> FileMetadata {
+            let hgid = {
+                let mut next_id = self.next_id.lock().unwrap();
+                *next_id += 1;
+                HgId::from_byte_array([*next_id; 20])
+            };
+            let key = Key::new(repo_path_buf(path), hgid);
+            let p1 = p1.cloned().unwrap_or_else(|| null_key(path));
+            let info = NodeInfo {
+                parents: [p1, null_key(path)],
+                linknode: hgid,
+            };
+            self.history.lock().unwrap().insert(key.clone(), info);
+            self.data
+                .lock()
+                .unwrap()
+                .insert(key, content.as_bytes().to_vec());
+            FileMetadata::regular(hgid)
+        }
+    }
+
+    impl LocalStore for TestRepo {
+        fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            Ok(keys.to_vec())
+        }
+    }
+
+    impl HgIdHistoryStore for TestRepo {
+        fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+            Ok(self.history.lock().unwrap().get(key).cloned())
+        }
+
+        fn refresh(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl HgIdDataStore for TestRepo {
+        fn get(&self, key: StoreKey) -> Result<StoreResult<Vec<u8>>> {
+            let data = match &key {
+                StoreKey::HgId(k) => self.data.lock().unwrap().get(k).cloned(),
+                _ => None,
+            };
+            Ok(match data {
+                Some(data) => StoreResult::Found(data),
+                None => StoreResult::NotFound(key),
+            })
+        }
+
+        fn get_meta(&self, key: StoreKey) -> Result<StoreResult<Metadata>> {
+            Ok(StoreResult::NotFound(key))
+        }
+
+        fn refresh(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn tracer(repo: &Arc<TestRepo>) -> CopyTracer {
+        CopyTracer::new(repo.clone(), repo.clone())
+    }
+
+    fn manifest(files: &[(&str, FileMetadata)]) -> TreeManifest {
+        let store = Arc::new(TestStore::new());
+        make_tree_manifest_from_meta(
+            store,
+            files
+                .iter()
+                .map(|(path, meta)| (repo_path_buf(path), *meta)),
+        )
+    }
+
+    fn key(path: &str, meta: &FileMetadata) -> Key {
+        Key::new(repo_path_buf(path), meta.hgid)
+    }
+
+    #[test]
+    fn test_history_rename() {
+        let repo = Arc::new(TestRepo::default());
+        let a = repo.add("a", "1\n2\n3\n", None);
+        let b = repo.add("dir/b", "completely different\n", Some(&key("a", &a)));
+        let b2 = repo.add("dir/b", "changed again\n", Some(&key("dir/b", &b)));
+
+        let old = manifest(&[("a", a)]);
+        let new = manifest(&[("dir/b", b2)]);
+        let copies = tracer(&repo).trace_copies(&old, &new).unwrap();
+        assert_eq!(
+            copies.get(&repo_path_buf("dir/b")),
+            Some(&Copy {
+                source: repo_path_buf("a"),
+                kind: CopyKind::History,
+                renamed: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_history_copy_and_chain() {
+        let repo = Arc::new(TestRepo::default());
+        let a = repo.add("a", "a\n", None);
+        let b = repo.add("b", "a\n", Some(&key("a", &a)));
+        let c = repo.add("c", "a\n", Some(&key("b", &b)));
+
+        let old = manifest(&[("a", a)]);
+        let new = manifest(&[("a", a), ("c", c)]);
+        let copies = tracer(&repo).trace_copies(&old, &new).unwrap();
+        assert_eq!(copies.len(), 1);
+        let copy = &copies[&repo_path_buf("c")];
+        assert_eq!(copy.source, repo_path_buf("a"));
+        assert!(!copy.renamed);
+    }
+
+    #[test]
+    fn test_content_rename() {
+        let repo = Arc::new(TestRepo::default());
+        let a = repo.add("a.txt", "1\n2\n3\n4\n", None);
+        let x = repo.add("x.txt", "unrelated\n", None);
+        let e = repo.add("e.rs", "fn main() {}\n", None);
+        let b = repo.add("b.txt", "1\n2\n3\nfour\n", None);
+        let f = repo.add("f/e.rs", "fn main() {}\n", None);
+        let n = repo.add("new.txt", "something new\n", None);
+
+        let old = manifest(&[("a.txt", a), ("x.txt", x), ("e.rs", e)]);
+        let new = manifest(&[("b.txt", b), ("f/e.rs", f), ("new.txt", n)]);
+        let copies = tracer(&repo).trace_copies(&old, &new).unwrap();
+        assert_eq!(copies.len(), 2);
+        assert_eq!(
+            copies[&repo_path_buf("b.txt")].source,
+            repo_path_buf("a.txt")
+        );
+        assert_eq!(copies[&repo_path_buf("b.txt")].kind, CopyKind::Similar(75));
+        assert_eq!(
+            copies[&repo_path_buf("f/e.rs")].source,
+            repo_path_buf("e.rs")
+        );
+        assert_eq!(copies[&repo_path_buf("f/e.rs")].kind, CopyKind::Exact);
+    }
+
+    #[test]
+    fn test_similarity_threshold() {
+        let repo = Arc::new(TestRepo::default());
+        let a = repo.add("a", "1\n2\n3\n4\n", None);
+        let b = repo.add("b", "1\n2\n3\nfour\n", None);
+
+        let old = manifest(&[("a", a)]);
+        let new = manifest(&[("b", b)]);
+        let config = CopyTraceConfig {
+            similarity_threshold: 80,
+            ..Default::default()
+        };
+        let copies = tracer(&repo)
+            .with_config(config)
+            .trace_copies(&old, &new)
+            .unwrap();
+        assert!(copies.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use xdiff::diff_hunks;
+
+/// How similar two texts are, as a percentage of the lines they have in common.
+pub fn similarity(a: &[u8], b: &[u8]) -> u8 {
+    if a == b {
+        return 100;
+    }
+    let a_lines = count_lines(a);
+    let b_lines = count_lines(b);
+    let removed: usize = diff_hunks(a, b).iter().map(|hunk| hunk.remove.len()).sum();
+    let common = a_lines - removed;
+    (common * 2 * 100 / (a_lines + b_lines)) as u8
+}
+
+/// The best similarity two texts with the given line counts can have. Used to skip diffing
+/// texts whose sizes are too different.
+pub fn max_similarity(a_lines: usize, b_lines: usize) -> u8 {
+    if a_lines + b_lines == 0 {
+        return 100;
+    }
+    (a_lines.min(b_lines) * 2 * 100 / (a_lines + b_lines)) as u8
+}
+
+/// Number of lines, as xdiff counts them: the last line may not end with a newline.
+pub fn count_lines(text: &[u8]) -> usize {
+    let newlines = text.iter().filter(|b| **b == b'\n').count();
+    if text.is_empty() || text.ends_with(b"\n") {
+        newlines
+    } else {
+        newlines + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\n"), 100);
+        assert_eq!(similarity(b"a\nb\nc\nd\n", b"a\nb\nc\nx\n"), 75);
+        assert_eq!(similarity(b"a\nb\n", b"a\nb\nc\nd\ne\nf\n"), 50);
+        assert_eq!(similarity(b"a\n", b"b\n"), 0);
+        assert_eq!(similarity(b"a\nb", b"a\nc"), 50);
+    }
+
+    #[test]
+    fn test_max_similarity() {
+        assert_eq!(max_similarity(0, 0), 100);
+        assert_eq!(max_similarity(2, 6), 50);
+        assert_eq!(max_similarity(3, 0), 0);
+        assert_eq!(count_lines(b""), 0);
+        assert_eq!(count_lines(b"a\nb"), 2);
+        assert_eq!(count_lines(b"a\nb\n"), 2);
+    }
+}
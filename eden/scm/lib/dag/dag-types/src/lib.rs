@@ -11,6 +11,7 @@ pub mod clone;
 pub mod id;
 pub mod location;
 pub mod segment;
+pub mod subgraph;
 
 pub use clone::CloneData;
 pub use id::Bytes;
@@ -21,3 +22,4 @@ pub use id::VertexName;
 pub use location::Location;
 pub use segment::FlatSegment;
 pub use segment::PreparedFlatSegments;
+pub use subgraph::SubgraphBundle;
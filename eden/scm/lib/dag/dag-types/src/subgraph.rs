@@ -0,0 +1,122 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::id::Group;
+use crate::id::Id;
+use crate::segment::PreparedFlatSegments;
+
+/// A portable subgraph, for example, a stack of draft commits.
+///
+/// Unlike [`CloneData`](crate::CloneData), which covers a full clone or a
+/// master fast-forward, a bundle can describe any set of vertexes and be
+/// imported into another graph that has the parents of the set.
+///
+/// `Id`s are from the exporting graph. The importing graph assigns its own
+/// `Id`s. The group of each vertex is the group of its `Id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize)]
+pub struct SubgraphBundle<Name> {
+    /// Format version. Importers reject versions they do not understand.
+    pub version: u32,
+
+    /// Segments of the subgraph. Parents outside the subgraph must exist in
+    /// the importing graph.
+    pub flat_segments: PreparedFlatSegments,
+
+    /// Names of all vertexes in the subgraph, and of the parents outside it.
+    pub idmap: HashMap<Id, Name>,
+}
+
+impl<Name> SubgraphBundle<Name> {
+    /// The version written by this implementation.
+    pub const VERSION: u32 = 1;
+
+    /// Create a bundle using the current format version.
+    pub fn new(flat_segments: PreparedFlatSegments, idmap: HashMap<Id, Name>) -> Self {
+        Self {
+            version: Self::VERSION,
+            flat_segments,
+            idmap,
+        }
+    }
+
+    pub fn convert_vertex<T, F: Fn(Name) -> T>(self, f: F) -> SubgraphBundle<T> {
+        let idmap = self.idmap.into_iter().map(|(k, v)| (k, f(v))).collect();
+        SubgraphBundle {
+            version: self.version,
+            flat_segments: self.flat_segments,
+            idmap,
+        }
+    }
+
+    /// Heads of the subgraph, and their groups.
+    pub fn heads(&self) -> Vec<(Id, Group)> {
+        let parents: BTreeSet<Id> = self
+            .flat_segments
+            .segments
+            .iter()
+            .flat_map(|seg| seg.parents.iter().copied())
+            .collect();
+        self.flat_segments
+            .segments
+            .iter()
+            .filter(|seg| !parents.contains(&seg.high))
+            .map(|seg| (seg.high, seg.high.group()))
+            .collect()
+    }
+
+    /// Parents of the subgraph that are not part of it.
+    pub fn external_parents(&self) -> BTreeSet<Id> {
+        let segments = &self.flat_segments.segments;
+        let contains = |id: Id| segments.iter().any(|seg| seg.low <= id && id <= seg.high);
+        segments
+            .iter()
+            .flat_map(|seg| seg.parents.iter().copied())
+            .filter(|&id| !contains(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::segment::FlatSegment;
+
+    #[test]
+    fn test_heads_and_external_parents() {
+        // 0..=2 is based on 10. 3..=4 is based on 1. 5 is based on 2 and 4.
+        // NON_MASTER 0..=1 is based on 3.
+        let n = Group::NON_MASTER.min_id();
+        let seg = |low: Id, high: Id, parents: Vec<Id>| FlatSegment { low, high, parents };
+        let flat_segments = PreparedFlatSegments {
+            segments: vec![
+                seg(Id(0), Id(2), vec![Id(10)]),
+                seg(Id(3), Id(4), vec![Id(1)]),
+                seg(Id(5), Id(5), vec![Id(2), Id(4)]),
+                seg(n, n + 1, vec![Id(3)]),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let bundle = SubgraphBundle::<u8>::new(flat_segments, HashMap::new());
+        assert_eq!(bundle.version, SubgraphBundle::<u8>::VERSION);
+        assert_eq!(
+            bundle.heads(),
+            [(Id(5), Group::MASTER), (n + 1, Group::NON_MASTER)]
+        );
+        assert_eq!(
+            bundle.external_parents().into_iter().collect::<Vec<_>>(),
+            [Id(10)]
+        );
+    }
+}
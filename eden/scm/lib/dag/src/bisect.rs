@@ -0,0 +1,184 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! # bisect
+//!
+//! Find the first "bad" vertex using binary search on the graph.
+//!
+//! The state is 3 [`NameSet`]s: "good", "bad" and "skip". Candidates and
+//! midpoints are calculated using [`IdSet`] span arithmetic, so only the
+//! vertexes marked by the user, and the picked midpoint, need their names
+//! resolved. This works with lazy graphs where most names are not known
+//! locally.
+
+use crate::errors::invalid_input;
+use crate::errors::programming;
+use crate::ops::DagAlgorithm;
+use crate::ops::ToIdSet;
+use crate::Id;
+use crate::IdSet;
+use crate::NameSet;
+use crate::Result;
+use crate::VertexName;
+
+/// Number of guesses spread across untested vertexes to consider when picking
+/// the next vertex to test.
+const SPREAD_GUESS_COUNT: u64 = 8;
+
+/// State of a bisection.
+#[derive(Clone)]
+pub struct Bisect {
+    good: NameSet,
+    bad: NameSet,
+    skip: NameSet,
+}
+
+/// Result of [`Bisect::next`].
+#[derive(Debug)]
+pub enum BisectStep {
+    /// The vertex that should be tested next, and the number of candidates
+    /// that are not tested yet.
+    Test { vertex: VertexName, untested: u64 },
+
+    /// The first bad vertex.
+    Found(VertexName),
+
+    /// Skipped vertexes prevent finding the first bad vertex. It is one of
+    /// the vertexes in the set.
+    Ambiguous(NameSet),
+}
+
+impl Bisect {
+    /// Start a new bisection without any vertexes marked.
+    pub fn new() -> Self {
+        Self {
+            good: NameSet::empty(),
+            bad: NameSet::empty(),
+            skip: NameSet::empty(),
+        }
+    }
+
+    /// Mark vertexes as good.
+    pub fn mark_good(&mut self, set: NameSet) {
+        self.good = self.good.union(&set);
+    }
+
+    /// Mark vertexes as bad.
+    pub fn mark_bad(&mut self, set: NameSet) {
+        self.bad = self.bad.union(&set);
+    }
+
+    /// Mark vertexes as untestable.
+    pub fn mark_skip(&mut self, set: NameSet) {
+        self.skip = self.skip.union(&set);
+    }
+
+    pub fn good(&self) -> &NameSet {
+        &self.good
+    }
+
+    pub fn bad(&self) -> &NameSet {
+        &self.bad
+    }
+
+    pub fn skip(&self) -> &NameSet {
+        &self.skip
+    }
+
+    /// Decide what to do next.
+    ///
+    /// Candidates of the first bad vertex are `::bad - ::good`, where `::bad`
+    /// means common ancestors of all bad vertexes. The vertex picked for
+    /// testing splits the candidates into two halves that are as even as
+    /// possible.
+    ///
+    /// `dag` must provide an `IdMap` via its `all()` set, like [`NameDag`]
+    /// does.
+    ///
+    /// [`NameDag`]: crate::NameDag
+    pub async fn next(&self, dag: &(impl DagAlgorithm + ?Sized)) -> Result<BisectStep> {
+        let all = dag.all().await?;
+        let (map, snapshot) = match (all.id_map(), all.dag()) {
+            (Some(map), Some(snapshot)) => (map, snapshot),
+            _ => return programming("bisect requires a Dag with an IdMap"),
+        };
+
+        let good = map.to_id_set(&self.good).await?;
+        let bad = map.to_id_set(&self.bad).await?;
+        let skip = map.to_id_set(&self.skip).await?;
+        if good.is_empty() || bad.is_empty() {
+            return invalid_input("bisect requires both good and bad vertexes");
+        }
+
+        let to_set =
+            |spans: IdSet| NameSet::from_spans_idmap_dag(spans, map.clone(), snapshot.clone());
+        let bad_ancestors = map
+            .to_id_set(&dag.common_ancestors(to_set(bad.clone())).await?)
+            .await?;
+        let good_ancestors = map.to_id_set(&dag.ancestors(to_set(good)).await?).await?;
+        let candidates = bad_ancestors.difference(&good_ancestors);
+        if candidates.is_empty() {
+            return invalid_input("bad vertexes must be descendants of good vertexes");
+        }
+
+        let untested = candidates.difference(&bad).difference(&skip);
+        if untested.is_empty() {
+            return if candidates.count() == 1 {
+                let vertex = map.vertex_name(candidates.max().unwrap()).await?;
+                Ok(BisectStep::Found(vertex))
+            } else {
+                Ok(BisectStep::Ambiguous(to_set(candidates)))
+            };
+        }
+
+        // Ids are topologically sorted. For a linear history, the untested
+        // vertexes right below and above the middle Id are the best guesses.
+        // Vertexes on other branches might have Ids far from the middle, so
+        // also try some guesses spread across all untested vertexes. Pick the
+        // guess that splits the candidates most evenly.
+        let n = candidates.count();
+        let middle = candidates.skip(n / 2).max().unwrap();
+        let below = IdSet::from_spans(vec![Id::MIN..=middle]);
+        let mut guesses = vec![
+            untested.intersection(&below).max(),
+            untested.difference(&below).min(),
+        ];
+        let untested_count = untested.count();
+        let k = untested_count.min(SPREAD_GUESS_COUNT);
+        for i in 0..k {
+            guesses.push(untested.skip(untested_count * (2 * i + 1) / (2 * k)).max());
+        }
+        let mut best: Option<(u64, Id)> = None;
+        for id in guesses.iter().flatten() {
+            // If `id` is bad, the candidates become its ancestors.
+            // Otherwise, they become the rest.
+            let ancestors = dag.ancestors(to_set(IdSet::from(*id))).await?;
+            let count = map
+                .to_id_set(&ancestors)
+                .await?
+                .intersection(&candidates)
+                .count();
+            let worst = count.max(n - count);
+            match best {
+                Some((best_worst, _)) if best_worst <= worst => {}
+                _ => best = Some((worst, *id)),
+            }
+        }
+        let id = best.unwrap().1;
+        let vertex = map.vertex_name(id).await?;
+        Ok(BisectStep::Test {
+            vertex,
+            untested: untested_count,
+        })
+    }
+}
+
+impl Default for Bisect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -33,6 +33,15 @@ pub enum DagError {
     #[error("ProgrammingError: {0}")]
     Programming(String),
 
+    /// The request made by the user is invalid. For example, bisect is asked
+    /// to search between vertexes that are not ancestors of each other.
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// Data from outside, such as a subgraph bundle, is invalid or corrupted.
+    #[error("invalid data: {0}")]
+    InvalidData(String),
+
     /// Logic error in this crate. A bug in this crate or the backend data.
     #[error("bug: {0}")]
     Bug(String),
@@ -93,6 +102,16 @@ pub fn programming<T>(message: impl ToString) -> crate::Result<T> {
     Err(DagError::Programming(message.to_string()))
 }
 
+/// Quick way to return an `InvalidInput` error.
+pub fn invalid_input<T>(message: impl ToString) -> crate::Result<T> {
+    Err(DagError::InvalidInput(message.to_string()))
+}
+
+/// Quick way to return an `InvalidData` error.
+pub fn invalid_data<T>(message: impl ToString) -> crate::Result<T> {
+    Err(DagError::InvalidData(message.to_string()))
+}
+
 pub trait NotFoundError {
     fn not_found_error(&self) -> DagError;
 
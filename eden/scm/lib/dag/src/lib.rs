@@ -12,6 +12,7 @@
 //!
 //! Building blocks for the commit graph used by source control.
 
+pub mod bisect;
 mod bsearch;
 mod default_impl;
 mod delegate;
@@ -39,6 +40,7 @@ pub use dag_types::CloneData;
 pub use dag_types::Group;
 pub use dag_types::Id;
 pub use dag_types::Location;
+pub use dag_types::SubgraphBundle;
 pub use dag_types::VertexName;
 pub use iddag::FirstAncestorConstraint;
 pub use iddag::IdDag;
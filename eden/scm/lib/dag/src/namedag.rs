@@ -28,6 +28,7 @@ use parking_lot::Mutex;
 use parking_lot::RwLock;
 
 use crate::clone::CloneData;
+use crate::errors::invalid_data;
 use crate::errors::programming;
 use crate::errors::DagError;
 use crate::errors::NotFoundError;
@@ -47,8 +48,10 @@ use crate::ops::CheckIntegrity;
 use crate::ops::DagAddHeads;
 use crate::ops::DagAlgorithm;
 use crate::ops::DagExportCloneData;
+use crate::ops::DagExportSubgraph;
 use crate::ops::DagImportCloneData;
 use crate::ops::DagImportPullData;
+use crate::ops::DagImportSubgraph;
 use crate::ops::DagPersistent;
 use crate::ops::DagPullFastForwardMasterData;
 use crate::ops::IdConvert;
@@ -71,6 +74,7 @@ use crate::types_ext::PreparedFlatSegmentsExt;
 use crate::IdSet;
 use crate::Level;
 use crate::Result;
+use crate::SubgraphBundle;
 use crate::VerLink;
 use crate::VertexListWithOptions;
 
@@ -860,6 +864,135 @@ where
     }
 }
 
+#[async_trait::async_trait]
+impl<IS, M, P, S> DagExportSubgraph for AbstractNameDag<IdDag<IS>, M, P, S>
+where
+    IS: IdDagStore,
+    IdDag<IS>: TryClone,
+    M: IdConvert + TryClone + Send + Sync + 'static,
+    P: TryClone + Send + Sync + 'static,
+    S: TryClone + Send + Sync + 'static,
+{
+    async fn export_subgraph(&self, set: NameSet) -> Result<SubgraphBundle<VertexName>> {
+        let id_set = self.to_id_set(&set).await?;
+        let flat_segments = self.dag.idset_to_flat_segments(id_set.clone())?;
+
+        // All vertexes are included, so the importing side does not need to
+        // resolve anything remotely.
+        let mut ids: Vec<Id> = id_set.iter_asc().collect();
+        let bundle = SubgraphBundle::new(flat_segments, HashMap::new());
+        ids.extend(bundle.external_parents());
+
+        let idmap: HashMap<Id, VertexName> = {
+            tracing::debug!("export subgraph: {} vertexes in idmap", ids.len());
+            let fallible_names = self.vertex_name_batch(&ids).await?;
+            let mut names = Vec::with_capacity(fallible_names.len());
+            for name in fallible_names {
+                names.push(name?);
+            }
+            ids.into_iter().zip(names).collect()
+        };
+
+        Ok(SubgraphBundle { idmap, ..bundle })
+    }
+}
+
+#[async_trait::async_trait]
+impl<IS, M, P, S> DagImportSubgraph for AbstractNameDag<IdDag<IS>, M, P, S>
+where
+    IS: IdDagStore + Persist + 'static,
+    IdDag<IS>: TryClone + 'static,
+    M: TryClone + IdMapAssignHead + Persist + Send + Sync + 'static,
+    P: Open<OpenTarget = Self> + TryClone + Send + Sync + 'static,
+    S: IntVersion + TryClone + Persist + Send + Sync + 'static,
+{
+    async fn import_subgraph(&mut self, bundle: SubgraphBundle<VertexName>) -> Result<()> {
+        if bundle.version != SubgraphBundle::<VertexName>::VERSION {
+            return programming(format!(
+                "unsupported subgraph bundle version {} (expected {})",
+                bundle.version,
+                SubgraphBundle::<VertexName>::VERSION,
+            ));
+        }
+        if !self.pending_heads.is_empty() {
+            return programming(format!(
+                "import_subgraph called with pending heads ({:?})",
+                &self.pending_heads.vertexes(),
+            ));
+        }
+
+        let name = |id: Id| -> Result<VertexName> {
+            match bundle.idmap.get(&id) {
+                Some(name) => Ok(name.clone()),
+                None => invalid_data(format!(
+                    "subgraph bundle does not provide name for {:?}",
+                    id
+                )),
+            }
+        };
+
+        // Parents of vertexes in the bundle. Vertexes outside the bundle are
+        // not in the map, and must exist in the local graph.
+        let mut parents: HashMap<VertexName, Vec<VertexName>> = HashMap::new();
+        for seg in &bundle.flat_segments.segments {
+            if seg.low > seg.high || seg.low.group() != seg.high.group() {
+                return invalid_data(format!("subgraph bundle has invalid segment {:?}", seg));
+            }
+            if seg.parents.iter().any(|&p| p >= seg.low) {
+                return invalid_data(format!(
+                    "subgraph bundle has segment {:?} with parents that might cause cycles",
+                    seg
+                ));
+            }
+            let mut parent_names = seg
+                .parents
+                .iter()
+                .map(|&p| name(p))
+                .collect::<Result<Vec<_>>>()?;
+            for id in seg.low.to(seg.high) {
+                let vertex = name(id)?;
+                parents.insert(vertex.clone(), parent_names);
+                parent_names = vec![vertex];
+            }
+        }
+
+        let mut master_heads = Vec::new();
+        let mut non_master_heads = Vec::new();
+        for (id, group) in bundle.heads() {
+            if group == Group::MASTER {
+                master_heads.push(name(id)?);
+            } else {
+                non_master_heads.push(name(id)?);
+            }
+        }
+
+        // Constructs a new graph so we don't expose a broken `self` state on error.
+        let mut new: Self = self.path.open()?;
+        new.set_remote_protocol(self.remote_protocol.clone());
+        new.maybe_reuse_caches_from(self);
+
+        // Insert to the in-memory graph first so the result can be checked
+        // before writing to disk. `flush` moves the master heads to the
+        // master group.
+        let heads: VertexListWithOptions = master_heads.clone().into();
+        let heads = heads.chain(non_master_heads);
+        new.add_heads(&parents, &heads).await?;
+        let problems = new.check_segments().await?;
+        if !problems.is_empty() {
+            return invalid_data(format!(
+                "importing subgraph bundle produces invalid segments: {:?}",
+                problems
+            ));
+        }
+        let master_heads =
+            VertexListWithOptions::from(master_heads).with_highest_group(Group::MASTER);
+        new.flush(&master_heads).await?;
+
+        *self = new;
+        Ok(())
+    }
+}
+
 impl<IS, M, P, S> AbstractNameDag<IdDag<IS>, M, P, S>
 where
     IS: IdDagStore,
@@ -24,6 +24,7 @@ use crate::nameset::id_static::IdStaticSet;
 use crate::nameset::NameSet;
 use crate::IdSet;
 use crate::Result;
+use crate::SubgraphBundle;
 use crate::VerLink;
 use crate::VertexListWithOptions;
 
@@ -309,6 +310,21 @@ pub trait DagPullFastForwardMasterData {
     ) -> Result<CloneData<VertexName>>;
 }
 
+/// Export a subgraph as a `SubgraphBundle`.
+#[async_trait::async_trait]
+pub trait DagExportSubgraph {
+    /// Export vertexes in `set`, with names of their parents outside `set`.
+    async fn export_subgraph(&self, set: NameSet) -> Result<SubgraphBundle<VertexName>>;
+}
+
+/// Import a `SubgraphBundle` into an existing DAG.
+#[async_trait::async_trait]
+pub trait DagImportSubgraph {
+    /// Insert vertexes in the bundle, and write them to disk. Parents outside
+    /// the bundle must exist in the DAG.
+    async fn import_subgraph(&mut self, bundle: SubgraphBundle<VertexName>) -> Result<()>;
+}
+
 /// Persistent the DAG on disk.
 #[async_trait::async_trait]
 pub trait DagPersistent {
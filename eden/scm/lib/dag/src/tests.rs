@@ -27,12 +27,18 @@ use crate::Result;
 mod drawdag;
 mod test_dag;
 
+#[cfg(test)]
+mod test_bisect;
+
 #[cfg(test)]
 mod test_integrity;
 
 #[cfg(test)]
 mod test_sparse;
 
+#[cfg(test)]
+mod test_subgraph;
+
 #[cfg(test)]
 mod test_discontinuous;
 
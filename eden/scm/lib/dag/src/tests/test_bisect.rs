@@ -0,0 +1,126 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use super::TestDag;
+use crate::bisect::Bisect;
+use crate::bisect::BisectStep;
+use crate::errors::DagError;
+use crate::ops::DagAlgorithm;
+use crate::NameSet;
+
+fn set(names: &str) -> NameSet {
+    NameSet::from_static_names(names.split_whitespace().map(|n| n.to_string().into()))
+}
+
+fn describe(step: BisectStep) -> String {
+    match step {
+        BisectStep::Test { vertex, untested } => {
+            format!("test {:?} ({} untested)", vertex, untested)
+        }
+        BisectStep::Found(vertex) => format!("found {:?}", vertex),
+        BisectStep::Ambiguous(set) => format!("ambiguous {:?}", set),
+    }
+}
+
+/// Run bisect on the test dag, treating `first_bad` and its descendants as bad.
+/// Return the steps.
+async fn run(dag: &TestDag, good: &str, bad: &str, skip: &str, first_bad: &str) -> Vec<String> {
+    let mut bisect = Bisect::new();
+    bisect.mark_good(set(good));
+    bisect.mark_bad(set(bad));
+    bisect.mark_skip(set(skip));
+    let bad_set = dag.dag.descendants(set(first_bad)).await.unwrap();
+    let mut steps = Vec::new();
+    loop {
+        let step = bisect.next(&dag.dag).await.unwrap();
+        let vertex = match &step {
+            BisectStep::Test { vertex, .. } => vertex.clone(),
+            _ => {
+                steps.push(describe(step));
+                return steps;
+            }
+        };
+        steps.push(describe(step));
+        if bad_set.contains(&vertex).await.unwrap() {
+            bisect.mark_bad(vertex.into());
+        } else {
+            bisect.mark_good(vertex.into());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_bisect_linear() {
+    let dag = TestDag::draw("A-B-C-D-E-F-G-H-I-J-K-L-M-N-O-P  # master: P");
+    assert_eq!(
+        run(&dag, "A", "P", "", "F").await,
+        [
+            "test I (14 untested)",
+            "test E (7 untested)",
+            "test G (3 untested)",
+            "test F (1 untested)",
+            "found F"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_bisect_merge() {
+    let dag = TestDag::draw(
+        r#"
+        A-B-C-D-E-F-G-M-N
+           \         /
+            H-I-J-K-L    # master: N"#,
+    );
+    assert_eq!(
+        run(&dag, "A", "N", "", "J").await,
+        [
+            "test G (12 untested)",
+            "test K (6 untested)",
+            "test I (3 untested)",
+            "test J (1 untested)",
+            "found J"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_bisect_skip() {
+    let dag = TestDag::draw("A-B-C-D-E  # master: E");
+    assert_eq!(
+        run(&dag, "A", "E", "C D", "D").await,
+        ["test B (1 untested)", "ambiguous <spans [C:E+2:4]>"]
+    );
+
+    // Errors.
+    let mut bisect = Bisect::new();
+    bisect.mark_good(set("C"));
+    let err = bisect.next(&dag.dag).await.unwrap_err();
+    assert!(matches!(err, DagError::InvalidInput(_)));
+    bisect.mark_bad(set("B"));
+    let err = bisect.next(&dag.dag).await.unwrap_err();
+    assert!(matches!(err, DagError::InvalidInput(_)));
+}
+
+#[tokio::test]
+async fn test_bisect_lazy() {
+    let server = TestDag::draw("A-B-C-D-E-F-G-H-I-J-K-L-M-N-O-P  # master: P");
+    let client = server.client_cloned_data().await;
+    assert!(!client.contains_vertex_locally("F"));
+
+    // Only marked vertexes and picked midpoints are resolved.
+    let mut bisect = Bisect::new();
+    bisect.mark_good(set("A"));
+    bisect.mark_bad(set("P"));
+    let step = bisect.next(&client.dag).await.unwrap();
+    assert_eq!(describe(step), "test I (14 untested)");
+    assert_eq!(
+        client.output(),
+        ["resolve names: [A], heads: [P]", "resolve paths: [P~7]"]
+    );
+    assert!(!client.contains_vertex_locally("F"));
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use super::TestDag;
+use crate::errors::DagError;
+use crate::ops::CheckIntegrity;
+use crate::ops::DagAlgorithm;
+use crate::ops::DagExportSubgraph;
+use crate::ops::DagImportSubgraph;
+use crate::Id;
+use crate::NameSet;
+use crate::SubgraphBundle;
+
+fn set(names: &str) -> NameSet {
+    NameSet::from_static_names(names.split_whitespace().map(|n| n.to_string().into()))
+}
+
+async fn describe(dag: &TestDag, names: &str) -> String {
+    let set = dag.dag.sort(&set(names)).await.unwrap();
+    format!("{:?}", set)
+}
+
+#[tokio::test]
+async fn test_subgraph_export_import() {
+    let server = TestDag::draw(
+        r#"
+        A-B-C-D-E-F
+             \
+              X-Y-Z
+               \
+                W          # master: F"#,
+    );
+    let mut client = TestDag::draw("A-B-C-D  # master: D");
+
+    let bundle = server
+        .dag
+        .export_subgraph(set("E F X Y Z W"))
+        .await
+        .unwrap();
+    assert_eq!(bundle.version, SubgraphBundle::<()>::VERSION);
+    assert_eq!(bundle.heads().len(), 3);
+
+    // The bundle survives serialization.
+    let bytes = mincode::serialize(&bundle).unwrap();
+    let bundle = mincode::deserialize(&bytes).unwrap();
+
+    client.dag.import_subgraph(bundle).await.unwrap();
+    assert!(client.dag.check_segments().await.unwrap().is_empty());
+
+    let master = client.dag.master_group().await.unwrap();
+    assert_eq!(format!("{:?}", master), "<spans [A:F+0:5]>");
+    assert_eq!(
+        format!("{:?}", client.dag.parents(set("X W")).await.unwrap()),
+        "<spans [X+N0, C+2]>"
+    );
+    assert_eq!(describe(&client, "Z W").await, "<spans [Z+N3, W+N1]>");
+
+    // Changes are on disk.
+    client.reopen();
+    assert_eq!(
+        format!("{:?}", client.dag.all().await.unwrap()),
+        "<spans [X:Z+N0:N3, A:F+0:5]>"
+    );
+}
+
+#[tokio::test]
+async fn test_subgraph_invalid() {
+    let server = TestDag::draw("A-B-C-D-E  # master: E");
+    let mut client = TestDag::draw("A-B");
+
+    // Parent "C" is missing in the client.
+    let bundle = server.dag.export_subgraph(set("D E")).await.unwrap();
+    assert!(client.dag.import_subgraph(bundle.clone()).await.is_err());
+
+    // Unsupported version.
+    let mut bad = bundle.clone();
+    bad.version = 0;
+    assert!(client.dag.import_subgraph(bad).await.is_err());
+
+    // Missing names.
+    let mut bad = bundle.clone();
+    bad.idmap.remove(&Id(3));
+    assert!(client.dag.import_subgraph(bad).await.is_err());
+
+    // Cycles.
+    let mut bad = bundle;
+    let mut seg = bad.flat_segments.segments.iter().next().unwrap().clone();
+    bad.flat_segments.segments.clear();
+    seg.parents = vec![seg.high];
+    bad.flat_segments.segments.insert(seg);
+    let err = client.dag.import_subgraph(bad).await.unwrap_err();
+    assert!(matches!(err, DagError::InvalidData(_)));
+
+    // The client is unchanged.
+    assert_eq!(
+        format!("{:?}", client.dag.all().await.unwrap()),
+        "<spans [A:B+N0:N1]>"
+    );
+}
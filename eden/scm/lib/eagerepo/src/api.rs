@@ -183,6 +183,11 @@ impl EdenApi for EagerRepo {
                 "EagerRepo does not support child_metadata for trees".to_string(),
             ));
         }
+        if attributes.aux_data {
+            return Err(not_implemented_error(
+                "EagerRepo does not support aux_data for trees".to_string(),
+            ));
+        }
         for key in keys {
             let data = self.get_sha1_blob_for_api(key.hgid)?;
             let mut entry = TreeEntry::default();
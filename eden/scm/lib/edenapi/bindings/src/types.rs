@@ -143,6 +143,7 @@ impl From<TreeAttributes> for ApiTreeAttributes {
             manifest_blob: v.manifest_blob,
             parents: v.parents,
             child_metadata: v.child_metadata,
+            aux_data: false,
         }
     }
 }
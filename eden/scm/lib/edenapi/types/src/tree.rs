@@ -63,6 +63,9 @@ pub struct TreeEntry {
     pub parents: Option<Parents>,
     #[serde(skip)]
     pub children: Option<Vec<Result<TreeChildEntry, EdenApiServerError>>>,
+    /// Digests and sizes of the directory, if `aux_data` was requested.
+    #[serde(skip)]
+    pub tree_aux_data: Option<DirectoryMetadata>,
 }
 
 impl TreeEntry {
@@ -72,6 +75,7 @@ impl TreeEntry {
             data: Some(data),
             parents: Some(parents),
             children: None,
+            tree_aux_data: None,
         }
     }
 
@@ -83,6 +87,14 @@ impl TreeEntry {
         self
     }
 
+    pub fn with_tree_aux_data<'a>(
+        &'a mut self,
+        tree_aux_data: Option<DirectoryMetadata>,
+    ) -> &'a mut Self {
+        self.tree_aux_data = tree_aux_data;
+        self
+    }
+
     pub fn key(&self) -> &Key {
         &self.key
     }
@@ -178,6 +190,7 @@ impl Arbitrary for TreeEntry {
             parents: Arbitrary::arbitrary(g),
             // Recursive TreeEntry in children causes stack overflow in QuickCheck
             children: None,
+            tree_aux_data: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -226,6 +239,8 @@ pub struct TreeAttributes {
     pub parents: bool,
     #[serde(default = "get_true")]
     pub child_metadata: bool,
+    #[serde(default)]
+    pub aux_data: bool,
 }
 
 fn get_true() -> bool {
@@ -238,6 +253,7 @@ impl TreeAttributes {
             manifest_blob: true,
             parents: true,
             child_metadata: true,
+            aux_data: true,
         }
     }
 }
@@ -248,6 +264,7 @@ impl Default for TreeAttributes {
             manifest_blob: true,
             parents: true,
             child_metadata: false,
+            aux_data: false,
         }
     }
 }
@@ -259,6 +276,7 @@ impl Arbitrary for TreeAttributes {
             manifest_blob: Arbitrary::arbitrary(g),
             parents: Arbitrary::arbitrary(g),
             child_metadata: Arbitrary::arbitrary(g),
+            aux_data: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -47,6 +47,9 @@ pub struct WireTreeEntry {
 
     #[serde(rename = "4", default, skip_serializing_if = "is_default")]
     pub error: Option<WireEdenApiServerError>,
+
+    #[serde(rename = "5", default, skip_serializing_if = "is_default")]
+    tree_aux_data: Option<WireDirectoryMetadata>,
 }
 
 impl ToWire for Result<TreeEntry, EdenApiServerError> {
@@ -60,6 +63,7 @@ impl ToWire for Result<TreeEntry, EdenApiServerError> {
                 parents: t.parents.to_wire(),
                 children: t.children.to_wire(),
                 error: None,
+                tree_aux_data: t.tree_aux_data.to_wire(),
             },
             Err(e) => WireTreeEntry {
                 key: e.key.to_wire(),
@@ -89,6 +93,7 @@ impl ToApi for WireTreeEntry {
                 data: self.data,
                 parents: self.parents.to_api()?,
                 children: self.children.to_api()?,
+                tree_aux_data: self.tree_aux_data.to_api()?,
             })
         })
     }
@@ -192,6 +197,9 @@ pub struct WireTreeAttributesRequest {
 
     #[serde(rename = "4", default, skip_serializing_if = "is_default")]
     with_child_metadata: bool,
+
+    #[serde(rename = "5", default, skip_serializing_if = "is_default")]
+    with_aux_data: bool,
 }
 
 impl ToWire for TreeAttributes {
@@ -202,6 +210,7 @@ impl ToWire for TreeAttributes {
             with_data: self.manifest_blob,
             with_parents: self.parents,
             with_child_metadata: self.child_metadata,
+            with_aux_data: self.aux_data,
         }
     }
 }
@@ -215,6 +224,7 @@ impl ToApi for WireTreeAttributesRequest {
             child_metadata: self.with_child_metadata,
             parents: self.with_parents,
             manifest_blob: self.with_data,
+            aux_data: self.with_aux_data,
         })
     }
 }
@@ -278,6 +288,7 @@ impl Arbitrary for WireTreeEntry {
             children: None,
             // TODO
             error: None,
+            tree_aux_data: Arbitrary::arbitrary(g),
         }
     }
 }
@@ -289,6 +300,7 @@ impl Arbitrary for WireTreeAttributesRequest {
             with_data: Arbitrary::arbitrary(g),
             with_parents: Arbitrary::arbitrary(g),
             with_child_metadata: Arbitrary::arbitrary(g),
+            with_aux_data: Arbitrary::arbitrary(g),
         }
     }
 }
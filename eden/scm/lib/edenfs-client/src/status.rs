@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs::read_link;
 use std::fs::symlink_metadata;
@@ -28,12 +29,14 @@ use clidispatch::io::CanColor;
 use clidispatch::io::IO;
 use eden::GetScmStatusParams;
 use eden::GetScmStatusResult;
+use eden::GlobParams;
 use eden::ScmFileStatus;
 use eden::ScmStatus;
 #[cfg(unix)]
 use fbthrift_socket::SocketTransport;
 use sha2::Digest;
 use sha2::Sha256;
+use status::Status;
 use thrift_types::edenfs as eden;
 use thrift_types::edenfs::client::EdenService;
 use thrift_types::fb303_core::client::BaseService;
@@ -41,52 +44,16 @@ use thrift_types::fbthrift::binary_protocol::BinaryProtocol;
 use thrift_types::fbthrift::ApplicationExceptionErrorCode;
 #[cfg(unix)]
 use tokio_uds_compat::UnixStream;
+use types::RepoPathBuf;
 
 use crate::path_relativizer::PathRelativizer;
 
-/// Standalone status command for edenfs.
-///
-/// TODO: This does not match the Python implementation. Namely:
-/// - No pager.
-/// - Colors are hard-coded here.
-/// - Global gitignore paths are hard-coded on edenfs side.
-/// - No matcher support.
-/// - No `--rev` support.
-///
-/// Consider integrating with `clidispatch` cleanly:
-/// - Split into multiple functions.
-///   - Communicate with EdenFS via Thrift.
-///   - Wrap result with dirstate (Move to another crate?).
-///   - Print status (Move to another crate).
-/// - Avoid `print!` directly. Use `clidispatch::io` instead.
-pub fn maybe_status_fastpath(
-    repo_root: &Path,
-    cwd: &Path,
-    print_config: PrintConfig,
-    io: &IO,
-) -> Result<u8> {
-    let rt = tokio::runtime::Runtime::new()?;
-
-    rt.block_on(async { maybe_status_fastpath_internal(repo_root, cwd, print_config, io).await })
-}
-
-#[cfg(windows)]
-async fn maybe_status_fastpath_internal(
-    repo_root: &Path,
-    cwd: &Path,
-    print_config: PrintConfig,
-    io: &IO,
-) -> Result<u8> {
-    Err(FallbackToPython.into())
-}
-
+/// Connect to the EdenFS instance serving the checkout at `repo_root`. Returns the mount point
+/// EdenFS knows the checkout by, and clients for its Thrift services.
 #[cfg(unix)]
-async fn maybe_status_fastpath_internal(
+async fn connect(
     repo_root: &Path,
-    cwd: &Path,
-    print_config: PrintConfig,
-    io: &IO,
-) -> Result<u8> {
+) -> Result<(String, Arc<impl EdenService>, Arc<impl BaseService>)> {
     // Look up the mount point name where Eden thinks this repository is
     // located.  This may be different from repo_root if a parent directory
     // of the Eden mount has been bind mounted to another location, resulting
@@ -114,42 +81,118 @@ async fn maybe_status_fastpath_internal(
     let transport = SocketTransport::new(sock2);
     let fb303_client = <dyn BaseService>::new(BinaryProtocol, transport);
 
-    // TODO(mbolin): Run read_hg_dirstate() and core.run() in parallel.
-    let dirstate_data = read_hg_dirstate(&repo_root)?;
+    Ok((eden_root, client, fb303_client))
+}
 
-    // If any of the files are present that should trigger the 'morestatus' extension, bail out of
-    // the wrapper here and default to the Python implementation. D9025269 has a prototype
-    // implementation of 'morestatus' in Rust, but we should gradually rewrite Mercurial in-place
-    // and call out to it here rather than maintain a parallel implementation in the wrapper.
-    let hg_dir = repo_root.join(".hg");
-    if needs_morestatus_extension(&hg_dir, &dirstate_data.p2) {
-        return Err(FallbackToPython.into());
+/// Status of a file relative to a commit, as reported by EdenFS.
+///
+/// EdenFS does not know about the dirstate: a file that was `hg add`ed is reported as `Added`
+/// just like an untracked file, and a file that was `hg rm`ed is reported as `Removed` just like
+/// a file that was deleted from disk. Callers combine this with [`read_hg_dirstate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdenFsFileStatus {
+    Added,
+    Modified,
+    Removed,
+    Ignored,
+}
+
+/// Files that differ from a commit in an EdenFS checkout.
+#[derive(Debug, Default)]
+pub struct EdenFsStatus {
+    pub entries: BTreeMap<Vec<u8>, EdenFsFileStatus>,
+    /// Paths EdenFS failed to compute the status of, and why.
+    pub errors: BTreeMap<Vec<u8>, String>,
+    /// Version of the running EdenFS server.
+    pub version: String,
+}
+
+impl EdenFsStatus {
+    fn from_thrift(status: ScmStatus, version: String) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        for (path, status) in status.entries {
+            let status = match status {
+                ScmFileStatus::ADDED => EdenFsFileStatus::Added,
+                ScmFileStatus::MODIFIED => EdenFsFileStatus::Modified,
+                ScmFileStatus::REMOVED => EdenFsFileStatus::Removed,
+                ScmFileStatus::IGNORED => EdenFsFileStatus::Ignored,
+                ScmFileStatus(value) => bail!("Unknown file status from EdenFS: {}", value),
+            };
+            entries.insert(path, status);
+        }
+
+        Ok(EdenFsStatus {
+            entries,
+            errors: status.errors,
+            version,
+        })
     }
+}
+
+/// Ask the EdenFS instance serving the checkout at `repo_root` which files differ from `commit`.
+pub fn get_status(
+    repo_root: &Path,
+    commit: CommitHash,
+    list_ignored: bool,
+) -> Result<EdenFsStatus> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(get_status_internal(repo_root, commit, list_ignored))
+}
+
+#[cfg(windows)]
+async fn get_status_internal(
+    repo_root: &Path,
+    commit: CommitHash,
+    list_ignored: bool,
+) -> Result<EdenFsStatus> {
+    Err(FallbackToPython.into())
+}
+
+#[cfg(unix)]
+async fn get_status_internal(
+    repo_root: &Path,
+    commit: CommitHash,
+    list_ignored: bool,
+) -> Result<EdenFsStatus> {
+    let (eden_root, client, fb303_client) = connect(repo_root).await?;
+    let result =
+        get_status_helper(&client, &fb303_client, &eden_root, commit, list_ignored).await?;
+    EdenFsStatus::from_thrift(result.status, result.version)
+}
 
-    let stdout = io::stdout();
-    let use_color = stdout.can_color();
-
-    let status = get_status_helper(
-        &client,
-        &fb303_client,
-        &eden_root,
-        dirstate_data.p1,
-        print_config.status_types.ignored,
-    )
-    .await?;
-
-    let relativizer = PathRelativizer::new(cwd, repo_root);
-    let relativizer = HgStatusPathRelativizer::new(print_config.root_relative, relativizer);
-    let return_code = print_config.print_status(
-        &repo_root,
-        &status.status,
-        &dirstate_data,
-        &relativizer,
-        use_color,
-        io,
-    )?;
-
-    if let Ok(version) = status.version.parse::<u32>() {
+/// List the files in `commit`, as seen by the EdenFS instance serving the checkout at
+/// `repo_root`.
+pub fn get_files(repo_root: &Path, commit: CommitHash) -> Result<Vec<Vec<u8>>> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(get_files_internal(repo_root, commit))
+}
+
+#[cfg(windows)]
+async fn get_files_internal(repo_root: &Path, commit: CommitHash) -> Result<Vec<Vec<u8>>> {
+    Err(FallbackToPython.into())
+}
+
+#[cfg(unix)]
+async fn get_files_internal(repo_root: &Path, commit: CommitHash) -> Result<Vec<Vec<u8>>> {
+    let (eden_root, client, _) = connect(repo_root).await?;
+    let glob = client
+        .globFiles(&GlobParams {
+            mountPoint: eden_root.into_bytes(),
+            globs: vec!["**".to_string()],
+            includeDotfiles: true,
+            revisions: vec![commit.to_vec()],
+            prefetchMetadata: false,
+            listOnlyFiles: true,
+            ..Default::default()
+        })
+        .await?;
+    Ok(glob.matchingFiles)
+}
+
+/// Print a warning if the running EdenFS server is too old. `version` is the server's version, as
+/// returned in [`EdenFsStatus::version`].
+pub fn warn_if_outdated(version: &str, use_color: bool, io: &IO) {
+    if let Ok(version) = version.parse::<u32>() {
         if use_color {
             let _ = io.write_err(BOLD);
         }
@@ -177,13 +220,13 @@ Your running Eden server is more than 45 days old.  You should run
             let _ = io.write_err(RESET);
         }
     }
-
-    Ok(return_code)
 }
 
 const NULL_COMMIT: [u8; 20] = [0; 20];
 
-fn needs_morestatus_extension(hg_dir: &Path, p2: &[u8; 20]) -> bool {
+/// Whether the checkout is in the middle of an operation (merge, rebase, etc.) that `hg status`
+/// reports on in addition to the file statuses.
+pub fn needs_morestatus_extension(hg_dir: &Path, p2: &[u8; 20]) -> bool {
     if p2 != &NULL_COMMIT {
         return true;
     }
@@ -390,6 +433,8 @@ impl PrintConfig {
             root_relative: command.hgplain || command.is_present("root-relative"),
         }
     }
+
+    #[cfg(test)]
     fn print_status(
         &self,
         repo_root: &Path,
@@ -399,7 +444,75 @@ impl PrintConfig {
         use_color: bool,
         io: &IO,
     ) -> Result<u8> {
-        let groups = group_entries(&repo_root, &status, &dirstate_data, io)?;
+        let eden_status = EdenFsStatus::from_thrift(status.clone(), String::new())?;
+        let groups = group_entries(repo_root, &eden_status, dirstate_data);
+        for path in &groups.invalid {
+            if let Err(e) = str::from_utf8(path) {
+                io.write_err(format!(
+                    "skipping invalid utf-8 filename: {} ({})\n",
+                    String::from_utf8_lossy(path),
+                    e
+                ))?;
+            }
+        }
+        let errors = status
+            .errors
+            .iter()
+            .map(|(path, error)| -> Result<(String, String)> {
+                Ok((str::from_utf8(path)?.to_string(), error.clone()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.print_groups(
+            &groups,
+            &dirstate_data.copymap,
+            &errors,
+            relativizer,
+            use_color,
+            io,
+        )
+    }
+
+    /// Print a status computed by the caller, such as the `workingcopy` crate. `errors` lists the
+    /// paths whose status could not be computed, and why.
+    pub fn print_computed_status(
+        &self,
+        repo_root: &Path,
+        cwd: &Path,
+        status: &Status,
+        errors: &[(String, String)],
+        io: &IO,
+    ) -> Result<u8> {
+        let use_color = io::stdout().can_color();
+        let relativizer = PathRelativizer::new(cwd, repo_root);
+        let relativizer = HgStatusPathRelativizer::new(self.root_relative, relativizer);
+
+        let groups = GroupedEntries {
+            modified: sorted_paths(status.modified()),
+            added: sorted_paths(status.added()),
+            removed: sorted_paths(status.removed()),
+            deleted: sorted_paths(status.deleted()),
+            unknown: sorted_paths(status.unknown()),
+            ignored: sorted_paths(status.ignored()),
+            clean: sorted_paths(status.clean()),
+            invalid: Vec::new(),
+        };
+        let copymap = status
+            .copies()
+            .map(|(dest, source)| (PathBuf::from(dest.as_str()), PathBuf::from(source.as_str())))
+            .collect();
+
+        self.print_groups(&groups, &copymap, errors, &relativizer, use_color, io)
+    }
+
+    fn print_groups(
+        &self,
+        groups: &GroupedEntries,
+        copymap: &HashMap<PathBuf, PathBuf>,
+        errors: &[(String, String)],
+        relativizer: &HgStatusPathRelativizer,
+        use_color: bool,
+        io: &IO,
+    ) -> Result<u8> {
         let endl = self.endl;
 
         let print_group =
@@ -436,7 +549,7 @@ impl PrintConfig {
                         endl
                     ))?;
                     if self.copies {
-                        if let Some(ref p) = dirstate_data.copymap.get(path) {
+                        if let Some(ref p) = copymap.get(path) {
                             io.write(format!(
                                 "  {}{}",
                                 &relativizer.relativize(p).display(),
@@ -476,12 +589,12 @@ impl PrintConfig {
         )?;
         print_group(PrintGroup::Clean, self.status_types.clean, &groups.clean)?;
 
-        if status.errors.is_empty() {
+        if errors.is_empty() {
             Ok(0)
         } else {
             io.write_err("Encountered errors computing status for some paths:\n")?;
-            for (path_str, error) in &status.errors {
-                let path = Path::new(str::from_utf8(path_str)?);
+            for (path_str, error) in errors {
+                let path = Path::new(path_str);
                 io.write_err(format!(
                     "  {}: {}\n",
                     &relativizer.relativize(&path.to_path_buf()).display(),
@@ -514,71 +627,73 @@ enum PrintGroup {
     Clean,
 }
 
+/// Files of an EdenFS status grouped the way `hg status` reports them, once combined with the
+/// dirstate.
 #[derive(Default)]
-struct GroupedEntries {
-    modified: Vec<PathBuf>,
-    added: Vec<PathBuf>,
-    removed: Vec<PathBuf>,
-    deleted: Vec<PathBuf>,
-    unknown: Vec<PathBuf>,
-    ignored: Vec<PathBuf>,
-    clean: Vec<PathBuf>,
+pub struct GroupedEntries {
+    pub modified: Vec<PathBuf>,
+    pub added: Vec<PathBuf>,
+    pub removed: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+    pub unknown: Vec<PathBuf>,
+    pub ignored: Vec<PathBuf>,
+    pub clean: Vec<PathBuf>,
+    /// Paths EdenFS reported that are not valid utf-8. They are not in any group.
+    pub invalid: Vec<Vec<u8>>,
 }
 
-fn group_entries(
+fn sorted_paths<'a>(files: impl Iterator<Item = &'a RepoPathBuf>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = files.map(|file| PathBuf::from(file.as_str())).collect();
+    paths.sort();
+    paths
+}
+
+/// Combine what EdenFS reports with the dirstate, which knows about added, removed and merged
+/// files. Clean files are not listed: EdenFS doesn't report them.
+pub fn group_entries(
     repo_root: &Path,
-    status: &ScmStatus,
+    status: &EdenFsStatus,
     dirstate_data: &DirstateData,
-    io: &IO,
-) -> Result<GroupedEntries> {
+) -> GroupedEntries {
     let mut result = GroupedEntries::default();
     let mut dirstates = dirstate_data.tuples.clone();
     for (path_str, status_code) in &status.entries {
         let path_str = match str::from_utf8(path_str) {
             Ok(s) => s,
-            Err(e) => {
-                io.write_err(format!(
-                    "skipping invalid utf-8 filename: {} ({})\n",
-                    String::from_utf8_lossy(path_str),
-                    e
-                ))?;
+            Err(_) => {
+                result.invalid.push(path_str.clone());
                 continue;
             }
         };
         let path = Path::new(path_str);
         let dirstate = dirstates.remove(path);
         use self::DirstateDataStatus::*;
-        let group = match (status_code.clone(), dirstate) {
-            (ScmFileStatus::MODIFIED, Some(DirstateDataTuple { status: Remove, .. })) => {
+        let group = match (*status_code, dirstate) {
+            (EdenFsFileStatus::Modified, Some(DirstateDataTuple { status: Remove, .. })) => {
                 &mut result.removed
             }
-            (ScmFileStatus::MODIFIED, _) => &mut result.modified,
+            (EdenFsFileStatus::Modified, _) => &mut result.modified,
 
-            (ScmFileStatus::REMOVED, Some(DirstateDataTuple { status: Remove, .. })) => {
+            (EdenFsFileStatus::Removed, Some(DirstateDataTuple { status: Remove, .. })) => {
                 &mut result.removed
             }
-            (ScmFileStatus::REMOVED, _) => &mut result.deleted,
+            (EdenFsFileStatus::Removed, _) => &mut result.deleted,
 
-            (ScmFileStatus::ADDED, Some(DirstateDataTuple { status: Add, .. }))
+            (EdenFsFileStatus::Added, Some(DirstateDataTuple { status: Add, .. }))
             | (
-                ScmFileStatus::ADDED,
+                EdenFsFileStatus::Added,
                 Some(DirstateDataTuple {
                     status: Normal,
                     merge_state: DirstateMergeState::OtherParent,
                     ..
                 }),
             ) => &mut result.added,
-            (ScmFileStatus::ADDED, _) => &mut result.unknown,
+            (EdenFsFileStatus::Added, _) => &mut result.unknown,
 
-            (ScmFileStatus::IGNORED, Some(DirstateDataTuple { status: Add, .. })) => {
+            (EdenFsFileStatus::Ignored, Some(DirstateDataTuple { status: Add, .. })) => {
                 &mut result.added
             }
-            (ScmFileStatus::IGNORED, _) => &mut result.ignored,
-
-            (ScmFileStatus(_), _) => unreachable!(
-                "Illegal state: this should not be reachable \
-                 once Thrift enums are translated as Rust enums."
-            ),
+            (EdenFsFileStatus::Ignored, _) => &mut result.ignored,
         };
         group.push(path.to_path_buf());
     }
@@ -610,7 +725,7 @@ fn group_entries(
         }
     }
 
-    Ok(result)
+    result
 }
 
 struct DirstateReader {
@@ -681,7 +796,8 @@ impl DirstateReader {
     }
 }
 
-fn read_hg_dirstate(repo_root: &Path) -> Result<DirstateData> {
+/// Read the legacy dirstate that Mercurial keeps in EdenFS checkouts.
+pub fn read_hg_dirstate(repo_root: &Path) -> Result<DirstateData> {
     let dirstate = repo_root.join(".hg").join("dirstate");
     let mut reader = DirstateReader {
         reader: BufReader::new(File::open(dirstate)?),
@@ -753,24 +869,26 @@ fn read_hg_dirstate(repo_root: &Path) -> Result<DirstateData> {
     })
 }
 
-type CommitHash = [u8; 20];
+pub type CommitHash = [u8; 20];
 
 #[derive(Clone, Default)]
-struct DirstateData {
-    p1: CommitHash,
-    p2: CommitHash,
-    tuples: HashMap<PathBuf, DirstateDataTuple>,
-    copymap: HashMap<PathBuf, PathBuf>,
+pub struct DirstateData {
+    pub p1: CommitHash,
+    pub p2: CommitHash,
+    /// Files that are not clean relative to `p1`. Clean files are not in the dirstate.
+    pub tuples: HashMap<PathBuf, DirstateDataTuple>,
+    /// Maps the destination of a copy or rename to its source.
+    pub copymap: HashMap<PathBuf, PathBuf>,
 }
 
 #[derive(Clone)]
-struct DirstateDataTuple {
-    status: DirstateDataStatus,
-    merge_state: DirstateMergeState,
+pub struct DirstateDataTuple {
+    pub status: DirstateDataStatus,
+    pub merge_state: DirstateMergeState,
 }
 
 #[derive(Clone, PartialEq)]
-enum DirstateDataStatus {
+pub enum DirstateDataStatus {
     Normal,
     Merge,
     Remove,
@@ -779,7 +897,7 @@ enum DirstateDataStatus {
 }
 
 #[derive(Clone, PartialEq)]
-enum DirstateMergeState {
+pub enum DirstateMergeState {
     NotApplicable,
     BothParents,
     OtherParent,
@@ -789,8 +907,6 @@ enum DirstateMergeState {
 // build and better OSS support.
 #[cfg(test)]
 mod test {
-    use std::collections::BTreeMap;
-
     use telemetry::hgargparse::hg_parser;
     use telemetry::hgargparse::parse_args;
     use telemetry::test_utils::generate_fixture;
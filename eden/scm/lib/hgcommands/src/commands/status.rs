@@ -5,14 +5,27 @@
  * GNU General Public License version 2.
  */
 
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
 use anyhow::Result;
 use clidispatch::errors;
+use clidispatch::io::CanColor;
 use clidispatch::io::IO;
 use clidispatch::repo::Repo;
 use cliparser::define_flags;
-use edenfs_client::status::maybe_status_fastpath;
+use edenfs_client::status::needs_morestatus_extension;
+use edenfs_client::status::read_hg_dirstate;
+use edenfs_client::status::warn_if_outdated;
 use edenfs_client::status::PrintConfig;
 use edenfs_client::status::PrintConfigStatusTypes;
+use parking_lot::Mutex;
+use pathmatcher::GitignoreMatcher;
+use treestate::dirstate::Dirstate;
+use util::path::expand_path;
+use workingcopy::edenfs::EdenFileSystem;
+use workingcopy::filesystem::PhysicalFileSystem;
 
 use crate::commands::FormatterOpts;
 use crate::commands::WalkOpts;
@@ -141,8 +154,104 @@ pub fn run(opts: StatusOpts, io: &IO, repo: Repo) -> Result<u8> {
         root_relative: opts.root_relative,
     };
 
+    if EdenFileSystem::is_eden(repo.path()) {
+        eden_status(&repo, &print_config, io)
+    } else {
+        physical_status(&repo, &print_config, io)
+    }
+}
+
+fn eden_status(repo: &Repo, print_config: &PrintConfig, io: &IO) -> Result<u8> {
+    // Unfinished operations (merge, rebase, etc.) are reported by the Python 'morestatus'
+    // extension.
+    let dirstate = read_hg_dirstate(repo.path())?;
+    if needs_morestatus_extension(repo.dot_hg_path(), &dirstate.p2) {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let eden_fs = EdenFileSystem::new(repo.path().to_path_buf());
+    let status = eden_fs.status(
+        print_config.status_types.ignored,
+        print_config.status_types.clean,
+    )?;
+
     let cwd = std::env::current_dir()?;
-    maybe_status_fastpath(repo.path(), &cwd, print_config, io)
+    let return_code = print_config.print_computed_status(
+        repo.path(),
+        &cwd,
+        &status.status,
+        &status.errors,
+        io,
+    )?;
+    warn_if_outdated(&status.version, std::io::stdout().can_color(), io);
+
+    Ok(return_code)
+}
+
+fn physical_status(repo: &Repo, print_config: &PrintConfig, io: &IO) -> Result<u8> {
+    // Sparse profiles change which files are tracked, and fsmonitor answers status from
+    // watchman. Neither is implemented here.
+    if extension_enabled(repo, "sparse") || extension_enabled(repo, "fsmonitor") {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    // Flat dirstates (treestate disabled) and treestates that were never written are left to
+    // Python, which can also create the treestate.
+    let dirstate = match Dirstate::read(repo.dot_hg_path())? {
+        Some(dirstate) => dirstate,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+    if needs_morestatus_extension(repo.dot_hg_path(), &dirstate.p2.into_byte_array()) {
+        return Err(errors::FallbackToPython.into());
+    }
+    let treestate = match dirstate.open_treestate(repo.dot_hg_path())? {
+        Some(treestate) => treestate,
+        None => return Err(errors::FallbackToPython.into()),
+    };
+
+    let ignore_paths = global_ignore_paths(repo);
+    let ignore = GitignoreMatcher::new(
+        repo.path(),
+        ignore_paths.iter().map(|path| path.as_path()).collect(),
+    );
+
+    let fs = PhysicalFileSystem::new(repo.path().to_path_buf())?;
+    let status = fs.status(
+        Arc::new(Mutex::new(treestate)),
+        Rc::new(ignore),
+        print_config.status_types.ignored,
+        print_config.status_types.clean,
+        0u32.into(),
+    )?;
+
+    // Files whose mtime doesn't tell whether they changed must be compared with p1, which needs
+    // the store. Python does it, and records the files that turn out to be clean so that the next
+    // status can be answered here.
+    if !status.lookups.is_empty() {
+        return Err(errors::FallbackToPython.into());
+    }
+
+    let cwd = std::env::current_dir()?;
+    print_config.print_computed_status(repo.path(), &cwd, &status.status, &[], io)
+}
+
+/// Whether the Python extension `name` is enabled. `extensions.<name>=!` disables it.
+fn extension_enabled(repo: &Repo, name: &str) -> bool {
+    repo.config()
+        .get("extensions", name)
+        .map_or(false, |value| !value.starts_with('!'))
+}
+
+/// Ignore files listed in the `ui.ignore` and `ui.ignore.*` config, relative to the repo root.
+fn global_ignore_paths(repo: &Repo) -> Vec<PathBuf> {
+    let config = repo.config();
+    config
+        .keys("ui")
+        .into_iter()
+        .filter(|name| name.as_ref() == "ignore" || name.starts_with("ignore."))
+        .filter_map(|name| config.get("ui", name))
+        .map(|path| repo.path().join(expand_path(path)))
+        .collect()
 }
 
 pub fn name() -> &'static str {
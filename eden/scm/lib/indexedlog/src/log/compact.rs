@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use crate::errors::IoResultExt;
+use crate::errors::ResultExt;
+use crate::lock::ScopedDirLock;
+use crate::log::FlushFilterContext;
+use crate::log::FlushFilterOutput;
+use crate::log::GenericPath;
+use crate::log::Log;
+use crate::log::OpenOptions;
+use crate::log::GENERATION_DIR_PREFIX;
+use crate::log::META_FILE;
+use crate::log::PRIMARY_FILE;
+
+/// Error type returned by compaction filter functions.
+type FilterError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+// Compaction
+impl Log {
+    /// Rewrite the [`Log`] so it only contains entries selected by `filter`.
+    ///
+    /// [`Log::append`] and [`IndexOutput::Remove`](crate::log::IndexOutput::Remove)
+    /// never remove data from disk. This function writes entries kept by
+    /// `filter` to a fresh generation of the log, with rebuilt indexes, and
+    /// swaps it in. `filter` decides what happens to each entry, in insertion
+    /// order, the same way a flush filter does: keep it, drop it, or replace
+    /// it. Its [`FlushFilterContext`] refers to the new generation, which
+    /// contains the entries kept so far.
+    ///
+    /// In-memory entries are written to disk first, so they are compacted
+    /// too. The directory is locked while compacting, so no writers can race
+    /// with it. Because the change is not append-only, a new epoch is used.
+    /// Other [`Log`]s that opened the directory earlier keep seeing their
+    /// snapshot, and pick up the compacted log on [`Log::sync`], re-applying
+    /// their in-memory entries. Files are not replaced in place: the new
+    /// generation is written to a sub-directory, then a single metadata
+    /// write switches to it. Files of the old generation are removed after
+    /// that, or by a later compaction if they are still in use.
+    ///
+    /// For a [`Log`] that is part of a [`MultiLog`](crate::multi::MultiLog),
+    /// call this between [`MultiLog::lock`](crate::multi::MultiLog::lock) and
+    /// [`MultiLog::write_meta`](crate::multi::MultiLog::write_meta), like
+    /// [`Log::sync`], so the shared metadata picks up the new generation.
+    ///
+    /// Return message useful for human consumption.
+    pub fn compact(
+        &mut self,
+        filter: impl FnMut(&FlushFilterContext, &[u8]) -> Result<FlushFilterOutput, FilterError>,
+    ) -> crate::Result<String> {
+        let result: crate::Result<_> = (|| {
+            self.sync()?;
+            match self.dir.as_opt_path().map(|dir| dir.to_path_buf()) {
+                None => {
+                    let mut log = self.open_options.open(())?;
+                    let message = copy_entries(self, &mut log, filter)?;
+                    *self = log;
+                    Ok(message)
+                }
+                Some(dir) => {
+                    let lock = ScopedDirLock::new(&dir)?;
+                    self.compact_with_lock(filter, &lock)
+                }
+            }
+        })();
+
+        result
+            .context("in Log::compact")
+            .context(|| format!("  Log.dir = {:?}", self.dir))
+    }
+
+    fn compact_with_lock(
+        &mut self,
+        filter: impl FnMut(&FlushFilterContext, &[u8]) -> Result<FlushFilterOutput, FilterError>,
+        lock: &ScopedDirLock,
+    ) -> crate::Result<String> {
+        let dir = self.dir.clone();
+        let open_options = self.open_options.clone();
+        let path = dir.as_opt_path().unwrap();
+
+        // Other writers might have appended entries since `sync`.
+        let old_log = open_options.open_with_lock(&dir, lock)?;
+
+        // Clean up after previous compactions, including interrupted ones.
+        remove_stale_generations(path, old_log.meta.generation, &open_options);
+
+        // Write the new generation to a temporary directory, so an
+        // interrupted compaction does not leave a partial generation behind.
+        let generation = list_generations(path)
+            .into_iter()
+            .chain(Some(old_log.meta.generation))
+            .max()
+            .unwrap_or_default()
+            + 1;
+        let tmp = tempfile::Builder::new()
+            .prefix(COMPACT_TMP_PREFIX)
+            .tempdir_in(path)
+            .context(path, "cannot create tempdir for compaction")?;
+        let mut new_log = open_options
+            .clone()
+            .create(true)
+            .flush_filter(None)
+            .auto_sync_threshold(None)
+            .with_zero_index_lag()
+            .open(tmp.path())?;
+        let message = copy_entries(&old_log, &mut new_log, filter)?;
+        new_log.sync()?;
+
+        let mut meta = new_log.meta.clone();
+        meta.generation = generation;
+        if meta.epoch == old_log.meta.epoch {
+            meta.epoch = meta.epoch.wrapping_add(1);
+        }
+
+        // Release mmaps and handles, so the new directory can be renamed on
+        // Windows. The "meta" and "lock" of the new directory are not part
+        // of the generation.
+        drop(new_log);
+        drop(old_log);
+        for name in [META_FILE, "lock"] {
+            let _ = fs::remove_file(tmp.path().join(name));
+        }
+        let generation_path = meta.data_dir(path);
+        fs::rename(tmp.path(), &generation_path).context(&generation_path, || {
+            format!("cannot rename from {:?}", tmp.path())
+        })?;
+
+        // Writing the metadata switches readers to the new generation. Until
+        // then, `self` keeps using the old generation, so it stays usable if
+        // anything above fails.
+        dir.write_meta(&meta, open_options.fsync)?;
+
+        // Replacing `self` also releases its mmaps of the old generation, so
+        // its files can be removed on Windows.
+        *self = open_options.open_with_lock(&dir, lock)?;
+
+        // For a MultiLog, the shared metadata on disk refers to the old
+        // generation until `MultiLog::write_meta`. Leave the old generation
+        // to the next compaction.
+        if let GenericPath::Filesystem(_) = dir {
+            remove_stale_generations(path, generation, &open_options);
+        }
+
+        Ok(message)
+    }
+}
+
+impl OpenOptions {
+    /// Compact the [`Log`] at the given directory so it only contains entries
+    /// selected by `filter`.
+    ///
+    /// See [`Log::compact`] for details.
+    ///
+    /// Return message useful for human consumption.
+    pub fn compact(
+        &self,
+        dir: impl Into<GenericPath>,
+        filter: impl FnMut(&FlushFilterContext, &[u8]) -> Result<FlushFilterOutput, FilterError>,
+    ) -> crate::Result<String> {
+        let dir = dir.into();
+        let mut log = self
+            .clone()
+            .create(false)
+            .open(dir.clone())
+            .context(|| format!("in log::OpenOptions::compact({:?})", &dir))?;
+        log.compact(filter)
+    }
+}
+
+/// Append entries of `old_log` selected by `filter` to `new_log`.
+fn copy_entries(
+    old_log: &Log,
+    new_log: &mut Log,
+    mut filter: impl FnMut(&FlushFilterContext, &[u8]) -> Result<FlushFilterOutput, FilterError>,
+) -> crate::Result<String> {
+    let mut old_count = 0;
+    let mut new_count = 0;
+    for entry in old_log.iter().decompressed() {
+        let content = entry?;
+        old_count += 1;
+        let context = FlushFilterContext { log: new_log };
+        match filter(&context, &content)
+            .map_err(|err| crate::Error::wrap(err, "failed to run filter function"))?
+        {
+            FlushFilterOutput::Drop => continue,
+            FlushFilterOutput::Keep => new_log.append(content)?,
+            FlushFilterOutput::Replace(content) => new_log.append(content)?,
+        }
+        new_count += 1;
+    }
+    Ok(format!(
+        "Compacted {} entries ({} bytes) to {} entries ({} bytes)\n",
+        old_count,
+        old_log.meta.primary_len,
+        new_count,
+        new_log.meta.primary_len + new_log.mem_buf.len() as u64,
+    ))
+}
+
+/// Prefix of temporary directories used by compaction.
+const COMPACT_TMP_PREFIX: &str = "compact";
+
+/// Generations in sub-directories of `dir`.
+pub(crate) fn list_generations(dir: &Path) -> Vec<u64> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?
+                .strip_prefix(GENERATION_DIR_PREFIX)?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Remove generations other than `generation`, and temporary directories
+/// left by interrupted compactions.
+///
+/// This is best-effort. Files in use cannot be removed on Windows. They are
+/// retried by the next compaction.
+fn remove_stale_generations(dir: &Path, generation: u64, open_options: &OpenOptions) {
+    if generation != 0 {
+        let mut names: Vec<String> = open_options
+            .index_defs
+            .iter()
+            .map(|def| def.filename())
+            .collect();
+        names.extend(open_options.fold_defs.iter().map(|def| def.filename()));
+        names.push(PRIMARY_FILE.to_string());
+        for name in names {
+            let _ = fs::remove_file(dir.join(name));
+        }
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let stale = match name
+            .strip_prefix(GENERATION_DIR_PREFIX)
+            .and_then(|n| n.parse::<u64>().ok())
+        {
+            Some(n) => n != generation,
+            None => name.starts_with(COMPACT_TMP_PREFIX),
+        };
+        if stale {
+            tracing::debug!("removing stale compaction output {:?}", entry.path());
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Transparent per-entry compression. See [`Compression`].
+
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+
+use super::open_options::Compression;
+use super::ENTRY_FLAG_LZ4;
+use super::ENTRY_FLAG_ZSTD;
+use super::ENTRY_FLAG_ZSTD_DICT;
+use crate::utils::xxhash32;
+
+/// Entries shorter than this are not worth compressing.
+const MIN_COMPRESS_LEN: usize = 64;
+
+/// Compress `data` for an entry. Return the entry flag describing the
+/// compression, and the compressed content.
+///
+/// Return `None` if the entry should be stored as-is, because compression is
+/// disabled, or the data is too short to benefit from it, or compressing does
+/// not make it smaller.
+pub(crate) fn compress(
+    compression: Compression,
+    dict: Option<&[u8]>,
+    data: &[u8],
+) -> crate::Result<Option<(u32, Vec<u8>)>> {
+    if data.len() < MIN_COMPRESS_LEN {
+        return Ok(None);
+    }
+    let (flag, content) = match (compression, dict) {
+        (Compression::None, _) => return Ok(None),
+        (Compression::Lz4, _) => {
+            let content = lz4_pyframe::compress(data)
+                .map_err(|e| crate::Error::wrap(Box::new(e), "cannot compress using lz4"))?;
+            (ENTRY_FLAG_LZ4, content)
+        }
+        (Compression::Zstd, None) => {
+            let content = zstdelta::diff(b"", data)
+                .map_err(|e| crate::Error::wrap(Box::new(e), "cannot compress using zstd"))?;
+            (ENTRY_FLAG_ZSTD, content)
+        }
+        (Compression::Zstd, Some(dict)) => {
+            // Prefix the content with the dictionary checksum, so reading it
+            // with a different dictionary is an error instead of garbage.
+            let mut content = vec![0; 4];
+            LittleEndian::write_u32(&mut content, xxhash32(dict));
+            let compressed = zstdelta::diff(dict, data).map_err(|e| {
+                crate::Error::wrap(Box::new(e), "cannot compress using zstd with dictionary")
+            })?;
+            content.extend_from_slice(&compressed);
+            (ENTRY_FLAG_ZSTD_DICT, content)
+        }
+    };
+    if content.len() >= data.len() {
+        Ok(None)
+    } else {
+        Ok(Some((flag, content)))
+    }
+}
+
+/// Decompress `content` stored with the given compression entry flag.
+///
+/// `dict` is the dictionary set by `OpenOptions::compression_dict`. It is
+/// only used by [`ENTRY_FLAG_ZSTD_DICT`] entries.
+pub(crate) fn decompress(flag: u32, content: &[u8], dict: Option<&[u8]>) -> crate::Result<Vec<u8>> {
+    match flag {
+        ENTRY_FLAG_LZ4 => lz4_pyframe::decompress(content).map_err(|e| {
+            crate::Error::wrap(Box::new(e), "cannot decompress using lz4").mark_corruption()
+        }),
+        ENTRY_FLAG_ZSTD => zstdelta::apply(b"", content).map_err(|e| {
+            crate::Error::wrap(Box::new(e), "cannot decompress using zstd").mark_corruption()
+        }),
+        ENTRY_FLAG_ZSTD_DICT => {
+            let dict = dict.ok_or_else(|| {
+                crate::Error::programming(
+                    "entry is compressed with a dictionary, but no compression_dict is set",
+                )
+            })?;
+            if content.len() < 4 {
+                return Err(crate::Error::blank()
+                    .mark_corruption()
+                    .message("dictionary checksum cannot be read"));
+            }
+            if LittleEndian::read_u32(content) != xxhash32(dict) {
+                return Err(crate::Error::programming(
+                    "entry is compressed with a different dictionary than compression_dict",
+                ));
+            }
+            zstdelta::apply(dict, &content[4..]).map_err(|e| {
+                crate::Error::wrap(Box::new(e), "cannot decompress using zstd with dictionary")
+                    .mark_corruption()
+            })
+        }
+        _ => Err(crate::Error::blank()
+            .mark_corruption()
+            .message(format!("unknown compression flag {}", flag))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"abcdefgh".repeat(20);
+        let dict = b"abcdefgh01234567".to_vec();
+        for (compression, dict, expected_flag) in [
+            (Compression::Lz4, None, ENTRY_FLAG_LZ4),
+            (Compression::Zstd, None, ENTRY_FLAG_ZSTD),
+            (Compression::Zstd, Some(&dict[..]), ENTRY_FLAG_ZSTD_DICT),
+        ] {
+            let (flag, content) = compress(compression, dict, &data).unwrap().unwrap();
+            assert_eq!(flag, expected_flag);
+            assert!(content.len() < data.len());
+            assert_eq!(decompress(flag, &content, dict).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_not_compressed() {
+        // Too short.
+        assert!(compress(Compression::Zstd, None, b"abc").unwrap().is_none());
+        // Disabled.
+        let data = b"abcdefgh".repeat(20);
+        assert!(compress(Compression::None, None, &data).unwrap().is_none());
+        // Does not get smaller.
+        let data: Vec<u8> = (0..=255).collect();
+        assert!(compress(Compression::Lz4, None, &data).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dict_mismatch() {
+        let data = b"abcdefgh".repeat(20);
+        let (flag, content) = compress(Compression::Zstd, Some(b"abcdefgh"), &data)
+            .unwrap()
+            .unwrap();
+        let err = decompress(flag, &content, None).unwrap_err();
+        assert!(!err.is_corruption());
+        let err = decompress(flag, &content, Some(b"12345678")).unwrap_err();
+        assert!(!err.is_corruption());
+    }
+}
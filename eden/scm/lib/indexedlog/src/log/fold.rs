@@ -97,6 +97,11 @@ impl FoldDef {
             def: self.clone(),
         }
     }
+
+    /// Name used in filesystem.
+    pub(crate) fn filename(&self) -> String {
+        format!("fold-{}", self.name)
+    }
 }
 
 impl Clone for FoldState {
@@ -168,7 +173,7 @@ impl FoldState {
         let opt_path = log
             .dir
             .as_opt_path()
-            .map(|p| p.join(format!("fold-{}", self.def.name)));
+            .map(|p| log.meta.data_dir(p).join(self.def.filename()));
         if let Some(path) = &opt_path {
             if let Err(e) = self.load_from_file(path) {
                 tracing::warn!("cannot load FoldState: {}", e);
@@ -193,9 +198,9 @@ impl FoldState {
         if self.offset > 0 {
             iter.next_offset = self.offset;
         }
-        for entry in iter {
+        for entry in iter.decompressed() {
             let entry = entry?;
-            self.fold.accumulate(entry)?;
+            self.fold.accumulate(&entry)?;
         }
 
         // Set self state as up-to-date, and write to disk.
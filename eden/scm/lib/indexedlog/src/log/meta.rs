@@ -11,11 +11,13 @@ use std::io::Read;
 use std::io::Write;
 use std::io::{self};
 use std::path::Path;
+use std::path::PathBuf;
 
 use vlqencoding::VLQDecode;
 use vlqencoding::VLQEncode;
 
 use crate::errors::IoResultExt;
+use crate::log::GENERATION_DIR_PREFIX;
 use crate::utils::atomic_read;
 use crate::utils::atomic_write;
 use crate::utils::xxhash;
@@ -33,16 +35,34 @@ pub struct LogMetadata {
     /// Used to detect non-append-only changes.
     /// Conceptually similar to "create time".
     pub(crate) epoch: u64,
+
+    /// Whether the log may contain compressed entries. Such logs use a
+    /// different header, so readers that cannot decompress entries refuse to
+    /// open them, instead of returning compressed data.
+    pub(crate) compressed: bool,
+
+    /// Generation of the primary log, index and fold files. Compaction writes
+    /// a new generation, then switches to it by writing the metadata.
+    /// Generation 0 uses files directly in the log directory. Other
+    /// generations use a sub-directory. See [`LogMetadata::data_dir`].
+    pub(crate) generation: u64,
 }
 
 impl LogMetadata {
-    const HEADER: &'static [u8] = b"meta\0";
+    const HEADER: &'static [u8] = b"meta";
+
+    // Flags following the header. Readers refuse unknown flags, so a flag
+    // makes the log unreadable by versions that do not understand it.
+    const FLAG_COMPRESSED: u8 = 1;
+    const FLAG_GENERATION: u8 = 2;
+    const KNOWN_FLAGS: u8 = Self::FLAG_COMPRESSED | Self::FLAG_GENERATION;
 
     /// Read metadata from a reader.
     pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
-        let mut header = vec![0; Self::HEADER.len()];
+        let mut header = vec![0; Self::HEADER.len() + 1];
         reader.read_exact(&mut header)?;
-        if header != Self::HEADER {
+        let flags = header[Self::HEADER.len()];
+        if &header[..Self::HEADER.len()] != Self::HEADER || flags & !Self::KNOWN_FLAGS != 0 {
             let msg = "invalid metadata header";
             return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
         }
@@ -78,10 +98,18 @@ impl LogMetadata {
         // format. So not being able to read it (because EOF) is not fatal.
         let epoch = reader.read_vlq().unwrap_or_default();
 
+        let generation = if flags & Self::FLAG_GENERATION != 0 {
+            reader.read_vlq()?
+        } else {
+            0
+        };
+
         Ok(Self {
             primary_len,
             indexes,
             epoch,
+            compressed: flags & Self::FLAG_COMPRESSED != 0,
+            generation,
         })
     }
 
@@ -97,7 +125,16 @@ impl LogMetadata {
             buf.write_vlq(*len)?;
         }
         buf.write_vlq(self.epoch)?;
+        let mut flags = 0;
+        if self.compressed {
+            flags |= Self::FLAG_COMPRESSED;
+        }
+        if self.generation != 0 {
+            flags |= Self::FLAG_GENERATION;
+            buf.write_vlq(self.generation)?;
+        }
         writer.write_all(Self::HEADER)?;
+        writer.write_all(&[flags])?;
         writer.write_vlq(xxhash(&buf))?;
         writer.write_vlq(buf.len())?;
         writer.write_all(&buf)?;
@@ -131,13 +168,31 @@ impl LogMetadata {
             primary_len: len,
             indexes: BTreeMap::new(),
             epoch: utils::epoch(),
+            compressed: false,
+            generation: 0,
+        }
+    }
+
+    /// Directory of the primary log, index and fold files, given the
+    /// directory of the metadata.
+    pub(crate) fn data_dir(&self, dir: &Path) -> PathBuf {
+        Self::generation_dir(dir, self.generation)
+    }
+
+    /// Directory of files of the given generation.
+    pub(crate) fn generation_dir(dir: &Path, generation: u64) -> PathBuf {
+        match generation {
+            0 => dir.to_path_buf(),
+            generation => dir.join(format!("{}{}", GENERATION_DIR_PREFIX, generation)),
         }
     }
 
     /// Test if two Metadata is compatible, aka. having the same length
     /// and epoch.
     pub(crate) fn is_compatible_with(&self, other: &Self) -> bool {
-        self.primary_len == other.primary_len && self.epoch == other.epoch
+        self.primary_len == other.primary_len
+            && self.epoch == other.epoch
+            && self.generation == other.generation
     }
 }
 
@@ -149,18 +204,18 @@ mod tests {
     use super::*;
 
     quickcheck! {
-        fn test_roundtrip_meta(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64) -> bool {
+        fn test_roundtrip_meta(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64, compressed: bool, generation: u64) -> bool {
             let mut buf = Vec::new();
-            let meta = LogMetadata { primary_len, indexes, epoch,  };
+            let meta = LogMetadata { primary_len, indexes, epoch, compressed, generation };
             meta.write(&mut buf).expect("write");
             let mut cur = Cursor::new(buf);
             let meta_read = LogMetadata::read(&mut cur).expect("read");
             meta_read == meta
         }
 
-        fn test_roundtrip_meta_file(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64) -> bool {
+        fn test_roundtrip_meta_file(primary_len: u64, indexes: BTreeMap<String, u64>, epoch: u64, compressed: bool, generation: u64) -> bool {
             let dir = tempdir().unwrap();
-            let meta = LogMetadata { primary_len, indexes, epoch,  };
+            let meta = LogMetadata { primary_len, indexes, epoch, compressed, generation };
             let path = dir.path().join("meta");
             meta.write_file(&path, false).expect("write_file");
             let meta_read = LogMetadata::read_file(&path).expect("read_file");
@@ -176,6 +231,8 @@ mod tests {
             primary_len: 1,
             indexes: Default::default(),
             epoch: 42,
+            compressed: false,
+            generation: 0,
         };
         let mut buf: Vec<u8> = Vec::new();
         meta.write(&mut buf).unwrap();
@@ -19,6 +19,11 @@
 //   ENTRY_LIST := '' | ENTRY_LIST + ENTRY
 //   ENTRY := ENTRY_FLAGS + LEN(CONTENT) + CHECKSUM + CONTENT
 //   CHECKSUM := '' | XXHASH64(CONTENT) | XXHASH32(CONTENT)
+//   CONTENT := DATA | LZ4(DATA) | ZSTD(DATA) | XXHASH32(DICT) + ZSTD(DATA, DICT)
+//
+// ENTRY_FLAGS decides the CHECKSUM and CONTENT formats. The CHECKSUM covers
+// CONTENT as stored, so integrity can be checked without decompressing.
+// LZ4 uses the lz4-pyframe format, which starts with LEN(DATA) as u32.
 //
 // Metadata:
 //   META := HEADER + XXHASH64(DATA) + LEN(DATA) + DATA
@@ -72,6 +77,8 @@ use crate::utils::xxhash;
 use crate::utils::xxhash32;
 use crate::utils::{self};
 
+mod compact;
+mod compression;
 mod fold;
 mod meta;
 mod open_options;
@@ -81,6 +88,7 @@ mod repair;
 pub(crate) mod tests;
 
 pub use open_options::ChecksumType;
+pub use open_options::Compression;
 pub use open_options::FlushFilterContext;
 pub use open_options::FlushFilterFunc;
 pub use open_options::FlushFilterOutput;
@@ -100,9 +108,15 @@ pub(crate) const PRIMARY_FILE: &str = "log";
 const PRIMARY_HEADER: &[u8] = b"indexedlog0\0";
 const PRIMARY_START_OFFSET: u64 = 12; // PRIMARY_HEADER.len() as u64;
 pub(crate) const META_FILE: &str = "meta";
+// Sub-directories of generations written by compaction.
+pub(crate) const GENERATION_DIR_PREFIX: &str = "gen";
 
 const ENTRY_FLAG_HAS_XXHASH64: u32 = 1;
 const ENTRY_FLAG_HAS_XXHASH32: u32 = 2;
+const ENTRY_FLAG_LZ4: u32 = 4;
+const ENTRY_FLAG_ZSTD: u32 = 8;
+const ENTRY_FLAG_ZSTD_DICT: u32 = 16;
+const ENTRY_FLAG_COMPRESSION_MASK: u32 = ENTRY_FLAG_LZ4 | ENTRY_FLAG_ZSTD | ENTRY_FLAG_ZSTD_DICT;
 
 // 1MB index checksum. This makes checksum file within one block (4KB) for 512MB index.
 const INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM: u32 = 20;
@@ -140,7 +154,7 @@ pub struct Log {
     // This could be improved to be per index. For now, it's a single state for simplicity. It's
     // probably fine considering index corruptions are rare.
     index_corrupted: bool,
-    open_options: OpenOptions,
+    pub(crate) open_options: OpenOptions,
 }
 
 /// Iterator over all entries in a [`Log`].
@@ -159,6 +173,14 @@ pub struct LogLookupIter<'a> {
     log: &'a Log,
 }
 
+/// Iterator over all entries in a [`Log`], decompressing compressed entries.
+/// See [`LogIter::decompressed`].
+pub struct LogDecompressedIter<'a>(LogIter<'a>);
+
+/// Iterator over [`Log`] entries selected by an index lookup, decompressing
+/// compressed entries. See [`LogLookupIter::decompressed`].
+pub struct LogLookupDecompressedIter<'a>(LogLookupIter<'a>);
+
 /// Iterator over keys and [`LogLookupIter`], filtered by an index prefix.
 ///
 /// It is a wrapper around [index::RangeIter].
@@ -245,6 +267,15 @@ impl Log {
     pub fn append<T: AsRef<[u8]>>(&mut self, data: T) -> crate::Result<()> {
         let result: crate::Result<_> = (|| {
             let data = data.as_ref();
+            let compressed = compression::compress(
+                self.open_options.compression,
+                self.open_options.compression_dict.as_deref(),
+                data,
+            )?;
+            let (compression_flag, content) = match &compressed {
+                Some((flag, content)) => (*flag, &content[..]),
+                None => (0, data),
+            };
 
             let checksum_type = if self.open_options.checksum_type == ChecksumType::Auto {
                 // xxhash64 is slower for smaller data. A quick benchmark on x64 platform shows:
@@ -264,7 +295,7 @@ impl Log {
                 //  120       3000      3428
                 //  128       3459      4266
                 const XXHASH64_THRESHOLD: usize = 88;
-                if data.len() >= XXHASH64_THRESHOLD {
+                if content.len() >= XXHASH64_THRESHOLD {
                     ChecksumType::Xxhash64
                 } else {
                     ChecksumType::Xxhash32
@@ -275,14 +306,10 @@ impl Log {
 
             let offset = self.meta.primary_len + self.mem_buf.len() as u64;
 
-            // Design note: Currently checksum_type is the only thing that decides
-            // entry_flags.  Entry flags is not designed to just cover different
-            // checksum types.  For example, if we'd like to introduce transparent
-            // compression (maybe not a good idea since it can be more cleanly built
-            // at an upper layer), or some other ways to store data (ex. reference
-            // to other data, or fixed length data), they can probably be done by
-            // extending the entry type.
-            let mut entry_flags = 0;
+            // Design note: entry_flags decide the checksum type and the compression.
+            // Other ways to store data (ex. reference to other data, or fixed length
+            // data) can probably be done by extending the entry flags further.
+            let mut entry_flags = compression_flag;
             entry_flags |= match checksum_type {
                 ChecksumType::Xxhash64 => ENTRY_FLAG_HAS_XXHASH64,
                 ChecksumType::Xxhash32 => ENTRY_FLAG_HAS_XXHASH32,
@@ -290,26 +317,33 @@ impl Log {
             };
 
             self.mem_buf.write_vlq(entry_flags).infallible()?;
-            self.mem_buf.write_vlq(data.len()).infallible()?;
+            self.mem_buf.write_vlq(content.len()).infallible()?;
 
             match checksum_type {
                 ChecksumType::Xxhash64 => {
                     self.mem_buf
-                        .write_u64::<LittleEndian>(xxhash(data))
+                        .write_u64::<LittleEndian>(xxhash(content))
                         .infallible()?;
                 }
                 ChecksumType::Xxhash32 => {
                     self.mem_buf
-                        .write_u32::<LittleEndian>(xxhash32(data))
+                        .write_u32::<LittleEndian>(xxhash32(content))
                         .infallible()?;
                 }
                 ChecksumType::Auto => unreachable!(),
             };
             let data_offset = self.meta.primary_len + self.mem_buf.len() as u64;
 
-            self.mem_buf.write_all(data).infallible()?;
+            self.mem_buf.write_all(content).infallible()?;
+            let next_offset = data_offset + content.len() as u64;
+            // Compressed data is not in the log. Index keys cannot refer to it.
+            let data_offset = if compressed.is_some() {
+                None
+            } else {
+                Some(data_offset)
+            };
             self.update_indexes_for_in_memory_entry(data, offset, data_offset)?;
-            self.update_fold_for_in_memory_entry(data, offset, data_offset)?;
+            self.update_fold_for_in_memory_entry(data, offset, next_offset)?;
 
             if let Some(threshold) = self.open_options.auto_sync_threshold {
                 if self.mem_buf.len() as u64 >= threshold {
@@ -523,11 +557,11 @@ impl Log {
                     .open_with_lock(&self.dir, &lock)
                     .context("re-open to run flush_filter")?;
 
-                for entry in self.iter_dirty() {
+                for entry in self.iter_dirty().decompressed() {
                     let content = entry?;
                     let context = FlushFilterContext { log: &log };
                     // Re-insert entries to that clean log.
-                    match filter(&context, content)
+                    match filter(&context, &content)
                         .map_err(|err| crate::Error::wrap(err, "failed to run filter function"))?
                     {
                         FlushFilterOutput::Drop => {}
@@ -551,7 +585,7 @@ impl Log {
                         )
                     })?;
 
-                for entry in self.iter_dirty() {
+                for entry in self.iter_dirty().decompressed() {
                     let content = entry?;
                     log.append(content)?;
                 }
@@ -561,7 +595,9 @@ impl Log {
             }
 
             // Step 2: Append to the primary log.
-            let primary_path = self.dir.as_opt_path().unwrap().join(PRIMARY_FILE);
+            let primary_path = meta
+                .data_dir(self.dir.as_opt_path().unwrap())
+                .join(PRIMARY_FILE);
             let mut primary_file = fs::OpenOptions::new()
                 .read(true)
                 .write(true)
@@ -611,6 +647,10 @@ impl Log {
             }
 
             meta.primary_len += self.mem_buf.len() as u64;
+            if self.open_options.compression != Compression::None && !self.mem_buf.is_empty() {
+                // Readers that cannot decompress entries must not open the log.
+                meta.compressed = true;
+            }
             self.mem_buf.clear();
 
             // Step 3: Reload primary log and indexes to get the latest view.
@@ -812,7 +852,7 @@ impl Log {
     ) -> crate::Result<String> {
         let mut message = String::new();
         {
-            if let Some(ref dir) = self.dir.as_opt_path() {
+            if let Some(dir) = self.dir.as_opt_path() {
                 for (i, def) in self.open_options.index_defs.iter().enumerate() {
                     let name = def.name;
 
@@ -855,9 +895,11 @@ impl Log {
                         }
                     }
 
-                    let tmp = tempfile::NamedTempFile::new_in(dir).context(&dir, || {
-                        format!("cannot create tempfile for rebuilding index {:?}", name)
-                    })?;
+                    let data_dir = self.meta.data_dir(dir);
+                    let tmp = tempfile::NamedTempFile::new_in(&data_dir)
+                        .context(&data_dir, || {
+                            format!("cannot create tempfile for rebuilding index {:?}", name)
+                        })?;
                     let index_len = {
                         let mut index = index::OpenOptions::new()
                             .key_buf(Some(Arc::new(self.disk_buf.clone())))
@@ -868,6 +910,7 @@ impl Log {
                             def,
                             &self.disk_buf,
                             self.meta.primary_len,
+                            self.open_options.compression_dict.as_deref(),
                         )?;
                         index.flush()?
                     };
@@ -882,7 +925,7 @@ impl Log {
 
                     let _ = utils::fix_perm_file(tmp.as_file(), false);
 
-                    let path = dir.join(def.filename());
+                    let path = data_dir.join(def.filename());
                     tmp.persist(&path).map_err(|e| {
                         crate::Error::wrap(Box::new(e), || {
                             format!("cannot persist tempfile to replace index {:?}", name)
@@ -1041,6 +1084,14 @@ impl Log {
         }
     }
 
+    /// Return the `index_id` of the index with the given name.
+    pub(crate) fn index_id(&self, name: &str) -> Option<usize> {
+        self.open_options
+            .index_defs
+            .iter()
+            .position(|def| def.name == name)
+    }
+
     /// Applies the given index function to the entry data and returns the index keys.
     pub fn index_func<'a>(
         &self,
@@ -1086,12 +1137,13 @@ impl Log {
     ///
     /// `offset` is the logical start offset of the entry.
     /// `data_offset` is the logical start offset of the real data (skips
-    /// length, and checksum header in the entry).
+    /// length, and checksum header in the entry). It is `None` if the entry
+    /// is compressed, in which case index keys are copied into the index.
     fn update_indexes_for_in_memory_entry(
         &mut self,
         data: &[u8],
         offset: u64,
-        data_offset: u64,
+        data_offset: Option<u64>,
     ) -> crate::Result<()> {
         let result = self.update_indexes_for_in_memory_entry_unchecked(data, offset, data_offset);
         self.maybe_set_index_error(result)
@@ -1102,10 +1154,10 @@ impl Log {
         &mut self,
         data: &[u8],
         offset: u64,
-        data_offset: u64,
+        next_offset: u64,
     ) -> crate::Result<()> {
         for fold_state in self.all_folds.iter_mut() {
-            fold_state.process_entry(data, offset, data_offset + data.len() as u64)?;
+            fold_state.process_entry(data, offset, next_offset)?;
         }
         Ok(())
     }
@@ -1114,16 +1166,23 @@ impl Log {
         &mut self,
         data: &[u8],
         offset: u64,
-        data_offset: u64,
+        data_offset: Option<u64>,
     ) -> crate::Result<()> {
         for (index, def) in self.indexes.iter_mut().zip(&self.open_options.index_defs) {
             for index_output in (def.func)(data) {
                 match index_output {
                     IndexOutput::Reference(range) => {
                         assert!(range.start <= range.end && range.end <= data.len() as u64);
-                        let start = range.start + data_offset;
-                        let end = range.end + data_offset;
-                        let key = InsertKey::Reference((start, end - start));
+                        let key = match data_offset {
+                            Some(data_offset) => {
+                                let start = range.start + data_offset;
+                                let end = range.end + data_offset;
+                                InsertKey::Reference((start, end - start))
+                            }
+                            None => {
+                                InsertKey::Embed(&data[range.start as usize..range.end as usize])
+                            }
+                        };
                         index.insert_advanced(key, InsertValue::Prepend(offset))?;
                     }
                     IndexOutput::Owned(key) => {
@@ -1178,6 +1237,7 @@ impl Log {
                 def,
                 &self.disk_buf,
                 self.meta.primary_len,
+                self.open_options.compression_dict.as_deref(),
             )?;
         }
         Ok(())
@@ -1189,6 +1249,7 @@ impl Log {
         def: &IndexDef,
         disk_buf: &Bytes,
         primary_len: u64,
+        compression_dict: Option<&[u8]>,
     ) -> crate::Result<usize> {
         // The index meta is used to store the next offset the index should be built.
         let mut offset = Self::get_index_log_len(index, true)?;
@@ -1204,14 +1265,25 @@ impl Log {
             })?
         {
             count += 1;
-            let data = entry_result.data;
+            let decompressed;
+            let data = if entry_result.compression == 0 {
+                entry_result.data
+            } else {
+                decompressed = entry_result.decompress(path, offset, compression_dict)?;
+                &decompressed
+            };
             for index_output in (def.func)(data) {
                 match index_output {
                     IndexOutput::Reference(range) => {
                         assert!(range.start <= range.end && range.end <= data.len() as u64);
-                        let start = range.start + entry_result.data_offset;
-                        let end = range.end + entry_result.data_offset;
-                        let key = InsertKey::Reference((start, end - start));
+                        let key = if entry_result.compression == 0 {
+                            let start = range.start + entry_result.data_offset;
+                            let end = range.end + entry_result.data_offset;
+                            InsertKey::Reference((start, end - start))
+                        } else {
+                            // Compressed data is not in the log. Copy the key.
+                            InsertKey::Embed(&data[range.start as usize..range.end as usize])
+                        };
 
                         index.insert_advanced(key, InsertValue::Prepend(offset))?;
                     }
@@ -1293,7 +1365,7 @@ impl Log {
         fsync: bool,
     ) -> crate::Result<(Bytes, Vec<Index>)> {
         let primary_buf = match dir.as_opt_path() {
-            Some(dir) => mmap_path(&dir.join(PRIMARY_FILE), meta.primary_len)?,
+            Some(dir) => mmap_path(&meta.data_dir(dir).join(PRIMARY_FILE), meta.primary_len)?,
             None => Bytes::new(),
         };
 
@@ -1313,6 +1385,7 @@ impl Log {
                     let index_len = meta.indexes.get(&def.metaname()).cloned().unwrap_or(0);
                     indexes.push(Self::load_index(
                         dir,
+                        meta,
                         &def,
                         index_len,
                         key_buf.clone(),
@@ -1329,7 +1402,7 @@ impl Log {
                 for (index, def) in indexes.iter().zip(index_defs) {
                     let index_len = meta.indexes.get(&def.metaname()).cloned().unwrap_or(0);
                     let index = if index_len > Self::get_index_log_len(index, true).unwrap_or(0) {
-                        Self::load_index(dir, &def, index_len, key_buf.clone(), fsync)?
+                        Self::load_index(dir, meta, &def, index_len, key_buf.clone(), fsync)?
                     } else {
                         let mut index = index.try_clone()?;
                         index.key_buf = key_buf.clone();
@@ -1351,6 +1424,7 @@ impl Log {
     /// Load a single index.
     fn load_index(
         dir: &GenericPath,
+        meta: &LogMetadata,
         def: &IndexDef,
         len: u64,
         buf: Arc<dyn ReadonlyBuffer + Send + Sync>,
@@ -1358,7 +1432,7 @@ impl Log {
     ) -> crate::Result<Index> {
         match dir.as_opt_path() {
             Some(dir) => {
-                let path = dir.join(def.filename());
+                let path = meta.data_dir(dir).join(def.filename());
                 index::OpenOptions::new()
                     .checksum_chunk_size_logarithm(INDEX_CHECKSUM_CHUNK_SIZE_LOGARITHM)
                     .logical_len(Some(len))
@@ -1377,6 +1451,8 @@ impl Log {
     /// Read the entry at the given offset. Return `None` if offset is out of bound, or the content
     /// of the data, the real offset of the data, and the next offset. Raise errors if
     /// integrity-check failed.
+    ///
+    /// Compressed entries are not decompressed. See [`Log::decompressed_entry_data`].
     fn read_entry(&self, offset: u64) -> crate::Result<Option<EntryResult>> {
         let result = if offset < self.meta.primary_len {
             Self::read_entry_from_buf(&self.dir, &self.disk_buf, offset)?
@@ -1391,9 +1467,46 @@ impl Log {
         Ok(result)
     }
 
+    /// The data of the entry at `offset`, read by [`Log::read_entry`].
+    ///
+    /// Compressed entries cannot be borrowed from the log buffers, so they
+    /// are an error. They are read by [`Log::decompressed_entry_data`].
+    fn borrowed_entry_data<'a>(
+        &self,
+        offset: u64,
+        entry: EntryResult<'a>,
+    ) -> crate::Result<&'a [u8]> {
+        if entry.compression == 0 {
+            Ok(entry.data)
+        } else {
+            let msg = format!(
+                "entry at {} in {:?} is compressed and must be read with decompressed()",
+                offset, self.dir
+            );
+            Err(crate::Error::programming(msg))
+        }
+    }
+
+    /// The data of the entry at `offset`, read by [`Log::read_entry`].
+    /// Uncompressed entries are borrowed from the log buffers.
+    fn decompressed_entry_data<'a>(
+        &self,
+        offset: u64,
+        entry: EntryResult<'a>,
+    ) -> crate::Result<Cow<'a, [u8]>> {
+        if entry.compression == 0 {
+            Ok(Cow::Borrowed(entry.data))
+        } else {
+            let dict = self.open_options.compression_dict.as_deref();
+            Ok(Cow::Owned(entry.decompress(&self.dir, offset, dict)?))
+        }
+    }
+
     /// Read an entry at the given offset of the given buffer. Verify its integrity. Return the
     /// data, the real data offset, and the next entry offset. Return None if the offset is at
     /// the end of the buffer.  Raise errors if there are integrity check issues.
+    ///
+    /// Compressed entries are not decompressed. Use [`EntryResult::decompress`] for that.
     fn read_entry_from_buf<'a>(
         path: &GenericPath,
         buf: &'a [u8],
@@ -1434,6 +1547,14 @@ impl Log {
         })?;
         let offset = offset + vlq_len as u64;
 
+        let compression = entry_flags & ENTRY_FLAG_COMPRESSION_MASK;
+        if compression.count_ones() > 1 {
+            return Err(data_error(format!(
+                "entry at {} has malformed compression metadata",
+                offset
+            )));
+        }
+
         // Depends on entry_flags, some of them have a checksum field.
         let checksum_flags = entry_flags & (ENTRY_FLAG_HAS_XXHASH64 | ENTRY_FLAG_HAS_XXHASH32);
         let (checksum, offset) = match checksum_flags {
@@ -1482,6 +1603,7 @@ impl Log {
                 data,
                 data_offset: offset,
                 next_offset: end,
+                compression,
             }))
         } else {
             Err(data_error(format!("integrity check failed at {}", offset)))
@@ -1596,6 +1718,8 @@ struct EntryResult<'a> {
     data: &'a [u8],
     data_offset: u64,
     next_offset: u64,
+    /// The compression entry flag. 0 if the entry is not compressed.
+    compression: u32,
 }
 
 impl<'a> EntryResult<'a> {
@@ -1607,14 +1731,25 @@ impl<'a> EntryResult<'a> {
             // So it does not need to be changed.
             data_offset: self.data_offset,
             next_offset: self.next_offset + offset,
+            compression: self.compression,
         }
     }
-}
 
-impl<'a> Iterator for LogLookupIter<'a> {
-    type Item = crate::Result<&'a [u8]>;
+    /// Decompress `data` of a compressed entry at `offset`.
+    fn decompress(
+        &self,
+        path: &GenericPath,
+        offset: u64,
+        compression_dict: Option<&[u8]>,
+    ) -> crate::Result<Vec<u8>> {
+        compression::decompress(self.compression, self.data, compression_dict)
+            .context(|| format!("while decompressing entry at {} in {:?}", offset, path))
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a> LogLookupIter<'a> {
+    /// Read the next entry, and the offset it was found at.
+    fn next_entry(&mut self) -> Option<crate::Result<(u64, EntryResult<'a>)>> {
         if self.errored {
             return None;
         }
@@ -1629,7 +1764,7 @@ impl<'a> Iterator for LogLookupIter<'a> {
                 .read_entry(offset)
                 .context("in LogLookupIter::next")
             {
-                Ok(Some(entry)) => Some(Ok(entry.data)),
+                Ok(Some(entry)) => Some(Ok((offset, entry))),
                 Ok(None) => None,
                 Err(err) => {
                     // Do not set this iterator to an error state. It's possible
@@ -1644,27 +1779,59 @@ impl<'a> Iterator for LogLookupIter<'a> {
             },
         }
     }
-}
 
-impl<'a> LogLookupIter<'a> {
     /// A convenient way to get data.
     pub fn into_vec(self) -> crate::Result<Vec<&'a [u8]>> {
         self.collect()
     }
+
+    /// Also return compressed entries, decompressed. Uncompressed entries are
+    /// still borrowed from the log.
+    pub fn decompressed(self) -> LogLookupDecompressedIter<'a> {
+        LogLookupDecompressedIter(self)
+    }
+
+    /// Like `next`, but also return compressed entries, decompressed.
+    pub(crate) fn next_decompressed(&mut self) -> Option<crate::Result<Cow<'a, [u8]>>> {
+        let log = self.log;
+        let item = self.next_entry()?;
+        Some(item.and_then(|(offset, entry)| log.decompressed_entry_data(offset, entry)))
+    }
 }
 
-impl<'a> Iterator for LogIter<'a> {
+impl<'a> Iterator for LogLookupIter<'a> {
     type Item = crate::Result<&'a [u8]>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let log = self.log;
+        let item = self.next_entry()?;
+        Some(item.and_then(|(offset, entry)| log.borrowed_entry_data(offset, entry)))
+    }
+}
+
+impl<'a> LogLookupDecompressedIter<'a> {
+    /// A convenient way to get data.
+    pub fn into_vec(self) -> crate::Result<Vec<Cow<'a, [u8]>>> {
+        self.collect()
+    }
+}
+
+impl<'a> Iterator for LogLookupDecompressedIter<'a> {
+    type Item = crate::Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_decompressed()
+    }
+}
+
+impl<'a> LogIter<'a> {
+    /// Read the next entry, and the offset it was found at.
+    fn next_entry(&mut self) -> Option<crate::Result<(u64, EntryResult<'a>)>> {
         if self.errored {
             return None;
         }
-        match self
-            .log
-            .read_entry(self.next_offset)
-            .context("in LogIter::next")
-        {
+        let offset = self.next_offset;
+        match self.log.read_entry(offset).context("in LogIter::next") {
             Err(e) => {
                 self.errored = true;
                 Some(Err(e))
@@ -1672,11 +1839,37 @@ impl<'a> Iterator for LogIter<'a> {
             Ok(Some(entry_result)) => {
                 assert!(entry_result.next_offset > self.next_offset);
                 self.next_offset = entry_result.next_offset;
-                Some(Ok(entry_result.data))
+                Some(Ok((offset, entry_result)))
             }
             Ok(None) => None,
         }
     }
+
+    /// Also return compressed entries, decompressed. Uncompressed entries are
+    /// still borrowed from the log.
+    pub fn decompressed(self) -> LogDecompressedIter<'a> {
+        LogDecompressedIter(self)
+    }
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = crate::Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let log = self.log;
+        let item = self.next_entry()?;
+        Some(item.and_then(|(offset, entry)| log.borrowed_entry_data(offset, entry)))
+    }
+}
+
+impl<'a> Iterator for LogDecompressedIter<'a> {
+    type Item = crate::Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let log = self.0.log;
+        let item = self.0.next_entry()?;
+        Some(item.and_then(|(offset, entry)| log.decompressed_entry_data(offset, entry)))
+    }
 }
 
 impl<'a> LogRangeIter<'a> {
@@ -1728,10 +1921,10 @@ impl<'a> DoubleEndedIterator for LogRangeIter<'a> {
 impl Debug for Log {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         let mut count = 0;
-        let mut iter = self.iter();
+        let mut iter = self.iter().decompressed();
         let bytes_per_line = 16;
         loop {
-            let offset = iter.next_offset;
+            let offset = iter.0.next_offset;
             count += 1;
             match iter.next() {
                 None => break,
@@ -8,8 +8,10 @@
 use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fmt::{self};
+use std::io;
 use std::ops::Range;
 
+use minibytes::Bytes;
 use tracing::debug_span;
 
 use super::fold::Fold;
@@ -114,6 +116,29 @@ pub enum ChecksumType {
     Xxhash32,
 }
 
+/// How to compress entries written by [`Log::append`].
+///
+/// Compression is recorded per entry. Reading decompresses entries
+/// transparently, and index functions see the uncompressed data. Entries
+/// written with a different (or no) compression, including entries written
+/// before compression was enabled, can still be read.
+///
+/// Compressed entries cannot be borrowed from the log buffer. Read them with
+/// the `decompressed()` iterators, which return owned data for them. Once compressed entries are written, the log can no longer be
+/// opened by versions of indexedlog without compression support.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Compression {
+    /// Store entries as-is.
+    None,
+
+    /// Use lz4. Fast, but compresses less than zstd.
+    Lz4,
+
+    /// Use zstd. Uses the dictionary set by [`OpenOptions::compression_dict`]
+    /// if there is one.
+    Zstd,
+}
+
 /// Options used to configured how an [`Log`] is opened.
 #[derive(Clone)]
 pub struct OpenOptions {
@@ -121,6 +146,8 @@ pub struct OpenOptions {
     pub(crate) fold_defs: Vec<FoldDef>,
     pub(crate) create: bool,
     pub(crate) checksum_type: ChecksumType,
+    pub(crate) compression: Compression,
+    pub(crate) compression_dict: Option<Bytes>,
     pub(crate) flush_filter: Option<FlushFilterFunc>,
     pub(crate) fsync: bool,
     pub(crate) auto_sync_threshold: Option<u64>,
@@ -231,12 +258,15 @@ impl OpenOptions {
     /// `fsync` is initially `false`.
     /// `index_defs` is initially empty.
     /// `auto_sync_threshold` is initially `None`.
+    /// `compression` is initially `Compression::None`.
     pub fn new() -> Self {
         Self {
             create: false,
             index_defs: Vec::new(),
             fold_defs: Vec::new(),
             checksum_type: ChecksumType::Auto,
+            compression: Compression::None,
+            compression_dict: None,
             flush_filter: None,
             fsync: false,
             auto_sync_threshold: None,
@@ -303,6 +333,25 @@ impl OpenOptions {
         self
     }
 
+    /// Sets how new entries are compressed.
+    ///
+    /// See [`Compression`] for details.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the dictionary used by zstd compression.
+    ///
+    /// A dictionary with content similar to the entries helps compressing
+    /// short entries. Entries compressed with a dictionary can only be read
+    /// if the same dictionary is set. Reading them with no, or a different,
+    /// dictionary is an error.
+    pub fn compression_dict(mut self, dict: impl Into<Option<Bytes>>) -> Self {
+        self.compression_dict = dict.into();
+        self
+    }
+
     /// Sets the flush filter function.
     ///
     /// The function will be called at [`Log::sync`] time, if there are
@@ -325,6 +374,18 @@ impl OpenOptions {
         self
     }
 
+    /// Never consider indexes lagging.
+    ///
+    /// Opening a [`Log`] then updates lagging indexes in memory, without
+    /// taking the directory lock to write them to disk. Used by
+    /// `RotateLogSnapshot`, which must not contend with writers.
+    pub(crate) fn with_unlimited_index_lag(mut self) -> Self {
+        for def in self.index_defs.iter_mut() {
+            def.lag_threshold = u64::MAX;
+        }
+        self
+    }
+
     /// Construct [`Log`] at given directory. Incrementally build up specified
     /// indexes.
     ///
@@ -431,14 +492,23 @@ impl OpenOptions {
         })?;
 
         let mem_buf = Box::pin(Vec::new());
-        let (disk_buf, indexes) = Log::load_log_and_indexes(
+        let (disk_buf, indexes) = match Log::load_log_and_indexes(
             dir,
             &meta,
             &self.index_defs,
             &mem_buf,
             reuse_indexes,
             self.fsync,
-        )?;
+        ) {
+            Err(err) if lock.is_none() && err.io_error_kind() == io::ErrorKind::NotFound => {
+                // A compaction might have removed the generation the
+                // lock-less metadata refers to. Compaction switches
+                // generations with the lock held. Retry with the lock.
+                let lock = dir.lock()?;
+                return self.open_internal(dir, reuse_indexes, Some(&lock));
+            }
+            result => result?,
+        };
         let disk_folds = self.empty_folds();
         let all_folds = disk_folds.clone();
         let mut log = Log {
@@ -523,6 +593,12 @@ impl fmt::Debug for OpenOptions {
         write!(f, "fsync: {}, ", self.fsync)?;
         write!(f, "create: {}, ", self.create)?;
         write!(f, "checksum_type: {:?}, ", self.checksum_type)?;
+        write!(f, "compression: {:?}, ", self.compression)?;
+        write!(
+            f,
+            "compression_dict: {:?}, ",
+            self.compression_dict.as_ref().map(|d| d.len())
+        )?;
         write!(f, "auto_sync_threshold: {:?}, ", self.auto_sync_threshold)?;
         let flush_filter_desc = match self.flush_filter {
             Some(ref _buf) => "Some(_)",
@@ -17,7 +17,9 @@ use std::path::Path;
 use crate::errors::IoResultExt;
 use crate::errors::ResultExt;
 use crate::lock::ScopedDirLock;
+use crate::log::compact::list_generations;
 use crate::log::GenericPath;
+use crate::log::Log;
 use crate::log::LogMetadata;
 use crate::log::OpenOptions;
 use crate::log::META_FILE;
@@ -55,9 +57,24 @@ impl OpenOptions {
 
             let lock = ScopedDirLock::new(dir)?;
 
-            let primary_path = dir.join(PRIMARY_FILE);
             let meta_path = dir.join(META_FILE);
 
+            // Compacted logs keep their files in a generation sub-directory.
+            // If metadata cannot be read, assume the latest generation.
+            let generation = match LogMetadata::read_file(&meta_path) {
+                Ok(meta) => meta.generation,
+                Err(_) => list_generations(dir)
+                    .into_iter()
+                    .filter(|&generation| {
+                        LogMetadata::generation_dir(dir, generation)
+                            .join(PRIMARY_FILE)
+                            .exists()
+                    })
+                    .max()
+                    .unwrap_or_default(),
+            };
+            let primary_path = LogMetadata::generation_dir(dir, generation).join(PRIMARY_FILE);
+
             // Make sure the header of the primary log file is okay.
             (|| -> crate::Result<()> {
                 #[allow(clippy::never_loop)]
@@ -122,7 +139,8 @@ impl OpenOptions {
                     }
                     Err(meta_err) => {
                         // Attempt to rebuild metadata.
-                        let meta = LogMetadata::new_with_primary_len(primary_len);
+                        let mut meta = LogMetadata::new_with_primary_len(primary_len);
+                        meta.generation = generation;
                         meta.write_file(&meta_path, self.fsync)
                             .context("while recreating meta")
                             .source(meta_err)?;
@@ -148,18 +166,32 @@ impl OpenOptions {
                 })
                 .context("cannot open log for repair")?;
 
-            let mut iter = log.iter();
-
-            // Read entries until hitting a checksum error.
+            // Read entries until hitting a checksum error. Do not decompress
+            // entries, so a missing compression dictionary does not cause
+            // valid entries to be truncated.
             let mut entry_count = 0;
-            while let Some(Ok(_)) = iter.next() {
+            let mut valid_len = PRIMARY_START_OFFSET;
+            let mut compressed = false;
+            while let Ok(Some(entry_result)) =
+                Log::read_entry_from_buf(&log.dir, &log.disk_buf, valid_len)
+            {
                 entry_count += 1;
+                valid_len = entry_result.next_offset;
+                compressed |= entry_result.compression != 0;
             }
 
-            let valid_len = iter.next_offset;
             assert!(valid_len >= PRIMARY_START_OFFSET);
             assert!(valid_len <= log.meta.primary_len);
 
+            // Rebuilt metadata does not know about compressed entries.
+            if compressed && !log.meta.compressed {
+                log.meta.compressed = true;
+                log.meta
+                    .write_file(&meta_path, log.open_options.fsync)
+                    .context("while trying to mark log as compressed")?;
+                message += "Marked log as compressed\n";
+            }
+
             if valid_len == log.meta.primary_len {
                 message += &format!(
                     "Verified {} entries, {} bytes in log\n",
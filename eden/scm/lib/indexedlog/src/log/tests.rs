@@ -150,6 +150,190 @@ fn test_checksum_type() {
     );
 }
 
+fn compressible_entry(key: &[u8]) -> Vec<u8> {
+    let mut data = key.to_vec();
+    data.extend_from_slice(&b"0123456789".repeat(20));
+    data
+}
+
+fn index_prefix(_data: &[u8]) -> Vec<IndexOutput> {
+    vec![IndexOutput::Reference(0..3)]
+}
+
+#[test]
+fn test_compression() {
+    let dir = tempdir().unwrap();
+    let log_path = dir.path().join("log");
+
+    let open = |compression| {
+        OpenOptions::new()
+            .compression(compression)
+            .index("prefix", index_prefix)
+            .create(true)
+            .open(&log_path)
+            .unwrap()
+    };
+    let entries: Vec<Vec<u8>> = [b"aaa", b"bbb", b"ccc", b"ddd"]
+        .iter()
+        .map(|key| compressible_entry(&key[..]))
+        .collect();
+
+    // An existing log without compression.
+    let mut log = open(Compression::None);
+    log.append(&entries[0]).unwrap();
+    log.sync().unwrap();
+    let meta_path = log_path.join(META_FILE);
+    assert!(
+        utils::atomic_read(&meta_path)
+            .unwrap()
+            .starts_with(b"meta\0")
+    );
+
+    let mut log = open(Compression::Zstd);
+    log.append(&entries[1]).unwrap();
+    log.append(b"short").unwrap();
+    let mut log2 = open(Compression::Lz4);
+    log2.append(&entries[2]).unwrap();
+    log2.sync().unwrap();
+
+    // In-memory compressed entries can be read and looked up.
+    assert_eq!(
+        log.lookup(0, b"bbb")
+            .unwrap()
+            .decompressed()
+            .into_vec()
+            .unwrap(),
+        vec![&entries[1][..]]
+    );
+    log.sync().unwrap();
+    log.append(&entries[3]).unwrap();
+
+    let expected: Vec<&[u8]> = vec![&entries[0], &entries[2], &entries[1], b"short", &entries[3]];
+    assert_eq!(
+        log.iter()
+            .decompressed()
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap(),
+        expected
+    );
+    for entry in &entries {
+        assert_eq!(
+            log.lookup(0, &entry[..3])
+                .unwrap()
+                .decompressed()
+                .into_vec()
+                .unwrap(),
+            vec![&entry[..]]
+        );
+    }
+
+    // Entries that are not compressed can still be borrowed. Compressed ones can't.
+    assert_eq!(
+        log.lookup(0, b"aaa").unwrap().into_vec().unwrap(),
+        vec![&entries[0][..]]
+    );
+    assert!(log.lookup(0, b"bbb").unwrap().into_vec().is_err());
+    assert!(log.iter().collect::<crate::Result<Vec<_>>>().is_err());
+
+    // Entries are stored compressed.
+    log.sync().unwrap();
+    assert!(log.disk_buf.len() < entries.iter().map(|e| e.len()).sum::<usize>());
+
+    // The metadata header changes so readers without compression support
+    // refuse to open the log.
+    assert!(
+        utils::atomic_read(&meta_path)
+            .unwrap()
+            .starts_with(b"meta\x01")
+    );
+
+    // Compression does not need to be enabled to read them.
+    let log = open(Compression::None);
+    assert_eq!(
+        log.iter()
+            .decompressed()
+            .collect::<crate::Result<Vec<_>>>()
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        log.lookup(0, b"ccc")
+            .unwrap()
+            .decompressed()
+            .into_vec()
+            .unwrap(),
+        vec![&entries[2][..]]
+    );
+    drop(log);
+
+    // Repair keeps the log marked as compressed after rebuilding metadata.
+    fs::remove_file(&meta_path).unwrap();
+    OpenOptions::new().repair(&log_path).unwrap();
+    assert!(
+        utils::atomic_read(&meta_path)
+            .unwrap()
+            .starts_with(b"meta\x01")
+    );
+}
+
+#[test]
+fn test_compression_dict() {
+    let dir = tempdir().unwrap();
+    let dict = Bytes::from(b"0123456789".repeat(3));
+    let open_opts = OpenOptions::new()
+        .compression(Compression::Zstd)
+        .compression_dict(dict)
+        .index("prefix", index_prefix)
+        .create(true);
+
+    let mut log = open_opts.open(dir.path()).unwrap();
+    log.append(compressible_entry(b"aaa")).unwrap();
+    log.sync().unwrap();
+
+    let log = open_opts.open(dir.path()).unwrap();
+    assert_eq!(
+        log.lookup(0, b"aaa")
+            .unwrap()
+            .decompressed()
+            .into_vec()
+            .unwrap(),
+        vec![&compressible_entry(b"aaa")[..]]
+    );
+
+    // Reading with a different dictionary is an error, but not a corruption.
+    let log = open_opts
+        .clone()
+        .compression_dict(Bytes::from_static(b"abc"))
+        .index_defs(Vec::new())
+        .open(dir.path())
+        .unwrap();
+    let err = log.iter().decompressed().next().unwrap().unwrap_err();
+    assert!(!err.is_corruption());
+
+    // Repair does not need the dictionary, and does not truncate the log.
+    let message = OpenOptions::new().repair(dir.path()).unwrap();
+    assert!(message.contains("Verified 1 entries"), "{}", message);
+}
+
+#[test]
+fn test_compression_rebuild_indexes() {
+    let dir = tempdir().unwrap();
+    let open_opts = OpenOptions::new()
+        .compression(Compression::Lz4)
+        .index_defs(vec![IndexDef::new("prefix", index_prefix).lag_threshold(0)])
+        .create(true);
+
+    let mut log = open_opts.open(dir.path()).unwrap();
+    log.append(compressible_entry(b"aaa")).unwrap();
+    log.append(compressible_entry(b"bbb")).unwrap();
+    log.sync().unwrap();
+    log.rebuild_indexes(true).unwrap();
+
+    let log = open_opts.open(dir.path()).unwrap();
+    assert_eq!(log.lookup(0, b"bbb").unwrap().count(), 1);
+    assert_eq!(log.lookup(0, b"ccc").unwrap().count(), 0);
+}
+
 #[test]
 fn test_iter_and_iter_dirty() {
     let dir = tempdir().unwrap();
@@ -1397,6 +1581,166 @@ fn test_non_append_only() {
     check_log(&log2, 0..20, 7);
 }
 
+#[test]
+fn test_compact() {
+    let dir = tempdir().unwrap();
+    let open_opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("key", index_ref).lag_threshold(1)]);
+
+    let mut log = open_opts.open(dir.path()).unwrap();
+    for data in [b"a1", b"b1", b"a2", b"b2", b"c1"] {
+        log.append(data).unwrap();
+    }
+    log.sync().unwrap();
+    log.append(b"a3").unwrap();
+    let len_before = log.meta.primary_len;
+
+    // Another Log with in-memory entries.
+    let mut log2 = open_opts.open(dir.path()).unwrap();
+    log2.append(b"d1").unwrap();
+
+    // Drop "a" entries, and rewrite "b" entries.
+    let message = log
+        .compact(|_context, data| {
+            Ok(match data[0] {
+                b'a' => FlushFilterOutput::Drop,
+                b'b' => FlushFilterOutput::Replace([b"B", &data[1..]].concat()),
+                _ => FlushFilterOutput::Keep,
+            })
+        })
+        .unwrap();
+    assert_eq!(
+        message,
+        "Compacted 6 entries (60 bytes) to 3 entries (36 bytes)\n"
+    );
+    assert!(log.meta.primary_len < len_before);
+
+    let check = |log: &Log, expected: Vec<&[u8]>| {
+        assert_eq!(
+            log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+            expected
+        );
+        for data in expected {
+            assert_eq!(log.lookup(0, data).unwrap().into_vec().unwrap(), vec![data]);
+        }
+        assert_eq!(log.lookup(0, b"a1").unwrap().count(), 0);
+    };
+    check(&log, vec![b"B1", b"B2", b"c1"]);
+    check(
+        &open_opts.open(dir.path()).unwrap(),
+        vec![b"B1", b"B2", b"c1"],
+    );
+
+    // Existing Logs keep their snapshot until sync, which re-applies their
+    // in-memory entries to the compacted log.
+    assert_eq!(log2.iter().count(), 6);
+    log2.sync().unwrap();
+    check(&log2, vec![b"B1", b"B2", b"c1", b"d1"]);
+
+    // The filter sees the compacted entries so far.
+    let message = open_opts
+        .compact(dir.path(), |context, data| {
+            let exists = context.log.lookup(0, &data[1..]).unwrap().count() > 0;
+            Ok(if exists {
+                FlushFilterOutput::Drop
+            } else {
+                FlushFilterOutput::Replace(data[1..].to_vec())
+            })
+        })
+        .unwrap();
+    assert_eq!(
+        message,
+        "Compacted 4 entries (44 bytes) to 2 entries (26 bytes)\n"
+    );
+    check(&open_opts.open(dir.path()).unwrap(), vec![b"1", b"2"]);
+}
+
+#[test]
+fn test_compact_generations() {
+    let dir = tempdir().unwrap();
+    let path = dir.path();
+    let open_opts = OpenOptions::new()
+        .create(true)
+        .index_defs(vec![IndexDef::new("key", index_ref).lag_threshold(0)]);
+    let mut log = open_opts.open(path).unwrap();
+    log.append(b"a").unwrap();
+    log.append(b"b").unwrap();
+    log.sync().unwrap();
+    let reader = open_opts.open(path).unwrap();
+
+    // Leftovers of an interrupted compaction, and a stale generation.
+    fs::create_dir(path.join("compact-interrupted")).unwrap();
+    fs::create_dir(path.join("gen5")).unwrap();
+
+    // Compaction writes a new generation and removes the old files.
+    log.compact(|_, _| Ok(FlushFilterOutput::Keep)).unwrap();
+    assert_eq!(log.meta.generation, 1);
+    assert!(path.join("gen1").join("log").exists());
+    assert!(!path.join("gen1").join("meta").exists());
+    for name in ["log", "index2-key", "compact-interrupted", "gen5"] {
+        assert!(!path.join(name).exists(), "{} should be removed", name);
+    }
+    let meta = utils::atomic_read(&path.join(META_FILE)).unwrap();
+    assert_eq!(&meta[..5], b"meta\x02");
+
+    // Logs opened before keep their snapshot, and pick up the new generation
+    // on sync.
+    let mut reader = reader;
+    assert_eq!(reader.iter().count(), 2);
+    reader.sync().unwrap();
+    assert_eq!(reader.meta.generation, 1);
+
+    reader.append(b"c").unwrap();
+    reader.sync().unwrap();
+    reader
+        .compact(|_, data| {
+            Ok(if data == b"a" {
+                FlushFilterOutput::Drop
+            } else {
+                FlushFilterOutput::Keep
+            })
+        })
+        .unwrap();
+    assert!(!path.join("gen1").exists());
+    assert!(path.join("gen2").join("log").exists());
+
+    let check = |log: &Log| {
+        assert_eq!(
+            log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![&b"b"[..], b"c"]
+        );
+        assert_eq!(log.lookup(0, b"c").unwrap().count(), 1);
+        assert_eq!(log.lookup(0, b"a").unwrap().count(), 0);
+    };
+    check(&open_opts.open(path).unwrap());
+
+    // Repair finds the generation if metadata is lost.
+    fs::remove_file(path.join(META_FILE)).unwrap();
+    open_opts.repair(path).unwrap();
+    check(&open_opts.open(path).unwrap());
+}
+
+#[test]
+fn test_compact_in_memory() {
+    let mut log = OpenOptions::new().index("key", index_ref).open(()).unwrap();
+    log.append(b"a").unwrap();
+    log.append(b"b").unwrap();
+    log.compact(|_, data| {
+        Ok(if data == b"a" {
+            FlushFilterOutput::Drop
+        } else {
+            FlushFilterOutput::Keep
+        })
+    })
+    .unwrap();
+    assert_eq!(
+        log.iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+        vec![b"b"]
+    );
+    assert_eq!(log.lookup(0, b"a").unwrap().count(), 0);
+}
+
 #[test]
 fn test_clear_dirty() {
     for lag in vec![0, 1000] {
@@ -29,6 +29,13 @@ use crate::log::{self};
 use crate::repair::OpenOptionsRepair;
 use crate::utils;
 
+mod query;
+
+pub use query::CompositeKey;
+pub use query::Query;
+pub use query::QueryEntry;
+pub use query::Transaction;
+
 /// Options used to configure how a [`MultiLog`] is opened.
 #[derive(Clone, Default)]
 pub struct OpenOptions {
@@ -51,7 +58,9 @@ pub struct OpenOptions {
 ///
 /// [`Log`]s will be accessible via indexing. For example, `multilog[0]`
 /// accesses the first [`Log`]. [`Log`]s can also be moved out of this
-/// struct by [`MultiLog::detach_logs`].
+/// struct by [`MultiLog::detach_logs`]. Entries can also be looked up by
+/// index names via [`MultiLog::query`], and written atomically via
+/// [`MultiLog::transaction`].
 ///
 /// [`MultiLog`] makes sure the data consistency on disk but not always
 /// in memory. In case [`MultiLog::write_meta`] is not called or is not
@@ -68,6 +77,9 @@ pub struct MultiLog {
     /// Logs loaded by MultiLog.
     logs: Vec<log::Log>,
 
+    /// Names of `logs`.
+    names: Vec<&'static str>,
+
     /// Log used for `MultiMeta`. For data recovery.
     multimeta_log: log::Log,
 
@@ -178,6 +190,11 @@ impl OpenOptions {
             Ok(MultiLog {
                 path: path.to_path_buf(),
                 logs,
+                names: self
+                    .name_open_options
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect(),
                 multimeta,
                 multimeta_log,
                 leacy_multimeta_source: self.leacy_multimeta_source,
@@ -675,6 +692,51 @@ mod tests {
         assert_eq!(mlog2[1].iter().count(), 0);
     }
 
+    #[test]
+    fn test_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        let mut mlog = simple_multilog(path);
+        for data in [b"1", b"2", b"3"] {
+            mlog[0].append(data).unwrap();
+        }
+        mlog[1].append(b"x").unwrap();
+        mlog.sync().unwrap();
+
+        let mut mlog2 = simple_multilog(path);
+        mlog2[0].append(b"4").unwrap();
+
+        // Compact while holding the MultiLog lock, then write the multimeta.
+        let lock = mlog.lock().unwrap();
+        mlog[0]
+            .compact(|_, data| {
+                Ok(if data == b"2" {
+                    log::FlushFilterOutput::Drop
+                } else {
+                    log::FlushFilterOutput::Keep
+                })
+            })
+            .unwrap();
+        mlog.write_meta(&lock).unwrap();
+        drop(lock);
+
+        let mlog3 = simple_multilog(path);
+        assert_eq!(
+            mlog3[0].iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![b"1", b"3"]
+        );
+        assert_eq!(mlog3[1].iter().count(), 1);
+
+        // mlog2 keeps its snapshot until it syncs. Its in-memory entries are
+        // written on top of the compacted log.
+        assert_eq!(mlog2[0].iter().count(), 4);
+        mlog2.sync().unwrap();
+        assert_eq!(
+            mlog2[0].iter().collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![b"1", b"3", b"4"]
+        );
+    }
+
     #[test]
     fn test_version() {
         let dir = tempfile::tempdir().unwrap();
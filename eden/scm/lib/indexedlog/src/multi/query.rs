@@ -0,0 +1,549 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Look up [`MultiLog`] entries by index names, instead of [`Log`] positions
+//! and index ids.
+
+use std::borrow::Cow;
+use std::ops::Bound;
+use std::ops::Deref;
+use std::ops::RangeBounds;
+
+use super::MultiLog;
+use crate::errors::ResultExt;
+use crate::log::FlushFilterContext;
+use crate::log::FlushFilterOutput;
+use crate::log::IndexOutput;
+use crate::log::Log;
+
+/// Index key made of several parts.
+///
+/// Keys are encoded so sorting them sorts by the first part, then by the
+/// second part, and so on. The encoding of the first parts of a key is a
+/// prefix of the encoding of the key, so [`Query::lookup_prefix`] can match
+/// keys by their leading parts.
+///
+/// Each part is followed by `\0\x01`. `\0` in parts is escaped as `\0\xff`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CompositeKey(Vec<u8>);
+
+impl CompositeKey {
+    /// Create a key without parts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a part to the key.
+    pub fn push(mut self, part: impl AsRef<[u8]>) -> Self {
+        for &byte in part.as_ref() {
+            self.0.push(byte);
+            if byte == 0 {
+                self.0.push(0xff);
+            }
+        }
+        self.0.extend_from_slice(b"\0\x01");
+        self
+    }
+
+    /// Split an encoded key into its parts.
+    pub fn parse(key: &[u8]) -> crate::Result<Vec<Vec<u8>>> {
+        let mut parts = Vec::new();
+        let mut part = Vec::new();
+        let mut iter = key.iter();
+        while let Some(&byte) = iter.next() {
+            if byte != 0 {
+                part.push(byte);
+                continue;
+            }
+            match iter.next() {
+                Some(0x01) => parts.push(std::mem::take(&mut part)),
+                Some(0xff) => part.push(0),
+                _ => {
+                    return Err(crate::Error::programming(format!(
+                        "{:?} is not a CompositeKey",
+                        key
+                    )));
+                }
+            }
+        }
+        if !part.is_empty() {
+            return Err(crate::Error::programming(format!(
+                "{:?} is not a CompositeKey",
+                key
+            )));
+        }
+        Ok(parts)
+    }
+}
+
+impl AsRef<[u8]> for CompositeKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<CompositeKey> for IndexOutput {
+    fn from(key: CompositeKey) -> Self {
+        IndexOutput::Owned(key.0.into_boxed_slice())
+    }
+}
+
+/// Looks up entries using indexes with the same name in [`Log`]s of a
+/// [`MultiLog`].
+///
+/// Created by [`MultiLog::query`] or [`MultiLog::query_log`].
+pub struct Query<'a> {
+    /// Log name, Log, and index id.
+    targets: Vec<(&'static str, &'a Log, usize)>,
+}
+
+/// An entry found by a [`Query`].
+#[derive(Debug, PartialEq)]
+pub struct QueryEntry<'a> {
+    /// Name of the [`Log`] containing the entry.
+    pub log: &'static str,
+
+    /// Index key of the entry.
+    pub key: Cow<'a, [u8]>,
+
+    /// Content of the entry.
+    pub data: &'a [u8],
+}
+
+impl<'a> Query<'a> {
+    /// Look up entries with the given key.
+    ///
+    /// Entries are ordered by [`Log`], in the order the [`Log`]s are defined
+    /// by [`OpenOptions`](super::OpenOptions). Entries in a same [`Log`] are
+    /// in the order of [`Log::lookup`].
+    pub fn lookup(&self, key: impl AsRef<[u8]>) -> crate::Result<Vec<QueryEntry<'a>>> {
+        let key = key.as_ref();
+        let mut entries = Vec::new();
+        for &(name, log, index_id) in self.targets.iter() {
+            for data in log.lookup(index_id, key)? {
+                entries.push(QueryEntry {
+                    log: name,
+                    key: Cow::Owned(key.to_vec()),
+                    data: data?,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Look up entries with keys starting with the given prefix.
+    ///
+    /// Entries are sorted by key. Entries with a same key are in the order of
+    /// [`Query::lookup`].
+    pub fn lookup_prefix(&self, prefix: impl AsRef<[u8]>) -> crate::Result<Vec<QueryEntry<'a>>> {
+        let prefix = prefix.as_ref();
+        let mut entries = Vec::new();
+        for &(name, log, index_id) in self.targets.iter() {
+            for item in log.lookup_prefix(index_id, prefix)? {
+                let (key, iter) = item?;
+                push_entries(&mut entries, name, key, iter)?;
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Look up entries with keys in the given range.
+    ///
+    /// Entries are sorted by key. Entries with a same key are in the order of
+    /// [`Query::lookup`].
+    pub fn lookup_range<'b>(
+        &self,
+        range: impl RangeBounds<&'b [u8]>,
+    ) -> crate::Result<Vec<QueryEntry<'a>>> {
+        let range: (Bound<&[u8]>, Bound<&[u8]>) =
+            (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut entries = Vec::new();
+        for &(name, log, index_id) in self.targets.iter() {
+            for item in log.lookup_range(index_id, range)? {
+                let (key, iter) = item?;
+                push_entries(&mut entries, name, key, iter)?;
+            }
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+}
+
+fn push_entries<'a>(
+    entries: &mut Vec<QueryEntry<'a>>,
+    log: &'static str,
+    key: Cow<'a, [u8]>,
+    iter: impl Iterator<Item = crate::Result<&'a [u8]>>,
+) -> crate::Result<()> {
+    for data in iter {
+        entries.push(QueryEntry {
+            log,
+            key: key.clone(),
+            data: data?,
+        });
+    }
+    Ok(())
+}
+
+/// A [`MultiLog`] being changed by [`MultiLog::transaction`].
+///
+/// Dereferences to the [`MultiLog`], so entries can be looked up, including
+/// entries appended by the transaction.
+pub struct Transaction<'a> {
+    mlog: &'a mut MultiLog,
+}
+
+impl<'a> Transaction<'a> {
+    /// Append data to the [`Log`] with the given name.
+    pub fn append(&mut self, log_name: &str, data: impl AsRef<[u8]>) -> crate::Result<()> {
+        match self.mlog.log_position(log_name) {
+            Some(position) => self.mlog.logs[position].append(data),
+            None => Err(crate::Error::programming(format!(
+                "MultiLog has no Log named {:?}",
+                log_name
+            ))),
+        }
+    }
+}
+
+impl<'a> Deref for Transaction<'a> {
+    type Target = MultiLog;
+
+    fn deref(&self) -> &Self::Target {
+        self.mlog
+    }
+}
+
+// Queries and transactions
+impl MultiLog {
+    /// Get the [`Log`] with the given name.
+    pub fn log(&self, name: &str) -> Option<&Log> {
+        self.log_position(name).map(|position| &self.logs[position])
+    }
+
+    /// Get the mutable [`Log`] with the given name.
+    pub fn log_mut(&mut self, name: &str) -> Option<&mut Log> {
+        let position = self.log_position(name)?;
+        Some(&mut self.logs[position])
+    }
+
+    /// Query using the index with the given name, in all [`Log`]s having
+    /// such an index.
+    pub fn query(&self, index_name: &str) -> crate::Result<Query<'_>> {
+        let targets: Vec<_> = self
+            .names
+            .iter()
+            .zip(self.logs.iter())
+            .filter_map(|(&name, log)| Some((name, log, log.index_id(index_name)?)))
+            .collect();
+        if targets.is_empty() {
+            return Err(crate::Error::programming(format!(
+                "no Log in MultiLog has index {:?}",
+                index_name
+            )))
+            .context(|| format!("in MultiLog::query({:?})", index_name));
+        }
+        Ok(Query { targets })
+    }
+
+    /// Query using the index with the given name, in the [`Log`] with the
+    /// given name.
+    pub fn query_log(&self, log_name: &str, index_name: &str) -> crate::Result<Query<'_>> {
+        let result: crate::Result<_> = (|| {
+            let position = self.log_position(log_name).ok_or_else(|| {
+                crate::Error::programming(format!("MultiLog has no Log named {:?}", log_name))
+            })?;
+            let log = &self.logs[position];
+            let index_id = log.index_id(index_name).ok_or_else(|| {
+                crate::Error::programming(format!(
+                    "Log {:?} has no index {:?}",
+                    log_name, index_name
+                ))
+            })?;
+            Ok(Query {
+                targets: vec![(self.names[position], log, index_id)],
+            })
+        })();
+        result.context(|| format!("in MultiLog::query_log({:?}, {:?})", log_name, index_name))
+    }
+
+    /// Change [`Log`]s atomically.
+    ///
+    /// Take the lock and reload the [`Log`]s, so `func` sees the latest
+    /// entries. If `func` succeeds, write entries it appended, then the
+    /// metadata, so other [`MultiLog`]s see all of them at once. If `func`
+    /// fails, drop entries it appended.
+    ///
+    /// In-memory entries appended before the transaction are written with
+    /// it, or kept in memory if `func` fails. Like [`Log::sync`], they go
+    /// through the flush filter of their [`Log`] if other processes changed
+    /// it.
+    ///
+    /// This function should not be called if logs were detached.
+    pub fn transaction<R>(
+        &mut self,
+        func: impl FnOnce(&mut Transaction) -> crate::Result<R>,
+    ) -> crate::Result<R> {
+        let result: crate::Result<_> = (|| {
+            let lock = self.lock()?;
+
+            // Reload Logs. Keep in-memory entries.
+            let mut pending: Vec<Vec<Vec<u8>>> = Vec::with_capacity(self.logs.len());
+            for log in self.logs.iter_mut() {
+                let entries = log
+                    .iter_dirty()
+                    .map(|entry| entry.map(|data| data.to_vec()))
+                    .collect::<crate::Result<Vec<_>>>()?;
+                let meta = log.meta.clone();
+                log.clear_dirty()?;
+                log.sync()?;
+                let filter = match log.open_options.flush_filter {
+                    Some(filter) if log.meta != meta => Some(filter),
+                    _ => None,
+                };
+                let mut kept = Vec::with_capacity(entries.len());
+                for data in entries {
+                    let data = match filter {
+                        None => data,
+                        Some(filter) => {
+                            let context = FlushFilterContext { log };
+                            match filter(&context, &data).map_err(|err| {
+                                crate::Error::wrap(err, "failed to run filter function")
+                            })? {
+                                FlushFilterOutput::Drop => continue,
+                                FlushFilterOutput::Keep => data,
+                                FlushFilterOutput::Replace(data) => data,
+                            }
+                        }
+                    };
+                    log.append(&data)?;
+                    kept.push(data);
+                }
+                pending.push(kept);
+            }
+
+            match func(&mut Transaction { mlog: self }) {
+                Ok(value) => {
+                    for log in self.logs.iter_mut() {
+                        log.sync()?;
+                    }
+                    self.write_meta(&lock)?;
+                    Ok(value)
+                }
+                Err(err) => {
+                    for (log, entries) in self.logs.iter_mut().zip(pending) {
+                        log.clear_dirty()?;
+                        for data in entries {
+                            log.append(data)?;
+                        }
+                    }
+                    Err(err)
+                }
+            }
+        })();
+        result.context("in MultiLog::transaction")
+    }
+
+    fn log_position(&self, name: &str) -> Option<usize> {
+        if self.logs.len() != self.names.len() {
+            // Logs were detached.
+            return None;
+        }
+        self.names.iter().position(|&n| n == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::log;
+    use crate::multi::OpenOptions;
+
+    fn node_index(_data: &[u8]) -> Vec<IndexOutput> {
+        vec![IndexOutput::Reference(0..1)]
+    }
+
+    // "pair" entries are "{a}{b}". They are indexed by (a, b).
+    fn pair_index(data: &[u8]) -> Vec<IndexOutput> {
+        vec![CompositeKey::new()
+            .push(&data[0..1])
+            .push(&data[1..])
+            .into()]
+    }
+
+    fn open_opts() -> OpenOptions {
+        OpenOptions::from_name_opts(vec![
+            ("a", log::OpenOptions::new().index("node", node_index)),
+            (
+                "b",
+                log::OpenOptions::new()
+                    .index("pair", pair_index)
+                    .index("node", node_index),
+            ),
+        ])
+    }
+
+    fn data<'a>(entries: &[QueryEntry<'a>]) -> Vec<(&'static str, &'a [u8])> {
+        entries.iter().map(|e| (e.log, e.data)).collect()
+    }
+
+    #[test]
+    fn test_composite_key() {
+        let key = CompositeKey::new().push(b"a\0b").push(b"").push(b"c");
+        assert_eq!(key.as_ref(), b"a\0\xffb\0\x01\0\x01c\0\x01");
+        assert_eq!(
+            CompositeKey::parse(key.as_ref()).unwrap(),
+            vec![b"a\0b".to_vec(), vec![], b"c".to_vec()]
+        );
+        assert!(CompositeKey::parse(b"a").is_err());
+        assert!(CompositeKey::parse(b"a\0\x02").is_err());
+
+        // Sorted by parts.
+        let key = |a: &[u8], b: &[u8]| CompositeKey::new().push(a).push(b);
+        let mut keys = vec![
+            key(b"a\0", b""),
+            key(b"ab", b""),
+            key(b"a", b"b"),
+            key(b"a", b""),
+            key(b"", b"z"),
+        ];
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                key(b"", b"z"),
+                key(b"a", b""),
+                key(b"a", b"b"),
+                key(b"a\0", b""),
+                key(b"ab", b""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_query() {
+        let dir = tempdir().unwrap();
+        let mut mlog = open_opts().open(dir.path()).unwrap();
+        mlog.transaction(|t| {
+            t.append("a", b"x1")?;
+            t.append("b", b"x2")?;
+            t.append("b", b"y1")?;
+            t.append("b", b"x\0z")
+        })
+        .unwrap();
+
+        // Spanning Logs.
+        let query = mlog.query("node").unwrap();
+        assert_eq!(
+            data(&query.lookup(b"x").unwrap()),
+            vec![("a", &b"x1"[..]), ("b", b"x\0z"), ("b", b"x2")]
+        );
+        let entries = query.lookup_range(&b"x"[..]..).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[3].key.as_ref(), b"y");
+
+        // Composite keys.
+        let query = mlog.query_log("b", "pair").unwrap();
+        let entries = query.lookup_prefix(CompositeKey::new().push(b"x")).unwrap();
+        assert_eq!(data(&entries), vec![("b", &b"x\0z"[..]), ("b", b"x2")]);
+        let parts = CompositeKey::parse(&entries[0].key).unwrap();
+        assert_eq!(parts, vec![b"x".to_vec(), b"\0z".to_vec()]);
+        let key = CompositeKey::new().push(b"y").push(b"1");
+        assert_eq!(data(&query.lookup(key).unwrap()), vec![("b", &b"y1"[..])]);
+
+        assert!(mlog.query("pair").is_ok());
+        assert!(mlog.query("missing").is_err());
+        assert!(mlog.query_log("a", "pair").is_err());
+        assert!(mlog.query_log("c", "node").is_err());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let dir = tempdir().unwrap();
+        let mut mlog = open_opts().open(dir.path()).unwrap();
+        let mut mlog2 = open_opts().open(dir.path()).unwrap();
+
+        // Entries appended before the transaction are kept on errors.
+        mlog.log_mut("a").unwrap().append(b"a1").unwrap();
+        let result: crate::Result<()> = mlog.transaction(|t| {
+            t.append("b", b"b1")?;
+            assert_eq!(t.query("node")?.lookup(b"b")?.len(), 1);
+            t.append("c", b"c1")
+        });
+        assert!(result.is_err());
+        assert_eq!(mlog.log("a").unwrap().iter_dirty().count(), 1);
+        assert_eq!(mlog.log("b").unwrap().iter().count(), 0);
+
+        // The transaction sees entries written by other MultiLogs.
+        mlog2.transaction(|t| t.append("b", b"b2")).unwrap();
+        let count = mlog
+            .transaction(|t| {
+                t.append("b", b"b3")?;
+                Ok(t.query("node")?.lookup(b"b")?.len())
+            })
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let mlog3 = open_opts().open(dir.path()).unwrap();
+        let entries = mlog3.query("node").unwrap().lookup_prefix(b"").unwrap();
+        assert_eq!(
+            data(&entries),
+            vec![("a", &b"a1"[..]), ("b", b"b3"), ("b", b"b2")]
+        );
+
+        // Detached Logs are not accessible by name.
+        let mut mlog3 = mlog3;
+        mlog3.detach_logs();
+        assert!(mlog3.log("a").is_none());
+        assert!(mlog3.query("node").is_err());
+    }
+
+    #[test]
+    fn test_transaction_flush_filter() {
+        // Drop entries whose "node" already exists.
+        let open_opts = || {
+            OpenOptions::from_name_opts(vec![(
+                "a",
+                log::OpenOptions::new()
+                    .index("node", node_index)
+                    .flush_filter(Some(|context, data| {
+                        Ok(if context.log.lookup(0, &data[..1])?.count() > 0 {
+                            FlushFilterOutput::Drop
+                        } else {
+                            FlushFilterOutput::Keep
+                        })
+                    })),
+            )])
+        };
+        let dir = tempdir().unwrap();
+        let mut mlog = open_opts().open(dir.path()).unwrap();
+        let mut mlog2 = open_opts().open(dir.path()).unwrap();
+
+        // The filter does not run if the Log is unchanged on disk.
+        mlog.log_mut("a").unwrap().append(b"x1").unwrap();
+        mlog.log_mut("a").unwrap().append(b"x2").unwrap();
+        mlog.transaction(|_| Ok(())).unwrap();
+        assert_eq!(mlog.log("a").unwrap().iter().count(), 2);
+
+        // Pending entries go through the filter if another MultiLog changed
+        // the Log.
+        mlog2.log_mut("a").unwrap().append(b"y1").unwrap();
+        mlog2.log_mut("a").unwrap().append(b"x3").unwrap();
+        mlog2.log_mut("a").unwrap().append(b"z1").unwrap();
+        mlog2.transaction(|t| t.append("a", b"z2")).unwrap();
+
+        let mlog3 = open_opts().open(dir.path()).unwrap();
+        let entries = mlog3.log("a").unwrap().iter();
+        assert_eq!(
+            entries.collect::<crate::Result<Vec<_>>>().unwrap(),
+            vec![&b"x1"[..], b"x2", b"y1", b"z1", b"z2"]
+        );
+    }
+}
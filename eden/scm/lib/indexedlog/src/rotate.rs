@@ -7,13 +7,16 @@
 
 //! Rotation support for a set of [`Log`]s.
 
+use std::borrow::Cow;
 use std::fmt;
 use std::fs;
 use std::io;
+use std::mem;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
 
 use minibytes::Bytes;
 use once_cell::sync::OnceCell;
@@ -108,6 +111,22 @@ impl OpenOptions {
         self
     }
 
+    /// Sets how new entries are compressed.
+    ///
+    /// See [log::Compression] for details.
+    pub fn compression(mut self, compression: log::Compression) -> Self {
+        self.log_open_options = self.log_open_options.compression(compression);
+        self
+    }
+
+    /// Sets the dictionary used by zstd compression.
+    ///
+    /// See [log::OpenOptions::compression_dict] for details.
+    pub fn compression_dict(mut self, dict: impl Into<Option<Bytes>>) -> Self {
+        self.log_open_options = self.log_open_options.compression_dict(dict);
+        self
+    }
+
     /// Set whether create the [`RotateLog`] structure if it does not exist.
     pub fn create(mut self, create: bool) -> Self {
         self.log_open_options = self.log_open_options.create(create);
@@ -253,6 +272,68 @@ impl OpenOptions {
         result.context("in rotate::OpenOptions::create_in_memory")
     }
 
+    /// Open a read-only [`RotateLogSnapshot`] at given location.
+    ///
+    /// Unlike [`OpenOptions::open`], this never takes the directory lock and
+    /// never writes to the directory, so it does not contend with writers in
+    /// other processes. Indexes that are lagging on disk are updated in memory.
+    /// The directory must exist. `create` is ignored.
+    pub fn open_snapshot(&self, dir: impl AsRef<Path>) -> crate::Result<RotateLogSnapshot> {
+        let dir = dir.as_ref();
+        let result: crate::Result<_> = (|| {
+            let span = debug_span!(
+                "RotateLog::open_snapshot",
+                dir = &dir.to_string_lossy().as_ref()
+            );
+            let _guard = span.enter();
+
+            let open_options = self.log_open_options.clone().with_unlimited_index_lag();
+            let mut latest = read_latest(dir)?;
+            let mut logs = Vec::with_capacity(self.max_log_count as usize);
+            loop {
+                match load_log(dir, latest, open_options.clone()) {
+                    Ok(log) => {
+                        logs.push(log);
+                        break;
+                    }
+                    Err(err) => {
+                        // A writer might have rotated, and removed the log,
+                        // after 'latest' was read. Retry with the new 'latest'.
+                        let new_latest = read_latest(dir)?;
+                        if new_latest == latest {
+                            return Err(err);
+                        }
+                        latest = new_latest;
+                    }
+                }
+            }
+
+            for index in 1..self.max_log_count {
+                let id = latest.wrapping_sub(index);
+                if !dir.join(id.to_string()).is_dir() {
+                    break;
+                }
+                match load_log(dir, id, open_options.clone()) {
+                    Ok(log) => logs.push(log),
+                    // Not fatal. The log might be removed by rotation.
+                    Err(_err) => break,
+                }
+            }
+            trace!(
+                name = "RotateLog::open_snapshot",
+                latest = latest,
+                logs_len = logs.len()
+            );
+
+            Ok(RotateLogSnapshot {
+                dir: Some(dir.into()),
+                logs: Arc::new(logs),
+            })
+        })();
+
+        result.context(|| format!("in rotate::OpenOptions::open_snapshot({:?})", dir))
+    }
+
     /// Try repair all logs in the specified directory.
     ///
     /// This just calls into [`log::OpenOptions::repair`] recursively.
@@ -465,21 +546,21 @@ impl RotateLog {
                         read_logs(self.dir.as_ref().unwrap(), &self.open_options, latest)?;
                     if let Some(filter) = self.open_options.log_open_options.flush_filter {
                         let log = new_logs[0].get_mut().unwrap();
-                        for entry in self.writable_log().iter_dirty() {
+                        for entry in self.writable_log().iter_dirty().decompressed() {
                             let content = entry?;
                             let context = FlushFilterContext { log };
-                            match filter(&context, content).map_err(|err| {
+                            match filter(&context, &content).map_err(|err| {
                                 crate::Error::wrap(err, "failed to run filter function")
                             })? {
                                 FlushFilterOutput::Drop => {}
-                                FlushFilterOutput::Keep => log.append(content)?,
+                                FlushFilterOutput::Keep => log.append(&content)?,
                                 FlushFilterOutput::Replace(content) => log.append(content)?,
                             }
                         }
                     } else {
                         let log = new_logs[0].get_mut().unwrap();
                         // Copy entries to new Logs.
-                        for entry in self.writable_log().iter_dirty() {
+                        for entry in self.writable_log().iter_dirty().decompressed() {
                             let bytes = entry?;
                             log.append(bytes)?;
                         }
@@ -522,6 +603,71 @@ impl RotateLog {
         Ok(())
     }
 
+    /// Remove the oldest logs until the logs use at most `max_bytes` on disk.
+    ///
+    /// This is FIFO eviction: logs are removed in the order they were
+    /// created, like rotation does, no matter how recently their entries
+    /// were read. The writable log is never removed, so the remaining logs
+    /// might still use more than `max_bytes`. In-memory entries are written
+    /// to disk first.
+    ///
+    /// Like [`RotateLog::remove_old_logs`], does nothing if rotation was
+    /// triggered elsewhere, or the [`RotateLog`] is in-memory.
+    ///
+    /// Return the number of bytes removed.
+    pub fn remove_oldest_logs_to_fit(&mut self, max_bytes: u64) -> crate::Result<u64> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => return Ok(0),
+        };
+        let result: crate::Result<_> = (|| {
+            self.sync()?;
+            let _lock = ScopedDirLock::new(&dir)?;
+            if read_latest(&dir)? != self.latest {
+                return Ok(0);
+            }
+
+            // Newest first.
+            let paths: Vec<PathBuf> = (0..self.logs.len())
+                .map(|index| dir.join(format!("{}", self.latest.wrapping_sub(index as u8))))
+                .collect();
+            let sizes: Vec<u64> = paths.iter().map(|path| log_dir_size(path)).collect();
+            let mut total = 0;
+            let mut keep = paths.len();
+            for (index, size) in sizes.iter().enumerate() {
+                total += size;
+                if index > 0 && total > max_bytes {
+                    keep = index;
+                    break;
+                }
+            }
+            if keep == paths.len() {
+                return Ok(0);
+            }
+
+            // Unmap the logs before removing their files.
+            let mut logs = mem::take(&mut self.logs);
+            logs.truncate(keep);
+            self.set_logs(logs);
+
+            let mut removed = 0;
+            for (path, size) in paths.iter().zip(sizes).skip(keep) {
+                if remove_log_dir(path) {
+                    removed += size;
+                }
+            }
+            debug!(
+                "Removed {} bytes of rotate logs, kept {} logs",
+                removed, keep
+            );
+            Ok(removed)
+        })();
+
+        result
+            .context("in RotateLog::remove_oldest_logs_to_fit")
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))
+    }
+
     /// Force create a new [`Log`]. Bump latest.
     ///
     /// This function requires it's protected by a directory lock, and the
@@ -576,38 +722,7 @@ impl RotateLog {
                             if (latest >= earliest && (id > latest || id < earliest))
                                 || (latest < earliest && (id > latest && id < earliest))
                             {
-                                // Explicitly delete the `meta` file first. This marks
-                                // the log as "deleted" in an atomic way.
-                                //
-                                // Errors are not fatal. On Windows, this can fail if
-                                // other processes have files in entry.path() mmap-ed.
-                                // Newly opened or flushed RotateLog will unmap files.
-                                // New rotation would trigger remove_dir_all to try
-                                // remove old logs again.
-                                match fs::remove_file(entry.path().join(log::META_FILE)) {
-                                    Ok(()) => {}
-                                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                                        // Meta file is already deleted.
-                                    }
-                                    Err(e) => {
-                                        // Don't delete the log if we were unable to delete the
-                                        // meta file.
-                                        debug!(
-                                            "Error removing rotate log meta: {:?} {:?}",
-                                            name, e
-                                        );
-                                        continue;
-                                    }
-                                }
-
-                                // Delete the rest of the directory.
-                                let res = fs::remove_dir_all(entry.path());
-                                match res {
-                                    Ok(_) => debug!("Removed rotate log: {:?}", name),
-                                    Err(err) => {
-                                        debug!("Error removing rotate log directory: {:?}", err)
-                                    }
-                                };
+                                remove_log_dir(&entry.path());
                             } else {
                                 debug!(
                                     "Not removing rotate log: {:?} (latest: {:?}, earliest: {:?})",
@@ -672,10 +787,39 @@ impl RotateLog {
         logs.into_iter().rev().flat_map(|log| log.iter())
     }
 
+    /// Iterate over all the entries, decompressing compressed ones.
+    ///
+    /// The entries are returned in FIFO order.
+    pub fn iter_decompressed(&self) -> impl Iterator<Item = crate::Result<Cow<'_, [u8]>>> {
+        let logs = self.logs();
+        logs.into_iter()
+            .rev()
+            .flat_map(|log| log.iter().decompressed())
+    }
+
     /// Iterate over all dirty entries.
     pub fn iter_dirty(&self) -> impl Iterator<Item = crate::Result<&[u8]>> {
         self.logs[0].get().unwrap().iter_dirty()
     }
+
+    /// Take a read-only [`RotateLogSnapshot`] of this [`RotateLog`], including
+    /// its in-memory entries.
+    ///
+    /// The snapshot shares the memory-mapped files with this [`RotateLog`]. It
+    /// can be moved to other threads, and is not affected by later changes.
+    pub fn snapshot(&self) -> crate::Result<RotateLogSnapshot> {
+        let logs = self
+            .logs()
+            .into_iter()
+            .map(|log| log.try_clone())
+            .collect::<crate::Result<Vec<_>>>()
+            .context("in RotateLog::snapshot")
+            .context(|| format!("  RotateLog.dir = {:?}", self.dir))?;
+        Ok(RotateLogSnapshot {
+            dir: self.dir.clone(),
+            logs: Arc::new(logs),
+        })
+    }
 }
 
 /// Wrap `Log` in a `OnceCell`.
@@ -693,6 +837,52 @@ fn load_log(dir: &Path, id: u8, open_options: log::OpenOptions) -> crate::Result
     open_options.create(false).open(&log_path)
 }
 
+/// Remove a log directory. Return `true` if it was removed.
+///
+/// Errors are not fatal. On Windows, this can fail if other processes have
+/// files in `path` mmap-ed. Newly opened or flushed RotateLog will unmap
+/// files. New rotation would trigger remove_dir_all to try remove old logs
+/// again.
+fn remove_log_dir(path: &Path) -> bool {
+    // Explicitly delete the `meta` file first. This marks the log as
+    // "deleted" in an atomic way.
+    match fs::remove_file(path.join(log::META_FILE)) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            // Meta file is already deleted.
+        }
+        Err(e) => {
+            // Don't delete the log if we were unable to delete the meta file.
+            debug!("Error removing rotate log meta: {:?} {:?}", path, e);
+            return false;
+        }
+    }
+
+    // Delete the rest of the directory.
+    match fs::remove_dir_all(path) {
+        Ok(_) => {
+            debug!("Removed rotate log: {:?}", path);
+            true
+        }
+        Err(err) => {
+            debug!("Error removing rotate log directory: {:?}", err);
+            false
+        }
+    }
+}
+
+/// Total size of the files in a log directory. Unreadable files count as empty.
+fn log_dir_size(path: &Path) -> u64 {
+    match path.read_dir() {
+        Ok(read_dir) => read_dir
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
 /// Get access to internals of [`RotateLog`].
 ///
 /// This can be useful when there are low-level needs. For example:
@@ -750,19 +940,33 @@ pub struct RotateLogLookupIter<'a> {
     key: Bytes,
 }
 
-impl<'a> Iterator for RotateLogLookupIter<'a> {
-    type Item = crate::Result<&'a [u8]>;
+/// Iterator over [`RotateLog`] entries selected by an index lookup,
+/// decompressing compressed entries. See [`RotateLogLookupIter::decompressed`].
+pub struct RotateLogLookupDecompressedIter<'a>(RotateLogLookupIter<'a>);
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.end {
-            return None;
-        }
-        match self.inner_iter.next() {
-            None => {
-                if self.log_index + 1 >= self.log_rotate.logs.len() {
-                    self.end = true;
-                    None
-                } else {
+impl<'a> RotateLogLookupIter<'a> {
+    /// Also return compressed entries, decompressed. Uncompressed entries are
+    /// still borrowed from the logs.
+    pub fn decompressed(self) -> RotateLogLookupDecompressedIter<'a> {
+        RotateLogLookupDecompressedIter(self)
+    }
+
+    /// Read the next entry using `read`, moving on to older logs when the
+    /// current one has no more.
+    fn next_with<T>(
+        &mut self,
+        read: impl Fn(&mut log::LogLookupIter<'a>) -> Option<crate::Result<T>>,
+    ) -> Option<crate::Result<T>> {
+        loop {
+            if self.end {
+                return None;
+            }
+            match read(&mut self.inner_iter) {
+                None => {
+                    if self.log_index + 1 >= self.log_rotate.logs.len() {
+                        self.end = true;
+                        return None;
+                    }
                     // Try the next log
                     self.log_index += 1;
                     match self.log_rotate.load_log(self.log_index) {
@@ -786,18 +990,107 @@ impl<'a> Iterator for RotateLogLookupIter<'a> {
                             }
                         }
                     }
-                    self.next()
                 }
+                Some(Err(err)) => {
+                    self.end = true;
+                    return Some(Err(err));
+                }
+                Some(Ok(data)) => return Some(Ok(data)),
             }
-            Some(Err(err)) => {
-                self.end = true;
-                Some(Err(err))
-            }
-            Some(Ok(slice)) => Some(Ok(slice)),
         }
     }
 }
 
+impl<'a> Iterator for RotateLogLookupIter<'a> {
+    type Item = crate::Result<&'a [u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with(|iter| iter.next())
+    }
+}
+
+impl<'a> Iterator for RotateLogLookupDecompressedIter<'a> {
+    type Item = crate::Result<Cow<'a, [u8]>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next_with(|iter| iter.next_decompressed())
+    }
+}
+
+/// A read-only view of [`RotateLog`] entries at a point in time.
+///
+/// A snapshot keeps the memory-mapped files of its [`Log`]s alive, so it is
+/// not affected by later writes, rotations, or removal of old logs, done by
+/// this or other processes. Reading it never takes the directory lock.
+///
+/// Cloning a snapshot is cheap. Clones share the same [`Log`]s, which are
+/// released when the last clone is dropped.
+#[derive(Clone)]
+pub struct RotateLogSnapshot {
+    dir: Option<PathBuf>,
+    // Newest first.
+    logs: Arc<Vec<Log>>,
+}
+
+impl RotateLogSnapshot {
+    /// Look up entries using the given index. The `index_id` is the index of
+    /// `index_defs` stored in [`OpenOptions`].
+    ///
+    /// Entries in newer [`Log`]s are returned first.
+    pub fn lookup(&self, index_id: usize, key: impl AsRef<[u8]>) -> crate::Result<Vec<Bytes>> {
+        let key = key.as_ref();
+        let result: crate::Result<_> = (|| {
+            let mut values = Vec::new();
+            for log in self.logs.iter() {
+                for value in log.lookup(index_id, key)?.decompressed() {
+                    values.push(match value? {
+                        Cow::Borrowed(value) => log.slice_to_bytes(value),
+                        Cow::Owned(value) => Bytes::from(value),
+                    });
+                }
+            }
+            Ok(values)
+        })();
+        result
+            .context(|| format!("in RotateLogSnapshot::lookup({}, {:?})", index_id, key))
+            .context(|| format!("  RotateLogSnapshot.dir = {:?}", self.dir))
+    }
+
+    /// Look up entries of many keys using the given index.
+    ///
+    /// Return entries of each key, in the same order as `keys`. Reading
+    /// memory-mapped files can block on disk IO, so async callers should run
+    /// this on a thread for blocking operations. The snapshot is cheap to
+    /// clone and can be moved there.
+    pub fn lookup_batch(
+        &self,
+        index_id: usize,
+        keys: &[impl AsRef<[u8]>],
+    ) -> crate::Result<Vec<Vec<Bytes>>> {
+        keys.iter()
+            .map(|key| self.lookup(index_id, key))
+            .collect::<crate::Result<Vec<_>>>()
+            .context(|| format!("in RotateLogSnapshot::lookup_batch({})", index_id))
+    }
+
+    /// Iterate over all the entries.
+    ///
+    /// The entries are returned in FIFO order.
+    pub fn iter(&self) -> impl Iterator<Item = crate::Result<&[u8]>> {
+        self.logs.iter().rev().flat_map(|log| log.iter())
+    }
+
+    /// Iterate over all the entries, decompressing compressed ones.
+    ///
+    /// The entries are returned in FIFO order.
+    pub fn iter_decompressed(&self) -> impl Iterator<Item = crate::Result<Cow<'_, [u8]>>> {
+        self.logs
+            .iter()
+            .rev()
+            .flat_map(|log| log.iter().decompressed())
+    }
+}
+
 fn create_empty_log(
     dir: Option<&Path>,
     open_options: &OpenOptions,
@@ -1076,6 +1369,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_remove_oldest_logs_to_fit() {
+        let dir = tempdir().unwrap();
+        let dir = &dir;
+        let open = || -> RotateLog {
+            OpenOptions::new()
+                .create(true)
+                .max_bytes_per_log(1)
+                .max_log_count(10)
+                .open(dir)
+                .unwrap()
+        };
+        let read_all =
+            |log: &RotateLog| -> Vec<Vec<u8>> { log.iter().map(|v| v.unwrap().to_vec()).collect() };
+
+        // Create 5 logs. The last one is empty and writable.
+        let mut rotate = open();
+        for i in 0..4 {
+            rotate.append(vec![i; 1000]).unwrap();
+            rotate.sync().unwrap();
+        }
+        let size = || -> u64 {
+            (0..=255u8)
+                .map(|id| log_dir_size(&dir.path().join(id.to_string())))
+                .sum()
+        };
+        let total = size();
+
+        // Nothing to remove.
+        assert_eq!(rotate.remove_oldest_logs_to_fit(total).unwrap(), 0);
+        assert_eq!(read_all(&rotate).len(), 4);
+
+        // Remove the oldest log.
+        let removed = rotate.remove_oldest_logs_to_fit(total - 1).unwrap();
+        assert!(removed > 0);
+        assert_eq!(size(), total - removed);
+        assert_eq!(
+            read_all(&rotate),
+            [vec![1; 1000], vec![2; 1000], vec![3; 1000]]
+        );
+        assert_eq!(read_all(&open()), read_all(&rotate));
+
+        // The writable log is kept.
+        rotate.remove_oldest_logs_to_fit(0).unwrap();
+        assert!(read_all(&rotate).is_empty());
+        assert!(read_all(&open()).is_empty());
+
+        // New data can be written after removal.
+        rotate.append(vec![4]).unwrap();
+        rotate.sync().unwrap();
+        assert_eq!(read_all(&open()), [[4]]);
+    }
+
     fn test_wrapping_rotate(max_log_count: u8) {
         let dir = tempdir().unwrap();
         let mut rotate = OpenOptions::new()
@@ -1182,6 +1528,84 @@ mod tests {
         assert_eq!(iter(&rotate2), vec![b"a2"]);
     }
 
+    #[test]
+    fn test_snapshot() {
+        let dir = tempdir().unwrap();
+        let open_opts = OpenOptions::new()
+            .create(true)
+            .max_bytes_per_log(1)
+            .max_log_count(3)
+            .index("first-byte", |_| vec![IndexOutput::Reference(0..1)]);
+
+        let mut rotate = open_opts.open(&dir).unwrap();
+        rotate.append(b"a1").unwrap();
+        rotate.sync().unwrap();
+        rotate.append(b"a2").unwrap();
+        rotate.sync().unwrap();
+        rotate.append(b"b1").unwrap();
+
+        // In-memory entries are included in snapshots of a RotateLog, but not
+        // in snapshots of the directory.
+        let snapshot = rotate.snapshot().unwrap();
+        assert_eq!(snapshot.lookup(0, b"b").unwrap(), vec![&b"b1"[..]]);
+        let dir_snapshot = open_opts.open_snapshot(&dir).unwrap();
+        assert_eq!(
+            dir_snapshot.lookup(0, b"a").unwrap(),
+            vec![&b"a2"[..], b"a1"]
+        );
+        assert!(dir_snapshot.lookup(0, b"b").unwrap().is_empty());
+
+        // Rotation removes "a1". Snapshots are not affected.
+        rotate.sync().unwrap();
+        rotate.append(b"a3").unwrap();
+        rotate.sync().unwrap();
+        fn iter(snapshot: &RotateLogSnapshot) -> Vec<&[u8]> {
+            snapshot
+                .iter()
+                .collect::<crate::Result<Vec<&[u8]>>>()
+                .unwrap()
+        }
+        assert_eq!(iter(&dir_snapshot), vec![b"a1", b"a2"]);
+        assert_eq!(iter(&snapshot), vec![b"a1", b"a2", b"b1"]);
+        assert_eq!(
+            iter(&open_opts.open_snapshot(&dir).unwrap()),
+            vec![b"b1", b"a3"]
+        );
+
+        let keys = vec![Bytes::from_static(b"a"), Bytes::from_static(b"c")];
+        let values = snapshot.lookup_batch(0, &keys).unwrap();
+        assert_eq!(values, vec![vec![&b"a2"[..], b"a1"], vec![]]);
+        let keys = vec![Bytes::from_static(b"a")];
+        assert!(snapshot.lookup_batch(1, &keys).is_err());
+    }
+
+    #[test]
+    fn test_open_snapshot_without_lock() {
+        let dir = tempdir().unwrap();
+        let index_def = |lag_threshold| {
+            IndexDef::new("first-byte", |_| vec![IndexOutput::Reference(0..1)])
+                .lag_threshold(lag_threshold)
+        };
+        let open_opts = OpenOptions::new()
+            .create(true)
+            .index_defs(vec![index_def(0)]);
+        let mut rotate = open_opts
+            .clone()
+            .index_defs(vec![index_def(u64::MAX)])
+            .open(&dir)
+            .unwrap();
+        rotate.append(b"a1").unwrap();
+        rotate.sync().unwrap();
+
+        // The index is lagging. Opening a snapshot does not try to take the
+        // lock to update it on disk.
+        let _lock = ScopedDirLock::new(&dir.path().join("0")).unwrap();
+        let snapshot = open_opts.open_snapshot(&dir).unwrap();
+        assert_eq!(snapshot.lookup(0, b"a").unwrap(), vec![&b"a1"[..]]);
+
+        assert!(open_opts.open_snapshot(dir.path().join("missing")).is_err());
+    }
+
     #[test]
     fn test_lookup_truncated_meta() {
         // Look up or iteration should work with rotated logs.
@@ -0,0 +1,229 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Minimal reference implementation of a peer cache server.
+//!
+//! Blobs are stored as plain files in a directory: `PUT /<hgid>` writes a blob, `GET /<hgid>`
+//! reads it back, or returns a 404. The server doesn't interpret the blobs, nor does it ever
+//! evict them. It is meant for testing and small deployments, point `peercache.url` at it:
+//!
+//!   peercache_server /var/cache/peercache 0.0.0.0:8123
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use anyhow::bail;
+use anyhow::Result;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8123";
+
+/// Refuse blobs bigger than this, a single file revision is not expected to be that large.
+const MAX_BODY_SIZE: u64 = 1 << 30;
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+struct Request {
+    method: String,
+    path: String,
+    content_length: u64,
+    keep_alive: bool,
+}
+
+/// Read the request line and headers of one request from the connection. The body, if any, is
+/// left in `reader`. Returns `None` when the client closed the connection.
+fn read_request(reader: &mut impl BufRead, writer: &mut impl Write) -> Result<Option<Request>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, path, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), Some(version)) => (method, path, version),
+        _ => bail!("malformed request line: {:?}", line),
+    };
+    let mut keep_alive = version == "HTTP/1.1";
+
+    let mut content_length = 0;
+    let mut expect_continue = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            bail!("connection closed while reading headers");
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse()?;
+            } else if name.eq_ignore_ascii_case("connection") {
+                keep_alive = !value.eq_ignore_ascii_case("close");
+            } else if name.eq_ignore_ascii_case("expect") {
+                expect_continue = value.eq_ignore_ascii_case("100-continue");
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        bail!("request body too large: {}", content_length);
+    }
+
+    // curl asks before sending larger bodies, and otherwise waits a while before sending them.
+    if expect_continue {
+        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        writer.flush()?;
+    }
+
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        content_length,
+        keep_alive,
+    }))
+}
+
+fn write_response(stream: &mut impl Write, status: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: application/octet-stream\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Map the request path to the blob file. Only 40 hex digits hgids are accepted, so a request
+/// can never escape the storage directory.
+fn blob_path(dir: &Path, path: &str) -> Option<PathBuf> {
+    let name = path.trim_start_matches('/');
+    if name.len() == 40 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let name = name.to_ascii_lowercase();
+        Some(dir.join(&name[..2]).join(&name[2..]))
+    } else {
+        None
+    }
+}
+
+/// Write the blob atomically, so concurrent readers never see a partial blob. The body is
+/// streamed to a temporary file instead of being buffered in memory.
+fn write_blob(path: &Path, body: &mut impl Read, len: u64) -> io::Result<()> {
+    let parent = path.parent().unwrap();
+    fs::create_dir_all(parent)?;
+    let tmp = parent.join(format!(
+        ".tmp-{}-{}",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = (|| {
+        let mut file = fs::File::create(&tmp)?;
+        if io::copy(body, &mut file)? != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        fs::rename(&tmp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Answer the request. `body` is the request body, what is not read from it is discarded by the
+/// caller.
+fn handle(
+    dir: &Path,
+    request: &Request,
+    body: &mut impl Read,
+    stream: &mut impl Write,
+) -> io::Result<()> {
+    let path = match blob_path(dir, &request.path) {
+        Some(path) => path,
+        None => return write_response(stream, "400 Bad Request", b""),
+    };
+    match request.method.as_str() {
+        "GET" => match fs::read(&path) {
+            Ok(data) => write_response(stream, "200 OK", &data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                write_response(stream, "404 Not Found", b"")
+            }
+            Err(err) => {
+                eprintln!("failed to read {}: {}", path.display(), err);
+                write_response(stream, "500 Internal Server Error", b"")
+            }
+        },
+        "PUT" => match write_blob(&path, body, request.content_length) {
+            Ok(()) => write_response(stream, "201 Created", b""),
+            // The client went away, there is no one to answer.
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(err),
+            Err(err) => {
+                eprintln!("failed to write {}: {}", path.display(), err);
+                write_response(stream, "500 Internal Server Error", b"")
+            }
+        },
+        _ => write_response(stream, "405 Method Not Allowed", b""),
+    }
+}
+
+fn serve(dir: &Path, stream: TcpStream) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_request(&mut reader, &mut writer)? {
+        let mut body = (&mut reader).take(request.content_length);
+        handle(dir, &request, &mut body, &mut writer)?;
+        io::copy(&mut body, &mut io::sink())?;
+        if !request.keep_alive {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let dir = match args.next() {
+        Some(dir) => PathBuf::from(dir),
+        None => bail!("usage: peercache_server DIR [ADDR]"),
+    };
+    let addr = args.next().unwrap_or_else(|| DEFAULT_ADDR.to_string());
+
+    fs::create_dir_all(&dir)?;
+    let listener = TcpListener::bind(&addr)?;
+    eprintln!("serving {} on {}", dir.display(), listener.local_addr()?);
+    run(&dir, listener);
+    Ok(())
+}
+
+/// Serve the blobs in `dir` to the connections accepted by `listener`, each connection is
+/// handled by its own thread.
+pub fn run(dir: &Path, listener: TcpListener) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let dir = dir.to_path_buf();
+        thread::spawn(move || {
+            if let Err(err) = serve(&dir, stream) {
+                eprintln!("error serving connection: {:?}", err);
+            }
+        });
+    }
+}
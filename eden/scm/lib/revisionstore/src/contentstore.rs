@@ -45,6 +45,7 @@ use crate::memcache::MemcacheStore;
 use crate::multiplexstore::MultiplexDeltaStore;
 use crate::packstore::CorruptionPolicy;
 use crate::packstore::MutableDataPackStore;
+use crate::peercache::PeerCacheStore;
 use crate::remotestore::HgIdRemoteStore;
 use crate::repack::RepackLocation;
 use crate::types::StoreKey;
@@ -269,6 +270,7 @@ pub struct ContentStoreBuilder<'a> {
     remotestore: Option<Arc<dyn HgIdRemoteStore>>,
     suffix: Option<PathBuf>,
     memcachestore: Option<Arc<MemcacheStore>>,
+    peercache: Option<Arc<PeerCacheStore>>,
     correlator: Option<String>,
     shared_indexedlog_local: Option<Arc<IndexedLogHgIdDataStore>>,
     shared_indexedlog_shared: Option<Arc<IndexedLogHgIdDataStore>>,
@@ -284,6 +286,7 @@ impl<'a> ContentStoreBuilder<'a> {
             config,
             remotestore: None,
             memcachestore: None,
+            peercache: None,
             suffix: None,
             correlator: None,
             shared_indexedlog_shared: None,
@@ -318,6 +321,13 @@ impl<'a> ContentStoreBuilder<'a> {
         self
     }
 
+    /// Peer cache to fetch from before the remote store. Build it once with
+    /// `PeerCacheStore::from_config` and share it between the file and tree stores.
+    pub fn peercache(mut self, peercache: Arc<PeerCacheStore>) -> Self {
+        self.peercache = Some(peercache);
+        self
+    }
+
     pub fn suffix(mut self, suffix: impl AsRef<Path>) -> Self {
         self.suffix = Some(suffix.as_ref().to_path_buf());
         self
@@ -560,6 +570,11 @@ impl<'a> ContentStoreBuilder<'a> {
                 remotestores.add(cache.clone());
             };
 
+            // Then the peer cache, which is still closer than the remote store.
+            if let Some(peercache) = self.peercache {
+                remotestores.add(peercache.remote_datastore(shared_mutabledatastore.clone()));
+            }
+
             // Second, the slower remotestore. For LFS blobs, the LFS pointers will be fetched
             // at this step and be written to the LFS store.
             let filenode_remotestore = remotestore.datastore(shared_store.clone());
@@ -89,6 +89,18 @@ impl Store {
         };
         Ok(())
     }
+
+    /// Remove the oldest data of a shared store until it uses at most `max_bytes` on disk. Data
+    /// is removed first in, first out, one rotated log at a time, however recently it was read.
+    /// The log being written to is always kept.
+    ///
+    /// Local stores never remove data. Returns the number of bytes removed.
+    pub fn remove_old_data_to_fit(&mut self, max_bytes: u64) -> Result<u64> {
+        match self {
+            Store::Local(_) => Ok(0),
+            Store::Shared(log) => Ok(log.remove_oldest_logs_to_fit(max_bytes)?),
+        }
+    }
 }
 
 /// Iterator returned from `Store::lookup`.
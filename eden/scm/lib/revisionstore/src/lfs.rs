@@ -115,11 +115,15 @@ use crate::util::get_lfs_pointers_path;
 use crate::util::get_str_config;
 
 /// The `LfsPointersStore` holds the mapping between a `HgId` and the content hash (sha256) of the LFS blob.
-struct LfsPointersStore(Store);
+struct LfsPointersStore {
+    inner: Store,
+    max_size: Option<u64>,
+}
 
 pub(crate) struct LfsIndexedLogBlobsStore {
     inner: RwLock<Store>,
     chunk_size: usize,
+    max_size: Option<u64>,
 }
 
 /// The `LfsBlobsStore` holds the actual blobs. Lookup is done via the content hash (sha256) of the
@@ -260,16 +264,29 @@ impl LfsPointersStore {
         Ok(open_options)
     }
 
+    /// Maximum size of the shared store on disk, enforced on every flush.
+    fn max_size(config: &ConfigSet) -> Result<Option<u64>> {
+        Ok(config
+            .get_opt::<ByteCount>("remotefilelog", "lfs.pointersmaxsize")?
+            .map(|size| size.value()))
+    }
+
     /// Create a local `LfsPointersStore`.
     fn local(path: &Path, config: &ConfigSet) -> Result<Self> {
         let path = get_lfs_pointers_path(path)?;
-        Ok(Self(LfsPointersStore::open_options(config)?.local(path)?))
+        Ok(Self {
+            inner: LfsPointersStore::open_options(config)?.local(path)?,
+            max_size: None,
+        })
     }
 
     /// Create a shared `LfsPointersStore`.
     fn shared(path: &Path, config: &ConfigSet) -> Result<Self> {
         let path = get_lfs_pointers_path(path)?;
-        Ok(Self(LfsPointersStore::open_options(config)?.shared(path)?))
+        Ok(Self {
+            inner: LfsPointersStore::open_options(config)?.shared(path)?,
+            max_size: LfsPointersStore::max_size(config)?,
+        })
     }
 
     /// Read an entry from the slice and deserialize it.
@@ -280,9 +297,9 @@ impl LfsPointersStore {
     /// Find the pointer corresponding to the passed in `StoreKey`.
     fn entry(&self, key: &StoreKey) -> Result<Option<LfsPointersEntry>> {
         let mut iter = match key {
-            StoreKey::HgId(key) => self.0.lookup(Self::INDEX_NODE, key.hgid)?,
+            StoreKey::HgId(key) => self.inner.lookup(Self::INDEX_NODE, key.hgid)?,
             StoreKey::Content(hash, _) => match hash {
-                ContentHash::Sha256(hash) => self.0.lookup(Self::INDEX_SHA256, hash)?,
+                ContentHash::Sha256(hash) => self.inner.lookup(Self::INDEX_SHA256, hash)?,
             },
         };
 
@@ -300,7 +317,20 @@ impl LfsPointersStore {
     }
 
     fn add(&mut self, entry: LfsPointersEntry) -> Result<()> {
-        Ok(self.0.append(serialize(&entry)?)?)
+        Ok(self.inner.append(serialize(&entry)?)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        if let Some(max_size) = self.max_size {
+            self.inner.remove_old_data_to_fit(max_size)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the oldest pointers until the store uses at most `max_size` bytes on disk.
+    fn gc(&mut self, max_size: u64) -> Result<u64> {
+        self.inner.remove_old_data_to_fit(max_size)
     }
 }
 
@@ -348,11 +378,19 @@ impl LfsIndexedLogBlobsStore {
         Ok(open_options)
     }
 
+    /// Maximum size of the store on disk, enforced on every flush.
+    fn max_size(config: &ConfigSet) -> Result<Option<u64>> {
+        Ok(config
+            .get_opt::<ByteCount>("remotefilelog", "lfs.blobsmaxsize")?
+            .map(|size| size.value()))
+    }
+
     pub fn shared(path: &Path, config: &ConfigSet) -> Result<Self> {
         let path = get_lfs_blobs_path(path)?;
         Ok(Self {
             inner: RwLock::new(LfsIndexedLogBlobsStore::open_options(config)?.shared(path)?),
             chunk_size: LfsIndexedLogBlobsStore::chunk_size(config)?,
+            max_size: LfsIndexedLogBlobsStore::max_size(config)?,
         })
     }
 
@@ -456,7 +494,17 @@ impl LfsIndexedLogBlobsStore {
     }
 
     pub fn flush(&self) -> Result<()> {
-        self.inner.write().flush()
+        let mut inner = self.inner.write();
+        inner.flush()?;
+        if let Some(max_size) = self.max_size {
+            inner.remove_old_data_to_fit(max_size)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the oldest blobs until the store uses at most `max_size` bytes on disk.
+    pub fn gc(&self, max_size: u64) -> Result<u64> {
+        self.inner.write().remove_old_data_to_fit(max_size)
     }
 }
 
@@ -593,6 +641,51 @@ impl LfsBlobsStore {
         Ok(())
     }
 
+    /// Remove the least recently used loose blobs until they use at most `max_size` bytes on
+    /// disk. The access time of a blob is used when available, its modification time otherwise.
+    fn gc_loose(path: &Path, max_size: u64) -> Result<u64> {
+        let mut blobs = Vec::new();
+        for dir in path.read_dir()? {
+            let dir = dir?;
+            if !dir.file_type()?.is_dir() {
+                continue;
+            }
+
+            for entry in dir.path().read_dir()? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if !metadata.is_file() {
+                    continue;
+                }
+
+                let used = metadata.accessed().or_else(|_| metadata.modified())?;
+                blobs.push((used, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut size: u64 = blobs.iter().map(|(_, len, _)| len).sum();
+        blobs.sort_unstable();
+
+        let mut removed = 0;
+        for (_, len, path) in blobs {
+            if size <= max_size {
+                break;
+            }
+
+            match remove_file(&path) {
+                Ok(()) => removed += len,
+                // Removed concurrently by another process.
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Cannot remove LFS blob {:?}", path));
+                }
+            }
+            size -= len;
+        }
+
+        Ok(removed)
+    }
+
     pub fn flush(&self) -> Result<()> {
         match self {
             LfsBlobsStore::IndexedLog(log) => log.flush(),
@@ -607,6 +700,20 @@ pub(crate) enum LfsStoreEntry {
     PointerAndBlob(LfsPointersEntry, Bytes),
 }
 
+/// Bytes reclaimed by `LfsStore::gc`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LfsGcStats {
+    pub pointers: u64,
+    pub blobs: u64,
+    pub objects: u64,
+}
+
+impl LfsGcStats {
+    pub fn total(&self) -> u64 {
+        self.pointers + self.blobs + self.objects
+    }
+}
+
 impl LfsStore {
     fn new(pointers: LfsPointersStore, blobs: LfsBlobsStore) -> Result<Self> {
         Ok(Self {
@@ -643,6 +750,47 @@ impl LfsStore {
         Ok(repair_str)
     }
 
+    /// Evict data from a shared `LfsStore` until each of its stores fits in its configured size.
+    ///
+    /// The pointers and blobs stores are capped by `remotefilelog.lfs.pointersmaxsize` and
+    /// `remotefilelog.lfs.blobsmaxsize`, and default to their `lfs.*storesize`. Their data is
+    /// removed first in, first out: reading data does not keep it longer. The loose blobs, written
+    /// before blobs moved to an indexedlog, are only removed when
+    /// `remotefilelog.lfs.objectsmaxsize` is set, least recently used first.
+    pub fn gc(path: impl AsRef<Path>, config: &ConfigSet) -> Result<LfsGcStats> {
+        let path = path.as_ref();
+
+        let pointers_max_size = match LfsPointersStore::max_size(config)? {
+            Some(max_size) => max_size,
+            None => config
+                .get_or("lfs", "pointersstoresize", || ByteCount::from(40_000_000))?
+                .value(),
+        };
+        let blobs_max_size = match LfsIndexedLogBlobsStore::max_size(config)? {
+            Some(max_size) => max_size,
+            None => config
+                .get_or("lfs", "blobsstoresize", || ByteCount::from(20_000_000_000))?
+                .value(),
+        };
+        let objects_max_size =
+            config.get_opt::<ByteCount>("remotefilelog", "lfs.objectsmaxsize")?;
+
+        let pointers = LfsPointersStore::shared(path, config)?.gc(pointers_max_size)?;
+        let blobs = LfsIndexedLogBlobsStore::shared(path, config)?.gc(blobs_max_size)?;
+        let objects = match objects_max_size {
+            Some(max_size) => {
+                LfsBlobsStore::gc_loose(&get_lfs_objects_path(path)?, max_size.value())?
+            }
+            None => 0,
+        };
+
+        Ok(LfsGcStats {
+            pointers,
+            blobs,
+            objects,
+        })
+    }
+
     fn blob_impl(&self, key: StoreKey) -> Result<StoreResult<(LfsPointersEntry, Bytes)>> {
         let pointer = self.pointers.read().entry(&key)?;
 
@@ -816,7 +964,7 @@ impl HgIdMutableDeltaStore for LfsStore {
 
     fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
         self.blobs.flush()?;
-        self.pointers.write().0.flush()?;
+        self.pointers.write().flush()?;
         Ok(None)
     }
 }
@@ -2174,6 +2322,78 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_gc() -> Result<()> {
+        let dir = TempDir::new()?;
+        let mut config = make_lfs_config(&dir, "test_gc");
+        // Rotate the indexedlogs on every flush.
+        config.set("lfs", "blobsstoresize", Some("4"), &Default::default());
+        config.set("lfs", "pointersstoresize", Some("4"), &Default::default());
+
+        let store = LfsStore::shared(&dir, &config)?;
+        let k1 = key("a", "2");
+        let k2 = key("b", "3");
+        for (k, data) in [(&k1, &[1, 2, 3, 4][..]), (&k2, &[5, 6, 7, 8][..])] {
+            let delta = Delta {
+                data: Bytes::from(data),
+                base: None,
+                key: k.clone(),
+            };
+            store.add(&delta, &Default::default())?;
+            store.flush()?;
+        }
+        drop(store);
+
+        let loose_data = Bytes::from(&[9, 10, 11, 12][..]);
+        let loose_sha256 = ContentHash::sha256(&loose_data).unwrap_sha256();
+        let loose_store = LfsBlobsStore::loose(get_lfs_objects_path(dir.path())?);
+        loose_store.add(&loose_sha256, loose_data)?;
+
+        let mut large_config = config.clone();
+        large_config.set(
+            "remotefilelog",
+            "lfs.blobsmaxsize",
+            Some("1GB"),
+            &Default::default(),
+        );
+        large_config.set(
+            "remotefilelog",
+            "lfs.pointersmaxsize",
+            Some("1GB"),
+            &Default::default(),
+        );
+        assert_eq!(LfsStore::gc(&dir, &large_config)?, LfsGcStats::default());
+
+        let store = LfsStore::shared(&dir, &config)?;
+        assert_eq!(
+            store.get_missing(&[StoreKey::from(&k1), StoreKey::from(&k2)])?,
+            vec![]
+        );
+        drop(store);
+
+        // The stores are capped to their tiny `lfs.*storesize`.
+        config.set(
+            "remotefilelog",
+            "lfs.objectsmaxsize",
+            Some("0"),
+            &Default::default(),
+        );
+        let stats = LfsStore::gc(&dir, &config)?;
+        assert!(stats.pointers > 0);
+        assert!(stats.blobs > 0);
+        assert_eq!(stats.objects, 4);
+        assert_eq!(stats.total(), stats.pointers + stats.blobs + stats.objects);
+
+        let store = LfsStore::shared(&dir, &config)?;
+        assert_eq!(
+            store.get_missing(&[StoreKey::from(&k1), StoreKey::from(&k2)])?,
+            vec![StoreKey::from(&k1), StoreKey::from(&k2)]
+        );
+        assert!(!loose_store.contains(&loose_sha256)?);
+
+        Ok(())
+    }
+
     #[test]
     fn test_partial_blob() -> Result<()> {
         let dir = TempDir::new()?;
@@ -135,6 +135,7 @@ mod lfs;
 mod memcache;
 mod metadatastore;
 mod missing;
+mod peercache;
 mod redacted;
 mod remotestore;
 mod repack;
@@ -190,6 +191,8 @@ pub use crate::indexedlogauxstore::AuxStore;
 pub use crate::indexedlogdatastore::IndexedLogHgIdDataStore;
 pub use crate::indexedloghistorystore::IndexedLogHgIdHistoryStore;
 pub use crate::indexedlogutil::StoreType;
+pub use crate::lfs::LfsGcStats;
+pub use crate::lfs::LfsStore;
 pub use crate::localstore::ExtStoredPolicy;
 pub use crate::localstore::LocalStore;
 pub use crate::memcache::MemcacheStore;
@@ -204,6 +207,7 @@ pub use crate::packstore::DataPackStore;
 pub use crate::packstore::HistoryPackStore;
 pub use crate::packstore::MutableDataPackStore;
 pub use crate::packstore::MutableHistoryPackStore;
+pub use crate::peercache::PeerCacheStore;
 pub use crate::redacted::redact_if_needed;
 pub use crate::remotestore::HgIdRemoteStore;
 pub use crate::repack::repack;
@@ -0,0 +1,545 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Client for a content-addressed HTTP peer cache, to be used in front of EdenApi.
+//!
+//! The cache is a plain HTTP service: `GET <url>/<hgid>` returns a blob previously stored with
+//! `PUT <url>/<hgid>`, or a 404 when the blob isn't known. Blobs are `mincode` serialized
+//! `PeerCacheEntry`, which include the parents so that the content can be verified against the
+//! requested hgid. Any server implementing these two methods can be used, `peercache_server` is
+//! a minimal reference implementation.
+
+use std::mem::size_of;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::format_err;
+use anyhow::Result;
+use configparser::config::ConfigSet;
+use crossbeam::channel::bounded;
+use crossbeam::channel::Receiver;
+use crossbeam::channel::Sender;
+use crossbeam::channel::TrySendError;
+use edenapi_types::FileEntry;
+use edenapi_types::TreeEntry;
+use hg_http::http_client;
+use http::StatusCode;
+use http_client::HttpClient;
+use http_client::Request;
+use minibytes::Bytes;
+use serde_derive::Deserialize;
+use serde_derive::Serialize;
+use tracing::debug;
+use tracing::info_span;
+use tracing::warn;
+use types::HgId;
+use types::Key;
+use types::Parents;
+use url::Url;
+
+use crate::datastore::Delta;
+use crate::datastore::HgIdDataStore;
+use crate::datastore::HgIdMutableDeltaStore;
+use crate::datastore::Metadata;
+use crate::datastore::RemoteDataStore;
+use crate::datastore::StoreResult;
+use crate::localstore::LocalStore;
+use crate::memcache::McData;
+use crate::types::StoreKey;
+
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "bin/peercache_server.rs"]
+mod peercache_server;
+
+/// Maximum number of entries waiting to be uploaded, more entries are dropped.
+const UPLOAD_QUEUE_SIZE: usize = 10_000;
+
+/// Maximum number of entries uploaded by a single batch of requests.
+const UPLOAD_BATCH_SIZE: usize = 100;
+
+/// Type of blobs stored in the peer cache.
+///
+/// Unlike `McData`, the parents are stored, as they are needed to verify the hgid. Whenever this
+/// type is changed, blobs already in the cache fail to deserialize and are refetched.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct PeerCacheEntry {
+    #[serde(with = "types::serde_with::key::tuple")]
+    key: Key,
+    #[serde(with = "types::serde_with::hgid::tuple")]
+    p1: HgId,
+    #[serde(with = "types::serde_with::hgid::tuple")]
+    p2: HgId,
+    data: Bytes,
+    metadata: Metadata,
+}
+
+impl PeerCacheEntry {
+    fn new(key: Key, parents: Parents, data: Bytes, metadata: Metadata) -> Self {
+        let (p1, p2) = parents.into_nodes();
+        Self {
+            key,
+            p1,
+            p2,
+            data,
+            metadata,
+        }
+    }
+
+    /// Verify that the content matches the hgid, and convert the entry to the Memcache type,
+    /// which is what the stores know how to handle.
+    fn verify(self) -> Result<McData> {
+        let computed = HgId::from_content(&self.data, Parents::new(self.p1, self.p2));
+        if computed != self.key.hgid {
+            return Err(format_err!(
+                "peercache returned corrupt data for {}, computed hgid {}",
+                self.key,
+                computed
+            ));
+        }
+        Ok(McData {
+            key: self.key,
+            data: self.data,
+            metadata: self.metadata,
+        })
+    }
+}
+
+/// Peer cache client. Configured with `peercache.url`, requests time out after
+/// `peercache.timeout` milliseconds.
+///
+/// All the operations are best effort: a cache that is down, slow or returns garbage only
+/// results in misses, and the data is then fetched from the next remote store. Uploads are done
+/// in the background, in batches, and are dropped when the cache can't keep up.
+pub struct PeerCacheStore {
+    client: PeerCacheClient,
+    uploads: Sender<PeerCacheEntry>,
+}
+
+impl PeerCacheStore {
+    /// Build a `PeerCacheStore` from the configuration. Returns `None` when no cache is
+    /// configured.
+    pub fn from_config(config: &ConfigSet) -> Result<Option<Self>> {
+        let mut url = match config.get_opt::<String>("peercache", "url")? {
+            Some(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+        // Like for LFS, a trailing '/' is needed so that `Url::join` keeps the last component.
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        let url = Url::parse(&url)?;
+        if !["http", "https"].contains(&url.scheme()) {
+            return Err(format_err!("Unsupported peercache url: {}", url));
+        }
+
+        let timeout = Duration::from_millis(config.get_or("peercache", "timeout", || 1000)?);
+
+        Ok(Some(Self::new(url, timeout)))
+    }
+
+    pub fn new(url: Url, timeout: Duration) -> Self {
+        let client = PeerCacheClient {
+            http: http_client("peercache"),
+            url,
+            timeout,
+        };
+
+        // The upload thread exits once the store, and thus the sender, is dropped.
+        let (uploads, queue) = bounded(UPLOAD_QUEUE_SIZE);
+        let uploader = client.clone();
+        thread::spawn(move || uploader.upload_loop(queue));
+
+        Self { client, uploads }
+    }
+
+    /// Fetch the keys from the cache. Misses are not reported, only the found entries are
+    /// returned, in no particular order. Entries that do not match the requested hgid are
+    /// returned as errors.
+    pub(crate) fn get_data_iter(
+        &self,
+        keys: &[Key],
+    ) -> Result<impl Iterator<Item = Result<McData>>> {
+        let found = self.client.get(keys)?;
+
+        let found: Vec<_> = found
+            .into_iter()
+            .filter_map(|res| match res {
+                // Only keep what was asked for, the cache is not trusted to return the right blob.
+                Ok(entry) if !keys.contains(&entry.key) => None,
+                Ok(entry) => Some(entry.verify()),
+                Err(err) => Some(Err(err)),
+            })
+            .collect();
+        Ok(found.into_iter())
+    }
+
+    /// Queue the file for upload. Redacted files and LFS pointers are not uploaded, as their
+    /// content can't be verified.
+    pub(crate) fn add_file(&self, entry: &FileEntry) {
+        if let Some(content) = entry.content() {
+            if let Ok(data) = content.data_checked(&entry.key, entry.parents) {
+                self.add_entry(PeerCacheEntry::new(
+                    entry.key.clone(),
+                    entry.parents,
+                    data.into(),
+                    *content.metadata(),
+                ));
+            }
+        }
+    }
+
+    /// Queue the tree for upload. Trees that can't be verified, like root hybrid manifests, are
+    /// not uploaded.
+    pub(crate) fn add_tree(&self, entry: &TreeEntry) {
+        if let (Ok(data), Some(parents)) = (entry.data_checked(), entry.parents) {
+            self.add_entry(PeerCacheEntry::new(
+                entry.key.clone(),
+                parents,
+                data.into(),
+                Metadata::default(),
+            ));
+        }
+    }
+
+    fn add_entry(&self, entry: PeerCacheEntry) {
+        if let Err(TrySendError::Full(entry)) = self.uploads.try_send(entry) {
+            debug!(
+                "peercache upload queue is full, not uploading {}",
+                entry.key
+            );
+        }
+    }
+
+    pub fn remote_datastore(
+        self: Arc<Self>,
+        store: Arc<dyn HgIdMutableDeltaStore>,
+    ) -> Arc<dyn RemoteDataStore> {
+        Arc::new(PeerCacheHgIdDataStore::new(self, store))
+    }
+}
+
+/// HTTP side of the peer cache, shared by the store and its upload thread.
+#[derive(Clone)]
+struct PeerCacheClient {
+    http: HttpClient,
+    url: Url,
+    timeout: Duration,
+}
+
+impl PeerCacheClient {
+    fn url_for(&self, hgid: &HgId) -> Result<Url> {
+        Ok(self.url.join(&hgid.to_hex())?)
+    }
+
+    fn get(&self, keys: &[Key]) -> Result<Vec<Result<PeerCacheEntry>>> {
+        let requests = keys
+            .iter()
+            .map(|key| Ok(Request::get(self.url_for(&key.hgid)?).timeout(self.timeout)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut found = Vec::new();
+        self.http.send(requests, |res| {
+            match res {
+                Ok(res) if res.status() == StatusCode::OK => {
+                    found.push(mincode::deserialize(res.body()).map_err(Into::into));
+                }
+                Ok(res) if res.status() == StatusCode::NOT_FOUND => {}
+                Ok(res) => found.push(Err(format_err!(
+                    "peercache returned unexpected status {}",
+                    res.status()
+                ))),
+                Err(err) => found.push(Err(err.into())),
+            }
+            Ok(())
+        })?;
+        Ok(found)
+    }
+
+    fn put(&self, entries: &[PeerCacheEntry]) -> Result<()> {
+        let requests = entries
+            .iter()
+            .map(|entry| {
+                Ok(Request::put(self.url_for(&entry.key.hgid)?)
+                    .body(mincode::serialize(entry)?)
+                    .timeout(self.timeout))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut failed = 0;
+        self.http.send(requests, |res| {
+            match res {
+                Ok(res) if res.status().is_success() => {}
+                Ok(res) => {
+                    failed += 1;
+                    debug!("peercache returned unexpected status {}", res.status());
+                }
+                Err(err) => {
+                    failed += 1;
+                    debug!("peercache upload failed: {:?}", err);
+                }
+            }
+            Ok(())
+        })?;
+
+        if failed > 0 {
+            return Err(format_err!(
+                "{} of {} uploads failed",
+                failed,
+                entries.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Upload the queued entries, in batches of up to `UPLOAD_BATCH_SIZE`. Errors are logged and
+    /// otherwise ignored.
+    fn upload_loop(&self, queue: Receiver<PeerCacheEntry>) {
+        while let Ok(entry) = queue.recv() {
+            let mut batch = vec![entry];
+            batch.extend(queue.try_iter().take(UPLOAD_BATCH_SIZE - 1));
+            if let Err(err) = self.put(&batch) {
+                warn!(
+                    "Failed to write {} entries to peercache: {:?}",
+                    batch.len(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+/// Adapter to use the peer cache as a `RemoteDataStore`. Fetched data is written to `store`.
+///
+/// The legacy stores do not keep the parents, without which the data can't be verified, so
+/// nothing is uploaded to the peer cache from here.
+struct PeerCacheHgIdDataStore {
+    store: Arc<dyn HgIdMutableDeltaStore>,
+    peercache: Arc<PeerCacheStore>,
+}
+
+impl PeerCacheHgIdDataStore {
+    pub fn new(peercache: Arc<PeerCacheStore>, store: Arc<dyn HgIdMutableDeltaStore>) -> Self {
+        Self { peercache, store }
+    }
+}
+
+impl HgIdDataStore for PeerCacheHgIdDataStore {
+    fn get(&self, key: StoreKey) -> Result<StoreResult<Vec<u8>>> {
+        match self.prefetch(&[key.clone()]) {
+            Ok(_) => self.store.get(key),
+            Err(_) => Ok(StoreResult::NotFound(key)),
+        }
+    }
+
+    fn get_meta(&self, key: StoreKey) -> Result<StoreResult<Metadata>> {
+        match self.prefetch(&[key.clone()]) {
+            Ok(_) => self.store.get_meta(key),
+            Err(_) => Ok(StoreResult::NotFound(key)),
+        }
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl LocalStore for PeerCacheHgIdDataStore {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        Ok(keys.to_vec())
+    }
+}
+
+impl RemoteDataStore for PeerCacheHgIdDataStore {
+    fn prefetch(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        let span = info_span!(
+            "PeerCacheHgIdDataStore::prefetch",
+            key_count = keys.len(),
+            hit_count = &0,
+            size = &0
+        );
+        let _guard = span.enter();
+
+        let mut hits = 0;
+        let mut size = 0;
+
+        let hgidkeys = keys
+            .iter()
+            .filter_map(|k| match k {
+                StoreKey::HgId(k) => Some(k.clone()),
+                StoreKey::Content(_, _) => None,
+            })
+            .collect::<Vec<_>>();
+
+        let found = match self.peercache.get_data_iter(&hgidkeys) {
+            Ok(found) => found,
+            Err(err) => {
+                warn!("Error fetching from peercache: {:?}", err);
+                return self.store.get_missing(keys);
+            }
+        };
+
+        for mcdata in found {
+            if let Ok(mcdata) = mcdata {
+                let metadata = mcdata.metadata;
+                let delta = Delta {
+                    data: mcdata.data,
+                    base: None,
+                    key: mcdata.key,
+                };
+
+                hits += 1;
+                size += delta.data.len() + size_of::<Key>();
+
+                self.store.add(&delta, &metadata)?;
+            }
+        }
+
+        span.record("hit_count", &hits);
+        span.record("size", &size);
+
+        self.store.get_missing(keys)
+    }
+
+    fn upload(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        Ok(keys.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+
+    use tempfile::TempDir;
+    use types::testutil::*;
+
+    use super::*;
+    use crate::peercache::peercache_server;
+
+    fn entry(path: &str, content: &[u8]) -> PeerCacheEntry {
+        let data = Bytes::copy_from_slice(content);
+        let parents = Parents::One(hgid("1"));
+        let key = Key::new(repo_path_buf(path), HgId::from_content(&data, parents));
+        PeerCacheEntry::new(key, parents, data, Metadata::default())
+    }
+
+    #[test]
+    fn test_from_config() -> Result<()> {
+        let mut config = ConfigSet::new();
+        assert!(PeerCacheStore::from_config(&config)?.is_none());
+
+        config.set(
+            "peercache",
+            "url",
+            Some("http://localhost:8123/cache"),
+            &Default::default(),
+        );
+        let store = PeerCacheStore::from_config(&config)?.unwrap();
+        let hgid = hgid("1");
+        assert_eq!(
+            store.client.url_for(&hgid)?.as_str(),
+            format!("http://localhost:8123/cache/{}", hgid.to_hex())
+        );
+        assert_eq!(store.client.timeout, Duration::from_millis(1000));
+
+        config.set("peercache", "url", Some("ftp://foo"), &Default::default());
+        assert!(PeerCacheStore::from_config(&config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_roundtrip() -> Result<()> {
+        let entry = entry("a", b"content");
+        let body = mincode::serialize(&entry)?;
+        assert_eq!(mincode::deserialize::<PeerCacheEntry>(&body)?, entry);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify() -> Result<()> {
+        let good = entry("a", b"content");
+        assert_eq!(good.clone().verify()?.data, good.data);
+
+        let mut bad = good;
+        bad.data = Bytes::from_static(b"garbage");
+        assert!(bad.verify().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_peercache_server() -> Result<()> {
+        let dir = TempDir::new()?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let path = dir.path().to_path_buf();
+        thread::spawn(move || peercache_server::run(&path, listener));
+        let store = PeerCacheStore::new(url, Duration::from_secs(10));
+
+        let good = entry("a", b"content");
+        let missing = entry("b", b"missing");
+        assert_eq!(store.get_data_iter(&[missing.key.clone()])?.count(), 0);
+
+        // Uploads happen in the background, wait for the entry to show up.
+        store.add_entry(good.clone());
+        let mut found = Vec::new();
+        for _ in 0..100 {
+            found = store
+                .get_data_iter(&[good.key.clone()])?
+                .collect::<Result<_>>()?;
+            if !found.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(found, vec![good.clone().verify()?]);
+
+        // A blob that doesn't match its hgid is reported as an error, and not returned.
+        let mut bad = entry("c", b"content");
+        bad.data = Bytes::from_static(b"garbage");
+        store.client.put(&[bad.clone()])?;
+        let found: Vec<_> = store.get_data_iter(&[bad.key.clone()])?.collect();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_err());
+
+        // Blobs stored under another key are ignored.
+        let other = Key::new(repo_path_buf("d"), good.key.hgid);
+        assert_eq!(store.get_data_iter(&[other])?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_peercache_server_truncated_upload() -> Result<()> {
+        let dir = TempDir::new()?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let path = dir.path().to_path_buf();
+        thread::spawn(move || peercache_server::run(&path, listener));
+
+        // The client announces a large body, but goes away after a few bytes.
+        let hex = hgid("1").to_hex();
+        let mut stream = TcpStream::connect(addr)?;
+        write!(
+            stream,
+            "PUT /{} HTTP/1.1\r\nContent-Length: 100000000\r\n\r\npartial",
+            hex
+        )?;
+        stream.shutdown(Shutdown::Write)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+        assert!(response.is_empty());
+
+        // Neither the blob nor its temporary file are left behind.
+        assert_eq!(fs::read_dir(dir.path().join(&hex[..2]))?.count(), 0);
+        Ok(())
+    }
+}
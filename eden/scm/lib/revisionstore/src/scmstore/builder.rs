@@ -26,6 +26,7 @@ use crate::lfs::LfsRemote;
 use crate::lfs::LfsStore;
 use crate::scmstore::activitylogger::ActivityLogger;
 use crate::scmstore::file::FileStoreMetrics;
+use crate::scmstore::tree::TreeStoreMetrics;
 use crate::scmstore::FileStore;
 use crate::scmstore::TreeStore;
 use crate::util::get_cache_path;
@@ -38,6 +39,7 @@ use crate::EdenApiFileStore;
 use crate::EdenApiTreeStore;
 use crate::ExtStoredPolicy;
 use crate::MemcacheStore;
+use crate::PeerCacheStore;
 
 pub struct FileStoreBuilder<'a> {
     config: &'a ConfigSet,
@@ -54,6 +56,7 @@ pub struct FileStoreBuilder<'a> {
 
     edenapi: Option<Arc<EdenApiFileStore>>,
     memcache: Option<Arc<MemcacheStore>>,
+    peercache: Option<Arc<PeerCacheStore>>,
 
     contentstore: Option<Arc<ContentStore>>,
 }
@@ -73,6 +76,7 @@ impl<'a> FileStoreBuilder<'a> {
             lfs_cache: None,
             edenapi: None,
             memcache: None,
+            peercache: None,
             contentstore: None,
         }
     }
@@ -112,6 +116,13 @@ impl<'a> FileStoreBuilder<'a> {
         self
     }
 
+    /// Peer cache to fetch from before the remote store. Build it once with
+    /// `PeerCacheStore::from_config` and share it between the file and tree stores.
+    pub fn peercache(mut self, peercache: Arc<PeerCacheStore>) -> Self {
+        self.peercache = Some(peercache);
+        self
+    }
+
     pub fn indexedlog_cache(mut self, indexedlog: Arc<IndexedLogHgIdDataStore>) -> Self {
         self.indexedlog_cache = Some(indexedlog);
         self
@@ -323,6 +334,8 @@ impl<'a> FileStoreBuilder<'a> {
 
         let memcache = self.memcache.take();
 
+        let peercache = self.peercache.take();
+
         let edenapi = if self.use_edenapi()? {
             if let Some(edenapi) = self.edenapi.take() {
                 Some(edenapi)
@@ -386,6 +399,8 @@ impl<'a> FileStoreBuilder<'a> {
             memcache,
             cache_to_memcache: true,
 
+            peercache,
+
             edenapi,
             lfs_remote,
 
@@ -413,6 +428,7 @@ pub struct TreeStoreBuilder<'a> {
     indexedlog_cache: Option<Arc<IndexedLogHgIdDataStore>>,
     edenapi: Option<Arc<EdenApiTreeStore>>,
     memcache: Option<Arc<MemcacheStore>>,
+    peercache: Option<Arc<PeerCacheStore>>,
     contentstore: Option<Arc<ContentStore>>,
     filestore: Option<Arc<FileStore>>,
 }
@@ -428,6 +444,7 @@ impl<'a> TreeStoreBuilder<'a> {
             indexedlog_cache: None,
             edenapi: None,
             memcache: None,
+            peercache: None,
             contentstore: None,
             filestore: None,
         }
@@ -458,6 +475,13 @@ impl<'a> TreeStoreBuilder<'a> {
         self
     }
 
+    /// Peer cache to fetch from before the remote store. Build it once with
+    /// `PeerCacheStore::from_config` and share it between the file and tree stores.
+    pub fn peercache(mut self, peercache: Arc<PeerCacheStore>) -> Self {
+        self.peercache = Some(peercache);
+        self
+    }
+
     pub fn indexedlog_cache(mut self, indexedlog: Arc<IndexedLogHgIdDataStore>) -> Self {
         self.indexedlog_cache = Some(indexedlog);
         self
@@ -544,6 +568,8 @@ impl<'a> TreeStoreBuilder<'a> {
 
         let memcache = self.memcache.take();
 
+        let peercache = self.peercache.take();
+
         let edenapi = if self.use_edenapi()? {
             if let Some(edenapi) = self.edenapi.take() {
                 Some(edenapi)
@@ -572,6 +598,8 @@ impl<'a> TreeStoreBuilder<'a> {
             memcache,
             cache_to_memcache: true,
 
+            peercache,
+
             edenapi,
 
             contentstore,
@@ -579,6 +607,7 @@ impl<'a> TreeStoreBuilder<'a> {
 
             creation_time: Instant::now(),
             flush_on_drop: true,
+            metrics: TreeStoreMetrics::new(),
         })
     }
 }
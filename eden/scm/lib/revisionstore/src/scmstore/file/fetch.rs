@@ -37,6 +37,7 @@ use crate::lfs::LfsRemoteInner;
 use crate::lfs::LfsStore;
 use crate::lfs::LfsStoreEntry;
 use crate::memcache::McData;
+use crate::peercache::PeerCacheStore;
 use crate::scmstore::attrs::StoreAttrs;
 use crate::scmstore::fetch::CommonFetchState;
 use crate::scmstore::fetch::FetchErrors;
@@ -385,9 +386,66 @@ impl FetchState {
         }
     }
 
+    fn fetch_peercache_inner(
+        &mut self,
+        store: &PeerCacheStore,
+        indexedlog_cache: Option<&IndexedLogHgIdDataStore>,
+    ) -> Result<()> {
+        let pending = self.pending_nonlfs(FileAttributes::CONTENT);
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.fetch_logger
+            .as_ref()
+            .map(|fl| fl.report_keys(pending.iter()));
+        self.metrics.peercache.fetch(pending.len());
+
+        let found = match store.get_data_iter(&pending) {
+            Ok(found) => found,
+            Err(err) => {
+                self.metrics.peercache.err(pending.len());
+                return Err(err);
+            }
+        };
+
+        let mut hits = 0;
+        let mut errors = 0;
+        for res in found {
+            match res {
+                Ok(mcdata) => {
+                    hits += 1;
+                    // Peer cache entries share the Memcache format, and are handled the same way.
+                    self.found_memcache(mcdata, indexedlog_cache);
+                }
+                Err(err) => {
+                    errors += 1;
+                    tracing::warn!("Error fetching file from peercache: {:?}", err);
+                }
+            }
+        }
+        self.metrics.peercache.hit(hits);
+        self.metrics.peercache.err(errors);
+        self.metrics
+            .peercache
+            .miss(pending.len().saturating_sub(hits + errors));
+        Ok(())
+    }
+
+    #[instrument(skip(self, store, indexedlog_cache))]
+    pub(crate) fn fetch_peercache(
+        &mut self,
+        store: &PeerCacheStore,
+        indexedlog_cache: Option<&IndexedLogHgIdDataStore>,
+    ) {
+        // The peer cache is best effort, whatever isn't found there is fetched from EdenApi.
+        if let Err(err) = self.fetch_peercache_inner(store, indexedlog_cache) {
+            tracing::warn!("Error fetching files from peercache: {:?}", err);
+        }
+    }
+
     #[instrument(
         level = "debug",
-        skip(entry, indexedlog_cache, lfs_cache, aux_cache, memcache)
+        skip(entry, indexedlog_cache, lfs_cache, aux_cache, memcache, peercache)
     )]
     fn found_edenapi(
         entry: FileEntry,
@@ -395,6 +453,7 @@ impl FetchState {
         lfs_cache: Option<Arc<LfsStore>>,
         aux_cache: Option<Arc<AuxStore>>,
         memcache: Option<Arc<MemcacheStore>>,
+        peercache: Option<Arc<PeerCacheStore>>,
     ) -> Result<(StoreFile, Option<LfsPointersEntry>)> {
         let key = entry.key.clone();
         let mut file = StoreFile::default();
@@ -408,6 +467,10 @@ impl FetchState {
             file.aux_data = Some(aux_data);
         }
 
+        if let Some(peercache) = peercache.as_ref() {
+            peercache.add_file(&entry);
+        }
+
         if let Some(content) = entry.content() {
             if content.metadata().is_lfs() {
                 let ptr: LfsPointersEntry = entry.try_into()?;
@@ -437,6 +500,7 @@ impl FetchState {
         lfs_cache: Option<Arc<LfsStore>>,
         aux_cache: Option<Arc<AuxStore>>,
         memcache: Option<Arc<MemcacheStore>>,
+        peercache: Option<Arc<PeerCacheStore>>,
     ) -> Result<()> {
         let fetchable = FileAttributes::CONTENT | FileAttributes::AUX;
         let span = tracing::info_span!(
@@ -479,6 +543,7 @@ impl FetchState {
                 let indexedlog_cache = indexedlog_cache.clone();
                 let aux_cache = aux_cache.clone();
                 let memcache = memcache.clone();
+                let peercache = peercache.clone();
                 spawn_blocking(move || {
                     res_entry.map(move |entry| {
                         (
@@ -489,6 +554,7 @@ impl FetchState {
                                 lfs_cache,
                                 aux_cache,
                                 memcache,
+                                peercache,
                             ),
                         )
                     })
@@ -529,10 +595,16 @@ impl FetchState {
         lfs_cache: Option<Arc<LfsStore>>,
         aux_cache: Option<Arc<AuxStore>>,
         memcache: Option<Arc<MemcacheStore>>,
+        peercache: Option<Arc<PeerCacheStore>>,
     ) {
-        if let Err(err) =
-            self.fetch_edenapi_inner(store, indexedlog_cache, lfs_cache, aux_cache, memcache)
-        {
+        if let Err(err) = self.fetch_edenapi_inner(
+            store,
+            indexedlog_cache,
+            lfs_cache,
+            aux_cache,
+            memcache,
+            peercache,
+        ) {
             self.errors.other_error(err);
         }
     }
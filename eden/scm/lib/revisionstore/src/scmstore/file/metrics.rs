@@ -65,6 +65,7 @@ pub struct FileStoreFetchMetrics {
     pub(crate) indexedlog: LocalAndCacheFetchMetrics,
     pub(crate) lfs: LocalAndCacheFetchMetrics,
     pub(crate) aux: LocalAndCacheFetchMetrics,
+    pub(crate) peercache: FetchMetrics,
     pub(crate) contentstore: ContentStoreFetchMetrics,
 }
 
@@ -73,6 +74,7 @@ impl AddAssign for FileStoreFetchMetrics {
         self.indexedlog += rhs.indexedlog;
         self.lfs += rhs.lfs;
         self.aux += rhs.aux;
+        self.peercache += rhs.peercache;
         self.contentstore += rhs.contentstore;
     }
 }
@@ -82,6 +84,7 @@ impl FileStoreFetchMetrics {
         namespaced("indexedlog", self.indexedlog.metrics())
             .chain(namespaced("lfs", self.lfs.metrics()))
             .chain(namespaced("aux", self.aux.metrics()))
+            .chain(namespaced("peercache", self.peercache.metrics()))
             .chain(namespaced("contentstore", self.contentstore.metrics()))
     }
 }
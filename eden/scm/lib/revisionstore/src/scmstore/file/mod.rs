@@ -60,6 +60,7 @@ use crate::LocalStore;
 use crate::MemcacheStore;
 use crate::Metadata;
 use crate::MultiplexDeltaStore;
+use crate::PeerCacheStore;
 use crate::RepackLocation;
 use crate::StoreKey;
 use crate::StoreResult;
@@ -92,6 +93,9 @@ pub struct FileStore {
     // Memcache
     pub(crate) memcache: Option<Arc<MemcacheStore>>,
 
+    // HTTP peer cache, checked before EdenApi
+    pub(crate) peercache: Option<Arc<PeerCacheStore>>,
+
     // Remote stores
     pub(crate) lfs_remote: Option<Arc<LfsRemote>>,
     pub(crate) edenapi: Option<Arc<EdenApiFileStore>>,
@@ -142,6 +146,7 @@ impl FileStore {
         let lfs_cache = self.lfs_cache.clone();
         let lfs_local = self.lfs_local.clone();
         let memcache = self.memcache.clone();
+        let peercache = self.peercache.clone();
         let edenapi = self.edenapi.clone();
         let lfs_remote = self.lfs_remote.clone();
         let contentstore = self.contentstore.clone();
@@ -186,6 +191,10 @@ impl FileStore {
                 }
             }
 
+            if let Some(ref peercache) = peercache {
+                state.fetch_peercache(peercache, indexedlog_cache.as_ref().map(|s| s.as_ref()));
+            }
+
             if prefer_computing_aux_data {
                 state.derive_computable(
                     aux_cache.as_ref().map(|s| s.as_ref()),
@@ -204,6 +213,7 @@ impl FileStore {
                     } else {
                         None
                     },
+                    peercache.clone(),
                 );
             }
 
@@ -344,6 +354,8 @@ impl FileStore {
             memcache: None,
             cache_to_memcache: self.cache_to_memcache.clone(),
 
+            peercache: None,
+
             edenapi: None,
             lfs_remote: None,
 
@@ -432,6 +444,8 @@ impl FileStore {
             memcache: None,
             cache_to_memcache: true,
 
+            peercache: None,
+
             edenapi: None,
             lfs_remote: None,
 
@@ -482,6 +496,8 @@ impl LegacyStore for FileStore {
             memcache: None,
             cache_to_memcache: false,
 
+            peercache: None,
+
             edenapi: None,
             lfs_remote: None,
 
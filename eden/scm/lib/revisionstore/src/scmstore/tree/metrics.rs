@@ -0,0 +1,46 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::ops::AddAssign;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::scmstore::metrics::namespaced;
+use crate::scmstore::metrics::FetchMetrics;
+
+#[derive(Clone, Debug, Default)]
+pub struct TreeStoreFetchMetrics {
+    pub(crate) peercache: FetchMetrics,
+}
+
+impl AddAssign for TreeStoreFetchMetrics {
+    fn add_assign(&mut self, rhs: Self) {
+        self.peercache += rhs.peercache;
+    }
+}
+
+impl TreeStoreFetchMetrics {
+    fn metrics(&self) -> impl Iterator<Item = (String, usize)> {
+        namespaced("peercache", self.peercache.metrics())
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TreeStoreMetrics {
+    pub(crate) fetch: TreeStoreFetchMetrics,
+}
+
+impl TreeStoreMetrics {
+    pub fn new() -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(TreeStoreMetrics::default()))
+    }
+
+    pub fn metrics(&self) -> impl Iterator<Item = (String, usize)> {
+        namespaced("scmstore.tree", namespaced("fetch", self.fetch.metrics()))
+    }
+}
@@ -17,10 +17,15 @@ use anyhow::Result;
 use crossbeam::channel::unbounded;
 use edenapi_types::TreeChildEntry;
 use minibytes::Bytes;
+use parking_lot::RwLock;
 use tracing::field;
 
+mod metrics;
 pub mod types;
 
+pub use self::metrics::TreeStoreFetchMetrics;
+pub use self::metrics::TreeStoreMetrics;
+
 use crate::datastore::HgIdDataStore;
 use crate::datastore::RemoteDataStore;
 use crate::indexedlogdatastore::Entry;
@@ -31,9 +36,11 @@ use crate::scmstore::fetch::FetchErrors;
 use crate::scmstore::fetch::FetchResults;
 use crate::scmstore::fetch::KeyFetchError;
 use crate::scmstore::file::FileStore;
+use crate::scmstore::metrics::FetchMetrics;
 use crate::scmstore::tree::types::LazyTree;
 use crate::scmstore::tree::types::StoreTree;
 use crate::scmstore::tree::types::TreeAttributes;
+use crate::scmstore::tree::types::TreeAuxData;
 use crate::util;
 use crate::ContentDataStore;
 use crate::ContentMetadata;
@@ -45,6 +52,7 @@ use crate::LegacyStore;
 use crate::LocalStore;
 use crate::MemcacheStore;
 use crate::Metadata;
+use crate::PeerCacheStore;
 use crate::RepackLocation;
 use crate::StoreKey;
 use crate::StoreResult;
@@ -68,6 +76,10 @@ pub struct TreeStore {
     // will be written to memcache.
     pub cache_to_memcache: bool,
 
+    /// If provided, this HTTP peer cache will be checked before EdenApi, and populated with the
+    /// trees fetched from EdenApi.
+    pub peercache: Option<Arc<PeerCacheStore>>,
+
     /// An EdenApi Client, EdenApiTreeStore provides the tree-specific subset of EdenApi functionality
     /// used by TreeStore.
     pub edenapi: Option<Arc<EdenApiTreeStore>>,
@@ -83,6 +95,8 @@ pub struct TreeStore {
     pub creation_time: Instant,
 
     pub flush_on_drop: bool,
+
+    pub metrics: Arc<RwLock<TreeStoreMetrics>>,
 }
 
 impl Drop for TreeStore {
@@ -95,19 +109,30 @@ impl Drop for TreeStore {
 
 impl TreeStore {
     pub fn fetch_batch(&self, reqs: impl Iterator<Item = Key>) -> Result<FetchResults<StoreTree>> {
+        self.fetch_batch_with_attrs(reqs, TreeAttributes::CONTENT)
+    }
+
+    /// Fetch the requested attributes of trees. Aux data is computed by the
+    /// server and is not cached locally, so requesting it always goes to EdenApi.
+    pub fn fetch_batch_with_attrs(
+        &self,
+        reqs: impl Iterator<Item = Key>,
+        attrs: TreeAttributes,
+    ) -> Result<FetchResults<StoreTree>> {
         let (found_tx, found_rx) = unbounded();
         let found_tx2 = found_tx.clone();
-        let mut common: CommonFetchState<StoreTree> =
-            CommonFetchState::new(reqs, TreeAttributes::CONTENT, found_tx);
+        let mut common: CommonFetchState<StoreTree> = CommonFetchState::new(reqs, attrs, found_tx);
 
         let keys_len = common.pending_len();
 
         let indexedlog_cache = self.indexedlog_cache.clone();
         let indexedlog_local = self.indexedlog_local.clone();
         let memcache = self.memcache.clone();
+        let peercache = self.peercache.clone();
         let edenapi = self.edenapi.clone();
         let contentstore = self.contentstore.clone();
         let creation_time = self.creation_time;
+        let metrics = self.metrics.clone();
         let cache_to_memcache = self.cache_to_memcache;
         let cache_to_local_cache = self.cache_to_local_cache;
         let (aux_local, aux_cache) = if let Some(ref filestore) = self.filestore {
@@ -163,11 +188,66 @@ impl TreeStore {
                 }
             }
 
-            if let Some(ref edenapi) = edenapi {
+            if let Some(ref peercache) = peercache {
                 let pending: Vec<_> = common
                     .pending(TreeAttributes::CONTENT, false)
                     .map(|(key, _attrs)| key.clone())
                     .collect();
+
+                // The peer cache is best effort, on error the trees are fetched from EdenApi.
+                if !pending.is_empty() {
+                    let mut peercache_metrics = FetchMetrics::default();
+                    peercache_metrics.fetch(pending.len());
+                    match peercache.get_data_iter(&pending) {
+                        Ok(found) => {
+                            let mut hits = 0;
+                            let mut errors = 0;
+                            for entry in found {
+                                let entry = match entry {
+                                    Ok(entry) => entry,
+                                    Err(err) => {
+                                        errors += 1;
+                                        tracing::warn!(
+                                            "Error fetching tree from peercache: {:?}",
+                                            err
+                                        );
+                                        continue;
+                                    }
+                                };
+                                hits += 1;
+                                let key = entry.key.clone();
+                                let entry = LazyTree::Memcache(entry);
+                                if indexedlog_cache.is_some() && cache_to_local_cache {
+                                    if let Some(entry) =
+                                        entry.indexedlog_cache_entry(key.clone())?
+                                    {
+                                        indexedlog_cache.as_ref().unwrap().put_entry(entry)?;
+                                    }
+                                }
+                                common.found(key, entry.into());
+                            }
+                            peercache_metrics.hit(hits);
+                            peercache_metrics.err(errors);
+                            peercache_metrics.miss(pending.len().saturating_sub(hits + errors));
+                        }
+                        Err(err) => {
+                            peercache_metrics.err(pending.len());
+                            tracing::warn!("Error fetching trees from peercache: {:?}", err);
+                        }
+                    }
+                    metrics.write().fetch.peercache += peercache_metrics;
+                }
+            }
+
+            if let Some(ref edenapi) = edenapi {
+                let mut fetch_aux = false;
+                let pending: Vec<_> = common
+                    .pending(TreeAttributes::CONTENT | TreeAttributes::AUX, false)
+                    .map(|(key, attrs)| {
+                        fetch_aux |= attrs.aux_data;
+                        key.clone()
+                    })
+                    .collect();
                 if !pending.is_empty() {
                     let span = tracing::info_span!(
                         "fetch_edenapi",
@@ -179,9 +259,10 @@ impl TreeStore {
                         download_speed = field::Empty,
                     );
                     let _enter = span.enter();
-                    let attributes = if aux_local.is_some() {
+                    let attributes = if aux_local.is_some() || fetch_aux {
                         Some(edenapi_types::TreeAttributes {
-                            child_metadata: true,
+                            child_metadata: aux_local.is_some(),
+                            aux_data: fetch_aux,
                             ..edenapi_types::TreeAttributes::default()
                         })
                     } else {
@@ -189,8 +270,17 @@ impl TreeStore {
                     };
                     let response = edenapi.trees_blocking(pending, attributes)?;
                     for entry in response.entries {
-                        let entry = entry?;
+                        let mut entry = entry?;
                         let key = entry.key.clone();
+                        let aux_data = match entry.tree_aux_data.take().map(TreeAuxData::try_from) {
+                            Some(Ok(aux_data)) => Some(aux_data),
+                            Some(Err(err)) => {
+                                // not failing tree fetching for aux related problems
+                                tracing::warn!("Error parsing tree aux data: {:?}", err);
+                                None
+                            }
+                            None => None,
+                        };
                         if let Some(ref aux_local) = aux_local {
                             if let Some(ref children) = entry.children {
                                 for file_entry in children {
@@ -226,6 +316,9 @@ impl TreeStore {
                                 );
                             }
                         }
+                        if let Some(ref peercache) = peercache {
+                            peercache.add_tree(&entry);
+                        }
                         let entry = LazyTree::EdenApi(entry);
                         if indexedlog_cache.is_some() && cache_to_local_cache {
                             if let Some(entry) = entry.indexedlog_cache_entry(key.clone())? {
@@ -237,7 +330,13 @@ impl TreeStore {
                                 memcache.as_ref().unwrap().add_mcdata(entry.try_into()?);
                             }
                         }
-                        common.found(key, entry.into());
+                        common.found(
+                            key,
+                            StoreTree {
+                                content: Some(entry),
+                                aux_data,
+                            },
+                        );
                     }
                     util::record_edenapi_stats(&span, &response.stats);
                 }
@@ -315,12 +414,14 @@ impl TreeStore {
             cache_to_local_cache: false,
             memcache: None,
             cache_to_memcache: false,
+            peercache: None,
             edenapi: None,
             contentstore: None,
             creation_time: Instant::now(),
             // TODO(meyer): Do we actually need the outer FileStore / TreeStore to be Arc'd?
             filestore: self.filestore.as_ref().map(|store| Arc::new(store.local())),
             flush_on_drop: false,
+            metrics: self.metrics.clone(),
         }
     }
 
@@ -334,6 +435,8 @@ impl TreeStore {
             memcache: None,
             cache_to_memcache: true,
 
+            peercache: None,
+
             edenapi: None,
 
             contentstore: None,
@@ -341,9 +444,14 @@ impl TreeStore {
             filestore: None,
             creation_time: Instant::now(),
             flush_on_drop: true,
+            metrics: TreeStoreMetrics::new(),
         }
     }
 
+    pub fn metrics(&self) -> Vec<(String, usize)> {
+        self.metrics.read().metrics().collect()
+    }
+
     #[allow(unused_must_use)]
     pub fn flush(&self) -> Result<()> {
         let mut result = Ok(());
@@ -387,12 +495,15 @@ impl LegacyStore for TreeStore {
             memcache: None,
             cache_to_memcache: false,
 
+            peercache: None,
+
             edenapi: None,
             contentstore: None,
 
             filestore: None,
             creation_time: Instant::now(),
             flush_on_drop: true,
+            metrics: self.metrics.clone(),
         })
     }
 
@@ -15,10 +15,14 @@ use crate::scmstore::attrs::StoreAttrs;
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TreeAttributes {
     pub content: bool,
+    pub aux_data: bool,
 }
 
 impl StoreAttrs for TreeAttributes {
-    const NONE: Self = TreeAttributes { content: false };
+    const NONE: Self = TreeAttributes {
+        content: false,
+        aux_data: false,
+    };
 
     /// Returns all the attributes which are present or can be computed from present attributes.
     fn with_computable(&self) -> TreeAttributes {
@@ -27,7 +31,15 @@ impl StoreAttrs for TreeAttributes {
 }
 
 impl TreeAttributes {
-    pub const CONTENT: Self = TreeAttributes { content: true };
+    pub const CONTENT: Self = TreeAttributes {
+        content: true,
+        aux_data: false,
+    };
+
+    pub const AUX: Self = TreeAttributes {
+        content: false,
+        aux_data: true,
+    };
 }
 
 impl Not for TreeAttributes {
@@ -36,6 +48,7 @@ impl Not for TreeAttributes {
     fn not(self) -> Self::Output {
         TreeAttributes {
             content: !self.content,
+            aux_data: !self.aux_data,
         }
     }
 }
@@ -46,6 +59,7 @@ impl BitAnd for TreeAttributes {
     fn bitand(self, rhs: Self) -> Self::Output {
         TreeAttributes {
             content: self.content & rhs.content,
+            aux_data: self.aux_data & rhs.aux_data,
         }
     }
 }
@@ -56,6 +70,7 @@ impl BitOr for TreeAttributes {
     fn bitor(self, rhs: Self) -> Self::Output {
         TreeAttributes {
             content: self.content | rhs.content,
+            aux_data: self.aux_data | rhs.aux_data,
         }
     }
 }
@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::anyhow;
+use anyhow::Error;
+use edenapi_types::DirectoryMetadata;
+use edenapi_types::Sha1;
+use serde::Deserialize;
+use serde::Serialize;
+use types::Sha256;
+
+/// Digests and sizes of a directory, as computed by the server.
+///
+/// The digests cover the names, types and content hashes of all descendants,
+/// so two directories with the same digest have the same content.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct TreeAuxData {
+    pub digest_sha1: Sha1,
+    pub digest_sha256: Sha256,
+    pub child_files_count: u64,
+    pub child_files_total_size: u64,
+    pub child_dirs_count: u64,
+    pub descendant_files_count: u64,
+    pub descendant_files_total_size: u64,
+}
+
+impl TryFrom<DirectoryMetadata> for TreeAuxData {
+    type Error = Error;
+
+    fn try_from(v: DirectoryMetadata) -> Result<Self, Self::Error> {
+        let missing = |field: &str| anyhow!("tree aux data is missing {}", field);
+        Ok(TreeAuxData {
+            digest_sha1: v
+                .simple_format_sha1
+                .ok_or_else(|| missing("simple_format_sha1"))?,
+            digest_sha256: Sha256::from_byte_array(
+                v.simple_format_sha256
+                    .ok_or_else(|| missing("simple_format_sha256"))?
+                    .into(),
+            ),
+            child_files_count: v
+                .child_files_count
+                .ok_or_else(|| missing("child_files_count"))?,
+            child_files_total_size: v
+                .child_files_total_size
+                .ok_or_else(|| missing("child_files_total_size"))?,
+            child_dirs_count: v
+                .child_dirs_count
+                .ok_or_else(|| missing("child_dirs_count"))?,
+            descendant_files_count: v
+                .descendant_files_count
+                .ok_or_else(|| missing("descendant_files_count"))?,
+            descendant_files_total_size: v
+                .descendant_files_total_size
+                .ok_or_else(|| missing("descendant_files_total_size"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_directory_metadata() {
+        let metadata = DirectoryMetadata {
+            fsnode_id: None,
+            simple_format_sha1: Some(Sha1::from_byte_array([1; 20])),
+            simple_format_sha256: Some(edenapi_types::Sha256::from_byte_array([2; 32])),
+            child_files_count: Some(2),
+            child_files_total_size: Some(10),
+            child_dirs_count: Some(1),
+            descendant_files_count: Some(3),
+            descendant_files_total_size: Some(30),
+        };
+        assert_eq!(
+            TreeAuxData::try_from(metadata).unwrap(),
+            TreeAuxData {
+                digest_sha1: Sha1::from_byte_array([1; 20]),
+                digest_sha256: Sha256::from_byte_array([2; 32]),
+                child_files_count: 2,
+                child_files_total_size: 10,
+                child_dirs_count: 1,
+                descendant_files_count: 3,
+                descendant_files_total_size: 30,
+            }
+        );
+
+        // The fsnode id is optional. Other fields are required.
+        let metadata = DirectoryMetadata {
+            child_files_total_size: None,
+            ..metadata
+        };
+        assert!(TreeAuxData::try_from(metadata).is_err());
+    }
+}
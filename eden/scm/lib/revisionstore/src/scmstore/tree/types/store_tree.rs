@@ -13,11 +13,13 @@ use manifest_tree::TreeEntry as ManifestTreeEntry;
 
 use crate::scmstore::tree::types::LazyTree;
 use crate::scmstore::tree::types::TreeAttributes;
+use crate::scmstore::tree::types::TreeAuxData;
 use crate::scmstore::value::StoreValue;
 
 #[derive(Debug)]
 pub struct StoreTree {
     pub(crate) content: Option<LazyTree>,
+    pub(crate) aux_data: Option<TreeAuxData>,
 }
 
 impl StoreTree {
@@ -27,6 +29,11 @@ impl StoreTree {
             .ok_or_else(|| anyhow!("no content available"))?
             .manifest_tree_entry()
     }
+
+    pub fn aux_data(&self) -> Result<TreeAuxData> {
+        self.aux_data
+            .ok_or_else(|| anyhow!("no aux data available"))
+    }
 }
 
 impl StoreValue for StoreTree {
@@ -36,6 +43,7 @@ impl StoreValue for StoreTree {
     fn attrs(&self) -> TreeAttributes {
         TreeAttributes {
             content: self.content.is_some(),
+            aux_data: self.aux_data.is_some(),
         }
     }
 
@@ -43,6 +51,7 @@ impl StoreValue for StoreTree {
     fn mask(self, attrs: TreeAttributes) -> Self {
         StoreTree {
             content: if attrs.content { self.content } else { None },
+            aux_data: if attrs.aux_data { self.aux_data } else { None },
         }
     }
 }
@@ -53,18 +62,34 @@ impl BitOr for StoreTree {
     fn bitor(self, rhs: Self) -> Self::Output {
         StoreTree {
             content: self.content.or(rhs.content),
+            aux_data: self.aux_data.or(rhs.aux_data),
         }
     }
 }
 
 impl Default for StoreTree {
     fn default() -> Self {
-        StoreTree { content: None }
+        StoreTree {
+            content: None,
+            aux_data: None,
+        }
     }
 }
 
 impl From<LazyTree> for StoreTree {
     fn from(v: LazyTree) -> Self {
-        StoreTree { content: Some(v) }
+        StoreTree {
+            content: Some(v),
+            aux_data: None,
+        }
+    }
+}
+
+impl From<TreeAuxData> for StoreTree {
+    fn from(v: TreeAuxData) -> Self {
+        StoreTree {
+            content: None,
+            aux_data: Some(v),
+        }
     }
 }
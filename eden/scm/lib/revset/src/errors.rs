@@ -0,0 +1,37 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RevsetError {
+    #[error("cannot parse revset at {1}: {0}")]
+    Parse(String, usize),
+
+    #[error("unknown revision {0:?}")]
+    UnknownRevision(String),
+
+    #[error("ambiguous identifier {0:?}")]
+    AmbiguousRevision(String),
+
+    #[error("unknown revset function {0:?}")]
+    UnknownFunction(String),
+
+    #[error("{0}() {1}")]
+    InvalidArguments(String, String),
+
+    #[error(transparent)]
+    Dag(#[from] dag::Error),
+
+    #[error(transparent)]
+    Commits(#[from] hgcommits::Error),
+
+    #[error(transparent)]
+    Metalog(#[from] metalog::Error),
+}
+
+pub type Result<T> = std::result::Result<T, RevsetError>;
@@ -0,0 +1,536 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Evaluate [`Expr`] to [`NameSet`].
+
+use std::sync::Arc;
+
+use dag::errors::BackendError;
+use dag::ops::DagAlgorithm;
+use dag::ops::PrefixLookup;
+use dag::NameSet;
+use dag::Vertex;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use hgcommits::ReadCommitText;
+use hgtime::HgTime;
+use metalog::MetaLog;
+use minibytes::Bytes;
+use pathmatcher::TreeMatcher;
+use types::HgId;
+
+use crate::errors::Result;
+use crate::errors::RevsetError;
+use crate::parser::parse;
+use crate::parser::Expr;
+use crate::refs::Refs;
+
+/// Evaluates revset expressions against a repo.
+///
+/// The commit graph is queried through [`DagAlgorithm`], so sets stay lazy
+/// where the graph supports it. Phases and bookmarks are read from the
+/// [`MetaLog`] once, when the context is created. Commit text is only read
+/// by filters like `author()`, for vertexes in the set being filtered.
+#[derive(Clone)]
+pub struct Context {
+    dag: Arc<dyn DagAlgorithm + Send + Sync>,
+    commits: Arc<dyn ReadCommitText + Send + Sync>,
+    prefix_lookup: Option<Arc<dyn PrefixLookup + Send + Sync>>,
+    refs: Refs,
+    working_parent: Option<Vertex>,
+}
+
+impl Context {
+    pub fn new(
+        dag: Arc<dyn DagAlgorithm + Send + Sync>,
+        commits: Arc<dyn ReadCommitText + Send + Sync>,
+        metalog: &MetaLog,
+    ) -> Result<Self> {
+        Ok(Self {
+            dag,
+            commits,
+            prefix_lookup: None,
+            refs: Refs::from_metalog(metalog)?,
+            working_parent: None,
+        })
+    }
+
+    /// Resolve hex prefixes of commit hashes using `prefix_lookup`.
+    /// Without it, only full hashes are resolved.
+    pub fn with_prefix_lookup(
+        mut self,
+        prefix_lookup: Arc<dyn PrefixLookup + Send + Sync>,
+    ) -> Self {
+        self.prefix_lookup = Some(prefix_lookup);
+        self
+    }
+
+    /// Resolve "." to `vertex`.
+    pub fn with_working_parent(mut self, vertex: Vertex) -> Self {
+        self.working_parent = Some(vertex);
+        self
+    }
+
+    /// Parse and evaluate a revset expression.
+    pub async fn eval_str(&self, spec: &str) -> Result<NameSet> {
+        let expr = parse(spec)?;
+        self.eval(&expr).await
+    }
+
+    /// Evaluate a parsed expression.
+    pub fn eval<'a>(&'a self, expr: &'a Expr) -> BoxFuture<'a, Result<NameSet>> {
+        async move {
+            let dag = &self.dag;
+            let set = match expr {
+                Expr::Name(name) => self.resolve(name).await?,
+                Expr::Range(roots, heads) => match (roots, heads) {
+                    (None, None) => dag.all().await?,
+                    (None, Some(heads)) => dag.ancestors(self.eval(heads).await?).await?,
+                    (Some(roots), None) => dag.descendants(self.eval(roots).await?).await?,
+                    (Some(roots), Some(heads)) => {
+                        let roots = self.eval(roots).await?;
+                        dag.range(roots, self.eval(heads).await?).await?
+                    }
+                },
+                Expr::Union(lhs, rhs) => self.eval(lhs).await?.union(&self.eval(rhs).await?),
+                Expr::Intersection(lhs, rhs) => {
+                    // Filters only look at vertexes in the other side.
+                    if let Some(filter) = self.filter(rhs)? {
+                        filter.apply(self.eval(lhs).await?)
+                    } else if let Some(filter) = self.filter(lhs)? {
+                        filter.apply(self.eval(rhs).await?)
+                    } else {
+                        self.eval(lhs).await?.intersection(&self.eval(rhs).await?)
+                    }
+                }
+                Expr::Difference(lhs, rhs) => {
+                    self.eval(lhs).await?.difference(&self.eval(rhs).await?)
+                }
+                Expr::Only(lhs, rhs) => {
+                    let lhs = self.eval(lhs).await?;
+                    dag.only(lhs, self.eval(rhs).await?).await?
+                }
+                Expr::Not(expr) => dag.all().await?.difference(&self.eval(expr).await?),
+                Expr::Func(name, args) => self.eval_func(name, args).await?,
+            };
+            Ok(set)
+        }
+        .boxed()
+    }
+
+    async fn eval_func(&self, name: &str, args: &[Expr]) -> Result<NameSet> {
+        let dag = &self.dag;
+        let arity = |n: usize| -> Result<()> {
+            if args.len() == n {
+                Ok(())
+            } else {
+                let message = format!("takes {} arguments, got {}", n, args.len());
+                Err(RevsetError::InvalidArguments(name.to_string(), message))
+            }
+        };
+        let set = match name {
+            "all" => {
+                arity(0)?;
+                dag.all().await?
+            }
+            "ancestors" | "descendants" | "heads" | "roots" | "parents" => {
+                arity(1)?;
+                let set = self.eval(&args[0]).await?;
+                match name {
+                    "ancestors" => dag.ancestors(set).await?,
+                    "descendants" => dag.descendants(set).await?,
+                    "heads" => dag.heads(set).await?,
+                    "roots" => dag.roots(set).await?,
+                    _ => dag.parents(set).await?,
+                }
+            }
+            "only" => {
+                arity(2)?;
+                let reachable = self.eval(&args[0]).await?;
+                dag.only(reachable, self.eval(&args[1]).await?).await?
+            }
+            "public" => {
+                arity(0)?;
+                self.public().await?
+            }
+            "draft" => {
+                arity(0)?;
+                let heads = self.vertexes(self.refs.visible_heads.clone()).await?;
+                let public = self.public().await?;
+                dag.only(heads, public).await?
+            }
+            "bookmark" => match args {
+                [] => {
+                    self.vertexes(self.refs.bookmarks.values().cloned().collect())
+                        .await?
+                }
+                [Expr::Name(bookmark)] => match self.refs.bookmarks.get(bookmark) {
+                    Some(vertex) => self.vertexes(vec![vertex.clone()]).await?,
+                    None => return Err(RevsetError::UnknownRevision(bookmark.clone())),
+                },
+                _ => {
+                    let message = "takes an optional bookmark name".to_string();
+                    return Err(RevsetError::InvalidArguments(name.to_string(), message));
+                }
+            },
+            _ => match self.filter(&Expr::Func(name.to_string(), args.to_vec()))? {
+                Some(filter) => filter.apply(dag.all().await?),
+                None => return Err(RevsetError::UnknownFunction(name.to_string())),
+            },
+        };
+        Ok(set)
+    }
+
+    /// Ancestors of remote bookmarks.
+    async fn public(&self) -> Result<NameSet> {
+        let heads = self.refs.remotenames.values().cloned().collect();
+        let heads = self.vertexes(heads).await?;
+        Ok(self.dag.ancestors(heads).await?)
+    }
+
+    /// Convert vertexes to a set that is known by the graph.
+    async fn vertexes(&self, vertexes: Vec<Vertex>) -> Result<NameSet> {
+        let set = NameSet::from_static_names(vertexes);
+        Ok(self.dag.sort(&set).await?)
+    }
+
+    async fn resolve(&self, name: &str) -> Result<NameSet> {
+        let unknown = || RevsetError::UnknownRevision(name.to_string());
+        let vertex = if name == "." {
+            self.working_parent.clone().ok_or_else(unknown)?
+        } else if let Some(vertex) = self.refs.bookmarks.get(name) {
+            vertex.clone()
+        } else if let Some(vertex) = self.refs.remotenames.get(name) {
+            vertex.clone()
+        } else if let Ok(id) = HgId::from_hex(name.as_bytes()) {
+            Vertex::copy_from(id.as_ref())
+        } else if let (Some(lookup), true) = (
+            &self.prefix_lookup,
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_hexdigit()),
+        ) {
+            let mut found = lookup
+                .vertexes_by_hex_prefix(name.to_lowercase().as_bytes(), 2)
+                .await?;
+            match found.len() {
+                0 => return Err(unknown()),
+                1 => found.pop().unwrap(),
+                _ => return Err(RevsetError::AmbiguousRevision(name.to_string())),
+            }
+        } else {
+            return Err(unknown());
+        };
+        match self.vertexes(vec![vertex]).await {
+            Err(RevsetError::Dag(dag::Error::VertexNotFound(_))) => Err(unknown()),
+            result => result,
+        }
+    }
+
+    /// Convert `author()`, `date()` and `file()` to filters.
+    fn filter(&self, expr: &Expr) -> Result<Option<Filter>> {
+        let (name, args) = match expr {
+            Expr::Func(name, args) => (name, args),
+            _ => return Ok(None),
+        };
+        let pattern = match (name.as_str(), &args[..]) {
+            ("author" | "date" | "file", [Expr::Name(pattern)]) => pattern,
+            ("author" | "date" | "file", _) => {
+                let message = "takes a string argument".to_string();
+                return Err(RevsetError::InvalidArguments(name.clone(), message));
+            }
+            _ => return Ok(None),
+        };
+        let invalid = |message: &str| RevsetError::InvalidArguments(name.clone(), message.into());
+        let predicate = match name.as_str() {
+            "author" => Predicate::Author(pattern.to_lowercase()),
+            "date" => {
+                let range = HgTime::parse_range(pattern).ok_or_else(|| invalid("invalid date"))?;
+                Predicate::Date(range.start.unixtime, range.end.unixtime)
+            }
+            _ => {
+                let rule = match pattern.strip_prefix("glob:") {
+                    Some(glob) => glob.to_string(),
+                    None => {
+                        let path = pattern.strip_prefix("path:").unwrap_or(pattern);
+                        format!("{}/**", pathmatcher::plain_to_glob(path))
+                    }
+                };
+                let matcher = TreeMatcher::from_rules([rule].iter())
+                    .map_err(|_| invalid("invalid pattern"))?;
+                Predicate::File(Arc::new(matcher))
+            }
+        };
+        Ok(Some(Filter {
+            commits: self.commits.clone(),
+            predicate: Arc::new(predicate),
+        }))
+    }
+}
+
+/// Filters vertexes by their commit text.
+struct Filter {
+    commits: Arc<dyn ReadCommitText + Send + Sync>,
+    predicate: Arc<Predicate>,
+}
+
+enum Predicate {
+    /// Case-insensitive substring of the author.
+    Author(String),
+    /// Range of commit time, in seconds since epoch. End is exclusive.
+    Date(i64, i64),
+    /// Matcher of changed files.
+    File(Arc<TreeMatcher>),
+}
+
+impl Filter {
+    fn apply(self, set: NameSet) -> NameSet {
+        let commits = self.commits;
+        let predicate = self.predicate;
+        set.filter(Box::new(move |vertex: &Vertex| {
+            let commits = commits.clone();
+            let predicate = predicate.clone();
+            let vertex = vertex.clone();
+            async move {
+                let text = commits
+                    .get_commit_raw_text(&vertex)
+                    .await
+                    .map_err(|e| match e {
+                        hgcommits::Error::Dag(e) => e,
+                        e => dag::Error::from(BackendError::Generic(e.to_string())),
+                    })?;
+                Ok(match text {
+                    Some(text) => predicate.matches(&text),
+                    None => false,
+                })
+            }
+            .boxed()
+        }))
+    }
+}
+
+impl Predicate {
+    /// Test commit text in the hg format:
+    ///
+    /// ```plain,ignore
+    /// manifest hex
+    /// author
+    /// time timezone [extras]
+    /// file 1
+    /// ...
+    ///
+    /// message
+    /// ```
+    fn matches(&self, text: &Bytes) -> bool {
+        let text = String::from_utf8_lossy(text);
+        let mut lines = text.split('\n').skip(1);
+        let author = lines.next().unwrap_or_default();
+        match self {
+            Predicate::Author(pattern) => author.to_lowercase().contains(pattern),
+            Predicate::Date(start, end) => {
+                let time = lines.next().unwrap_or_default();
+                let time = time.split(' ').next().unwrap_or_default();
+                match time.parse::<f64>() {
+                    Ok(time) => {
+                        let time = time as i64;
+                        *start <= time && time < *end
+                    }
+                    Err(_) => false,
+                }
+            }
+            Predicate::File(matcher) => lines
+                .skip(1)
+                .take_while(|line| !line.is_empty())
+                .any(|path| matcher.matches(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use dag::ops::DagAddHeads;
+    use dag::MemDag;
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    struct TestCommits(HashMap<Vertex, Bytes>);
+
+    #[async_trait::async_trait]
+    impl ReadCommitText for TestCommits {
+        async fn get_commit_raw_text(&self, vertex: &Vertex) -> hgcommits::Result<Option<Bytes>> {
+            Ok(self.0.get(vertex).cloned())
+        }
+    }
+
+    /// Vertex named by a single letter, like "A", as a 20-byte hash.
+    fn v(name: &str) -> Vertex {
+        Vertex::copy_from(&[name.as_bytes()[0]; 20])
+    }
+
+    fn hex(name: &str) -> String {
+        v(name).to_hex()
+    }
+
+    async fn context(dir: &std::path::Path) -> Context {
+        // A - B - C - D - E   master: C
+        //          \
+        //           F - G     bookmark: feature
+        let mut dag = MemDag::new();
+        let parents: HashMap<Vertex, Vec<Vertex>> = [
+            ("A", ""),
+            ("B", "A"),
+            ("C", "B"),
+            ("D", "C"),
+            ("E", "D"),
+            ("F", "C"),
+            ("G", "F"),
+        ]
+        .iter()
+        .map(|(c, p)| (v(c), p.chars().map(|p| v(&p.to_string())).collect()))
+        .collect();
+        let heads = vec![v("E"), v("G")];
+        dag.add_heads(&parents, &heads.into()).await.unwrap();
+
+        let mut texts = HashMap::new();
+        for (i, (name, author, files)) in [
+            ("A", "Alice <alice@example.com>", "a"),
+            ("B", "Bob <bob@example.com>", "b"),
+            ("C", "alice", "dir/c"),
+            ("D", "Bob", "dir/d\ne"),
+            ("E", "Carol", "e"),
+            ("F", "Carol", "dir/sub/f"),
+            ("G", "Alice", "g"),
+        ]
+        .iter()
+        .enumerate()
+        {
+            let time = 86400 * (i + 1);
+            let text = format!("{}\n{}\n{} 0\n{}\n\nmessage", hex("0"), author, time, files);
+            texts.insert(v(name), Bytes::from(text));
+        }
+
+        let mut metalog = MetaLog::open(dir, None).unwrap();
+        metalog
+            .set("bookmarks", format!("{} feature\n", hex("G")).as_bytes())
+            .unwrap();
+        metalog
+            .set(
+                "remotenames",
+                format!(
+                    "{} bookmarks remote/master\n{} bookmarks default-push/x\n",
+                    hex("C"),
+                    hex("E")
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        metalog
+            .set(
+                "visibleheads",
+                format!("v1\n{}\n{}\n", hex("E"), hex("G")).as_bytes(),
+            )
+            .unwrap();
+
+        let dag = Arc::new(dag);
+        Context::new(dag.clone(), Arc::new(TestCommits(texts)), &metalog)
+            .unwrap()
+            .with_prefix_lookup(dag)
+            .with_working_parent(v("D"))
+    }
+
+    async fn eval(context: &Context, spec: &str) -> String {
+        match context.eval_str(spec).await {
+            Ok(set) => {
+                let vertexes: Vec<Vertex> = set.iter().await.unwrap().try_collect().await.unwrap();
+                let mut names: Vec<char> = vertexes.iter().map(|v| v.as_ref()[0] as char).collect();
+                names.sort_unstable();
+                names.into_iter().collect()
+            }
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_eval_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = context(dir.path()).await;
+        assert_eq!(eval(&context, "feature").await, "G");
+        assert_eq!(eval(&context, "remote/master").await, "C");
+        assert_eq!(eval(&context, ".").await, "D");
+        assert_eq!(eval(&context, &hex("B")).await, "B");
+        assert_eq!(eval(&context, "4545").await, "E");
+        assert_eq!(eval(&context, "x").await, "unknown revision \"x\"");
+        assert_eq!(
+            eval(&context, "'default-push/x'").await,
+            "unknown revision \"default-push/x\""
+        );
+        assert_eq!(
+            eval(&context, &hex("Z")).await,
+            format!("unknown revision {:?}", hex("Z"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eval_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = context(dir.path()).await;
+        assert_eq!(eval(&context, "::").await, "ABCDEFG");
+        assert_eq!(eval(&context, "::remote/master").await, "ABC");
+        assert_eq!(eval(&context, "remote/master::").await, "CDEFG");
+        assert_eq!(eval(&context, "remote/master::feature").await, "CFG");
+        assert_eq!(eval(&context, "ancestors(.) - ::remote/master").await, "D");
+        assert_eq!(eval(&context, "only(feature, .)").await, "FG");
+        assert_eq!(eval(&context, "feature % .").await, "FG");
+        assert_eq!(eval(&context, "heads(all())").await, "EG");
+        assert_eq!(eval(&context, "roots(draft())").await, "DF");
+        assert_eq!(eval(&context, "parents(roots(draft()))").await, "C");
+        assert_eq!(eval(&context, "not ::.").await, "EFG");
+        assert_eq!(eval(&context, "descendants(feature) | . & ::.").await, "DG");
+    }
+
+    #[tokio::test]
+    async fn test_eval_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = context(dir.path()).await;
+        assert_eq!(eval(&context, "public()").await, "ABC");
+        assert_eq!(eval(&context, "draft()").await, "DEFG");
+        assert_eq!(eval(&context, "bookmark()").await, "G");
+        assert_eq!(eval(&context, "bookmark(feature)").await, "G");
+        assert_eq!(
+            eval(&context, "bookmark(x)").await,
+            "unknown revision \"x\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_eval_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = context(dir.path()).await;
+        assert_eq!(eval(&context, "author(alice)").await, "ACG");
+        assert_eq!(eval(&context, "draft() & author(ALICE)").await, "G");
+        assert_eq!(eval(&context, "author('@example.com') & ::.").await, "AB");
+        assert_eq!(eval(&context, "file(dir)").await, "CDF");
+        assert_eq!(eval(&context, "file('glob:dir/*')").await, "CD");
+        assert_eq!(eval(&context, "file(e)").await, "DE");
+        assert_eq!(eval(&context, "date('>345601 0')").await, "EFG");
+        assert_eq!(eval(&context, "date('172800 0 to 259200 0')").await, "BC");
+        assert_eq!(
+            eval(&context, "author()").await,
+            "author() takes a string argument"
+        );
+        assert_eq!(eval(&context, "date(x)").await, "date() invalid date");
+        assert_eq!(
+            eval(&context, "foo()").await,
+            "unknown revset function \"foo\""
+        );
+        assert_eq!(
+            eval(&context, "ancestors()").await,
+            "ancestors() takes 1 arguments, got 0"
+        );
+    }
+}
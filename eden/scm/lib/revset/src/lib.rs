@@ -0,0 +1,25 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! # revset
+//!
+//! Parse and evaluate revset expressions, like `draft() & author(alice)`.
+//!
+//! Expressions are compiled to [`dag::NameSet`] operations. Bookmarks and
+//! phases are read from [`metalog::MetaLog`], and commit text is read using
+//! [`hgcommits::ReadCommitText`]. See [`Context`] for the main structure.
+
+mod errors;
+mod eval;
+mod parser;
+mod refs;
+
+pub use errors::Result;
+pub use errors::RevsetError as Error;
+pub use eval::Context;
+pub use parser::parse;
+pub use parser::Expr;
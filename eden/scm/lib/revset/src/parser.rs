@@ -0,0 +1,359 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Parse revset expressions into [`Expr`].
+//!
+//! Operators, from the lowest precedence to the highest:
+//! - `x | y`, `x + y`, `x or y`: union
+//! - `x & y`, `x and y`: intersection, `x - y`: difference, `x % y`: only
+//! - `!x`, `not x`: complement
+//! - `x::y`, `::y`, `x::`, `::`: ranges
+//!
+//! Names can be quoted using `'` or `"` if they contain special characters.
+
+use crate::errors::Result;
+use crate::errors::RevsetError;
+
+/// Parsed revset expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    /// A name, like a bookmark, a commit hash, or a function argument.
+    Name(String),
+
+    /// `x::y`. Missing sides mean roots or heads of the graph.
+    Range(Option<Box<Expr>>, Option<Box<Expr>>),
+
+    Union(Box<Expr>, Box<Expr>),
+
+    Intersection(Box<Expr>, Box<Expr>),
+
+    Difference(Box<Expr>, Box<Expr>),
+
+    /// `x % y`, same as `only(x, y)`.
+    Only(Box<Expr>, Box<Expr>),
+
+    Not(Box<Expr>),
+
+    /// Function call, like `ancestors(x)`.
+    Func(String, Vec<Expr>),
+}
+
+/// Parse a revset expression.
+pub fn parse(spec: &str) -> Result<Expr> {
+    let tokens = tokenize(spec)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: spec.len(),
+    };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => parser.error("unexpected token"),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Symbol(String),
+    String(String),
+    Op(&'static str),
+}
+
+const OPS: &[&str] = &["::", "(", ")", ",", "|", "+", "&", "-", "%", "!"];
+
+fn is_symbol_char(ch: char) -> bool {
+    ch.is_alphanumeric() || "._/@$".contains(ch) || !ch.is_ascii()
+}
+
+/// Split `spec` into tokens, with their byte offsets.
+fn tokenize(spec: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = spec.char_indices().peekable();
+    'outer: while let Some(&(pos, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        for op in OPS {
+            if spec[pos..].starts_with(op) {
+                tokens.push((pos, Token::Op(op)));
+                for _ in 0..op.len() {
+                    chars.next();
+                }
+                continue 'outer;
+            }
+        }
+        if ch == '\'' || ch == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    None => {
+                        return Err(RevsetError::Parse("unterminated string".into(), pos));
+                    }
+                    Some((_, c)) if c == ch => break,
+                    Some((escape_pos, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, c)) if c == '\\' || c == '\'' || c == '"' => value.push(c),
+                        _ => {
+                            return Err(RevsetError::Parse("invalid escape".into(), escape_pos));
+                        }
+                    },
+                    Some((_, c)) => value.push(c),
+                }
+            }
+            tokens.push((pos, Token::String(value)));
+        } else if is_symbol_char(ch) {
+            let mut value = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if !is_symbol_char(c) {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            tokens.push((pos, Token::Symbol(value)));
+        } else {
+            return Err(RevsetError::Parse(
+                format!("unexpected character {:?}", ch),
+                pos,
+            ));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Offset used in errors at the end of the input.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T> {
+        let offset = match self.tokens.get(self.pos) {
+            Some((offset, _)) => *offset,
+            None => self.end,
+        };
+        Err(RevsetError::Parse(message.to_string(), offset))
+    }
+
+    /// Consume the next token if it is one of the operators or keywords.
+    fn eat(&mut self, ops: &[&str]) -> Option<&'static str> {
+        let matched = match self.peek() {
+            Some(Token::Op(op)) => ops.iter().find(|o| *o == op).map(|_| *op),
+            Some(Token::Symbol(s)) => match s.as_str() {
+                "and" if ops.contains(&"&") => Some("&"),
+                "or" if ops.contains(&"|") => Some("|"),
+                "not" if ops.contains(&"!") => Some("!"),
+                _ => None,
+            },
+            _ => None,
+        };
+        if matched.is_some() {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    /// Whether the next tokens are a function call.
+    fn is_call(&self) -> bool {
+        matches!(self.tokens.get(self.pos + 1), Some((_, Token::Op("("))))
+    }
+
+    /// Whether the next token can start a primary expression.
+    fn at_primary(&self) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) => !matches!(s.as_str(), "and" | "or" | "not"),
+            Some(Token::String(_)) | Some(Token::Op("(")) => true,
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.eat(&["|", "+"]).is_some() {
+            let rhs = self.parse_and()?;
+            expr = Expr::Union(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while let Some(op) = self.eat(&["&", "-", "%"]) {
+            let lhs = Box::new(expr);
+            let rhs = Box::new(self.parse_not()?);
+            expr = match op {
+                "&" => Expr::Intersection(lhs, rhs),
+                "-" => Expr::Difference(lhs, rhs),
+                _ => Expr::Only(lhs, rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat(&["!"]).is_some() {
+            let expr = self.parse_not()?;
+            Ok(Expr::Not(Box::new(expr)))
+        } else {
+            self.parse_range()
+        }
+    }
+
+    fn parse_range(&mut self) -> Result<Expr> {
+        let lhs = if self.eat(&["::"]).is_some() {
+            None
+        } else {
+            let expr = self.parse_primary()?;
+            if self.eat(&["::"]).is_none() {
+                return Ok(expr);
+            }
+            Some(Box::new(expr))
+        };
+        let rhs = if self.at_primary() {
+            Some(Box::new(self.parse_primary()?))
+        } else {
+            None
+        };
+        Ok(Expr::Range(lhs, rhs))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Op("(")) => {
+                self.next();
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Op(")")) => Ok(expr),
+                    _ => {
+                        self.pos -= 1;
+                        self.error("expected ')'")
+                    }
+                }
+            }
+            Some(Token::String(_)) => match self.next() {
+                Some(Token::String(s)) => Ok(Expr::Name(s)),
+                _ => unreachable!(),
+            },
+            Some(Token::Symbol(_)) if self.at_primary() => {
+                let is_call = self.is_call();
+                let name = match self.next() {
+                    Some(Token::Symbol(s)) => s,
+                    _ => unreachable!(),
+                };
+                if !is_call {
+                    return Ok(Expr::Name(name));
+                }
+                self.next();
+                let mut args = Vec::new();
+                if self.eat(&[")"]).is_some() {
+                    return Ok(Expr::Func(name, args));
+                }
+                loop {
+                    args.push(self.parse_or()?);
+                    match self.eat(&[",", ")"]) {
+                        Some(",") => continue,
+                        Some(_) => break,
+                        None => return self.error("expected ',' or ')'"),
+                    }
+                }
+                Ok(Expr::Func(name, args))
+            }
+            _ => self.error("expected a name, a function call, or '('"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(spec: &str) -> String {
+        match parse(spec) {
+            Ok(expr) => format!("{:?}", expr),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_names() {
+        assert_eq!(p("master"), r#"Name("master")"#);
+        assert_eq!(p("remote/master"), r#"Name("remote/master")"#);
+        assert_eq!(p(" 'a b' "), r#"Name("a b")"#);
+        assert_eq!(p(r#""a\"b""#), r#"Name("a\"b")"#);
+        assert_eq!(p("."), r#"Name(".")"#);
+    }
+
+    #[test]
+    fn test_parse_operators() {
+        assert_eq!(p("a::b"), r#"Range(Some(Name("a")), Some(Name("b")))"#);
+        assert_eq!(p("::b"), r#"Range(None, Some(Name("b")))"#);
+        assert_eq!(p("a::"), r#"Range(Some(Name("a")), None)"#);
+        assert_eq!(p("::"), "Range(None, None)");
+        assert_eq!(
+            p("a | b & c"),
+            r#"Union(Name("a"), Intersection(Name("b"), Name("c")))"#
+        );
+        assert_eq!(
+            p("(a or b) and not c"),
+            r#"Intersection(Union(Name("a"), Name("b")), Not(Name("c")))"#
+        );
+        assert_eq!(
+            p("a - b % c"),
+            r#"Only(Difference(Name("a"), Name("b")), Name("c"))"#
+        );
+        assert_eq!(
+            p("!::a + a::"),
+            r#"Union(Not(Range(None, Some(Name("a")))), Range(Some(Name("a")), None))"#
+        );
+    }
+
+    #[test]
+    fn test_parse_functions() {
+        assert_eq!(p("draft()"), r#"Func("draft", [])"#);
+        assert_eq!(
+            p("only(a, ::b)"),
+            r#"Func("only", [Name("a"), Range(None, Some(Name("b")))])"#
+        );
+        assert_eq!(
+            p("author('alice') & date(\"2021-01-01 to 2021-02-01\")"),
+            r#"Intersection(Func("author", [Name("alice")]), Func("date", [Name("2021-01-01 to 2021-02-01")]))"#
+        );
+        // Keywords are not function names.
+        assert_eq!(p("not(a)"), r#"Not(Name("a"))"#);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            p(""),
+            "cannot parse revset at 0: expected a name, a function call, or '('"
+        );
+        assert_eq!(p("a b"), "cannot parse revset at 2: unexpected token");
+        assert_eq!(p("(a"), "cannot parse revset at 2: expected ')'");
+        assert_eq!(p("f(a b)"), "cannot parse revset at 4: expected ',' or ')'");
+        assert_eq!(p("'a"), "cannot parse revset at 0: unterminated string");
+        assert_eq!(
+            p("a:b"),
+            "cannot parse revset at 1: unexpected character ':'"
+        );
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! References stored in [`MetaLog`]: bookmarks, remote bookmarks, and
+//! visible heads.
+//!
+//! The formats match `edenscm.mercurial.bookmarks` and
+//! `edenscm.mercurial.visibility`.
+
+use std::collections::BTreeMap;
+
+use dag::Vertex;
+use metalog::MetaLog;
+use types::HgId;
+
+use crate::errors::Result;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Refs {
+    /// Local bookmarks, by name.
+    pub(crate) bookmarks: BTreeMap<String, Vertex>,
+
+    /// Remote bookmarks, by full name, like "remote/master".
+    pub(crate) remotenames: BTreeMap<String, Vertex>,
+
+    /// Heads of visible draft commits.
+    pub(crate) visible_heads: Vec<Vertex>,
+}
+
+impl Refs {
+    pub(crate) fn from_metalog(metalog: &MetaLog) -> Result<Self> {
+        let mut refs = Refs::default();
+
+        // "{hex} {name}" per line.
+        if let Some(data) = metalog.get("bookmarks")? {
+            for line in utf8(&data)?.lines() {
+                let (hex, name) = split(line, "bookmarks")?;
+                refs.bookmarks.insert(name.to_string(), parse_hex(hex)?);
+            }
+        }
+
+        // "{hex} {type} {fullname}" per line. Only bookmarks are used.
+        if let Some(data) = metalog.get("remotenames")? {
+            for line in utf8(&data)?.lines() {
+                let (hex, rest) = split(line, "remotenames")?;
+                let (name_type, name) = split(rest, "remotenames")?;
+                if name_type != "bookmarks" || name.starts_with("default-push/") {
+                    continue;
+                }
+                refs.remotenames.insert(name.to_string(), parse_hex(hex)?);
+            }
+        }
+
+        // "v1" followed by "{hex}" per line.
+        if let Some(data) = metalog.get("visibleheads")? {
+            let mut lines = utf8(&data)?.lines();
+            match lines.next() {
+                None => {}
+                Some("v1") => {
+                    for line in lines {
+                        refs.visible_heads.push(parse_hex(line.trim())?);
+                    }
+                }
+                Some(version) => {
+                    let message = format!("invalid visibleheads format {:?}", version);
+                    return Err(metalog::Error::from(message).into());
+                }
+            }
+        }
+
+        Ok(refs)
+    }
+}
+
+fn utf8(data: &[u8]) -> Result<&str> {
+    Ok(std::str::from_utf8(data).map_err(metalog::Error::from)?)
+}
+
+fn split<'a>(line: &'a str, key: &str) -> Result<(&'a str, &'a str)> {
+    match line.split_once(' ') {
+        Some(split) => Ok(split),
+        None => Err(metalog::Error::from(format!("corrupt entry in {}: {:?}", key, line)).into()),
+    }
+}
+
+fn parse_hex(hex: &str) -> Result<Vertex> {
+    match HgId::from_hex(hex.as_bytes()) {
+        Ok(id) => Ok(Vertex::copy_from(id.as_ref())),
+        Err(_) => Err(metalog::Error::from(format!("invalid commit hash {:?}", hex)).into()),
+    }
+}
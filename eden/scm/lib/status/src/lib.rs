@@ -14,8 +14,11 @@ use types::RepoPathBuf;
 #[derive(Default)]
 pub struct Status {
     all: HashMap<RepoPathBuf, FileStatus>,
+    // Maps the destination of a copy or rename to its source.
+    copymap: HashMap<RepoPathBuf, RepoPathBuf>,
 }
 
+#[derive(Default)]
 pub struct StatusBuilder(Status);
 
 impl StatusBuilder {
@@ -52,6 +55,21 @@ impl StatusBuilder {
         self
     }
 
+    pub fn ignored(mut self, ignored: Vec<RepoPathBuf>) -> Self {
+        Self::index(&mut self.0.all, ignored, FileStatus::Ignored);
+        self
+    }
+
+    pub fn clean(mut self, clean: Vec<RepoPathBuf>) -> Self {
+        Self::index(&mut self.0.all, clean, FileStatus::Clean);
+        self
+    }
+
+    pub fn copymap(mut self, copymap: HashMap<RepoPathBuf, RepoPathBuf>) -> Self {
+        self.0.copymap = copymap;
+        self
+    }
+
     // This fn has to take 'deconstructed' self, because you can't borrow &mut self and &self.xxx at the same time
     fn index(
         all: &mut HashMap<RepoPathBuf, FileStatus>,
@@ -88,6 +106,24 @@ impl Status {
         self.filter_status(FileStatus::Unknown)
     }
 
+    pub fn ignored(&self) -> impl Iterator<Item = &RepoPathBuf> {
+        self.filter_status(FileStatus::Ignored)
+    }
+
+    pub fn clean(&self) -> impl Iterator<Item = &RepoPathBuf> {
+        self.filter_status(FileStatus::Clean)
+    }
+
+    /// The file `file` was copied or renamed from, if any.
+    pub fn copy_source(&self, file: &RepoPath) -> Option<&RepoPathBuf> {
+        self.copymap.get(file)
+    }
+
+    /// Copies and renames, as (destination, source) pairs.
+    pub fn copies(&self) -> impl Iterator<Item = (&RepoPathBuf, &RepoPathBuf)> {
+        self.copymap.iter()
+    }
+
     pub fn status(&self, file: &RepoPath) -> Option<FileStatus> {
         self.all.get(file).copied()
     }
@@ -99,13 +135,15 @@ impl Status {
     }
 }
 
-#[derive(Clone, Copy, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum FileStatus {
     Modified,
     Added,
     Removed,
     Deleted,
     Unknown,
+    Ignored,
+    Clean,
 }
 
 impl FileStatus {
@@ -116,6 +154,8 @@ impl FileStatus {
             FileStatus::Removed => "R",
             FileStatus::Unknown => "?",
             FileStatus::Deleted => "!",
+            FileStatus::Ignored => "I",
+            FileStatus::Clean => "C",
         }
     }
 }
@@ -6,7 +6,176 @@
  */
 
 //! Directory State.
+//!
+//! `.hg/dirstate` holds the parents of the working copy, and points to the root of the treestate
+//! that tracks its files. It is written by `treestate.py`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind as IoErrorKind;
+use std::path::Path;
+
+use anyhow::Result;
+use types::hgid::NULL_ID;
+use types::HgId;
+
+use crate::errors::ErrorKind;
+use crate::store::BlockId;
+use crate::treestate::TreeState;
+
+const HEADER: &[u8] = b"\ntreestate\n\0";
 
 /// A dirstate object. This maintains .hg/dirstate file
+#[derive(Debug, PartialEq)]
+pub struct Dirstate {
+    pub p1: HgId,
+    pub p2: HgId,
+    /// Name of the treestate file in `.hg/treestate`, and its root. None until the treestate is
+    /// first written.
+    pub tree: Option<(String, BlockId)>,
+}
+
+impl Dirstate {
+    /// Read the dirstate of the repo whose `.hg` directory is `dot_hg_path`. A missing dirstate is
+    /// an empty one, as right after `hg init`. Returns None for a flat dirstate, which is written
+    /// instead when treestate is disabled and isn't understood here.
+    pub fn read(dot_hg_path: &Path) -> Result<Option<Self>> {
+        match fs::read(dot_hg_path.join("dirstate")) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == IoErrorKind::NotFound => Self::parse(&[]),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(content: &[u8]) -> Result<Option<Self>> {
+        let (p1, rest) = split_parent(content)?;
+        let (p2, rest) = split_parent(rest)?;
+
+        let metadata = match rest.strip_prefix(HEADER) {
+            Some(metadata) => metadata,
+            None if rest.is_empty() => rest,
+            None => return Ok(None),
+        };
+        let metadata: HashMap<&str, &str> = std::str::from_utf8(metadata)
+            .map_err(|_| ErrorKind::CorruptDirstate)?
+            .split('\0')
+            .filter_map(|entry| entry.split_once('='))
+            .collect();
+
+        let tree = if metadata.is_empty() {
+            None
+        } else {
+            let filename = metadata.get("filename");
+            let root_id = metadata.get("rootid").and_then(|id| id.parse().ok());
+            match (filename, root_id) {
+                (Some(filename), Some(root_id)) => Some((filename.to_string(), BlockId(root_id))),
+                _ => return Err(ErrorKind::CorruptDirstate.into()),
+            }
+        };
+
+        Ok(Some(Dirstate { p1, p2, tree }))
+    }
+
+    /// Open the treestate tracking the working copy files, or None if it was never written.
+    /// Changes made to it are not persisted unless the dirstate is rewritten to point to the new
+    /// root.
+    pub fn open_treestate(&self, dot_hg_path: &Path) -> Result<Option<TreeState>> {
+        match &self.tree {
+            Some((filename, root_id)) => {
+                let path = dot_hg_path.join("treestate").join(filename);
+                Ok(Some(TreeState::open(path, Some(*root_id))?))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+fn split_parent(content: &[u8]) -> Result<(HgId, &[u8])> {
+    if content.is_empty() {
+        return Ok((NULL_ID, content));
+    }
+    if content.len() < HgId::len() {
+        return Err(ErrorKind::CorruptDirstate.into());
+    }
+    let (id, rest) = content.split_at(HgId::len());
+    Ok((HgId::from_slice(id)?, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::filestate::FileStateV2;
+    use crate::filestate::StateFlags;
+
+    fn dirstate_bytes(p1: &HgId, p2: &HgId, metadata: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        content.extend_from_slice(p1.as_ref());
+        content.extend_from_slice(p2.as_ref());
+        content.extend_from_slice(HEADER);
+        content.extend_from_slice(metadata.as_bytes());
+        content
+    }
+
+    #[test]
+    fn test_parse() {
+        let p1 = HgId::from_byte_array([1; HgId::len()]);
+        let content = dirstate_bytes(&p1, &NULL_ID, "filename=abc\0rootid=42\0threshold=0");
+        assert_eq!(
+            Dirstate::parse(&content).unwrap(),
+            Some(Dirstate {
+                p1,
+                p2: NULL_ID,
+                tree: Some(("abc".to_string(), BlockId(42))),
+            })
+        );
+
+        let empty = Dirstate::parse(&[]).unwrap().unwrap();
+        assert_eq!(empty.p1, NULL_ID);
+        assert_eq!(empty.tree, None);
+
+        assert!(Dirstate::parse(&content[..30]).is_err());
+        assert!(Dirstate::parse(&dirstate_bytes(&p1, &NULL_ID, "filename=abc")).is_err());
+
+        // A flat dirstate lists the files after the parents instead of the treestate header.
+        let mut flat = content[..40].to_vec();
+        flat.extend_from_slice(b"n\0\0\x01\xa4\0\0\0\x01");
+        assert_eq!(Dirstate::parse(&flat).unwrap(), None);
+    }
+
+    #[test]
+    fn test_open_treestate() {
+        let dir = TempDir::new("dirstate").expect("tempdir");
+        let dot_hg = dir.path();
+        std::fs::create_dir(dot_hg.join("treestate")).expect("mkdir");
+
+        let mut tree = TreeState::open(dot_hg.join("treestate").join("abc"), None).expect("open");
+        let state = FileStateV2 {
+            mode: 0o644,
+            size: 1,
+            mtime: 1,
+            state: StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT,
+            copied: None,
+        };
+        tree.insert("a", &state).expect("insert");
+        let root_id = tree.flush().expect("flush");
+
+        let metadata = format!("filename=abc\0rootid={}", root_id.0);
+        let content = dirstate_bytes(&NULL_ID, &NULL_ID, &metadata);
+        std::fs::write(dot_hg.join("dirstate"), content).expect("write");
+
+        let dirstate = Dirstate::read(dot_hg).expect("read").expect("treestate");
+        let mut tree = dirstate
+            .open_treestate(dot_hg)
+            .expect("open")
+            .expect("written");
+        assert_eq!(tree.get("a").expect("get"), Some(&state));
 
-pub struct Dirstate {}
+        // Opening the treestate of a dirstate that doesn't point to one must not create it.
+        let empty = Dirstate::parse(&[]).expect("parse").expect("treestate");
+        assert!(empty.open_treestate(dot_hg).expect("open").is_none());
+        let files = std::fs::read_dir(dot_hg.join("treestate")).expect("read_dir");
+        assert_eq!(files.count(), 1);
+    }
+}
@@ -23,6 +23,8 @@ pub enum ErrorKind {
     ReadOnlyStore,
     #[error("treedirstate is corrupt")]
     CorruptTree,
+    #[error("dirstate is corrupt")]
+    CorruptDirstate,
     #[error("callback error: {0}")]
     CallbackError(String),
 }
@@ -0,0 +1,95 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::Result;
+use treestate::treestate::TreeState;
+use types::RepoPathBuf;
+
+/// Key of the treestate metadata entry that stores the cursor of the last query.
+const CURSOR_KEY: &str = "changesource";
+
+/// Files that may have changed in the working copy since a previous query.
+pub struct ChangedFiles {
+    /// Files that may have been modified, created or deleted. Directories are not listed.
+    pub paths: Vec<RepoPathBuf>,
+    /// The source does not know what changed since the cursor it was given (for example the
+    /// cursor was too old, or there was none). `paths` may be incomplete, and the whole working
+    /// copy needs to be checked.
+    pub is_fresh: bool,
+    /// Cursor to pass to the next query.
+    pub cursor: String,
+}
+
+/// Tells which files changed in the working copy, so that `pending_changes` doesn't need to walk
+/// all of it.
+pub trait ChangeSource: Send + Sync {
+    /// Files changed since the query that returned `cursor`. A cursor the source doesn't
+    /// understand, such as one from another source, is treated like no cursor.
+    fn changed_since(&self, cursor: Option<&str>) -> Result<ChangedFiles>;
+}
+
+/// The cursor stored by `write_cursor`, if any.
+pub fn read_cursor(treestate: &TreeState) -> Option<String> {
+    parse_metadata(treestate.get_metadata())
+        .into_iter()
+        .find(|(key, _)| key == CURSOR_KEY)
+        .map(|(_, value)| value)
+}
+
+/// Store `cursor` in the treestate metadata. It is persisted the next time the treestate is
+/// flushed. Other metadata entries are preserved.
+pub fn write_cursor(treestate: &mut TreeState, cursor: &str) {
+    let mut metadata = parse_metadata(treestate.get_metadata());
+    metadata.retain(|(key, _)| key != CURSOR_KEY);
+    metadata.push((CURSOR_KEY.to_string(), cursor.to_string()));
+    treestate.set_metadata(format_metadata(&metadata));
+}
+
+/// Treestate metadata is a list of "key=value" entries separated by NUL bytes.
+fn parse_metadata(data: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(data)
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn format_metadata(metadata: &[(String, String)]) -> Vec<u8> {
+    metadata
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("\0")
+        .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_cursor() -> Result<()> {
+        let dir = tempdir()?;
+        let mut treestate = TreeState::open(dir.path().join("1"), None)?;
+        assert_eq!(read_cursor(&treestate), None);
+
+        treestate.set_metadata(b"clock=c:1:2\0other=x");
+        write_cursor(&mut treestate, "mtime:10");
+        assert_eq!(read_cursor(&treestate).as_deref(), Some("mtime:10"));
+
+        write_cursor(&mut treestate, "mtime:20");
+        assert_eq!(read_cursor(&treestate).as_deref(), Some("mtime:20"));
+        assert_eq!(
+            treestate.get_metadata(),
+            b"clock=c:1:2\0other=x\0changesource=mtime:20"
+        );
+        Ok(())
+    }
+}
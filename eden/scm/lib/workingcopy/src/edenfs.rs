@@ -0,0 +1,297 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use edenfs_client::status::get_files;
+use edenfs_client::status::get_status;
+use edenfs_client::status::group_entries;
+use edenfs_client::status::read_hg_dirstate;
+use edenfs_client::status::DirstateData;
+use edenfs_client::status::EdenFsStatus;
+use status::Status;
+use status::StatusBuilder;
+use types::RepoPathBuf;
+
+/// A working copy served by EdenFS. EdenFS tracks which files changed itself, so there is no need
+/// to walk the working copy.
+pub struct EdenFileSystem {
+    root: PathBuf,
+}
+
+/// Result of `EdenFileSystem::status`.
+pub struct EdenStatus {
+    pub status: Status,
+    /// Paths whose status could not be computed, and why.
+    pub errors: Vec<(String, String)>,
+    /// Version of the running EdenFS server.
+    pub version: String,
+}
+
+impl EdenFileSystem {
+    pub fn new(root: PathBuf) -> Self {
+        EdenFileSystem { root }
+    }
+
+    /// Whether `root` is an EdenFS checkout.
+    pub fn is_eden(root: &Path) -> bool {
+        root.join(".eden").join("root").exists()
+    }
+
+    /// Status of the working copy relative to its first parent. Ignored files are only listed if
+    /// `list_ignored` is set, and clean files if `list_clean` is.
+    pub fn status(&self, list_ignored: bool, list_clean: bool) -> Result<EdenStatus> {
+        let dirstate = read_hg_dirstate(&self.root)?;
+        let eden_status = get_status(&self.root, dirstate.p1, list_ignored)?;
+        // EdenFS only reports changed files. The clean ones are the other files in p1.
+        let p1_files = if list_clean {
+            get_files(&self.root, dirstate.p1)?
+        } else {
+            Vec::new()
+        };
+        build_status(&self.root, eden_status, dirstate, p1_files)
+    }
+}
+
+/// Combine what EdenFS reports with the dirstate, which knows about added, removed and merged
+/// files. Files of `p1_files` that are in neither are clean.
+fn build_status(
+    root: &Path,
+    eden_status: EdenFsStatus,
+    dirstate: DirstateData,
+    p1_files: Vec<Vec<u8>>,
+) -> Result<EdenStatus> {
+    let groups = group_entries(root, &eden_status, &dirstate);
+    let modified = to_repo_paths(groups.modified)?;
+    let added = to_repo_paths(groups.added)?;
+    let removed = to_repo_paths(groups.removed)?;
+    let deleted = to_repo_paths(groups.deleted)?;
+    let unknown = to_repo_paths(groups.unknown)?;
+    let ignored = to_repo_paths(groups.ignored)?;
+
+    let mut errors: Vec<(String, String)> = groups
+        .invalid
+        .iter()
+        .map(|path| {
+            let path = String::from_utf8_lossy(path).into_owned();
+            (path, "invalid utf-8 filename".to_string())
+        })
+        .collect();
+
+    let mut copymap = HashMap::new();
+    for (dest, source) in dirstate.copymap {
+        copymap.insert(to_repo_path(dest)?, to_repo_path(source)?);
+    }
+
+    for (path, error) in eden_status.errors {
+        errors.push((String::from_utf8_lossy(&path).into_owned(), error));
+    }
+
+    let mut clean = vec![];
+    if !p1_files.is_empty() {
+        let changed: HashSet<&RepoPathBuf> = modified
+            .iter()
+            .chain(&added)
+            .chain(&removed)
+            .chain(&deleted)
+            .chain(&unknown)
+            .chain(&ignored)
+            .collect();
+        for path in p1_files {
+            let path = match String::from_utf8(path) {
+                Ok(path) => RepoPathBuf::from_string(path)?,
+                Err(e) => {
+                    let path = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                    errors.push((path, "invalid utf-8 filename".to_string()));
+                    continue;
+                }
+            };
+            if !changed.contains(&path) {
+                clean.push(path);
+            }
+        }
+    }
+
+    let status = StatusBuilder::new()
+        .modified(modified)
+        .added(added)
+        .removed(removed)
+        .deleted(deleted)
+        .unknown(unknown)
+        .ignored(ignored)
+        .clean(clean)
+        .copymap(copymap)
+        .build();
+
+    Ok(EdenStatus {
+        status,
+        errors,
+        version: eden_status.version,
+    })
+}
+
+fn to_repo_paths(paths: Vec<PathBuf>) -> Result<Vec<RepoPathBuf>> {
+    paths.into_iter().map(to_repo_path).collect()
+}
+
+fn to_repo_path(path: PathBuf) -> Result<RepoPathBuf> {
+    let path = path
+        .into_os_string()
+        .into_string()
+        .map_err(|path| anyhow!("invalid utf-8 filename: {}", path.to_string_lossy()))?;
+    Ok(RepoPathBuf::from_string(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use edenfs_client::status::DirstateDataStatus;
+    use edenfs_client::status::DirstateDataTuple;
+    use edenfs_client::status::DirstateMergeState;
+    use edenfs_client::status::EdenFsFileStatus;
+    use status::FileStatus;
+    use tempfile::tempdir;
+    use types::RepoPath;
+
+    use super::*;
+
+    fn tuple(status: DirstateDataStatus, merge_state: DirstateMergeState) -> DirstateDataTuple {
+        DirstateDataTuple {
+            status,
+            merge_state,
+        }
+    }
+
+    fn status_of(status: &EdenStatus, path: &str) -> Option<FileStatus> {
+        status.status.status(RepoPath::from_str(path).unwrap())
+    }
+
+    #[test]
+    fn test_build_status() {
+        let mut eden_status = EdenFsStatus::default();
+        for (path, file_status) in [
+            ("modified", EdenFsFileStatus::Modified),
+            ("added", EdenFsFileStatus::Added),
+            ("unknown", EdenFsFileStatus::Added),
+            ("removed", EdenFsFileStatus::Removed),
+            ("deleted", EdenFsFileStatus::Removed),
+            ("ignored", EdenFsFileStatus::Ignored),
+            ("added_ignored", EdenFsFileStatus::Ignored),
+            ("other_parent", EdenFsFileStatus::Added),
+        ] {
+            eden_status
+                .entries
+                .insert(path.as_bytes().to_vec(), file_status);
+        }
+        eden_status
+            .errors
+            .insert(b"broken".to_vec(), "oops".to_string());
+
+        let mut dirstate = DirstateData::default();
+        for (path, status, merge_state) in [
+            (
+                "added",
+                DirstateDataStatus::Add,
+                DirstateMergeState::NotApplicable,
+            ),
+            (
+                "removed",
+                DirstateDataStatus::Remove,
+                DirstateMergeState::NotApplicable,
+            ),
+            (
+                "added_ignored",
+                DirstateDataStatus::Add,
+                DirstateMergeState::NotApplicable,
+            ),
+            (
+                "other_parent",
+                DirstateDataStatus::Normal,
+                DirstateMergeState::OtherParent,
+            ),
+            (
+                "merged",
+                DirstateDataStatus::Merge,
+                DirstateMergeState::BothParents,
+            ),
+            (
+                "added_missing",
+                DirstateDataStatus::Add,
+                DirstateMergeState::NotApplicable,
+            ),
+            (
+                "added_clean",
+                DirstateDataStatus::Add,
+                DirstateMergeState::NotApplicable,
+            ),
+            (
+                "removed_clean",
+                DirstateDataStatus::Remove,
+                DirstateMergeState::NotApplicable,
+            ),
+        ] {
+            dirstate
+                .tuples
+                .insert(PathBuf::from(path), tuple(status, merge_state));
+        }
+        dirstate
+            .copymap
+            .insert(PathBuf::from("added"), PathBuf::from("removed"));
+
+        let p1_files = [
+            "modified",
+            "removed",
+            "deleted",
+            "merged",
+            "removed_clean",
+            "clean",
+        ]
+        .iter()
+        .map(|path| path.as_bytes().to_vec())
+        .collect();
+
+        // Files added to the dirstate but not reported by EdenFS are looked up on disk.
+        let root = tempdir().unwrap();
+        std::fs::write(root.path().join("added_clean"), b"").unwrap();
+
+        let status = build_status(root.path(), eden_status, dirstate, p1_files).unwrap();
+
+        for (path, expected) in [
+            ("modified", FileStatus::Modified),
+            ("added", FileStatus::Added),
+            ("unknown", FileStatus::Unknown),
+            ("removed", FileStatus::Removed),
+            ("deleted", FileStatus::Deleted),
+            ("ignored", FileStatus::Ignored),
+            ("added_ignored", FileStatus::Added),
+            ("other_parent", FileStatus::Added),
+            ("merged", FileStatus::Modified),
+            ("added_missing", FileStatus::Deleted),
+            ("added_clean", FileStatus::Added),
+            ("removed_clean", FileStatus::Removed),
+            ("clean", FileStatus::Clean),
+        ] {
+            assert_eq!(status_of(&status, path), Some(expected), "{}", path);
+        }
+
+        assert_eq!(
+            status
+                .status
+                .copy_source(RepoPath::from_str("added").unwrap())
+                .map(|p| p.as_str()),
+            Some("removed")
+        );
+        assert_eq!(
+            status.errors,
+            vec![("broken".to_string(), "oops".to_string())]
+        );
+    }
+}
@@ -5,16 +5,23 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs::Metadata;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 use std::time::SystemTime;
 
 use anyhow::Error;
 use anyhow::Result;
 use parking_lot::Mutex;
+use pathmatcher::AlwaysMatcher;
+use pathmatcher::DifferenceMatcher;
 use pathmatcher::Matcher;
+use status::Status;
+use status::StatusBuilder;
+use treestate::filestate::FileStateV2;
 use treestate::filestate::StateFlags;
 use treestate::tree::VisitorResult;
 use treestate::treestate::TreeState;
@@ -24,6 +31,9 @@ use vfs::is_executable;
 use vfs::is_symlink;
 use vfs::VFS;
 
+use crate::changesource::read_cursor;
+use crate::changesource::write_cursor;
+use crate::changesource::ChangeSource;
 use crate::walker::WalkEntry;
 use crate::walker::WalkError;
 use crate::walker::Walker;
@@ -61,18 +71,37 @@ impl TryFrom<i32> for HgModifiedTime {
     }
 }
 
+/// Result of `PhysicalFileSystem::status`.
+pub struct PhysicalStatus {
+    pub status: Status,
+    /// Files that look unchanged, but whose mtime is too recent, or was not recorded, to be sure.
+    /// Telling needs comparing their contents with the parent. They are in none of the status
+    /// groups.
+    pub lookups: Vec<RepoPathBuf>,
+}
+
 pub struct PhysicalFileSystem {
     // TODO: Make this an Arc<Mutex<VFS>> so we can persist the vfs pathauditor cache
     vfs: VFS,
+    change_source: Option<Arc<dyn ChangeSource>>,
 }
 
 impl PhysicalFileSystem {
     pub fn new(root: PathBuf) -> Result<Self> {
         Ok(PhysicalFileSystem {
             vfs: VFS::new(root)?,
+            change_source: None,
         })
     }
 
+    /// Ask `change_source` which files changed, instead of walking the whole working copy. Its
+    /// cursor is kept in the treestate metadata, so the treestate must be flushed after the
+    /// pending changes are consumed for the next query to be incremental.
+    pub fn with_change_source(mut self, change_source: Arc<dyn ChangeSource>) -> Self {
+        self.change_source = Some(change_source);
+        self
+    }
+
     pub fn pending_changes<M: Matcher + Clone>(
         &self,
         treestate: Arc<Mutex<TreeState>>,
@@ -81,20 +110,190 @@ impl PhysicalFileSystem {
         last_write: HgModifiedTime,
     ) -> Result<PendingChanges<M>> {
         let walker = Walker::new(self.vfs.root().to_path_buf(), matcher.clone(), false)?;
+
+        let mut stage = PendingChangesStage::Walk;
+        let mut candidates = vec![];
+        let mut next_cursor = None;
+        if let Some(change_source) = &self.change_source {
+            let mut treestate = treestate.lock();
+            let cursor = read_cursor(&treestate);
+            let changed = change_source.changed_since(cursor.as_deref())?;
+            if !changed.is_fresh {
+                // Files that were reported as changed before are still changed, even if they
+                // weren't touched since.
+                let mut paths: HashSet<RepoPathBuf> = changed.paths.into_iter().collect();
+                paths.extend(get_nonnormal(&mut treestate)?);
+                candidates = paths.into_iter().collect();
+                candidates.sort_unstable_by(|a, b| b.cmp(a));
+                stage = PendingChangesStage::Candidates;
+            }
+            next_cursor = Some(changed.cursor);
+        }
+
         let pending_changes = PendingChanges {
             vfs: self.vfs.clone(),
             walker,
             matcher,
             treestate,
-            stage: PendingChangesStage::Walk,
+            stage,
             include_directories,
             seen: HashSet::new(),
             lookups: vec![],
             tree_iter: None,
             last_write,
+            candidates,
+            next_cursor,
         };
         Ok(pending_changes)
     }
+
+    /// Status of the working copy relative to its parents, as tracked by `treestate`. Untracked
+    /// files matched by `ignore` are only listed if `list_ignored` is set, and clean files only
+    /// if `list_clean` is. This is `dirstate.status` from Python.
+    pub fn status<I: Matcher + Clone + 'static>(
+        &self,
+        treestate: Arc<Mutex<TreeState>>,
+        ignore: I,
+        list_ignored: bool,
+        list_clean: bool,
+        last_write: HgModifiedTime,
+    ) -> Result<PhysicalStatus> {
+        let matcher: Rc<dyn Matcher> = if list_ignored {
+            Rc::new(AlwaysMatcher::new())
+        } else {
+            Rc::new(DifferenceMatcher::new(AlwaysMatcher::new(), ignore.clone()))
+        };
+
+        let mut modified = vec![];
+        let mut added = vec![];
+        let mut removed = vec![];
+        let mut deleted = vec![];
+        let mut unknown = vec![];
+        let mut ignored = vec![];
+
+        // Files that differ from p1 on disk.
+        let mut pending = self.pending_changes(treestate.clone(), matcher, false, last_write)?;
+        for change in pending.by_ref() {
+            let (path, exists) = match change? {
+                PendingChangeResult::File(ChangeType::Changed(path)) => (path, true),
+                PendingChangeResult::File(ChangeType::Deleted(path)) => (path, false),
+                PendingChangeResult::SeenDirectory(_) => continue,
+            };
+            let state = treestate.lock().get(&path)?.map(|state| state.state);
+            match HgState::from(state.unwrap_or_else(StateFlags::empty)) {
+                HgState::Untracked => {
+                    if !ignore.matches_file(&path)? {
+                        unknown.push(path);
+                    } else if list_ignored {
+                        ignored.push(path);
+                    }
+                }
+                HgState::Normal | HgState::Merged | HgState::Added if !exists => deleted.push(path),
+                HgState::Normal => modified.push(path),
+                // Handled below, whether they changed on disk or not.
+                HgState::Merged | HgState::Added | HgState::Removed => {}
+            }
+        }
+        let lookups = pending.lookups().to_vec();
+
+        let mut seen: HashSet<RepoPathBuf> = modified.iter().chain(&deleted).cloned().collect();
+        let mut copymap = HashMap::new();
+
+        // Files whose status depends on the treestate rather than on their contents: files from
+        // p2, added, removed or merged files, and copies.
+        let mask = StateFlags::EXIST_P2 | StateFlags::COPIED;
+        let files = get_files(&mut treestate.lock(), |state| {
+            state.intersects(mask)
+                || state.contains(StateFlags::EXIST_P1) != state.contains(StateFlags::EXIST_NEXT)
+        })?;
+        for (path, state) in files {
+            if let Some(copied) = &state.copied {
+                copymap.insert(path.clone(), RepoPathBuf::from_utf8(copied.to_vec())?);
+            }
+            if seen.contains(&path) {
+                continue;
+            }
+            let exists = || self.vfs.metadata(&path).is_ok();
+            let group = match HgState::from(state.state) {
+                // From p2 only: considered modified, whatever its contents.
+                HgState::Normal if !state.state.contains(StateFlags::EXIST_P1) => {
+                    if exists() {
+                        &mut modified
+                    } else {
+                        &mut deleted
+                    }
+                }
+                HgState::Merged => &mut modified,
+                HgState::Added => {
+                    if exists() {
+                        &mut added
+                    } else {
+                        &mut deleted
+                    }
+                }
+                HgState::Removed => &mut removed,
+                // Clean files retroactively marked as copied.
+                HgState::Normal if state.state.contains(StateFlags::COPIED) => &mut modified,
+                HgState::Normal | HgState::Untracked => continue,
+            };
+            seen.insert(path.clone());
+            group.push(path);
+        }
+
+        let mut clean = vec![];
+        if list_clean {
+            seen.extend(lookups.iter().cloned());
+            let files = get_files(&mut treestate.lock(), |state| {
+                state.contains(StateFlags::EXIST_P1)
+            })?;
+            clean = files
+                .into_iter()
+                .map(|(path, _)| path)
+                .filter(|path| !seen.contains(path))
+                .collect();
+        }
+
+        let status = StatusBuilder::new()
+            .modified(modified)
+            .added(added)
+            .removed(removed)
+            .deleted(deleted)
+            .unknown(unknown)
+            .ignored(ignored)
+            .clean(clean)
+            .copymap(copymap)
+            .build();
+        Ok(PhysicalStatus { status, lookups })
+    }
+}
+
+/// The state of a file in the Python dirstate, derived from its treestate flags.
+enum HgState {
+    /// In exactly one parent, and in the working copy ('n').
+    Normal,
+    /// In both parents, and in the working copy ('m').
+    Merged,
+    /// In the working copy only ('a').
+    Added,
+    /// In a parent, but not in the working copy ('r').
+    Removed,
+    /// Not tracked ('?').
+    Untracked,
+}
+
+impl From<StateFlags> for HgState {
+    fn from(flags: StateFlags) -> Self {
+        let p1 = flags.contains(StateFlags::EXIST_P1);
+        let p2 = flags.contains(StateFlags::EXIST_P2);
+        let next = flags.contains(StateFlags::EXIST_NEXT);
+        match (p1 || p2, next) {
+            (true, true) if p1 && p2 => HgState::Merged,
+            (true, true) => HgState::Normal,
+            (true, false) => HgState::Removed,
+            (false, true) => HgState::Added,
+            (false, false) => HgState::Untracked,
+        }
+    }
 }
 
 pub struct PendingChanges<M: Matcher + Clone> {
@@ -108,12 +307,17 @@ pub struct PendingChanges<M: Matcher + Clone> {
     lookups: Vec<RepoPathBuf>,
     tree_iter: Option<Box<dyn Iterator<Item = Result<PendingChangeResult>> + Send>>,
     last_write: HgModifiedTime,
+    // Files a change source reported, in reverse order.
+    candidates: Vec<RepoPathBuf>,
+    // Set when a change source is used. Stored in the treestate once all changes are returned.
+    next_cursor: Option<String>,
 }
 
 #[derive(PartialEq)]
 enum PendingChangesStage {
     Walk,
     IterateTree,
+    Candidates,
     Lookups,
     Finished,
 }
@@ -123,6 +327,7 @@ impl PendingChangesStage {
         match self {
             PendingChangesStage::Walk => PendingChangesStage::IterateTree,
             PendingChangesStage::IterateTree => PendingChangesStage::Lookups,
+            PendingChangesStage::Candidates => PendingChangesStage::Lookups,
             PendingChangesStage::Lookups => PendingChangesStage::Finished,
             PendingChangesStage::Finished => PendingChangesStage::Finished,
         }
@@ -256,15 +461,9 @@ impl<M: Matcher + Clone> PendingChanges<M> {
         let tracked = tracked.unwrap();
 
         for path in tracked.into_iter() {
+            // The walk already compared the files it found.
             if self.seen.contains(&path) {
-                match self.matcher.matches_file(&path) {
-                    Err(e) => {
-                        results.push(Err(e));
-                        continue;
-                    }
-                    Ok(false) => continue,
-                    Ok(true) => {}
-                }
+                continue;
             }
 
             // If it's behind a symlink consider it deleted.
@@ -331,9 +530,141 @@ impl<M: Matcher + Clone> PendingChanges<M> {
         Ok(result)
     }
 
+    fn next_candidate(&mut self) -> Option<Result<PendingChangeResult>> {
+        while let Some(path) = self.candidates.pop() {
+            match self.check_candidate(path) {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+
+    fn check_candidate(&mut self, path: RepoPathBuf) -> Result<Option<PendingChangeResult>> {
+        let flags = self.treestate.lock().get(&path)?.map(|state| state.state);
+        let tracked = flags.is_some_and(|flags| {
+            flags.intersects(StateFlags::EXIST_P1 | StateFlags::EXIST_P2 | StateFlags::EXIST_NEXT)
+        });
+        if !tracked && !self.matcher.matches_file(&path)? {
+            return Ok(None);
+        }
+
+        let metadata = match self.vfs.metadata(&path) {
+            Ok(metadata) if metadata.is_file() || metadata.file_type().is_symlink() => metadata,
+            // If it's missing or not a file anymore, consider it deleted.
+            _ => {
+                let in_parent = flags.is_some_and(|flags| flags.intersects(StateFlags::EXIST_P1));
+                if in_parent {
+                    return Ok(Some(PendingChangeResult::File(ChangeType::Deleted(path))));
+                }
+                if flags.is_some() && !tracked {
+                    // An untracked file that was reported as changed before is gone.
+                    self.treestate.lock().remove(&path)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        if self.is_changed(&path, &metadata)? {
+            Ok(Some(PendingChangeResult::File(ChangeType::Changed(path))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Files that were not returned as changed, but whose contents must be compared with the
+    /// parent to be sure. Complete once all the changes were iterated over.
+    pub fn lookups(&self) -> &[RepoPathBuf] {
+        &self.lookups
+    }
+
     fn next_lookup(&mut self) -> Option<Result<PendingChangeResult>> {
         None
     }
+
+    /// Mark a changed file NEED_CHECK, so it is checked again by the next query to the change
+    /// source even if it isn't touched until then.
+    fn mark_need_check(&mut self, path: &RepoPath) -> Result<()> {
+        let mut treestate = self.treestate.lock();
+        let state = match treestate.get(path)? {
+            Some(state) if state.state.intersects(StateFlags::NEED_CHECK) => return Ok(()),
+            Some(state) => {
+                let mut state = state.clone();
+                state.state |= StateFlags::NEED_CHECK;
+                state
+            }
+            // Untracked files are added to the treestate, like fsmonitor does.
+            None => FileStateV2 {
+                mode: 0o666,
+                size: -1,
+                mtime: -1,
+                state: StateFlags::NEED_CHECK,
+                copied: None,
+            },
+        };
+        treestate.insert(path, &state)
+    }
+
+    fn finish(&mut self) {
+        if let Some(cursor) = self.next_cursor.take() {
+            write_cursor(&mut self.treestate.lock(), &cursor);
+        }
+    }
+}
+
+/// Files of the treestate whose flags match `filter`, with their state.
+fn get_files(
+    treestate: &mut TreeState,
+    filter: impl Fn(StateFlags) -> bool,
+) -> Result<Vec<(RepoPathBuf, FileStateV2)>> {
+    let mut result = Vec::new();
+    treestate.visit(
+        &mut |components, state| {
+            let path = components.concat();
+            let path = RepoPathBuf::from_utf8(path)?;
+            result.push((path, state.clone()));
+            Ok(VisitorResult::NotChanged)
+        },
+        &|_path, _dir| true,
+        &|_path, file| filter(file.state),
+    )?;
+    Ok(result)
+}
+
+/// Files whose state differs from "clean in p1": files marked NEED_CHECK, and files that are
+/// added, removed, or come from p2.
+fn get_nonnormal(treestate: &mut TreeState) -> Result<Vec<RepoPathBuf>> {
+    // (set, unset) pairs: a file is nonnormal if it has all the `set` flags and none of the
+    // `unset` flags for one of the pairs.
+    let filters = [
+        (StateFlags::NEED_CHECK, StateFlags::empty()),
+        (StateFlags::EXIST_P2, StateFlags::empty()),
+        (StateFlags::EXIST_NEXT, StateFlags::EXIST_P1),
+        (StateFlags::EXIST_P1, StateFlags::EXIST_NEXT),
+    ];
+
+    let mut result = Vec::new();
+    treestate.visit(
+        &mut |components, _| {
+            let path = components.concat();
+            let path = RepoPathBuf::from_utf8(path)?;
+            result.push(path);
+            Ok(VisitorResult::NotChanged)
+        },
+        &|_path, dir| match dir.get_aggregated_state() {
+            None => true,
+            Some(state) => filters.iter().any(|(set, unset)| {
+                state.union.contains(*set) && !state.intersection.intersects(*unset)
+            }),
+        },
+        &|_path, file| {
+            filters
+                .iter()
+                .any(|(set, unset)| file.state.contains(*set) && !file.state.intersects(*unset))
+        },
+    )?;
+    Ok(result)
 }
 
 impl<M: Matcher + Clone> Iterator for PendingChanges<M> {
@@ -345,16 +676,29 @@ impl<M: Matcher + Clone> Iterator for PendingChanges<M> {
             let change = match self.stage {
                 PendingChangesStage::Walk => self.next_walk(),
                 PendingChangesStage::IterateTree => self.next_tree(),
+                PendingChangesStage::Candidates => self.next_candidate(),
                 PendingChangesStage::Lookups => self.next_lookup(),
                 PendingChangesStage::Finished => None,
             };
 
+            if let Some(Ok(PendingChangeResult::File(
+                ChangeType::Changed(path) | ChangeType::Deleted(path),
+            ))) = &change
+            {
+                if self.next_cursor.is_some() {
+                    if let Err(e) = self.mark_need_check(path) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+
             if change.is_some() {
                 return change;
             }
 
             self.stage = self.stage.next();
             if self.stage == PendingChangesStage::Finished {
+                self.finish();
                 return None;
             }
         }
@@ -365,3 +709,168 @@ fn normalize(path: RepoPathBuf) -> RepoPathBuf {
     // TODO: Support path normalization on case insensitive file systems
     path
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pathmatcher::AlwaysMatcher;
+    use pathmatcher::GitignoreMatcher;
+    use status::FileStatus;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::changesource::ChangedFiles;
+
+    struct FakeChangeSource(Vec<&'static str>);
+
+    impl ChangeSource for FakeChangeSource {
+        fn changed_since(&self, cursor: Option<&str>) -> Result<ChangedFiles> {
+            Ok(ChangedFiles {
+                paths: self
+                    .0
+                    .iter()
+                    .map(|p| RepoPathBuf::from_string(p.to_string()).unwrap())
+                    .collect(),
+                is_fresh: cursor.is_none(),
+                cursor: "next".to_string(),
+            })
+        }
+    }
+
+    fn changes(fs: &PhysicalFileSystem, treestate: &Arc<Mutex<TreeState>>) -> Result<Vec<String>> {
+        let pending = fs.pending_changes(
+            treestate.clone(),
+            Arc::new(AlwaysMatcher::new()),
+            false,
+            0u32.into(),
+        )?;
+        let mut result = Vec::new();
+        for change in pending {
+            match change? {
+                PendingChangeResult::File(ChangeType::Changed(path)) => {
+                    result.push(format!("changed {}", path))
+                }
+                PendingChangeResult::File(ChangeType::Deleted(path)) => {
+                    result.push(format!("deleted {}", path))
+                }
+                PendingChangeResult::SeenDirectory(_) => {}
+            }
+        }
+        result.sort();
+        Ok(result)
+    }
+
+    #[test]
+    fn test_change_source() -> Result<()> {
+        let root = tempdir()?;
+        fs::create_dir(root.path().join(".hg"))?;
+        fs::write(root.path().join("a"), "a")?;
+        fs::write(root.path().join("b"), "b")?;
+        let treestate = Arc::new(Mutex::new(TreeState::open(
+            root.path().join(".hg/treestate"),
+            None,
+        )?));
+
+        // No cursor yet: the working copy is walked.
+        let fs = PhysicalFileSystem::new(root.path().to_path_buf())?
+            .with_change_source(Arc::new(FakeChangeSource(vec!["b"])));
+        assert_eq!(changes(&fs, &treestate)?, vec!["changed a", "changed b"]);
+        assert_eq!(read_cursor(&treestate.lock()).as_deref(), Some("next"));
+
+        // Only reported files, and files reported before, are checked.
+        fs::write(root.path().join("c"), "c")?;
+        fs::remove_file(root.path().join("a"))?;
+        assert_eq!(changes(&fs, &treestate)?, vec!["changed b"]);
+        assert!(treestate.lock().get("a")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() -> Result<()> {
+        let root = tempdir()?;
+        fs::create_dir(root.path().join(".hg"))?;
+        let mut treestate = TreeState::open(root.path().join(".hg/treestate"), None)?;
+
+        let p1 = StateFlags::EXIST_P1 | StateFlags::EXIST_NEXT;
+        let mut add = |path: &str, state: StateFlags, content: Option<&str>| -> Result<()> {
+            let (size, mtime) = match content {
+                Some(content) => {
+                    let file = root.path().join(path);
+                    fs::write(&file, content)?;
+                    let mtime: HgModifiedTime = fs::metadata(&file)?.modified()?.try_into()?;
+                    (content.len() as i32, mtime.0 as i32)
+                }
+                None => (0, 0),
+            };
+            let copied = state
+                .contains(StateFlags::COPIED)
+                .then(|| b"clean".to_vec().into_boxed_slice());
+            let state = FileStateV2 {
+                mode: 0o644,
+                size,
+                mtime,
+                state,
+                copied,
+            };
+            treestate.insert(path, &state)
+        };
+        add("clean", p1, Some("clean"))?;
+        add("modified", p1, Some("modified"))?;
+        add("deleted", p1, None)?;
+        add("added", StateFlags::EXIST_NEXT, Some("added"))?;
+        add("added_missing", StateFlags::EXIST_NEXT, None)?;
+        add("removed", StateFlags::EXIST_P1, None)?;
+        add("merged", p1 | StateFlags::EXIST_P2, Some("merged"))?;
+        add(
+            "other_parent",
+            StateFlags::EXIST_P2 | StateFlags::EXIST_NEXT,
+            Some("p2"),
+        )?;
+        add(
+            "copied",
+            StateFlags::EXIST_NEXT | StateFlags::COPIED,
+            Some("copy"),
+        )?;
+        add("lookup", p1 | StateFlags::NEED_CHECK, Some("lookup"))?;
+        fs::write(root.path().join("modified"), "modified!")?;
+        fs::write(root.path().join("unknown"), "unknown")?;
+        fs::write(root.path().join("ignored"), "ignored")?;
+        fs::write(root.path().join(".gitignore"), "ignored\n")?;
+
+        let treestate = Arc::new(Mutex::new(treestate));
+        let fs = PhysicalFileSystem::new(root.path().to_path_buf())?;
+        let ignore = Rc::new(GitignoreMatcher::new(root.path(), Vec::new()));
+        let status = fs.status(treestate, ignore, true, true, 0u32.into())?;
+
+        for (path, expected) in [
+            ("clean", Some(FileStatus::Clean)),
+            ("modified", Some(FileStatus::Modified)),
+            ("deleted", Some(FileStatus::Deleted)),
+            ("added", Some(FileStatus::Added)),
+            ("added_missing", Some(FileStatus::Deleted)),
+            ("removed", Some(FileStatus::Removed)),
+            ("merged", Some(FileStatus::Modified)),
+            ("other_parent", Some(FileStatus::Modified)),
+            ("copied", Some(FileStatus::Added)),
+            ("unknown", Some(FileStatus::Unknown)),
+            ("ignored", Some(FileStatus::Ignored)),
+            ("lookup", None),
+        ] {
+            let path = RepoPath::from_str(path)?;
+            assert_eq!(status.status.status(path), expected, "{}", path);
+        }
+        assert_eq!(
+            status
+                .status
+                .copy_source(RepoPath::from_str("copied")?)
+                .map(|p| p.as_str()),
+            Some("clean")
+        );
+        assert_eq!(
+            status.lookups,
+            vec![RepoPathBuf::from_string("lookup".to_string())?]
+        );
+        Ok(())
+    }
+}
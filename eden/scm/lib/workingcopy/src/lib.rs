@@ -5,5 +5,9 @@
  * GNU General Public License version 2.
  */
 
+pub mod changesource;
+pub mod edenfs;
 pub mod filesystem;
+pub mod mtimejournal;
 pub mod walker;
+pub mod watchman;
@@ -0,0 +1,410 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A change source that doesn't need file system notifications.
+//!
+//! The journal records every directory of the working copy, with its mtime and the files directly
+//! in it. A directory's mtime changes when entries are added to it or removed from it, so only
+//! directories whose mtime changed are read again. Files written in place don't change the mtime
+//! of their directory, so every known file is still stat'ed. A query costs one stat per directory
+//! and per file, plus reading the directories that changed.
+//!
+//! The journal is written to a new file at every query, named after the cursor returned. It
+//! becomes current when the cursor is persisted with the treestate, so that the two never
+//! disagree. Journals older than the current one are removed by the next query.
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use types::RepoPathBuf;
+
+use crate::changesource::ChangeSource;
+use crate::changesource::ChangedFiles;
+
+const CURSOR_PREFIX: &str = "mtime:";
+
+pub struct MtimeJournal {
+    root: PathBuf,
+    journal_dir: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Journal {
+    /// Directories of the working copy, by path relative to the root ("" is the root).
+    dirs: BTreeMap<String, JournalDir>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalDir {
+    /// Modification time, in nanoseconds since the unix epoch.
+    mtime: u64,
+    /// Names of the files (and symlinks) directly in the directory.
+    files: Vec<String>,
+}
+
+impl MtimeJournal {
+    /// Keep the journal in the `.hg` directory of the working copy at `root`.
+    pub fn new(root: PathBuf) -> Self {
+        let journal_dir = root.join(".hg").join("mtimejournal");
+        Self::with_journal_dir(root, journal_dir)
+    }
+
+    pub fn with_journal_dir(root: PathBuf, journal_dir: PathBuf) -> Self {
+        MtimeJournal { root, journal_dir }
+    }
+
+    fn load(&self, generation: u64) -> Option<Journal> {
+        let data = fs::read(self.journal_dir.join(generation.to_string())).ok()?;
+        // A journal that can't be read is rebuilt.
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn save(&self, generation: u64, journal: &Journal) -> Result<()> {
+        fs::create_dir_all(&self.journal_dir)?;
+        let path = self.journal_dir.join(generation.to_string());
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(journal)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Remove the journals older than `current`. They can't be used anymore, since the cursor
+    /// persisted in the treestate only moves forward. Newer ones may belong to queries whose
+    /// cursor is not persisted yet.
+    fn remove_old(&self, current: u64) {
+        let entries = match fs::read_dir(&self.journal_dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let generation = name.to_str().and_then(|name| name.parse::<u64>().ok());
+            if generation.is_some_and(|generation| generation < current) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    /// Update `old` to match the working copy, returning the new journal and the files that
+    /// changed since `since` (in seconds since the unix epoch).
+    fn update(&self, old: Journal, since: u64) -> Result<(Journal, Vec<String>)> {
+        let known_dirs: HashSet<String> = old.dirs.keys().cloned().collect();
+        let mut journal = Journal::default();
+        let mut changed = Vec::new();
+
+        for (dir, old_dir) in old.dirs {
+            let path = self.root.join(&dir);
+            let mtime = match dir_metadata(&path, dir.is_empty()) {
+                Some(metadata) => mtime_nanos(&metadata)?,
+                None => {
+                    // The directory is gone: so are its files. Its subdirectories are handled
+                    // when they are visited.
+                    changed.extend(old_dir.files.iter().map(|name| join(&dir, name)));
+                    continue;
+                }
+            };
+
+            // If the directory was modified around the previous query, its mtime may not have
+            // changed when it was modified again, so read it anyway.
+            if mtime == old_dir.mtime && mtime / 1_000_000_000 < since {
+                // No file was added or removed, but files may have been written in place.
+                for name in &old_dir.files {
+                    if file_changed(&path.join(name), since) {
+                        changed.push(join(&dir, name));
+                    }
+                }
+                journal.dirs.insert(dir, old_dir);
+                continue;
+            }
+
+            let (files, subdirs) = read_dir(&path)?;
+            let old_files: HashSet<&String> = old_dir.files.iter().collect();
+            let new_files: HashSet<&String> = files.iter().collect();
+            for name in &old_dir.files {
+                if !new_files.contains(name) {
+                    changed.push(join(&dir, name));
+                }
+            }
+            for name in &files {
+                if !old_files.contains(name) || file_changed(&path.join(name), since) {
+                    changed.push(join(&dir, name));
+                }
+            }
+            for subdir in subdirs {
+                let subdir = join(&dir, &subdir);
+                if !known_dirs.contains(&subdir) {
+                    self.scan(subdir, &mut journal, Some(&mut changed))?;
+                }
+            }
+
+            journal.dirs.insert(dir, JournalDir { mtime, files });
+        }
+
+        Ok((journal, changed))
+    }
+
+    /// Add `dir` and everything under it to `journal`. Files found are added to `changed`.
+    fn scan(
+        &self,
+        dir: String,
+        journal: &mut Journal,
+        mut changed: Option<&mut Vec<String>>,
+    ) -> Result<()> {
+        let mut to_visit = vec![dir];
+        while let Some(dir) = to_visit.pop() {
+            let path = self.root.join(&dir);
+            let metadata = match dir_metadata(&path, dir.is_empty()) {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let mtime = mtime_nanos(&metadata)?;
+            let (files, subdirs) = read_dir(&path)?;
+            if let Some(changed) = changed.as_deref_mut() {
+                changed.extend(files.iter().map(|name| join(&dir, name)));
+            }
+            to_visit.extend(subdirs.iter().map(|subdir| join(&dir, subdir)));
+            journal.dirs.insert(dir, JournalDir { mtime, files });
+        }
+        Ok(())
+    }
+}
+
+impl ChangeSource for MtimeJournal {
+    fn changed_since(&self, cursor: Option<&str>) -> Result<ChangedFiles> {
+        // Files modified from now on are reported by the next query. The time of the query also
+        // names the journal it writes.
+        let start = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos() as u64;
+
+        let generation = cursor
+            .and_then(|cursor| cursor.strip_prefix(CURSOR_PREFIX))
+            .and_then(|generation| generation.parse::<u64>().ok());
+        let old = generation.and_then(|generation| Some((generation, self.load(generation)?)));
+
+        let (journal, changed, is_fresh) = match old {
+            Some((generation, old)) => {
+                self.remove_old(generation);
+                let (journal, changed) = self.update(old, generation / 1_000_000_000)?;
+                (journal, changed, false)
+            }
+            None => {
+                let mut journal = Journal::default();
+                self.scan(String::new(), &mut journal, None)?;
+                (journal, Vec::new(), true)
+            }
+        };
+        self.save(start, &journal)?;
+
+        let mut paths = Vec::with_capacity(changed.len());
+        for path in changed {
+            paths.push(RepoPathBuf::from_string(path)?);
+        }
+        Ok(ChangedFiles {
+            paths,
+            is_fresh,
+            cursor: format!("{}{}", CURSOR_PREFIX, start),
+        })
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Metadata of a directory of the working copy. `None` if it's missing, not a directory, or
+/// the root of a nested repository.
+fn dir_metadata(path: &Path, is_root: bool) -> Option<Metadata> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if !metadata.is_dir() || (!is_root && path.join(".hg").exists()) {
+        return None;
+    }
+    Some(metadata)
+}
+
+fn mtime_nanos(metadata: &Metadata) -> Result<u64> {
+    let mtime = metadata
+        .modified()?
+        .duration_since(SystemTime::UNIX_EPOCH)?;
+    Ok(mtime.as_nanos() as u64)
+}
+
+/// Whether the file at `path` was modified (or had its mode changed) since `since`, in seconds.
+/// Files that can't be stat'ed are considered changed.
+fn file_changed(path: &Path, since: u64) -> bool {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return true,
+    };
+    let mtime = match metadata.modified() {
+        Ok(mtime) => mtime,
+        Err(_) => return true,
+    };
+    let mtime = mtime
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |mtime| mtime.as_secs());
+    #[cfg(unix)]
+    let mtime = {
+        use std::os::unix::fs::MetadataExt;
+        mtime.max(metadata.ctime().max(0) as u64)
+    };
+    mtime >= since
+}
+
+/// The files and subdirectories directly in `path`. `.hg` and names that aren't valid utf-8
+/// are skipped.
+fn read_dir(path: &Path) -> io::Result<(Vec<String>, Vec<String>)> {
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if name != ".hg" {
+                subdirs.push(name);
+            }
+        } else if file_type.is_file() || file_type.is_symlink() {
+            files.push(name);
+        }
+    }
+    files.sort();
+    subdirs.sort();
+    Ok((files, subdirs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::create_dir_all;
+    use std::time::Duration;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_fresh() -> Result<()> {
+        let root = tempdir()?;
+        create_dir_all(root.path().join(".hg"))?;
+        fs::write(root.path().join("a"), "a")?;
+
+        let journal = MtimeJournal::new(root.path().to_path_buf());
+        let changed = journal.changed_since(None)?;
+        assert!(changed.is_fresh);
+        assert!(changed.cursor.starts_with(CURSOR_PREFIX));
+
+        // Cursors from other sources aren't understood.
+        assert!(journal.changed_since(Some("c:1:2"))?.is_fresh);
+        assert!(!journal.changed_since(Some(&changed.cursor))?.is_fresh);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes() -> Result<()> {
+        let root = tempdir()?;
+        let root_path = root.path();
+        create_dir_all(root_path.join(".hg"))?;
+        create_dir_all(root_path.join("dir/gone"))?;
+        create_dir_all(root_path.join("nested/.hg"))?;
+        for file in ["same", "modified", "deleted", "dir/gone/x", "nested/y"] {
+            fs::write(root_path.join(file), "old")?;
+        }
+
+        let journal = MtimeJournal::new(root_path.to_path_buf());
+        let cursor = journal.changed_since(None)?.cursor;
+
+        fs::write(root_path.join("modified"), "new")?;
+        fs::remove_file(root_path.join("deleted"))?;
+        fs::write(root_path.join("added"), "new")?;
+        fs::remove_dir_all(root_path.join("dir/gone"))?;
+        create_dir_all(root_path.join("dir/new/sub"))?;
+        fs::write(root_path.join("dir/new/sub/z"), "new")?;
+        fs::write(root_path.join("nested/w"), "new")?;
+
+        let changed = journal.changed_since(Some(&cursor))?;
+        assert!(!changed.is_fresh);
+        let paths: HashSet<&str> = changed.paths.iter().map(|p| p.as_str()).collect();
+        // Files that weren't touched may be reported too, as they were written in the same
+        // second as the previous query.
+        for path in [
+            "added",
+            "deleted",
+            "dir/gone/x",
+            "dir/new/sub/z",
+            "modified",
+        ] {
+            assert!(paths.contains(path), "{} should be reported", path);
+        }
+        assert!(!paths.contains("nested/w"));
+        assert!(!paths.contains("nested/y"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_written_in_place() -> Result<()> {
+        let root = tempdir()?;
+        create_dir_all(root.path().join(".hg"))?;
+        create_dir_all(root.path().join("dir"))?;
+        fs::write(root.path().join("dir/file"), "old")?;
+
+        // Make the directory look untouched since long before the previous query.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs();
+        let past = SystemTime::UNIX_EPOCH + Duration::from_secs(now - 10);
+        fs::File::open(root.path().join("dir"))?.set_modified(past)?;
+
+        let journal = MtimeJournal::new(root.path().to_path_buf());
+        let mut old = Journal::default();
+        journal.scan(String::new(), &mut old, None)?;
+
+        // Writing the file in place doesn't change the mtime of its directory, but the file is
+        // still reported.
+        fs::write(root.path().join("dir/file"), "new")?;
+        let (new, changed) = journal.update(old, now)?;
+        assert_eq!(changed, vec!["dir/file".to_string()]);
+        assert!(new.dirs.contains_key("dir"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generations() -> Result<()> {
+        let root = tempdir()?;
+        create_dir_all(root.path().join(".hg"))?;
+        let journal = MtimeJournal::new(root.path().to_path_buf());
+        let generations =
+            || -> Result<usize> { Ok(fs::read_dir(root.path().join(".hg/mtimejournal"))?.count()) };
+
+        let first = journal.changed_since(None)?.cursor;
+        let second = journal.changed_since(Some(&first))?.cursor;
+        assert_eq!(generations()?, 2);
+
+        // A query that wasn't persisted doesn't prevent using the previous cursor again.
+        assert!(!journal.changed_since(Some(&first))?.is_fresh);
+
+        // Once a newer cursor is used, older journals are removed.
+        assert!(!journal.changed_since(Some(&second))?.is_fresh);
+        assert_eq!(generations()?, 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Asks Watchman which files changed, using its JSON protocol: each request and response is a
+//! JSON value on a single line.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Result;
+use serde_json::json;
+use serde_json::Value;
+use types::RepoPathBuf;
+
+use crate::changesource::ChangeSource;
+use crate::changesource::ChangedFiles;
+
+/// Watchman clocks look like "c:1638393322:1234:1:567".
+const CLOCK_PREFIX: &str = "c:";
+
+/// A clock that predates every Watchman instance. Queries since it are always fresh.
+const NULL_CLOCK: &str = "c:0:0";
+
+pub struct WatchmanChangeSource {
+    root: PathBuf,
+    sockpath: Option<PathBuf>,
+    timeout: Duration,
+}
+
+impl WatchmanChangeSource {
+    pub fn new(root: PathBuf) -> Self {
+        WatchmanChangeSource {
+            root,
+            sockpath: None,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Use the Watchman instance listening on `sockpath`, instead of asking the `watchman`
+    /// binary where it listens.
+    pub fn with_sockpath(mut self, sockpath: PathBuf) -> Self {
+        self.sockpath = Some(sockpath);
+        self
+    }
+
+    /// How long Watchman may take to sync with the file system before answering a query.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn sockpath(&self) -> Result<PathBuf> {
+        if let Some(sockpath) = &self.sockpath {
+            return Ok(sockpath.clone());
+        }
+        if let Some(sockpath) = std::env::var_os("WATCHMAN_SOCK") {
+            return Ok(PathBuf::from(sockpath));
+        }
+
+        let output = Command::new("watchman")
+            .args(["--output-encoding=json", "--no-pretty", "get-sockname"])
+            .output()?;
+        if !output.status.success() {
+            bail!(
+                "watchman get-sockname failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let response: Value = serde_json::from_slice(&output.stdout)?;
+        let sockname = response["sockname"]
+            .as_str()
+            .ok_or_else(|| anyhow!("watchman get-sockname returned no sockname"))?;
+        Ok(PathBuf::from(sockname))
+    }
+}
+
+impl ChangeSource for WatchmanChangeSource {
+    fn changed_since(&self, cursor: Option<&str>) -> Result<ChangedFiles> {
+        let since = cursor
+            .filter(|cursor| cursor.starts_with(CLOCK_PREFIX))
+            .unwrap_or(NULL_CLOCK);
+
+        let mut conn = Connection::connect(&self.sockpath()?)?;
+
+        let root = self.root.to_str().ok_or_else(|| {
+            anyhow!(
+                "working copy path is not valid utf-8: {}",
+                self.root.display()
+            )
+        })?;
+        let watch = conn.command(json!(["watch-project", root]))?;
+        let watch_root = watch["watch"]
+            .as_str()
+            .ok_or_else(|| anyhow!("watchman watch-project returned no watch root"))?;
+
+        let mut query = json!({
+            "fields": ["name"],
+            "since": since,
+            "expression": ["not", ["anyof", ["dirname", ".hg"], ["name", ".hg", "wholename"]]],
+            "sync_timeout": self.timeout.as_millis() as u64,
+            "empty_on_fresh_instance": true,
+        });
+        if let Some(relative_path) = watch["relative_path"].as_str() {
+            query["relative_root"] = json!(relative_path);
+        }
+        let response = conn.command(json!(["query", watch_root, query]))?;
+        parse_query_response(&response)
+    }
+}
+
+fn parse_query_response(response: &Value) -> Result<ChangedFiles> {
+    let cursor = response["clock"]
+        .as_str()
+        .ok_or_else(|| anyhow!("watchman query returned no clock"))?
+        .to_string();
+    let is_fresh = response["is_fresh_instance"].as_bool().unwrap_or(true);
+
+    let mut paths = Vec::new();
+    if let Some(files) = response["files"].as_array() {
+        for file in files {
+            let name = file
+                .as_str()
+                .ok_or_else(|| anyhow!("unexpected file entry from watchman: {}", file))?;
+            paths.push(RepoPathBuf::from_string(name.to_string())?);
+        }
+    }
+
+    Ok(ChangedFiles {
+        paths,
+        is_fresh,
+        cursor,
+    })
+}
+
+struct Connection {
+    #[cfg(unix)]
+    reader: BufReader<std::os::unix::net::UnixStream>,
+}
+
+impl Connection {
+    #[cfg(unix)]
+    fn connect(sockpath: &Path) -> Result<Self> {
+        let stream = std::os::unix::net::UnixStream::connect(sockpath).map_err(|e| {
+            anyhow!(
+                "cannot connect to watchman at {}: {}",
+                sockpath.display(),
+                e
+            )
+        })?;
+        Ok(Connection {
+            reader: BufReader::new(stream),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn connect(sockpath: &Path) -> Result<Self> {
+        bail!(
+            "connecting to watchman at {} is only supported on unix",
+            sockpath.display()
+        )
+    }
+
+    #[cfg(unix)]
+    fn command(&mut self, request: Value) -> Result<Value> {
+        let mut data = serde_json::to_vec(&request)?;
+        data.push(b'\n');
+        self.reader.get_mut().write_all(&data)?;
+
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                bail!("watchman closed the connection");
+            }
+            let response: Value = serde_json::from_str(&line)?;
+            // Subscription updates can arrive before the response. There are no subscriptions on
+            // this connection, but skip them anyway.
+            if response["unilateral"].as_bool() == Some(true) {
+                continue;
+            }
+            if let Some(error) = response["error"].as_str() {
+                bail!("watchman error: {}", error);
+            }
+            return Ok(response);
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn command(&mut self, _request: Value) -> Result<Value> {
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_response() -> Result<()> {
+        let response = json!({
+            "version": "2021.11.29.00",
+            "clock": "c:1638393322:1234:1:567",
+            "is_fresh_instance": false,
+            "files": ["a.txt", "dir/b.txt"],
+        });
+        let changed = parse_query_response(&response)?;
+        assert_eq!(changed.cursor, "c:1638393322:1234:1:567");
+        assert!(!changed.is_fresh);
+        assert_eq!(
+            changed.paths,
+            vec![
+                RepoPathBuf::from_string("a.txt".to_string())?,
+                RepoPathBuf::from_string("dir/b.txt".to_string())?,
+            ]
+        );
+
+        let response = json!({"clock": "c:1:2", "is_fresh_instance": true, "files": []});
+        let changed = parse_query_response(&response)?;
+        assert!(changed.is_fresh);
+        assert!(changed.paths.is_empty());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_query() -> Result<()> {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir()?;
+        let sockpath = dir.path().join("sock");
+        let listener = UnixListener::bind(&sockpath)?;
+
+        // A fake Watchman that serves one watch-project and one query.
+        let server = std::thread::spawn(move || -> Result<Vec<Value>> {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream);
+            let mut requests = Vec::new();
+            for response in [
+                json!({"watch": "/repo", "relative_path": "sub"}),
+                json!({"unilateral": true, "subscription": "other"}),
+                json!({"clock": "c:5:6", "is_fresh_instance": false, "files": ["x"]}),
+            ] {
+                if response["unilateral"].is_null() {
+                    let mut line = String::new();
+                    reader.read_line(&mut line)?;
+                    requests.push(serde_json::from_str(&line)?);
+                }
+                let mut data = serde_json::to_vec(&response)?;
+                data.push(b'\n');
+                reader.get_mut().write_all(&data)?;
+            }
+            Ok(requests)
+        });
+
+        let source = WatchmanChangeSource::new(PathBuf::from("/repo/sub")).with_sockpath(sockpath);
+        let changed = source.changed_since(Some("c:1:2"))?;
+        assert_eq!(changed.cursor, "c:5:6");
+        assert_eq!(changed.paths.len(), 1);
+
+        let requests = server.join().unwrap()?;
+        assert_eq!(requests[0], json!(["watch-project", "/repo/sub"]));
+        assert_eq!(requests[1][0], "query");
+        assert_eq!(requests[1][1], "/repo");
+        assert_eq!(requests[1][2]["since"], "c:1:2");
+        assert_eq!(requests[1][2]["relative_root"], "sub");
+        Ok(())
+    }
+}
@@ -146,6 +146,7 @@ Show debug commands if there are no other candidates
   debuginternals
   debugknown
   debuglabelcomplete
+  debuglfsgc
   debuglocks
   debugmakepublic
   debugmanifestdirs
@@ -461,6 +462,7 @@ Show all commands + options
   debuginternals: output
   debugknown: 
   debuglabelcomplete: 
+  debuglfsgc: 
   debuglocks: force-lock, force-wlock, force-undolog-lock, set-lock, set-wlock, wait
   debugmakepublic: rev, delete
   debugmanifestdirs: rev